@@ -0,0 +1,13 @@
+extern crate wfd;
+
+use std::env;
+use std::io;
+
+// Serves dialog requests from stdin, or from a Unix socket if its path is given
+fn main() -> io::Result<()> {
+    match env::args_os().nth(1) {
+        #[cfg(unix)]
+        Some(path) => wfd::serve_unix_socket(&wfd::NativeDialogs, path.as_ref()),
+        _ => wfd::serve_stdio(&wfd::NativeDialogs),
+    }
+}
@@ -9,7 +9,7 @@ fn main() {
             ("Executable Files", "*.exe;*.com;*.scr"),
         ],
         default_extension: "dll",
-        default_folder: r"C:\Windows\System32",
+        default_folder: r"C:\Windows\System32".into(),
         file_name: "win32k.sys",
         file_name_label: "Select some files!",
         file_type_index: 1,
@@ -41,6 +41,24 @@ fn main() {
             DialogError::UserCancelled => {
                 println!("User cancelled dialog");
             }
+            DialogError::InvalidPath { field, path, .. } => {
+                println!("Invalid path for {}: {}", field, path);
+            }
+            DialogError::SelectionRejected { rejections } => {
+                for rejection in rejections {
+                    println!("Selection rejected: {}", rejection.reason);
+                }
+            }
+            DialogError::Io { path, error } => {
+                println!("I/O error for {}: {}", path.display(), error);
+            }
+            DialogError::NonInteractive => {
+                println!("No user can answer the dialog");
+            }
+            // Errors from options that this example doesn't use, and any variants added later
+            e => {
+                println!("Dialog failed: {:?}", e);
+            }
         },
     }
 }
@@ -0,0 +1,185 @@
+// The lifecycle of a dialog, split into the stages that a platform backend implements. The stages
+// are run and instrumented here so that the lifecycle can be exercised with a fake backend.
+
+use std::path::PathBuf;
+
+use crate::filters::FileTypeLayout;
+use crate::trace::{self, Stage};
+use crate::{DialogError, DialogParams, HWND};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DialogKind {
+    Open,
+    Save,
+}
+
+// The items selected in a dialog
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Selection {
+    pub(crate) paths: Vec<PathBuf>,
+    // The 1-based index of the selected file type, as displayed by the dialog until `run` maps
+    // it to an index into `DialogParams::file_types`
+    pub(crate) file_type_index: u32,
+}
+
+pub(crate) trait Backend {
+    // Creates the dialog
+    fn create(&mut self, kind: DialogKind) -> Result<(), DialogError>;
+    // Applies the parameters to the dialog, returning the layout of the displayed file types
+    fn configure(&mut self, params: &DialogParams) -> Result<FileTypeLayout, DialogError>;
+    // Shows the dialog, returning once it has been closed
+    fn show(&mut self, owner: Option<HWND>) -> Result<(), DialogError>;
+    // Returns the items that were selected in the dialog
+    fn collect(&mut self) -> Result<Selection, DialogError>;
+}
+
+// Creates, configures and shows a dialog, returning the selected items
+pub(crate) fn run<B: Backend>(
+    backend: &mut B,
+    kind: DialogKind,
+    params: &DialogParams,
+) -> Result<Selection, DialogError> {
+    let _span = trace::dialog_span(kind);
+    trace::parameters(params);
+
+    let result = run_stages(backend, kind, params);
+    trace::outcome(&result);
+    result
+}
+
+fn run_stages<B: Backend>(
+    backend: &mut B,
+    kind: DialogKind,
+    params: &DialogParams,
+) -> Result<Selection, DialogError> {
+    {
+        let _span = trace::stage_span(Stage::Create);
+        backend.create(kind)?;
+    }
+
+    let file_type_layout = {
+        let _span = trace::stage_span(Stage::Configure);
+        backend.configure(params)?
+    };
+
+    {
+        let _span = trace::stage_span(Stage::Show);
+        backend.show(params.owner)?;
+    }
+
+    let _span = trace::stage_span(Stage::Collect);
+    let selection = backend.collect()?;
+    let selection = Selection {
+        paths: selection
+            .paths
+            .into_iter()
+            .map(|path| params.path_form.apply(path))
+            .collect(),
+        file_type_index: file_type_layout.caller_index(selection.file_type_index),
+    };
+    trace::selection(&selection);
+    Ok(selection)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::filters::AggregateFilter;
+    use crate::PathForm;
+
+    // A backend that records the stages that were run and returns a predefined selection. Each
+    // stage makes a COM call with the HRESULT configured for it, so that failures can be tested.
+    #[derive(Default)]
+    pub(crate) struct FakeBackend {
+        pub(crate) stages: Vec<&'static str>,
+        pub(crate) failing_method: Option<(&'static str, i32)>,
+        pub(crate) selection: Vec<PathBuf>,
+        pub(crate) file_type_index: u32,
+    }
+
+    impl FakeBackend {
+        fn call(&mut self, stage: &'static str, method: &'static str) -> Result<(), DialogError> {
+            self.stages.push(stage);
+            let hresult = match self.failing_method {
+                Some((failing, hresult)) if failing == method => hresult,
+                _ => 0,
+            };
+            crate::com(|| hresult, method)
+        }
+    }
+
+    impl Backend for FakeBackend {
+        fn create(&mut self, _kind: DialogKind) -> Result<(), DialogError> {
+            self.call("create", "CoCreateInstance")
+        }
+
+        fn configure(&mut self, params: &DialogParams) -> Result<FileTypeLayout, DialogError> {
+            self.call("configure", "IFileDialog::SetOptions")?;
+            trace::options("IFileDialog::SetOptions", params.options);
+            Ok(FileTypeLayout::new(
+                &params.file_types,
+                params.aggregate_filter,
+            ))
+        }
+
+        fn show(&mut self, _owner: Option<HWND>) -> Result<(), DialogError> {
+            self.call("show", "IModalWindow::Show")
+        }
+
+        fn collect(&mut self) -> Result<Selection, DialogError> {
+            self.call("collect", "IFileOpenDialog::GetResults")?;
+            Ok(Selection {
+                paths: self.selection.clone(),
+                file_type_index: self.file_type_index,
+            })
+        }
+    }
+
+    #[test]
+    fn run_stages_in_order() {
+        let mut backend = FakeBackend {
+            selection: vec![PathBuf::from(r"\\?\C:\Users\ben\report.txt")],
+            file_type_index: 2,
+            ..Default::default()
+        };
+        let params = DialogParams {
+            file_types: vec![("Text", "*.txt"), ("Logs", "*.log")],
+            aggregate_filter: AggregateFilter::First,
+            path_form: PathForm::Plain,
+            ..Default::default()
+        };
+
+        let selection = run(&mut backend, DialogKind::Open, &params).unwrap();
+        assert_eq!(
+            backend.stages,
+            vec!["create", "configure", "show", "collect"]
+        );
+        assert_eq!(
+            selection,
+            Selection {
+                paths: vec![PathBuf::from(r"C:\Users\ben\report.txt")],
+                file_type_index: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn run_stops_at_failing_stage() {
+        let mut backend = FakeBackend {
+            failing_method: Some(("IModalWindow::Show", 0x8000_4005_u32 as i32)),
+            ..Default::default()
+        };
+        let result = run(&mut backend, DialogKind::Save, &DialogParams::default());
+        match result {
+            Err(DialogError::HResultFailed {
+                error_method,
+                hresult,
+            }) => {
+                assert_eq!(error_method, "IModalWindow::Show");
+                assert_eq!(hresult as u32, 0x8000_4005);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(backend.stages, vec!["create", "configure", "show"]);
+    }
+}
@@ -0,0 +1,917 @@
+// A broker that shows dialogs on behalf of processes that cannot link wfd, such as editor plugins
+// and tools running in containers. Requests are JSON-RPC 2.0 messages, one per line, read from
+// stdin or a Unix socket. Each dialog is shown on its own thread so that an open dialog does not
+// hold up the other requests, and responses are written as the dialogs close, matched to their
+// requests by id.
+
+use std::convert::TryInto;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+#[cfg(unix)]
+use std::time::Duration;
+
+use crate::backend::DialogKind;
+use crate::json::{self, object, Value};
+use crate::replay::{encode_error, encode_open, encode_save};
+use crate::trace;
+use crate::{
+    open_dialog, save_dialog, DialogError, DialogParams, FolderSpec, OpenDialogResult,
+    SaveDialogResult, FOS_ALLNONSTORAGEITEMS, FOS_ALLOWMULTISELECT, FOS_CREATEPROMPT,
+    FOS_DEFAULTNOMINIMODE, FOS_DONTADDTORECENT, FOS_FILEMUSTEXIST, FOS_FORCEFILESYSTEM,
+    FOS_FORCEPREVIEWPANEON, FOS_FORCESHOWHIDDEN, FOS_HIDEMRUPLACES, FOS_HIDEPINNEDPLACES,
+    FOS_NOCHANGEDIR, FOS_NODEREFERENCELINKS, FOS_NOREADONLYRETURN, FOS_NOTESTFILECREATE,
+    FOS_NOVALIDATE, FOS_OVERWRITEPROMPT, FOS_PATHMUSTEXIST, FOS_PICKFOLDERS, FOS_SHAREAWARE,
+    FOS_STRICTFILETYPES, FOS_SUPPORTSTREAMABLEITEMS,
+};
+
+// The error codes defined by JSON-RPC
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// The code of the error returned when a dialog fails, with the encoded `DialogError` as its data
+const DIALOG_ERROR: i64 = -32000;
+// The code of the error returned when a request is refused because the client already has
+// `MAX_OPEN_DIALOGS` dialogs open
+const TOO_MANY_DIALOGS: i64 = -32001;
+
+// The number of dialogs that each client may have open at once. Further requests are refused
+// rather than each being given a thread, so that a client cannot exhaust the broker's threads.
+const MAX_OPEN_DIALOGS: usize = 8;
+
+// The length in bytes of the longest request that is read. The rest of a longer line is skipped
+// without being stored, so that a client cannot make the broker buffer without limit.
+const MAX_REQUEST_LENGTH: usize = 1024 * 1024;
+
+// How long the socket broker waits after failing to accept a connection, as errors such as
+// running out of file descriptors last until other connections are closed
+#[cfg(unix)]
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// The options that can be named in the `options` and `un_options` of a request
+const OPTIONS: [(&str, u32); 22] = [
+    ("FOS_ALLNONSTORAGEITEMS", FOS_ALLNONSTORAGEITEMS),
+    ("FOS_ALLOWMULTISELECT", FOS_ALLOWMULTISELECT),
+    ("FOS_CREATEPROMPT", FOS_CREATEPROMPT),
+    ("FOS_DEFAULTNOMINIMODE", FOS_DEFAULTNOMINIMODE),
+    ("FOS_DONTADDTORECENT", FOS_DONTADDTORECENT),
+    ("FOS_FILEMUSTEXIST", FOS_FILEMUSTEXIST),
+    ("FOS_FORCEFILESYSTEM", FOS_FORCEFILESYSTEM),
+    ("FOS_FORCEPREVIEWPANEON", FOS_FORCEPREVIEWPANEON),
+    ("FOS_FORCESHOWHIDDEN", FOS_FORCESHOWHIDDEN),
+    ("FOS_HIDEMRUPLACES", FOS_HIDEMRUPLACES),
+    ("FOS_HIDEPINNEDPLACES", FOS_HIDEPINNEDPLACES),
+    ("FOS_NOCHANGEDIR", FOS_NOCHANGEDIR),
+    ("FOS_NODEREFERENCELINKS", FOS_NODEREFERENCELINKS),
+    ("FOS_NOREADONLYRETURN", FOS_NOREADONLYRETURN),
+    ("FOS_NOTESTFILECREATE", FOS_NOTESTFILECREATE),
+    ("FOS_NOVALIDATE", FOS_NOVALIDATE),
+    ("FOS_OVERWRITEPROMPT", FOS_OVERWRITEPROMPT),
+    ("FOS_PATHMUSTEXIST", FOS_PATHMUSTEXIST),
+    ("FOS_PICKFOLDERS", FOS_PICKFOLDERS),
+    ("FOS_SHAREAWARE", FOS_SHAREAWARE),
+    ("FOS_STRICTFILETYPES", FOS_STRICTFILETYPES),
+    ("FOS_SUPPORTSTREAMABLEITEMS", FOS_SUPPORTSTREAMABLEITEMS),
+];
+
+/// Shows the dialogs requested through the broker. Each method may be called from several
+/// threads at once, one for each dialog that is open.
+pub trait DialogHandler: Sync {
+    /// Shows an Open dialog with the requested parameters
+    fn open(&self, params: DialogParams) -> Result<OpenDialogResult, DialogError>;
+    /// Shows a Save dialog with the requested parameters
+    fn save(&self, params: DialogParams) -> Result<SaveDialogResult, DialogError>;
+}
+
+/// A [`DialogHandler`] that shows dialogs using [`open_dialog`] and [`save_dialog`]
+///
+/// [`DialogHandler`]: trait.DialogHandler.html
+/// [`open_dialog`]: fn.open_dialog.html
+/// [`save_dialog`]: fn.save_dialog.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeDialogs;
+
+impl DialogHandler for NativeDialogs {
+    fn open(&self, params: DialogParams) -> Result<OpenDialogResult, DialogError> {
+        open_dialog(params)
+    }
+
+    fn save(&self, params: DialogParams) -> Result<SaveDialogResult, DialogError> {
+        save_dialog(params)
+    }
+}
+
+/// Serves dialog requests read from stdin, writing the responses to stdout, until stdin is
+/// closed. Returns once every requested dialog has been answered.
+///
+/// Each line of the input is a JSON-RPC 2.0 request for the `open` or `save` method. The params
+/// of a request are an object with any of the members `title`, `ok_button_label`, `file_name`,
+/// `file_name_label`, `default_extension`, `folder`, `default_folder` and `save_as_item` as
+/// strings, `file_type_index` as a number, `add_to_recent` and `require_writable` as booleans,
+/// `file_types` as an array of `[label, pattern]` pairs, and `options` and `un_options` as arrays
+/// of option names such as `"FOS_ALLOWMULTISELECT"`. Parameters that are left out keep their
+/// default values.
+///
+/// The result of a successful request is an object with the selected `paths` (or `path` for the
+/// Save dialog), the `file_type_index`, the `sandbox`, the selected `host_paths` (or `host_path`)
+/// and the `shortcut_targets` (or `shortcut_target`). A dialog that fails is answered with an
+/// error with the code `-32000`, the kind of the [`DialogError`] as its message and the error
+/// itself as its data. Requests are answered in the order that their dialogs close, so clients
+/// must match the responses by id. Requests without an id are notifications, and as they cannot
+/// be answered no dialog is shown for them. At most 8 dialogs are open at once, and requests made
+/// while that many are open are answered with an error with the code `-32001`. Lines that are not
+/// valid UTF-8 or are longer than 1 MiB are answered with a parse error with the code `-32700`.
+///
+/// # Examples
+///
+/// A request for an Open dialog that allows multiple text files to be selected
+///
+/// ```text
+/// {"jsonrpc":"2.0","id":1,"method":"open","params":{"file_types":[["Text","*.txt"]],"options":["FOS_ALLOWMULTISELECT"]}}
+/// ```
+///
+/// and its response
+///
+/// ```text
+/// {"jsonrpc":"2.0","id":1,"result":{"paths":["C:\\a.txt","C:\\b.txt"],"file_type_index":1,"sandbox":"none","host_paths":["C:\\a.txt","C:\\b.txt"],"shortcut_targets":[]}}
+/// ```
+///
+/// # Errors
+/// Returns an error if stdin cannot be read. Failing to write a response does not stop the
+/// broker.
+///
+/// [`DialogError`]: enum.DialogError.html
+pub fn serve_stdio<H: DialogHandler>(handler: &H) -> io::Result<()> {
+    let stdin = io::stdin();
+    serve(handler, stdin.lock(), io::stdout())
+}
+
+/// Serves dialog requests from the clients that connect to a Unix socket bound to the given
+/// path, which must not already exist. Each connection is served in the same way as stdin by
+/// [`serve_stdio`], and the responses are written back to the connection that made the request.
+///
+/// The socket can only be used by the user running the broker, as it is created with the mode
+/// `0600` and, where the platform reports the user of the connecting process, connections from
+/// other users are closed without being served.
+///
+/// # Errors
+/// Returns an error if the socket cannot be bound. Failing to accept a connection is retried after
+/// a short delay, and errors of individual connections only close that connection.
+///
+/// [`serve_stdio`]: fn.serve_stdio.html
+#[cfg(unix)]
+pub fn serve_unix_socket<H: DialogHandler>(handler: &H, path: &Path) -> io::Result<()> {
+    use std::fs;
+    use std::io::BufReader;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    trace::broker_error("accept", &e);
+                    thread::sleep(ACCEPT_RETRY_DELAY);
+                    continue;
+                }
+            };
+            // The permissions are set after the socket is bound, so a connection could have been
+            // made by another user before they applied
+            if !is_same_user(&stream) {
+                continue;
+            }
+            scope.spawn(move || -> io::Result<()> {
+                let reader = BufReader::new(stream.try_clone()?);
+                serve(handler, reader, stream)
+            });
+        }
+        Ok(())
+    })
+}
+
+// Returns true if the process at the other end of a connection runs as the same user as the
+// broker, or if the platform doesn't report the user of the process
+#[cfg(unix)]
+fn is_same_user(stream: &std::os::unix::net::UnixStream) -> bool {
+    match peer_uid(stream) {
+        Ok(Some(uid)) => uid == unsafe { libc::geteuid() },
+        Ok(None) => true,
+        Err(_) => false,
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> io::Result<Option<libc::uid_t>> {
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let mut credentials: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result == 0 {
+        Ok(Some(credentials.uid))
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> io::Result<Option<libc::uid_t>> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == 0 {
+        Ok(Some(uid))
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Other platforms rely on the permissions of the socket alone
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+))]
+fn peer_uid(_stream: &std::os::unix::net::UnixStream) -> io::Result<Option<libc::uid_t>> {
+    Ok(None)
+}
+
+// Serves the requests read from `input` until it ends, writing the responses to `output`
+fn serve<H, R, W>(handler: &H, mut input: R, output: W) -> io::Result<()>
+where
+    H: DialogHandler,
+    R: BufRead,
+    W: Write + Send,
+{
+    let output = Mutex::new(output);
+    // Only this thread increases the count, so it cannot exceed the limit once checked
+    let open_dialogs = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        while let Some(line) = read_line(&mut input)? {
+            let line = match line {
+                Line::Request(line) => line,
+                Line::Invalid(message) => {
+                    let response = error_response(Value::Null, PARSE_ERROR, message, None);
+                    let _ = respond(&output, &response);
+                    continue;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_request(&line) {
+                Ok(request) => {
+                    if open_dialogs.load(Ordering::SeqCst) >= MAX_OPEN_DIALOGS {
+                        let message = "too many dialogs are open";
+                        let response = error_response(request.id, TOO_MANY_DIALOGS, message, None);
+                        let _ = respond(&output, &response);
+                        continue;
+                    }
+
+                    open_dialogs.fetch_add(1, Ordering::SeqCst);
+                    let (output, open_dialogs) = (&output, &open_dialogs);
+                    scope.spawn(move || {
+                        let response = handle(handler, request);
+                        // The dialog is closed, so the client may request another as soon as it
+                        // reads the response
+                        open_dialogs.fetch_sub(1, Ordering::SeqCst);
+                        let _ = respond(output, &response);
+                    });
+                }
+                Err(Some(response)) => {
+                    let _ = respond(&output, &response);
+                }
+                Err(None) => {}
+            }
+        }
+        Ok(())
+    })
+}
+
+// A line of the input
+enum Line {
+    Request(String),
+    // A line that cannot be a request, with the message of the parse error that answers it
+    Invalid(&'static str),
+}
+
+// Reads the next line of `input`, or returns `None` at the end of the input
+fn read_line<R: BufRead>(input: &mut R) -> io::Result<Option<Line>> {
+    let mut line = vec![];
+    // One byte more than the limit is read to tell a line of the maximum length from a longer one
+    let limit = MAX_REQUEST_LENGTH as u64 + 1;
+    if Read::take(&mut *input, limit).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') && line.len() > MAX_REQUEST_LENGTH {
+        skip_line(input)?;
+        return Ok(Some(Line::Invalid("the request is too long")));
+    }
+
+    Ok(Some(match String::from_utf8(line) {
+        Ok(line) => Line::Request(line),
+        Err(_) => Line::Invalid("the request is not valid UTF-8"),
+    }))
+}
+
+// Discards the rest of the current line of `input`
+fn skip_line<R: BufRead>(input: &mut R) -> io::Result<()> {
+    loop {
+        let available = match input.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            return Ok(());
+        }
+        let newline = available.iter().position(|&byte| byte == b'\n');
+        let length = newline.map_or(available.len(), |index| index + 1);
+        input.consume(length);
+        if newline.is_some() {
+            return Ok(());
+        }
+    }
+}
+
+fn respond<W: Write>(output: &Mutex<W>, response: &Value) -> io::Result<()> {
+    let mut line = json::write(response);
+    line.push('\n');
+    let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+    output.write_all(line.as_bytes())?;
+    output.flush()
+}
+
+// A request for a dialog
+struct Request {
+    id: Value,
+    kind: DialogKind,
+    params: Value,
+}
+
+// Parses a request for a dialog. If the line is not a valid request, the error response to send
+// instead is returned, or `None` if it was a notification which must not be answered.
+fn parse_request(line: &str) -> Result<Request, Option<Value>> {
+    let request = json::parse(line)
+        .map_err(|e| Some(error_response(Value::Null, PARSE_ERROR, &e.0, None)))?;
+    if !matches!(request, Value::Object(_)) {
+        return Err(Some(error_response(
+            Value::Null,
+            INVALID_REQUEST,
+            "the request must be an object",
+            None,
+        )));
+    }
+
+    let id = match request.get("id") {
+        Some(id @ Value::Null) | Some(id @ Value::Number(_)) | Some(id @ Value::String(_)) => {
+            id.clone()
+        }
+        Some(_) => {
+            return Err(Some(error_response(
+                Value::Null,
+                INVALID_REQUEST,
+                "the id must be a string, number or null",
+                None,
+            )))
+        }
+        None => return Err(None),
+    };
+    let invalid = |code, message: &str| Err(Some(error_response(id.clone(), code, message, None)));
+
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return invalid(INVALID_REQUEST, "the jsonrpc version must be \"2.0\"");
+    }
+    let kind = match request.get("method").map(Value::as_str) {
+        Some(Some("open")) => DialogKind::Open,
+        Some(Some("save")) => DialogKind::Save,
+        Some(Some(method)) => {
+            return invalid(METHOD_NOT_FOUND, &format!("unknown method `{}`", method))
+        }
+        _ => return invalid(INVALID_REQUEST, "the method must be a string"),
+    };
+    let params = match request.get("params") {
+        None => object(vec![]),
+        Some(params @ Value::Object(_)) => params.clone(),
+        Some(_) => return invalid(INVALID_PARAMS, "the params must be an object"),
+    };
+
+    Ok(Request { id, kind, params })
+}
+
+// Shows the requested dialog, returning the response to the request
+fn handle<H: DialogHandler>(handler: &H, request: Request) -> Value {
+    let params = match decode_params(&request.params) {
+        Ok(params) => params,
+        Err(message) => return error_response(request.id, INVALID_PARAMS, &message, None),
+    };
+
+    let result = match request.kind {
+        DialogKind::Open => handler.open(params).map(|result| encode_open(&result)),
+        DialogKind::Save => handler.save(params).map(|result| encode_save(&result)),
+    };
+    match result {
+        Ok(result) => object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", request.id),
+            ("result", result),
+        ]),
+        Err(e) => {
+            let data = encode_error(&e);
+            let kind = data
+                .get("kind")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            error_response(request.id, DIALOG_ERROR, &kind, Some(data))
+        }
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str, data: Option<Value>) -> Value {
+    let mut error = vec![("code", code.into()), ("message", message.into())];
+    if let Some(data) = data {
+        error.push(("data", data));
+    }
+    object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        ("error", object(error)),
+    ])
+}
+
+// Decodes the params of a request, returning the reason that they are invalid on failure
+fn decode_params(value: &Value) -> Result<DialogParams<'_>, String> {
+    let mut params = DialogParams::default();
+    let members = match value {
+        Value::Object(members) => members,
+        _ => return Err("the params must be an object".to_string()),
+    };
+
+    for (name, value) in members {
+        let name = name.as_str();
+        match name {
+            "title" => params.title = string(name, value)?,
+            "ok_button_label" => params.ok_button_label = string(name, value)?,
+            "file_name" => params.file_name = string(name, value)?,
+            "file_name_label" => params.file_name_label = string(name, value)?,
+            "default_extension" => params.default_extension = string(name, value)?,
+            "folder" => params.folder = FolderSpec::from(string(name, value)?),
+            "default_folder" => params.default_folder = FolderSpec::from(string(name, value)?),
+            "save_as_item" => params.save_as_item = Path::new(string(name, value)?),
+            "file_type_index" => {
+                params.file_type_index = value
+                    .as_u64()
+                    .and_then(|index| index.try_into().ok())
+                    .ok_or_else(|| format!("`{}` must be an unsigned 32-bit integer", name))?
+            }
+            "add_to_recent" => params.add_to_recent = boolean(name, value)?,
+            "require_writable" => params.require_writable = boolean(name, value)?,
+            "file_types" => params.file_types = file_types(value)?,
+            "options" => params.options = options(name, value)?,
+            "un_options" => params.un_options = options(name, value)?,
+            _ => return Err(format!("unknown parameter `{}`", name)),
+        }
+    }
+    Ok(params)
+}
+
+fn string<'a>(name: &str, value: &'a Value) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("`{}` must be a string", name))
+}
+
+fn boolean(name: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("`{}` must be a boolean", name))
+}
+
+fn file_types(value: &Value) -> Result<Vec<(&str, &str)>, String> {
+    let invalid = || "`file_types` must be an array of [label, pattern] pairs".to_string();
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|file_type| match file_type.as_array() {
+            Some([label, pattern]) => Ok((
+                label.as_str().ok_or_else(invalid)?,
+                pattern.as_str().ok_or_else(invalid)?,
+            )),
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
+// Combines the named options into a bitmask
+fn options(name: &str, value: &Value) -> Result<u32, String> {
+    let names = value
+        .as_array()
+        .ok_or_else(|| format!("`{}` must be an array of option names", name))?;
+    names.iter().try_fold(0, |options, option| {
+        let option = option
+            .as_str()
+            .ok_or_else(|| format!("`{}` must be an array of option names", name))?;
+        OPTIONS
+            .iter()
+            .find(|(name, _)| *name == option)
+            .map(|(_, value)| options | value)
+            .ok_or_else(|| format!("unknown option `{}`", option))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sandbox;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, Receiver};
+
+    // Answers dialogs from the params of the request. An Open dialog selects `file_name` in
+    // `/home/ben`, along with `other.txt` if multiple files can be selected, and a Save dialog
+    // selects `file_name` with the default extension. Dialogs titled `cancel` are cancelled, and
+    // dialogs titled `wait` stay open until the test releases them.
+    struct ScriptedDialogs {
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl ScriptedDialogs {
+        fn new(release: Receiver<()>) -> Self {
+            ScriptedDialogs {
+                release: Mutex::new(release),
+            }
+        }
+
+        fn show(&self, params: &DialogParams) -> Result<(), DialogError> {
+            match params.title {
+                "cancel" => Err(DialogError::UserCancelled),
+                "wait" => {
+                    self.release.lock().unwrap().recv().unwrap();
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    impl DialogHandler for ScriptedDialogs {
+        fn open(&self, params: DialogParams) -> Result<OpenDialogResult, DialogError> {
+            self.show(&params)?;
+            let mut paths = vec![Path::new("/home/ben").join(params.file_name)];
+            if params.options & FOS_ALLOWMULTISELECT != 0 {
+                paths.push(PathBuf::from("/home/ben/other.txt"));
+            }
+            Ok(OpenDialogResult {
+                selected_file_path: paths[0].clone(),
+                selected_host_paths: paths.clone(),
+                selected_file_paths: paths,
+                selected_file_type_index: params.file_type_index,
+                sandbox: Sandbox::None,
+                shortcut_targets: vec![],
+            })
+        }
+
+        fn save(&self, params: DialogParams) -> Result<SaveDialogResult, DialogError> {
+            self.show(&params)?;
+            let path = Path::new("/home/ben")
+                .join(params.file_name)
+                .with_extension(params.default_extension);
+            Ok(SaveDialogResult {
+                selected_host_path: path.clone(),
+                selected_file_path: path,
+                selected_filter_index: params.file_type_index,
+                sandbox: Sandbox::None,
+                shortcut_target: None,
+            })
+        }
+    }
+
+    fn parse_responses(output: &[u8]) -> Vec<Value> {
+        String::from_utf8(output.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| json::parse(line).unwrap())
+            .collect()
+    }
+
+    fn error_code(response: &Value) -> Option<i64> {
+        response.get("error")?.get("code")?.as_i64()
+    }
+
+    #[test]
+    fn requests_and_responses() {
+        let (_release, receiver) = mpsc::channel();
+        let handler = ScriptedDialogs::new(receiver);
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"open","params":{"file_name":"a.txt","file_types":[["Text","*.txt"],["All","*.*"]],"file_type_index":2,"options":["FOS_ALLOWMULTISELECT","FOS_FILEMUSTEXIST"]}}"#,
+            r#"{"jsonrpc":"2.0","id":"save","method":"save","params":{"file_name":"report","default_extension":"csv","folder":"/home/ben"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"open","params":{"title":"cancel"}}"#,
+            "",
+            r#"{"jsonrpc":"2.0","id":4,"method":"open""#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"delete"}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"open","params":{"options":["FOS_BOGUS"]}}"#,
+            r#"{"jsonrpc":"2.0","id":7,"method":"open","params":{"colour":"red"}}"#,
+            r#"{"jsonrpc":"2.0","method":"open","params":{}}"#,
+            r#"[{"jsonrpc":"2.0","id":8,"method":"open"}]"#,
+            r#"{"jsonrpc":"1.0","id":9,"method":"open"}"#,
+            r#"{"jsonrpc":"2.0","id":10,"method":"save","params":[]}"#,
+            r#"{"jsonrpc":"2.0","id":{},"method":"open"}"#,
+        ]
+        .join("\n");
+
+        let mut output = vec![];
+        serve(&handler, input.as_bytes(), &mut output).unwrap();
+        let responses = parse_responses(&output);
+        assert_eq!(responses.len(), 11);
+        let by_id = responses
+            .iter()
+            .filter(|response| !response.get("id").unwrap().is_null())
+            .map(|response| (json::write(response.get("id").unwrap()), response))
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(
+            json::write(by_id["1"]),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"paths":["/home/ben/a.txt","/home/ben/other.txt"],"file_type_index":2,"sandbox":"none","host_paths":["/home/ben/a.txt","/home/ben/other.txt"],"shortcut_targets":[]}}"#
+        );
+        assert_eq!(
+            json::write(by_id["\"save\""]),
+            r#"{"jsonrpc":"2.0","id":"save","result":{"path":"/home/ben/report.csv","file_type_index":1,"sandbox":"none","host_path":"/home/ben/report.csv","shortcut_target":null}}"#
+        );
+        assert_eq!(
+            json::write(by_id["3"]),
+            r#"{"jsonrpc":"2.0","id":3,"error":{"code":-32000,"message":"UserCancelled","data":{"kind":"UserCancelled"}}}"#
+        );
+        assert_eq!(error_code(by_id["5"]), Some(METHOD_NOT_FOUND));
+        assert_eq!(
+            json::write(by_id["6"]),
+            r#"{"jsonrpc":"2.0","id":6,"error":{"code":-32602,"message":"unknown option `FOS_BOGUS`"}}"#
+        );
+        assert_eq!(error_code(by_id["7"]), Some(INVALID_PARAMS));
+        assert_eq!(error_code(by_id["9"]), Some(INVALID_REQUEST));
+        assert_eq!(error_code(by_id["10"]), Some(INVALID_PARAMS));
+
+        // Responses to requests whose id cannot be determined have a null id
+        let mut codes = responses
+            .iter()
+            .filter(|response| response.get("id").unwrap().is_null())
+            .filter_map(error_code)
+            .collect::<Vec<_>>();
+        codes.sort_unstable();
+        assert_eq!(codes, vec![PARSE_ERROR, INVALID_REQUEST, INVALID_REQUEST]);
+    }
+
+    // Writes each response to a channel as it is written, so that it can be read while the broker
+    // is still serving
+    struct ChannelWriter(mpsc::Sender<Vec<u8>>);
+
+    impl Write for ChannelWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.0.send(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn open_dialogs_are_limited() {
+        let (release, receiver) = mpsc::channel();
+        let handler = ScriptedDialogs::new(receiver);
+        let input = (0..=MAX_OPEN_DIALOGS)
+            .map(|id| {
+                format!(
+                    r#"{{"jsonrpc":"2.0","id":{},"method":"open","params":{{"title":"wait"}}}}"#,
+                    id
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (sender, responses) = mpsc::channel();
+
+        thread::scope(|scope| {
+            scope.spawn(|| serve(&handler, input.as_bytes(), ChannelWriter(sender)).unwrap());
+
+            // The last request is refused while the others wait to be released
+            let response = parse_responses(&responses.recv().unwrap()).remove(0);
+            assert_eq!(response.get("id"), Some(&Value::from(MAX_OPEN_DIALOGS)));
+            assert_eq!(error_code(&response), Some(TOO_MANY_DIALOGS));
+
+            for _ in 0..MAX_OPEN_DIALOGS {
+                release.send(()).unwrap();
+            }
+        });
+        let results = responses
+            .iter()
+            .map(|line| parse_responses(&line).remove(0))
+            .filter(|response| response.get("result").is_some())
+            .count();
+        assert_eq!(results, MAX_OPEN_DIALOGS);
+    }
+
+    #[test]
+    fn invalid_lines_are_answered() {
+        let (_release, receiver) = mpsc::channel();
+        let handler = ScriptedDialogs::new(receiver);
+        // A request of exactly the maximum length is served
+        let prefix = r#"{"jsonrpc":"2.0","id":2,"method":"open","params":{"file_name":""#;
+        let suffix = r#""}}"#;
+        let file_name = "a".repeat(MAX_REQUEST_LENGTH - prefix.len() - suffix.len());
+        let longest = format!("{}{}{}", prefix, file_name, suffix);
+        assert_eq!(longest.len(), MAX_REQUEST_LENGTH);
+        let mut input = b"\xff\xfe\n".to_vec();
+        input.extend_from_slice(&[b' '; MAX_REQUEST_LENGTH + 1]);
+        input.extend_from_slice(b"\n");
+        input.extend_from_slice(longest.as_bytes());
+        input.extend_from_slice(b"\n");
+        input.extend_from_slice(br#"{"jsonrpc":"2.0","id":1,"method":"open","params":{}}"#);
+
+        // A small buffer makes the long line span many reads
+        let input = io::BufReader::with_capacity(16, &input[..]);
+        let mut output = vec![];
+        serve(&handler, input, &mut output).unwrap();
+        let responses = parse_responses(&output);
+        assert_eq!(responses.len(), 4);
+
+        let errors = responses
+            .iter()
+            .filter(|response| error_code(response) == Some(PARSE_ERROR))
+            .map(|response| response.get("error").unwrap().get("message").unwrap())
+            .map(json::write)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                r#""the request is not valid UTF-8""#,
+                r#""the request is too long""#
+            ]
+        );
+        let mut results = responses
+            .iter()
+            .filter(|response| response.get("result").is_some())
+            .map(|response| json::write(response.get("id").unwrap()))
+            .collect::<Vec<_>>();
+        results.sort();
+        assert_eq!(results, ["1", "2"]);
+    }
+
+    #[test]
+    fn decode_request_params() {
+        let value = json::parse(
+            r#"{"title":"Export","ok_button_label":"Export","file_name_label":"Name","default_folder":"file:///home/ben/Documents","save_as_item":"/home/ben/report.csv","add_to_recent":true,"require_writable":true,"un_options":["FOS_OVERWRITEPROMPT"]}"#,
+        )
+        .unwrap();
+        let params = decode_params(&value).unwrap();
+        assert_eq!(params.title, "Export");
+        assert_eq!(params.ok_button_label, "Export");
+        assert_eq!(params.file_name_label, "Name");
+        assert_eq!(
+            params.default_folder,
+            FolderSpec::Uri("file:///home/ben/Documents")
+        );
+        assert_eq!(params.save_as_item, Path::new("/home/ben/report.csv"));
+        assert!(params.add_to_recent);
+        assert!(params.require_writable);
+        assert_eq!(params.un_options, FOS_OVERWRITEPROMPT);
+
+        let invalid = |params: &str| decode_params(&json::parse(params).unwrap()).unwrap_err();
+        assert_eq!(invalid(r#"{"title":1}"#), "`title` must be a string");
+        assert_eq!(
+            invalid(r#"{"file_type_index":-1}"#),
+            "`file_type_index` must be an unsigned 32-bit integer"
+        );
+        assert_eq!(
+            invalid(r#"{"file_types":[["Text"]]}"#),
+            "`file_types` must be an array of [label, pattern] pairs"
+        );
+        assert_eq!(
+            invalid(r#"{"options":"FOS_PICKFOLDERS"}"#),
+            "`options` must be an array of option names"
+        );
+        assert_eq!(
+            invalid(r#"{"add_to_recent":"yes"}"#),
+            "`add_to_recent` must be a boolean"
+        );
+    }
+
+    #[test]
+    fn option_names() {
+        for (name, value) in OPTIONS.iter() {
+            assert!(name.starts_with("FOS_"));
+            assert_eq!(value.count_ones(), 1, "{}", name);
+        }
+        let all = OPTIONS.iter().fold(0, |all, (_, value)| all | value);
+        assert_eq!(all.count_ones() as usize, OPTIONS.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn concurrent_requests_over_socket() {
+        use std::env;
+        use std::fs;
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixStream;
+        use std::process;
+        use std::time::{Duration, Instant};
+
+        let path = env::temp_dir().join(format!("wfd-broker-socket-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let (release, receiver) = mpsc::channel();
+        let handler: &'static ScriptedDialogs = Box::leak(Box::new(ScriptedDialogs::new(receiver)));
+        {
+            let path = path.clone();
+            thread::spawn(move || serve_unix_socket(handler, &path));
+        }
+
+        let mut stream = loop {
+            match UnixStream::connect(&path) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        // The permissions are set just after the socket is bound
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mode = loop {
+            let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+            if mode == 0o600 || Instant::now() > deadline {
+                break mode;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(mode, 0o600);
+        assert!(is_same_user(&stream));
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut read_response = || {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            json::parse(&line).unwrap()
+        };
+
+        // The first dialog stays open while the second is answered
+        writeln!(
+            stream,
+            r#"{{"jsonrpc":"2.0","id":1,"method":"open","params":{{"title":"wait","file_name":"a.txt"}}}}"#
+        )
+        .unwrap();
+        writeln!(
+            stream,
+            r#"{{"jsonrpc":"2.0","id":2,"method":"save","params":{{"file_name":"b","default_extension":"txt"}}}}"#
+        )
+        .unwrap();
+        let response = read_response();
+        assert_eq!(response.get("id"), Some(&Value::from(2)));
+        assert_eq!(
+            response.get("result").unwrap().get("path"),
+            Some(&Value::from("/home/ben/b.txt"))
+        );
+
+        release.send(()).unwrap();
+        let response = read_response();
+        assert_eq!(response.get("id"), Some(&Value::from(1)));
+        assert_eq!(
+            response.get("result").unwrap().get("paths"),
+            Some(&Value::from(vec!["/home/ben/a.txt"]))
+        );
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,936 @@
+// The Windows Open and Save dialogs, shown through the COM `IFileDialog` interfaces. Only built on
+// Windows with the `native-windows` feature.
+
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::rc::Rc;
+use std::slice;
+
+use libc::wcslen;
+use winapi::{
+    ctypes::c_void,
+    shared::{minwindef::LPVOID, ntdef::LPWSTR, winerror::S_OK},
+    um::{
+        combaseapi::{CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL},
+        objbase::{COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE},
+        oleidl::IOleWindow,
+        shobjidl::{IFileDialog, IFileOpenDialog, IFileSaveDialog, IShellItemArray},
+        shobjidl_core::{
+            CLSID_FileOpenDialog, CLSID_FileSaveDialog, IShellItem, SHCreateItemFromParsingName,
+            SFGAOF, SICHINT_CANONICAL, SIGDN_FILESYSPATH,
+        },
+        shtypes::COMDLG_FILTERSPEC,
+        winuser::{GetDlgItem, GetFocus, IDOK},
+    },
+    Interface,
+};
+
+use crate::backend::{Backend, DialogKind, Selection};
+use crate::com_events::{self, EventHandler};
+use crate::com_filter;
+use crate::filters::FileTypeLayout;
+use crate::{
+    path_form, sanitize_file_name, trace, uri, DialogError, DialogParams, FilterItem, FolderSpec,
+    InvalidPathBehavior, ItemFilter, ItemVisibility, NullTermUTF16, OverwritePolicy, SaveTarget,
+    FOS_OVERWRITEPROMPT, HWND,
+};
+
+const SFGAO_FILESYSTEM: u32 = 0x4000_0000;
+const SFGAO_FOLDER: u32 = 0x2000_0000;
+const SFGAO_HIDDEN: u32 = 0x0008_0000;
+const SFGAO_READONLY: u32 = 0x0004_0000;
+
+type FileExtensionFilterPair<'a> = (&'a str, &'a str);
+
+// The Windows Open and Save dialogs, shown through the stages of a `Backend`
+pub(crate) struct ComDialog {
+    dialog: *mut IFileDialog,
+    kind: DialogKind,
+    com_initialized: bool,
+    // The cookie of the event handler called when the OK button is clicked, if it is advised
+    events_cookie: Option<u32>,
+    // The items accepted by the event handler when the OK button was clicked
+    accepted_items: Rc<RefCell<Option<Vec<PathBuf>>>>,
+}
+
+impl ComDialog {
+    pub(crate) fn new() -> Self {
+        ComDialog {
+            dialog: null_mut(),
+            kind: DialogKind::Open,
+            com_initialized: false,
+            events_cookie: None,
+            accepted_items: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    fn dialog(&self) -> &IFileDialog {
+        unsafe { &*self.dialog }
+    }
+
+    // Only valid for dialogs created with `DialogKind::Open`
+    fn open_dialog(&self) -> &IFileOpenDialog {
+        unsafe { &*(self.dialog as *mut IFileOpenDialog) }
+    }
+
+    // Only valid for dialogs created with `DialogKind::Save`
+    fn save_dialog(&self) -> &IFileSaveDialog {
+        unsafe { &*(self.dialog as *mut IFileSaveDialog) }
+    }
+
+    fn set_save_as_item(&self, params: &DialogParams) -> Result<(), DialogError> {
+        let file_save_dialog = self.save_dialog();
+        let save_as_item = match uri::input_path(params.save_as_item) {
+            Ok(save_as_item) => save_as_item,
+            Err(cause) => {
+                let path = params.save_as_item.to_string_lossy().into_owned();
+                return invalid_path_field(
+                    "save_as_item",
+                    path,
+                    cause,
+                    params.invalid_path_behavior,
+                )
+                .map(|_| ());
+            }
+        };
+        let resolved = resolve_path_field(
+            "save_as_item",
+            save_as_item.as_os_str(),
+            params.invalid_path_behavior,
+        )?;
+        if let Some(resolved) = resolved {
+            if resolved.is_ancestor {
+                // The item itself no longer exists, so fall back to opening the dialog in the
+                // nearest existing ancestor with the item's file name pre-populated
+                com!(
+                    file_save_dialog.SetFolder(resolved.item),
+                    "IFileDialog::SetFolder"
+                )?;
+                if let Some(file_name) = save_as_item.file_name() {
+                    let file_name = file_name.to_null_term_utf16("save_as_item")?;
+                    com!(
+                        file_save_dialog.SetFileName(file_name.as_ptr()),
+                        "IFileDialog::SetFileName"
+                    )?;
+                }
+            } else {
+                com!(
+                    file_save_dialog.SetSaveAsItem(resolved.item),
+                    "IFileDialog::SetSaveAsItem"
+                )?;
+            }
+            unsafe {
+                let item = &*resolved.item;
+                item.Release();
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_open_results(&self) -> Result<Vec<PathBuf>, DialogError> {
+        // IFileOpenDialog::GetResults
+        let mut shell_item_array: *mut IShellItemArray = null_mut();
+        com!(
+            self.open_dialog().GetResults(&mut shell_item_array),
+            "IFileOpenDialog::GetResults"
+        )?;
+        shell_item_array_paths(shell_item_array)
+    }
+
+    // Installs an event handler that accepts the items selected in the view when the OK button is
+    // clicked, whether they are files or folders, or the current folder if no items are selected,
+    // see `FilesAndFolders`
+    fn advise_files_and_folders(&mut self) -> Result<(), DialogError> {
+        self.advise(FilesAndFolders {
+            accepted_items: Rc::clone(&self.accepted_items),
+        })
+    }
+
+    // Installs an event handler that keeps a Save dialog open when the selected file cannot be
+    // written, telling the user why. A file whose path cannot be read is left to the check made
+    // once the dialog has closed.
+    fn advise_require_writable(&mut self, title: &str) -> Result<(), DialogError> {
+        self.advise(move |dialog| {
+            let path = match save_result(dialog) {
+                Ok(path) => path,
+                Err(_) => return true,
+            };
+            match SaveTarget::new(path.clone()).check_writable() {
+                Ok(()) => true,
+                Err(error) => {
+                    let text = crate::unwritable_message(&path, &error);
+                    crate::show_rejection(dialog_window(dialog), title, &text);
+                    false
+                }
+            }
+        })
+    }
+
+    // Installs an event handler, which is kept until the dialog is dropped. A closure is called
+    // when the OK button is clicked, keeping the dialog open if it returns false. The values
+    // borrowed by the handler must outlive the dialog.
+    fn advise<'a, H>(&mut self, handler: H) -> Result<(), DialogError>
+    where
+        H: EventHandler + 'a,
+    {
+        // The handler is unadvised when the dialog is dropped, which `run_native_dialog` does
+        // while the params that the handlers borrow are still alive
+        let events = unsafe { com_events::create(handler) };
+
+        let mut cookie: u32 = 0;
+        let result = com!(
+            self.dialog().Advise(events, &mut cookie),
+            "IFileDialog::Advise"
+        );
+        // The dialog holds its own reference until the handler is unadvised
+        unsafe { (*events).Release() };
+        result?;
+        self.events_cookie = Some(cookie);
+        Ok(())
+    }
+
+    fn collect_save_result(&self) -> Result<PathBuf, DialogError> {
+        save_result(self.dialog())
+    }
+}
+
+// The event handler of an Open dialog that selects files and folders together. Clicking the OK
+// button of a file dialog opens a selected folder instead of returning it, and does nothing when
+// no items are selected. So while no files are selected the file name box holds `.`, which makes
+// the OK button navigate to the current folder, and a navigation started by the OK button is
+// refused and the dialog closed with the selected items instead. Files, whether selected or typed
+// in the file name box, are accepted by the dialog as usual and read from its results.
+struct FilesAndFolders {
+    accepted_items: Rc<RefCell<Option<Vec<PathBuf>>>>,
+}
+
+impl FilesAndFolders {
+    fn accept(&self, dialog: &IFileDialog) {
+        *self.accepted_items.borrow_mut() = selected_items(dialog).ok();
+    }
+
+    // Selected files are entered in the file name box by the dialog itself
+    fn enter_current_folder(&self, dialog: &IFileDialog) {
+        let files_selected = view_selection(dialog)
+            .map(|paths| paths.iter().any(|path| !path.is_dir()))
+            .unwrap_or(false);
+        if !files_selected {
+            let name = ".".as_null_term_utf16();
+            // IFileDialog::SetFileName
+            let _ = com!(
+                dialog.SetFileName(name.as_ptr()),
+                "IFileDialog::SetFileName"
+            );
+        }
+    }
+}
+
+impl EventHandler for FilesAndFolders {
+    // A navigation while the OK button has the focus comes from clicking it with a folder
+    // selected, and one to the current folder while the file name box holds `.` from pressing
+    // Enter in the box. Others, such as double-clicking a folder or reloading the current folder,
+    // are allowed.
+    fn on_folder_changing(&self, dialog: &IFileDialog, folder: &IShellItem) -> bool {
+        let started_by_ok = ok_button_has_focus(dialog)
+            || (file_name_is_current_folder(dialog) && is_current_folder(dialog, folder));
+        if !started_by_ok {
+            return true;
+        }
+
+        self.accept(dialog);
+        // IFileDialog::Close, after which IModalWindow::Show returns S_OK
+        let _ = com!(dialog.Close(S_OK), "IFileDialog::Close");
+        false
+    }
+
+    fn on_folder_change(&self, dialog: &IFileDialog) {
+        self.enter_current_folder(dialog);
+    }
+
+    fn on_selection_change(&self, dialog: &IFileDialog) {
+        self.enter_current_folder(dialog);
+    }
+}
+
+impl Backend for ComDialog {
+    fn create(&mut self, kind: DialogKind) -> Result<(), DialogError> {
+        // Initialize COM
+        com!(
+            CoInitializeEx(
+                null_mut(),
+                COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE,
+            ),
+            "CoInitializeEx"
+        )?;
+        self.com_initialized = true;
+
+        // Create IFileOpenDialog or IFileSaveDialog instance
+        self.kind = kind;
+        let (clsid, iid, method) = match kind {
+            DialogKind::Open => (
+                &CLSID_FileOpenDialog,
+                IFileOpenDialog::uuidof(),
+                "CoCreateInstance - IFileOpenDialog",
+            ),
+            DialogKind::Save => (
+                &CLSID_FileSaveDialog,
+                IFileSaveDialog::uuidof(),
+                "CoCreateInstance - FileSaveDialog",
+            ),
+        };
+        com!(
+            CoCreateInstance(
+                clsid,
+                null_mut(),
+                CLSCTX_ALL,
+                &iid,
+                &mut self.dialog as *mut *mut IFileDialog as *mut *mut c_void,
+            ),
+            method
+        )
+    }
+
+    fn configure(&mut self, params: &DialogParams) -> Result<FileTypeLayout, DialogError> {
+        // IFileDialog::SetSaveAsItem
+        if self.kind == DialogKind::Save && !params.save_as_item.as_os_str().is_empty() {
+            self.set_save_as_item(params)?;
+        }
+
+        // Perform configuration shared by Open and Save dialogs
+        let file_type_layout = configure_file_dialog(self.dialog(), params)?;
+
+        // IFileDialog::Advise
+        if self.kind == DialogKind::Open && params.select_files_and_folders {
+            self.advise_files_and_folders()?;
+        }
+
+        if self.kind == DialogKind::Save && params.require_writable {
+            self.advise_require_writable(params.title)?;
+        }
+
+        if self.kind == DialogKind::Save {
+            // The shell's overwrite prompt is only used by the ShellPrompt policy, the others
+            // handle existing files once the dialog has closed
+            let prompt_unset = params.un_options & FOS_OVERWRITEPROMPT != 0;
+            let overwrite_prompt =
+                params.overwrite_policy == OverwritePolicy::ShellPrompt && !prompt_unset;
+            set_option(self.dialog(), FOS_OVERWRITEPROMPT, overwrite_prompt)?;
+        }
+
+        Ok(file_type_layout)
+    }
+
+    fn show(&mut self, owner: Option<HWND>) -> Result<(), DialogError> {
+        show_dialog(self.dialog(), owner)
+    }
+
+    fn collect(&mut self) -> Result<Selection, DialogError> {
+        let accepted_items = self.accepted_items.borrow_mut().take();
+        let paths = match (self.kind, accepted_items) {
+            (DialogKind::Open, Some(items)) if !items.is_empty() => items,
+            (DialogKind::Open, _) => self.collect_open_results()?,
+            (DialogKind::Save, _) => vec![self.collect_save_result()?],
+        };
+
+        // IFileDialog::GetFileTypeIndex
+        let file_type_index = get_file_type_index(self.dialog())?;
+
+        Ok(Selection {
+            paths,
+            file_type_index,
+        })
+    }
+}
+
+impl Drop for ComDialog {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.dialog.is_null() {
+                if let Some(cookie) = self.events_cookie {
+                    (*self.dialog).Unadvise(cookie);
+                }
+                (*self.dialog).Release();
+            }
+
+            // Un-initialize COM
+            if self.com_initialized {
+                CoUninitialize();
+            }
+        }
+    }
+}
+
+#[allow(overflowing_literals)]
+#[allow(unused_comparisons)]
+fn show_dialog(file_dialog: &IFileDialog, owner: Option<HWND>) -> Result<(), DialogError> {
+    let owner_hwnd = owner.unwrap_or(null_mut());
+
+    // IModalWindow::Show
+    let result = com!(file_dialog.Show(owner_hwnd), "IModalWindow::Show");
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => match e {
+            DialogError::HResultFailed { hresult, .. } => {
+                if hresult == 0x8007_04C7 {
+                    Err(DialogError::UserCancelled)
+                } else {
+                    Err(e)
+                }
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+// Applies the parameters shared by Open and Save dialogs, returning the layout of the file types
+// used to map the selected file type index back to `params.file_types`
+fn configure_file_dialog(
+    file_dialog: &IFileDialog,
+    params: &DialogParams,
+) -> Result<FileTypeLayout, DialogError> {
+    // IFileDialog::SetDefaultExtension
+    if !params.default_extension.is_empty() {
+        let default_extension = params
+            .default_extension
+            .to_null_term_utf16("default_extension")?;
+        com!(
+            file_dialog.SetDefaultExtension(default_extension.as_ptr()),
+            "IFileDialog::SetDefaultExtension"
+        )?;
+    }
+
+    // IFileDialog::SetDefaultFolder
+    if !params.default_folder.is_empty() {
+        let resolved = resolve_folder_field(
+            "default_folder",
+            &params.default_folder,
+            params.invalid_path_behavior,
+        )?;
+        if let Some(resolved) = resolved {
+            com!(
+                file_dialog.SetDefaultFolder(resolved.item),
+                "IFileDialog::SetDefaultFolder"
+            )?;
+            unsafe {
+                let default_folder = &*resolved.item;
+                default_folder.Release();
+            }
+        }
+    }
+
+    // IFileDialog::SetFolder
+    if !params.folder.is_empty() {
+        let resolved =
+            resolve_folder_field("folder", &params.folder, params.invalid_path_behavior)?;
+        if let Some(resolved) = resolved {
+            com!(
+                file_dialog.SetFolder(resolved.item),
+                "IFileDialog::SetFolder"
+            )?;
+            unsafe {
+                let folder = &*resolved.item;
+                folder.Release();
+            }
+        }
+    }
+
+    // IFileDialog::SetFileName
+    if !params.file_name.is_empty() {
+        let initial_file_name = match &params.sanitize_file_name {
+            Some(options) => sanitize_file_name(params.file_name, options),
+            None => params.file_name.to_string(),
+        };
+        let initial_file_name = initial_file_name.as_str().to_null_term_utf16("file_name")?;
+        com!(
+            file_dialog.SetFileName(initial_file_name.as_ptr()),
+            "IFileDialog::SetFileName"
+        )?;
+    }
+
+    // IFileDialog::SetFileNameLabel
+    if !params.file_name_label.is_empty() {
+        let file_name_label = params
+            .file_name_label
+            .to_null_term_utf16("file_name_label")?;
+        com!(
+            file_dialog.SetFileNameLabel(file_name_label.as_ptr()),
+            "IFileDialog::SetFileNameLabel"
+        )?;
+    }
+
+    let file_type_layout = FileTypeLayout::new(&params.file_types, params.aggregate_filter);
+    let file_types = file_type_layout.file_types(&params.file_types);
+    if !file_types.is_empty() {
+        add_filters(file_dialog, &file_types)?;
+    }
+
+    // IFileDialog::SetFileTypeIndex
+    let file_type_index = file_type_layout.dialog_index(params.file_type_index);
+    if !file_types.is_empty() && file_type_index > 0 {
+        com!(
+            file_dialog.SetFileTypeIndex(file_type_index),
+            "IFileDialog::SetFileTypeIndex"
+        )?;
+    }
+
+    // IFileDialog::SetOkButtonLabel
+    if !params.ok_button_label.is_empty() {
+        let ok_button_label = params
+            .ok_button_label
+            .to_null_term_utf16("ok_button_label")?;
+        com!(
+            file_dialog.SetOkButtonLabel(ok_button_label.as_ptr()),
+            "IFileDialog::SetOkButtonLabel"
+        )?;
+    }
+
+    if params.options > 0 || params.un_options > 0 {
+        // IFileDialog::GetOptions
+        let mut existing_options: u32 = 0;
+        com!(
+            file_dialog.GetOptions(&mut existing_options),
+            "IFileDialog::GetOptions"
+        )?;
+        trace::options("IFileDialog::GetOptions", existing_options);
+
+        existing_options &= !params.un_options;
+        existing_options |= params.options;
+
+        // IFileDialog::SetOptions
+        com!(
+            file_dialog.SetOptions(existing_options),
+            "IFileDialog::SetOptions"
+        )?;
+        trace::options("IFileDialog::SetOptions", existing_options);
+    }
+
+    // IFileDialog::SetFilter
+    if let Some(item_filter) = &params.item_filter {
+        set_item_filter(file_dialog, item_filter)?;
+    }
+
+    // IFileDialog::SetTitle
+    if !params.title.is_empty() {
+        let title = params.title.to_null_term_utf16("title")?;
+        com!(
+            file_dialog.SetTitle(title.as_ptr()),
+            "IFileDialog::SetTitle"
+        )?;
+    }
+
+    Ok(file_type_layout)
+}
+
+// Sets or clears a single FOS_* option on the dialog, leaving the other options unchanged
+fn set_option(file_dialog: &IFileDialog, option: u32, enabled: bool) -> Result<(), DialogError> {
+    // IFileDialog::GetOptions
+    let mut options: u32 = 0;
+    com!(
+        file_dialog.GetOptions(&mut options),
+        "IFileDialog::GetOptions"
+    )?;
+    trace::options("IFileDialog::GetOptions", options);
+
+    if enabled {
+        options |= option;
+    } else {
+        options &= !option;
+    }
+
+    // IFileDialog::SetOptions
+    com!(file_dialog.SetOptions(options), "IFileDialog::SetOptions")?;
+    trace::options("IFileDialog::SetOptions", options);
+    Ok(())
+}
+
+// Installs a filter object that calls the item filter for each item displayed by the dialog. The
+// dialog holds the only reference once it is set, which is released along with the dialog while
+// `params`, and so the item filter, is still alive.
+fn set_item_filter(file_dialog: &IFileDialog, item_filter: &ItemFilter) -> Result<(), DialogError> {
+    let filter = unsafe { com_filter::create(move |item| is_item_included(item_filter, item)) };
+    let result = com!(file_dialog.SetFilter(filter), "IFileDialog::SetFilter");
+    unsafe { (*filter).Release() };
+    result
+}
+
+// Items without a file system path, such as libraries, are always shown
+fn is_item_included(item_filter: &ItemFilter, item: &IShellItem) -> bool {
+    // IShellItem::GetAttributes
+    let mut attribs: SFGAOF = 0;
+    let mask = SFGAO_FILESYSTEM | SFGAO_FOLDER | SFGAO_HIDDEN | SFGAO_READONLY;
+    let result = com!(
+        item.GetAttributes(mask, &mut attribs),
+        "IShellItem::GetAttributes"
+    );
+    if result.is_err() || attribs & SFGAO_FILESYSTEM == 0 {
+        return true;
+    }
+
+    let path = match get_shell_item_display_name(item) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => return true,
+    };
+    let item = FilterItem {
+        path: &path,
+        is_folder: attribs & SFGAO_FOLDER != 0,
+        is_hidden: attribs & SFGAO_HIDDEN != 0,
+        is_read_only: attribs & SFGAO_READONLY != 0,
+    };
+    item_filter.visibility(&item) == ItemVisibility::Include
+}
+
+fn add_filters(
+    dialog: &IFileDialog,
+    filters: &[FileExtensionFilterPair],
+) -> Result<(), DialogError> {
+    // Create a vec holding the UTF-16 string pairs for the filter - we need
+    // to have these in a vec since we need to be able to pass a pointer to them
+    // in the COMDLG_FILTERSPEC structs passed to SetFileTypes.
+    let temp_filters = filters
+        .iter()
+        .map(|filter| {
+            let name = filter.0.to_null_term_utf16("file_types")?;
+            let pattern = filter.1.to_null_term_utf16("file_types")?;
+            Ok((name, pattern))
+        })
+        .collect::<Result<Vec<(Vec<u16>, Vec<u16>)>, DialogError>>()?;
+
+    let filter_specs = temp_filters
+        .iter()
+        .map(|x| COMDLG_FILTERSPEC {
+            pszName: x.0.as_ptr(),
+            pszSpec: x.1.as_ptr(),
+        })
+        .collect::<Vec<COMDLG_FILTERSPEC>>();
+
+    // IFileDialog::SetFileTypes
+    com!(
+        dialog.SetFileTypes(filter_specs.len() as u32, filter_specs.as_ptr()),
+        "IFileDialog::SetFileTypes"
+    )?;
+
+    Ok(())
+}
+
+// A shell item created for one of the path fields of `DialogParams`
+struct ResolvedItem {
+    item: *mut IShellItem,
+    // True if the item refers to an ancestor directory of the requested path rather than the
+    // path itself
+    is_ancestor: bool,
+}
+
+// Resolves the folder in the given `DialogParams` field and creates a shell item for it, applying
+// the provided `InvalidPathBehavior` if the folder cannot be resolved
+fn resolve_folder_field(
+    field: &str,
+    folder: &FolderSpec,
+    behavior: InvalidPathBehavior,
+) -> Result<Option<ResolvedItem>, DialogError> {
+    match folder.resolve() {
+        Ok(path) => resolve_path_field(field, path.as_os_str(), behavior),
+        Err(cause) => invalid_path_field(field, folder.to_string(), cause, behavior),
+    }
+}
+
+// Applies the provided `InvalidPathBehavior` to a field whose path could not be determined, such
+// as an unknown folder or a malformed URI. There is no path to find an ancestor of, so the field
+// is ignored unless the behavior is to fail.
+fn invalid_path_field(
+    field: &str,
+    path: String,
+    cause: DialogError,
+    behavior: InvalidPathBehavior,
+) -> Result<Option<ResolvedItem>, DialogError> {
+    match behavior {
+        InvalidPathBehavior::Error => Err(DialogError::InvalidPath {
+            field: field.to_string(),
+            path,
+            cause: Box::new(cause),
+        }),
+        InvalidPathBehavior::NearestAncestor | InvalidPathBehavior::Ignore => Ok(None),
+    }
+}
+
+// Creates a shell item for the path in the given `DialogParams` field, applying the provided
+// `InvalidPathBehavior` if the path cannot be resolved. Returns `None` if the field should be
+// ignored.
+fn resolve_path_field(
+    field: &str,
+    path: &OsStr,
+    behavior: InvalidPathBehavior,
+) -> Result<Option<ResolvedItem>, DialogError> {
+    // A null character is rejected whatever the behavior, as it would truncate the path to a
+    // different one that may exist
+    path.to_null_term_utf16(field)?;
+
+    let normalized_path = path_form::normalize_input(path);
+    let cause = match create_shell_item(&normalized_path) {
+        Ok(item) => {
+            return Ok(Some(ResolvedItem {
+                item,
+                is_ancestor: false,
+            }))
+        }
+        Err(e) => e,
+    };
+
+    match behavior {
+        InvalidPathBehavior::Error => Err(DialogError::InvalidPath {
+            field: field.to_string(),
+            path: path.to_string_lossy().into_owned(),
+            cause: Box::new(cause),
+        }),
+        InvalidPathBehavior::Ignore => Ok(None),
+        InvalidPathBehavior::NearestAncestor => Ok(Path::new(&normalized_path)
+            .ancestors()
+            .skip(1)
+            .map(Path::as_os_str)
+            .filter(|ancestor| !ancestor.is_empty())
+            .find_map(|ancestor| create_shell_item(ancestor).ok())
+            .map(|item| ResolvedItem {
+                item,
+                is_ancestor: true,
+            })),
+    }
+}
+
+fn create_shell_item(path: &OsStr) -> Result<*mut IShellItem, DialogError> {
+    let mut item: *mut IShellItem = null_mut();
+    let path = path.as_null_term_utf16();
+    com!(
+        SHCreateItemFromParsingName(
+            path.as_ptr(),
+            null_mut(),
+            &IShellItem::uuidof(),
+            &mut item as *mut *mut IShellItem as *mut *mut c_void
+        ),
+        "SHCreateItemFromParsingName"
+    )?;
+    Ok(item)
+}
+
+// Returns the file system paths of the items in a shell item array, releasing the array
+fn shell_item_array_paths(
+    shell_item_array: *mut IShellItemArray,
+) -> Result<Vec<PathBuf>, DialogError> {
+    let shell_item_array = unsafe { &*shell_item_array };
+    let file_paths = shell_item_array_paths_inner(shell_item_array);
+    unsafe { shell_item_array.Release() };
+    file_paths
+}
+
+fn shell_item_array_paths_inner(
+    shell_item_array: &IShellItemArray,
+) -> Result<Vec<PathBuf>, DialogError> {
+    // IShellItemArray::GetCount
+    let mut item_count: u32 = 0;
+    com!(
+        shell_item_array.GetCount(&mut item_count),
+        "IShellItemArray::GetCount"
+    )?;
+
+    let mut file_paths: Vec<PathBuf> = vec![];
+    for i in 0..item_count {
+        // IShellItemArray::GetItemAt
+        let mut shell_item: *mut IShellItem = null_mut();
+        com!(
+            shell_item_array.GetItemAt(i, &mut shell_item),
+            "IShellItemArray::GetItemAt"
+        )?;
+        let shell_item = unsafe { &*shell_item };
+        let file_path = shell_item_file_path(shell_item);
+
+        // Free non-owned allocation
+        unsafe { shell_item.Release() };
+        file_paths.extend(file_path?);
+    }
+
+    Ok(file_paths)
+}
+
+// Returns the path of a shell item, or `None` for shell items that do not have the
+// SFGAO_FILESYSTEM attribute which indicates that they represent a valid path to a file or folder
+fn shell_item_file_path(shell_item: &IShellItem) -> Result<Option<PathBuf>, DialogError> {
+    // IShellItem::GetAttributes
+    let mut attribs: SFGAOF = 0;
+    com!(
+        shell_item.GetAttributes(SFGAO_FILESYSTEM, &mut attribs),
+        "IShellItem::GetAttributes"
+    )?;
+    if attribs & SFGAO_FILESYSTEM == 0 {
+        return Ok(None);
+    }
+
+    let file_name = get_shell_item_display_name(shell_item)?;
+    Ok(Some(PathBuf::from(file_name)))
+}
+
+// Returns the items selected in the view of an Open dialog, including folders, or the current
+// folder if no items are selected
+fn selected_items(file_dialog: &IFileDialog) -> Result<Vec<PathBuf>, DialogError> {
+    let paths = view_selection(file_dialog)?;
+    if !paths.is_empty() {
+        return Ok(paths);
+    }
+
+    // IFileDialog::GetFolder
+    let mut folder: *mut IShellItem = null_mut();
+    com!(file_dialog.GetFolder(&mut folder), "IFileDialog::GetFolder")?;
+    let folder = unsafe { &*folder };
+    let path = shell_item_file_path(folder);
+    unsafe { folder.Release() };
+    Ok(path?.into_iter().collect())
+}
+
+// Returns the items selected in the view of an Open dialog, including folders
+fn view_selection(file_dialog: &IFileDialog) -> Result<Vec<PathBuf>, DialogError> {
+    // IUnknown::QueryInterface
+    let mut open_dialog: *mut IFileOpenDialog = null_mut();
+    com!(
+        file_dialog.QueryInterface(
+            &IFileOpenDialog::uuidof(),
+            &mut open_dialog as *mut *mut IFileOpenDialog as *mut *mut c_void,
+        ),
+        "IUnknown::QueryInterface - IFileOpenDialog"
+    )?;
+    let open_dialog = unsafe { &*open_dialog };
+
+    // IFileOpenDialog::GetSelectedItems
+    let mut shell_item_array: *mut IShellItemArray = null_mut();
+    let selected = com!(
+        open_dialog.GetSelectedItems(&mut shell_item_array),
+        "IFileOpenDialog::GetSelectedItems"
+    );
+    unsafe { open_dialog.Release() };
+    // The call fails when nothing is selected
+    match selected {
+        Ok(()) => shell_item_array_paths(shell_item_array),
+        Err(_) => Ok(vec![]),
+    }
+}
+
+// Returns true if the file name box holds the `.` entered by `FilesAndFolders`
+fn file_name_is_current_folder(file_dialog: &IFileDialog) -> bool {
+    let mut file_name: LPWSTR = null_mut();
+    // IFileDialog::GetFileName
+    if com!(
+        file_dialog.GetFileName(&mut file_name),
+        "IFileDialog::GetFileName"
+    )
+    .is_err()
+        || file_name.is_null()
+    {
+        return false;
+    }
+    let slice = unsafe { slice::from_raw_parts(file_name, wcslen(file_name)) };
+    let is_dot = slice == [u16::from(b'.')];
+
+    // Free non-owned allocation
+    unsafe { CoTaskMemFree(file_name as LPVOID) };
+
+    is_dot
+}
+
+// Returns true if `folder` is the folder that the dialog is showing
+fn is_current_folder(file_dialog: &IFileDialog, folder: &IShellItem) -> bool {
+    // IFileDialog::GetFolder
+    let mut current: *mut IShellItem = null_mut();
+    if com!(
+        file_dialog.GetFolder(&mut current),
+        "IFileDialog::GetFolder"
+    )
+    .is_err()
+    {
+        return false;
+    }
+    let current = unsafe { &*current };
+
+    // IShellItem::Compare, which returns S_FALSE for different items
+    let mut order = 0;
+    let folder = folder as *const IShellItem as *mut IShellItem;
+    let result = unsafe { current.Compare(folder, SICHINT_CANONICAL, &mut order) };
+    unsafe { current.Release() };
+    result == S_OK && order == 0
+}
+
+// Returns true if the keyboard focus is on the OK button of a dialog that is being shown, which
+// it is after the button has been clicked
+fn ok_button_has_focus(file_dialog: &IFileDialog) -> bool {
+    let window = match dialog_window(file_dialog) {
+        Some(window) => window,
+        None => return false,
+    };
+    unsafe {
+        let ok_button = GetDlgItem(window, IDOK);
+        !ok_button.is_null() && GetFocus() == ok_button
+    }
+}
+
+// Returns the file selected in a Save dialog, either once it has closed or while its OK button
+// is being handled
+fn save_result(file_dialog: &IFileDialog) -> Result<PathBuf, DialogError> {
+    // IFileDialog::GetResult
+    let mut shell_item: *mut IShellItem = null_mut();
+    com!(
+        file_dialog.GetResult(&mut shell_item),
+        "IFileDialog::GetResult"
+    )?;
+    let shell_item = unsafe { &*shell_item };
+    let file_name = get_shell_item_display_name(shell_item);
+    unsafe { shell_item.Release() };
+
+    Ok(PathBuf::from(file_name?))
+}
+
+// Returns the window of a dialog that is being shown, so that messages shown while handling its
+// events are owned by it
+fn dialog_window(file_dialog: &IFileDialog) -> Option<HWND> {
+    // IUnknown::QueryInterface
+    let mut ole_window: *mut IOleWindow = null_mut();
+    com!(
+        file_dialog.QueryInterface(
+            &IOleWindow::uuidof(),
+            &mut ole_window as *mut *mut IOleWindow as *mut *mut c_void,
+        ),
+        "IUnknown::QueryInterface - IOleWindow"
+    )
+    .ok()?;
+    let ole_window = unsafe { &*ole_window };
+
+    // IOleWindow::GetWindow
+    let mut hwnd: HWND = null_mut();
+    let result = com!(ole_window.GetWindow(&mut hwnd), "IOleWindow::GetWindow");
+    unsafe { ole_window.Release() };
+    result.ok().map(|()| hwnd)
+}
+
+fn get_file_type_index(file_dialog: &IFileDialog) -> Result<u32, DialogError> {
+    // IFileDialog::GetFileTypeIndex
+    let mut selected_filter_index: u32 = 0;
+    com!(
+        file_dialog.GetFileTypeIndex(&mut selected_filter_index),
+        "IFileDialog::GetFileTypeIndex"
+    )?;
+    Ok(selected_filter_index)
+}
+
+fn get_shell_item_display_name(shell_item: &IShellItem) -> Result<OsString, DialogError> {
+    let mut display_name: LPWSTR = null_mut();
+    // IShellItem::GetDisplayName
+    com!(
+        shell_item.GetDisplayName(SIGDN_FILESYSPATH, &mut display_name),
+        "IShellItem::GetDisplayName"
+    )?;
+    let slice = unsafe { slice::from_raw_parts(display_name, wcslen(display_name)) };
+    let result = OsString::from_wide(slice);
+
+    // Free non-owned allocation
+    unsafe { CoTaskMemFree(display_name as LPVOID) };
+
+    Ok(result)
+}
@@ -0,0 +1,343 @@
+// A COM `IFileDialogEvents` object that passes the events of a dialog to an `EventHandler`, such
+// as a closure called when its OK button is clicked. Only built on Windows with the
+// `native-windows` feature.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use winapi::{
+    shared::winerror::{
+        ERROR_CANCELLED, E_NOTIMPL, E_POINTER, HRESULT, HRESULT_FROM_WIN32, S_FALSE, S_OK,
+    },
+    um::{
+        shobjidl::{
+            IFileDialog, IFileDialogEvents, IFileDialogEventsVtbl, FDE_OVERWRITE_RESPONSE,
+            FDE_SHAREVIOLATION_RESPONSE,
+        },
+        shobjidl_core::IShellItem,
+    },
+};
+
+use crate::com_object::{ComObject, UNKNOWN_VTBL};
+
+// The events of a dialog that are passed on. By default the dialog behaves as it would without an
+// event handler.
+pub(crate) trait EventHandler {
+    // Called when the OK button is clicked, returning false to keep the dialog open
+    fn on_file_ok(&self, _dialog: &IFileDialog) -> bool {
+        true
+    }
+
+    // Called before the dialog navigates to `folder`, returning false to stay in the current
+    // folder
+    fn on_folder_changing(&self, _dialog: &IFileDialog, _folder: &IShellItem) -> bool {
+        true
+    }
+
+    // Called once the dialog has navigated to a folder
+    fn on_folder_change(&self, _dialog: &IFileDialog) {}
+
+    // Called when the items selected in the view change
+    fn on_selection_change(&self, _dialog: &IFileDialog) {}
+}
+
+// A closure handles the OK button alone
+impl<F> EventHandler for F
+where
+    F: Fn(&IFileDialog) -> bool,
+{
+    fn on_file_ok(&self, dialog: &IFileDialog) -> bool {
+        self(dialog)
+    }
+}
+
+// The object behind an `IFileDialogEvents` pointer
+type FileDialogEvents<'a> = ComObject<Box<dyn EventHandler + 'a>>;
+
+static VTBL: IFileDialogEventsVtbl = IFileDialogEventsVtbl {
+    parent: UNKNOWN_VTBL,
+    OnFileOk: on_file_ok,
+    OnFolderChanging: on_folder_changing,
+    OnFolderChange: on_folder_change,
+    OnSelectionChange: on_selection_change,
+    OnShareViolation: on_share_violation,
+    OnTypeChange: on_notification,
+    OnOverwrite: on_overwrite,
+};
+
+// Creates an events object that passes the events of a dialog to `handler`, with a reference
+// count of one that is owned by the caller. The object keeps `handler` until its last reference is
+// released.
+//
+// Safety: the lifetime of `handler` is not kept by the returned pointer, so every reference,
+// including those passed to the shell, must be released while the values borrowed by the handler
+// are still alive. A dialog that the object is advised to holds a reference, so it must be
+// unadvised, or the dialog released, first.
+pub(crate) unsafe fn create<'a, H>(handler: H) -> *mut IFileDialogEvents
+where
+    H: EventHandler + 'a,
+{
+    FileDialogEvents::create(&VTBL, Box::new(handler))
+}
+
+// Returns S_OK to close the dialog and S_FALSE to keep it open. A panicking handler cannot unwind
+// into the shell, so the dialog is closed and its own results are used instead.
+unsafe extern "system" fn on_file_ok(
+    this: *mut IFileDialogEvents,
+    pfd: *mut IFileDialog,
+) -> HRESULT {
+    if pfd.is_null() {
+        return E_POINTER;
+    }
+    let handler = FileDialogEvents::value(this);
+    match panic::catch_unwind(AssertUnwindSafe(|| handler.on_file_ok(&*pfd))) {
+        Ok(false) => S_FALSE,
+        Ok(true) | Err(_) => S_OK,
+    }
+}
+
+// Returns S_OK to allow the navigation, and the error of a cancelled operation to prevent it
+// without the dialog reporting a failure. A panicking handler allows the navigation.
+unsafe extern "system" fn on_folder_changing(
+    this: *mut IFileDialogEvents,
+    pfd: *mut IFileDialog,
+    psi_folder: *mut IShellItem,
+) -> HRESULT {
+    if pfd.is_null() || psi_folder.is_null() {
+        return E_POINTER;
+    }
+    let handler = FileDialogEvents::value(this);
+    let allow = AssertUnwindSafe(|| handler.on_folder_changing(&*pfd, &*psi_folder));
+    match panic::catch_unwind(allow) {
+        Ok(false) => HRESULT_FROM_WIN32(ERROR_CANCELLED),
+        Ok(true) | Err(_) => S_OK,
+    }
+}
+
+unsafe extern "system" fn on_folder_change(
+    this: *mut IFileDialogEvents,
+    pfd: *mut IFileDialog,
+) -> HRESULT {
+    if pfd.is_null() {
+        return E_POINTER;
+    }
+    let handler = FileDialogEvents::value(this);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| handler.on_folder_change(&*pfd)));
+    S_OK
+}
+
+unsafe extern "system" fn on_selection_change(
+    this: *mut IFileDialogEvents,
+    pfd: *mut IFileDialog,
+) -> HRESULT {
+    if pfd.is_null() {
+        return E_POINTER;
+    }
+    let handler = FileDialogEvents::value(this);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| handler.on_selection_change(&*pfd)));
+    S_OK
+}
+
+unsafe extern "system" fn on_notification(
+    _this: *mut IFileDialogEvents,
+    _pfd: *mut IFileDialog,
+) -> HRESULT {
+    S_OK
+}
+
+// The responses to sharing violations and overwrites are left to the dialog
+unsafe extern "system" fn on_share_violation(
+    _this: *mut IFileDialogEvents,
+    _pfd: *mut IFileDialog,
+    _psi: *mut IShellItem,
+    _response: *mut FDE_SHAREVIOLATION_RESPONSE,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+unsafe extern "system" fn on_overwrite(
+    _this: *mut IFileDialogEvents,
+    _pfd: *mut IFileDialog,
+    _psi: *mut IShellItem,
+    _response: *mut FDE_OVERWRITE_RESPONSE,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::ptr::{self, null, null_mut};
+
+    use winapi::um::shobjidl::IFileDialogVtbl;
+    use winapi::um::shobjidl_core::IShellItemVtbl;
+
+    // A dialog that is only compared by address, as the event handlers under test never call it
+    fn fake_dialog() -> IFileDialog {
+        IFileDialog {
+            lpVtbl: null::<IFileDialogVtbl>(),
+        }
+    }
+
+    fn fake_folder() -> IShellItem {
+        IShellItem {
+            lpVtbl: null::<IShellItemVtbl>(),
+        }
+    }
+
+    // Records the events that it receives, and refuses navigation when `allow` is false
+    struct Recorder<'a> {
+        dialog: *const IFileDialog,
+        folder: *const IShellItem,
+        allow: &'a Cell<bool>,
+        events: &'a RefCell<Vec<&'static str>>,
+    }
+
+    impl<'a> EventHandler for Recorder<'a> {
+        fn on_file_ok(&self, dialog: &IFileDialog) -> bool {
+            assert!(ptr::eq(dialog, self.dialog));
+            self.events.borrow_mut().push("file_ok");
+            self.allow.get()
+        }
+
+        fn on_folder_changing(&self, dialog: &IFileDialog, folder: &IShellItem) -> bool {
+            assert!(ptr::eq(dialog, self.dialog));
+            assert!(ptr::eq(folder, self.folder));
+            self.events.borrow_mut().push("folder_changing");
+            self.allow.get()
+        }
+
+        fn on_folder_change(&self, dialog: &IFileDialog) {
+            assert!(ptr::eq(dialog, self.dialog));
+            self.events.borrow_mut().push("folder_change");
+        }
+
+        fn on_selection_change(&self, dialog: &IFileDialog) {
+            assert!(ptr::eq(dialog, self.dialog));
+            self.events.borrow_mut().push("selection_change");
+        }
+    }
+
+    #[test]
+    fn on_file_ok_calls_closure() {
+        let mut dialog = fake_dialog();
+        let dialog_ptr: *mut IFileDialog = &mut dialog;
+        let accept = Cell::new(true);
+        let calls = Cell::new(0);
+        let events = unsafe {
+            create(|dialog: &IFileDialog| {
+                assert!(ptr::eq(dialog, dialog_ptr));
+                calls.set(calls.get() + 1);
+                accept.get()
+            })
+        };
+
+        unsafe {
+            assert_eq!((*events).OnFileOk(dialog_ptr), S_OK);
+            accept.set(false);
+            assert_eq!((*events).OnFileOk(dialog_ptr), S_FALSE);
+            assert_eq!((*events).OnFileOk(null_mut()), E_POINTER);
+            assert_eq!(calls.get(), 2);
+            assert_eq!((*events).Release(), 0);
+        }
+    }
+
+    #[test]
+    fn other_events_use_dialog_defaults() {
+        let mut dialog = fake_dialog();
+        let called = Cell::new(false);
+        let events = unsafe {
+            create(|_: &IFileDialog| {
+                called.set(true);
+                true
+            })
+        };
+
+        unsafe {
+            let mut share_response: FDE_SHAREVIOLATION_RESPONSE = 0;
+            let mut overwrite_response: FDE_OVERWRITE_RESPONSE = 0;
+            assert_eq!(
+                (*events).OnFolderChanging(&mut dialog, &mut fake_folder()),
+                S_OK
+            );
+            assert_eq!((*events).OnFolderChange(&mut dialog), S_OK);
+            assert_eq!((*events).OnSelectionChange(&mut dialog), S_OK);
+            assert_eq!((*events).OnTypeChange(&mut dialog), S_OK);
+            assert_eq!(
+                (*events).OnShareViolation(&mut dialog, null_mut(), &mut share_response),
+                E_NOTIMPL
+            );
+            assert_eq!(
+                (*events).OnOverwrite(&mut dialog, null_mut(), &mut overwrite_response),
+                E_NOTIMPL
+            );
+            assert_eq!((*events).Release(), 0);
+        }
+        assert!(!called.get());
+    }
+
+    #[test]
+    fn events_are_passed_to_handler() {
+        let mut dialog = fake_dialog();
+        let mut folder = fake_folder();
+        let allow = Cell::new(true);
+        let received = RefCell::new(vec![]);
+        let events = unsafe {
+            create(Recorder {
+                dialog: &dialog,
+                folder: &folder,
+                allow: &allow,
+                events: &received,
+            })
+        };
+
+        unsafe {
+            assert_eq!((*events).OnFolderChanging(&mut dialog, &mut folder), S_OK);
+            assert_eq!((*events).OnFolderChange(&mut dialog), S_OK);
+            assert_eq!((*events).OnSelectionChange(&mut dialog), S_OK);
+            assert_eq!((*events).OnFileOk(&mut dialog), S_OK);
+
+            // Refused navigation is reported as cancelled, which the dialog does not show
+            allow.set(false);
+            assert_eq!(
+                (*events).OnFolderChanging(&mut dialog, &mut folder),
+                HRESULT_FROM_WIN32(ERROR_CANCELLED)
+            );
+            assert_eq!((*events).OnFileOk(&mut dialog), S_FALSE);
+
+            assert_eq!(
+                (*events).OnFolderChanging(null_mut(), &mut folder),
+                E_POINTER
+            );
+            assert_eq!(
+                (*events).OnFolderChanging(&mut dialog, null_mut()),
+                E_POINTER
+            );
+            assert_eq!((*events).OnFolderChange(null_mut()), E_POINTER);
+            assert_eq!((*events).OnSelectionChange(null_mut()), E_POINTER);
+            assert_eq!((*events).Release(), 0);
+        }
+        assert_eq!(
+            *received.borrow(),
+            vec![
+                "folder_changing",
+                "folder_change",
+                "selection_change",
+                "file_ok",
+                "folder_changing",
+                "file_ok"
+            ]
+        );
+    }
+
+    #[test]
+    fn panicking_closure_closes_dialog() {
+        let mut dialog = fake_dialog();
+        let events = unsafe { create(|_: &IFileDialog| panic!("handler failed")) };
+
+        unsafe {
+            assert_eq!((*events).OnFileOk(&mut dialog), S_OK);
+            assert_eq!((*events).Release(), 0);
+        }
+    }
+}
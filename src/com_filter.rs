@@ -0,0 +1,115 @@
+// A COM `IShellItemFilter` object created from a closure, which a dialog calls to decide whether
+// each item is shown. Only built on Windows with the `native-windows` feature.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use winapi::{
+    shared::winerror::{E_NOTIMPL, E_POINTER, HRESULT, S_FALSE, S_OK},
+    um::shobjidl_core::{IShellItem, IShellItemFilter, IShellItemFilterVtbl, SHCONTF},
+};
+
+use crate::com_object::{ComObject, UNKNOWN_VTBL};
+
+// The object behind an `IShellItemFilter` pointer
+type ShellItemFilter<'a> = ComObject<Box<dyn Fn(&IShellItem) -> bool + 'a>>;
+
+static VTBL: IShellItemFilterVtbl = IShellItemFilterVtbl {
+    parent: UNKNOWN_VTBL,
+    IncludeItem: include_item,
+    GetEnumFlagsForItem: get_enum_flags_for_item,
+};
+
+// Creates a filter object that shows the items for which `include` returns true, with a reference
+// count of one that is owned by the caller. The object keeps `include` until its last reference is
+// released.
+//
+// Safety: the lifetime of `include` is not kept by the returned pointer, so every reference,
+// including those passed to the shell, must be released while the values borrowed by the closure
+// are still alive. A dialog that the filter is set on holds a reference, so the dialog must be
+// released first.
+pub(crate) unsafe fn create<'a, F>(include: F) -> *mut IShellItemFilter
+where
+    F: Fn(&IShellItem) -> bool + 'a,
+{
+    ShellItemFilter::create(&VTBL, Box::new(include))
+}
+
+// Returns S_OK to show the item and S_FALSE to hide it. A panicking closure cannot unwind into the
+// shell, so the item is shown instead.
+unsafe extern "system" fn include_item(
+    this: *mut IShellItemFilter,
+    psi: *mut IShellItem,
+) -> HRESULT {
+    if psi.is_null() {
+        return E_POINTER;
+    }
+    let include = ShellItemFilter::value(this);
+    match panic::catch_unwind(AssertUnwindSafe(|| include(&*psi))) {
+        Ok(false) => S_FALSE,
+        Ok(true) | Err(_) => S_OK,
+    }
+}
+
+// The enumeration flags of folders are left to the dialog
+unsafe extern "system" fn get_enum_flags_for_item(
+    _this: *mut IShellItemFilter,
+    _psi: *mut IShellItem,
+    _flags: *mut SHCONTF,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::ptr::{self, null, null_mut};
+
+    use winapi::um::shobjidl_core::IShellItemVtbl;
+
+    // An item that is only compared by address, as the filter objects under test never call it
+    fn fake_item() -> IShellItem {
+        IShellItem {
+            lpVtbl: null::<IShellItemVtbl>(),
+        }
+    }
+
+    #[test]
+    fn include_item_calls_closure() {
+        let mut shown = fake_item();
+        let mut hidden = fake_item();
+        let shown_ptr: *mut IShellItem = &mut shown;
+        let calls = Cell::new(0);
+        let filter = unsafe {
+            create(|item: &IShellItem| {
+                calls.set(calls.get() + 1);
+                ptr::eq(item, shown_ptr)
+            })
+        };
+
+        unsafe {
+            assert_eq!((*filter).IncludeItem(shown_ptr), S_OK);
+            assert_eq!((*filter).IncludeItem(&mut hidden), S_FALSE);
+            assert_eq!((*filter).IncludeItem(null_mut()), E_POINTER);
+            assert_eq!(calls.get(), 2);
+
+            let mut flags: SHCONTF = 0;
+            assert_eq!(
+                (*filter).GetEnumFlagsForItem(shown_ptr, &mut flags),
+                E_NOTIMPL
+            );
+            assert_eq!((*filter).Release(), 0);
+        }
+    }
+
+    #[test]
+    fn panicking_closure_includes_item() {
+        let mut item = fake_item();
+        let filter = unsafe { create(|_: &IShellItem| panic!("filter failed")) };
+
+        unsafe {
+            assert_eq!((*filter).IncludeItem(&mut item), S_OK);
+            assert_eq!((*filter).Release(), 0);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// A reference-counted COM object made of a vtable and a Rust value, which implements `IUnknown`
+// for the objects that are passed to the shell. Only built on Windows with the `native-windows`
+// feature.
+
+use std::ptr::null_mut;
+use std::sync::atomic::{self, AtomicU32, Ordering};
+
+use winapi::{
+    ctypes::c_void,
+    shared::{
+        guiddef::{IsEqualIID, GUID, REFIID},
+        minwindef::ULONG,
+        winerror::{E_NOINTERFACE, E_POINTER, HRESULT, S_OK},
+    },
+    um::unknwnbase::{IUnknown, IUnknownVtbl},
+    Interface,
+};
+
+// The object behind an interface pointer. Callers only see a pointer to the first field, so the
+// layout must start with the vtable pointer. The `IUnknown` methods don't know the type of
+// `value`, and only use the fields before it, which are at the same offsets whatever its type.
+#[repr(C)]
+pub(crate) struct ComObject<T> {
+    vtbl: *const c_void,
+    ref_count: AtomicU32,
+    iid: GUID,
+    drop_object: unsafe fn(*mut IUnknown),
+    value: T,
+}
+
+// The `IUnknown` methods that start the vtable of every `ComObject`
+pub(crate) const UNKNOWN_VTBL: IUnknownVtbl = IUnknownVtbl {
+    QueryInterface: query_interface,
+    AddRef: add_ref,
+    Release: release,
+};
+
+impl<T> ComObject<T> {
+    // Creates an object that implements the interface `I` with `vtbl`, which must start with
+    // `UNKNOWN_VTBL`, with a reference count of one that is owned by the caller. The object keeps
+    // `value` until its last reference is released.
+    pub(crate) fn create<I: Interface, V>(vtbl: &'static V, value: T) -> *mut I {
+        let object = Box::new(ComObject {
+            vtbl: vtbl as *const V as *const c_void,
+            ref_count: AtomicU32::new(1),
+            iid: I::uuidof(),
+            drop_object: drop_object::<T>,
+            value,
+        });
+        Box::into_raw(object) as *mut I
+    }
+
+    // Returns the value of the object behind `this`, which must have been created with a value of
+    // type `T`
+    pub(crate) unsafe fn value<'b, I>(this: *mut I) -> &'b T {
+        &(*(this as *const ComObject<T>)).value
+    }
+}
+
+unsafe fn drop_object<T>(this: *mut IUnknown) {
+    drop(Box::from_raw(this as *mut ComObject<T>));
+}
+
+unsafe extern "system" fn query_interface(
+    this: *mut IUnknown,
+    riid: REFIID,
+    ppv: *mut *mut c_void,
+) -> HRESULT {
+    if ppv.is_null() || riid.is_null() {
+        return E_POINTER;
+    }
+    let object = &*(this as *const ComObject<()>);
+    if IsEqualIID(&*riid, &IUnknown::uuidof()) || IsEqualIID(&*riid, &object.iid) {
+        add_ref(this);
+        *ppv = this as *mut c_void;
+        S_OK
+    } else {
+        *ppv = null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+    let object = &*(this as *const ComObject<()>);
+    object.ref_count.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+    let object = &*(this as *const ComObject<()>);
+    let count = object.ref_count.fetch_sub(1, Ordering::Release) - 1;
+    if count == 0 {
+        // Synchronizes with the releases of other threads before the object is dropped
+        atomic::fence(Ordering::Acquire);
+        (object.drop_object)(this);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    use winapi::um::shobjidl_core::IShellItemFilter;
+
+    // A vtable with only the `IUnknown` methods, for objects that are only queried and released
+    static VTBL: IUnknownVtbl = UNKNOWN_VTBL;
+
+    fn create<T>(value: T) -> *mut IUnknown {
+        ComObject::<T>::create::<IShellItemFilter, _>(&VTBL, value) as *mut IUnknown
+    }
+
+    #[test]
+    fn value_is_kept() {
+        let object = create(String::from("value"));
+
+        unsafe {
+            assert_eq!(ComObject::<String>::value(object), "value");
+            assert_eq!((*object).Release(), 0);
+        }
+    }
+
+    #[test]
+    fn query_interface() {
+        let object = create(());
+        let unknown_iid = GUID {
+            Data1: 0x1234_5678,
+            Data2: 0,
+            Data3: 0,
+            Data4: [0; 8],
+        };
+
+        unsafe {
+            for iid in [IUnknown::uuidof(), IShellItemFilter::uuidof()].iter() {
+                let mut queried: *mut c_void = null_mut();
+                assert_eq!((*object).QueryInterface(iid, &mut queried), S_OK);
+                assert_eq!(queried, object as *mut c_void);
+            }
+
+            let mut queried = object as *mut c_void;
+            assert_eq!(
+                (*object).QueryInterface(&unknown_iid, &mut queried),
+                E_NOINTERFACE
+            );
+            assert!(queried.is_null());
+            assert_eq!(
+                (*object).QueryInterface(&unknown_iid, null_mut()),
+                E_POINTER
+            );
+
+            // The two successful queries each added a reference
+            assert_eq!((*object).Release(), 2);
+            assert_eq!((*object).Release(), 1);
+            assert_eq!((*object).Release(), 0);
+        }
+    }
+
+    #[test]
+    fn last_release_drops_value() {
+        let captured = Rc::new(());
+        let object = create(captured.clone());
+        assert_eq!(Rc::strong_count(&captured), 2);
+
+        unsafe {
+            assert_eq!((*object).AddRef(), 2);
+            assert_eq!((*object).Release(), 1);
+            assert_eq!(Rc::strong_count(&captured), 2);
+            assert_eq!((*object).Release(), 0);
+        }
+        assert_eq!(Rc::strong_count(&captured), 1);
+    }
+}
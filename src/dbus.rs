@@ -0,0 +1,680 @@
+// A minimal D-Bus client, sufficient for calling methods on the session bus that take and return
+// strings, string arrays and byte arrays. Messages are marshalled in little-endian order, see
+// https://dbus.freedesktop.org/doc/dbus-specification.html
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::uri;
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+#[cfg(test)]
+const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+// Messages larger than this are rejected rather than allocated
+const MAX_MESSAGE_LEN: usize = 1 << 26;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// A message sent to or received from the bus. Only the header fields used by method calls and
+// their replies are supported.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Message {
+    pub(crate) kind: u8,
+    pub(crate) serial: u32,
+    pub(crate) path: Option<String>,
+    pub(crate) interface: Option<String>,
+    pub(crate) member: Option<String>,
+    pub(crate) error_name: Option<String>,
+    pub(crate) reply_serial: Option<u32>,
+    pub(crate) destination: Option<String>,
+    pub(crate) sender: Option<String>,
+    pub(crate) signature: String,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Message {
+    pub(crate) fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+    ) -> Self {
+        Message {
+            kind: METHOD_CALL,
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            destination: Some(destination.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn with_body(self, signature: &str, body: Writer) -> Self {
+        Message {
+            signature: signature.to_string(),
+            body: body.buf,
+            ..self
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut fields = Writer::default();
+        let mut string_field = |code: u8, signature: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                fields.align(8);
+                fields.u8(code);
+                fields.signature(signature);
+                if signature == "g" {
+                    fields.signature(value);
+                } else {
+                    fields.string(value);
+                }
+            }
+        };
+        string_field(FIELD_PATH, "o", &self.path);
+        string_field(FIELD_INTERFACE, "s", &self.interface);
+        string_field(FIELD_MEMBER, "s", &self.member);
+        string_field(FIELD_ERROR_NAME, "s", &self.error_name);
+        string_field(FIELD_DESTINATION, "s", &self.destination);
+        string_field(FIELD_SENDER, "s", &self.sender);
+        if !self.signature.is_empty() {
+            string_field(FIELD_SIGNATURE, "g", &Some(self.signature.clone()));
+        }
+        if let Some(reply_serial) = self.reply_serial {
+            fields.align(8);
+            fields.u8(FIELD_REPLY_SERIAL);
+            fields.signature("u");
+            fields.u32(reply_serial);
+        }
+
+        let mut message = Writer::default();
+        message.buf.extend_from_slice(&[b'l', self.kind, 0, 1]);
+        message.u32(self.body.len() as u32);
+        message.u32(self.serial);
+        // The fields were marshalled from offset 0, which has the same alignment as offset 16
+        message.u32(fields.buf.len() as u32);
+        message.buf.extend_from_slice(&fields.buf);
+        message.align(8);
+        message.buf.extend_from_slice(&self.body);
+        message.buf
+    }
+
+    pub(crate) fn read_from<R: Read>(reader: &mut R) -> io::Result<Message> {
+        let mut header = [0; 16];
+        reader.read_exact(&mut header)?;
+        if header[0] != b'l' {
+            return Err(invalid("big-endian messages are not supported"));
+        }
+        let le_u32 =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let body_len = le_u32(4) as usize;
+        let fields_len = le_u32(12) as usize;
+        let padding = (8 - (16 + fields_len) % 8) % 8;
+        if body_len + fields_len > MAX_MESSAGE_LEN {
+            return Err(invalid("message is too large"));
+        }
+
+        let mut rest = vec![0; fields_len + padding + body_len];
+        reader.read_exact(&mut rest)?;
+        let mut message = Message {
+            kind: header[1],
+            serial: le_u32(8),
+            body: rest.split_off(fields_len + padding),
+            ..Default::default()
+        };
+
+        // Header fields are read from an offset of 16, which is aligned to 8 bytes
+        let mut fields = Reader::new(&rest[..fields_len]);
+        while !fields.is_empty() {
+            fields.align(8)?;
+            let code = fields.u8()?;
+            let signature = fields.signature()?;
+            match (code, signature.as_str()) {
+                (FIELD_REPLY_SERIAL, "u") => message.reply_serial = Some(fields.u32()?),
+                (FIELD_SIGNATURE, "g") => message.signature = fields.signature()?,
+                (_, "s") | (_, "o") => {
+                    let value = Some(fields.string()?);
+                    match code {
+                        FIELD_PATH => message.path = value,
+                        FIELD_INTERFACE => message.interface = value,
+                        FIELD_MEMBER => message.member = value,
+                        FIELD_ERROR_NAME => message.error_name = value,
+                        FIELD_DESTINATION => message.destination = value,
+                        FIELD_SENDER => message.sender = value,
+                        _ => {}
+                    }
+                }
+                (_, "u") => {
+                    fields.u32()?;
+                }
+                (_, "g") => {
+                    fields.signature()?;
+                }
+                _ => return Err(invalid("unsupported header field type")),
+            }
+        }
+        Ok(message)
+    }
+}
+
+// Marshals values into a message body
+#[derive(Default)]
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        while self.buf.len() % alignment != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    pub(crate) fn signature(&mut self, value: &str) {
+        self.u8(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+    }
+
+    // Writes an array whose elements have the given alignment, i.e. 8 for structs and dict
+    // entries, using `write` to marshal the elements
+    pub(crate) fn array<F: FnOnce(&mut Writer)>(&mut self, alignment: usize, write: F) {
+        self.u32(0);
+        let len_offset = self.buf.len() - 4;
+        self.align(alignment);
+        let start = self.buf.len();
+        write(self);
+        let len = (self.buf.len() - start) as u32;
+        self.buf[len_offset..len_offset + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    #[cfg(test)]
+    pub(crate) fn dict_entry(&mut self) {
+        self.align(8);
+    }
+}
+
+// Unmarshals values from a message body
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or_else(|| invalid("message is truncated"))?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn align(&mut self, alignment: usize) -> io::Result<()> {
+        let padding = (alignment - self.pos % alignment) % alignment;
+        self.take(padding).map(|_| ())
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        self.align(4)?;
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len + 1)?;
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| invalid("string is not UTF-8"))
+    }
+
+    pub(crate) fn signature(&mut self) -> io::Result<String> {
+        let len = self.u8()? as usize;
+        let bytes = self.take(len + 1)?;
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| invalid("signature is not UTF-8"))
+    }
+
+    pub(crate) fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    // Reads an array whose elements have the given alignment, calling `read` for each element
+    pub(crate) fn array<F>(&mut self, alignment: usize, mut read: F) -> io::Result<()>
+    where
+        F: FnMut(&mut Reader<'a>) -> io::Result<()>,
+    {
+        let len = self.u32()? as usize;
+        self.align(alignment)?;
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err(invalid("message is truncated"));
+        }
+        while self.pos < end {
+            read(self)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn dict_entry(&mut self) -> io::Result<()> {
+        self.align(8)
+    }
+}
+
+// A connection to a message bus
+pub(crate) struct Connection {
+    stream: UnixStream,
+    serial: u32,
+}
+
+impl Connection {
+    // Connects to the session bus in `DBUS_SESSION_BUS_ADDRESS`, or the default session bus in
+    // the runtime directory of the user
+    pub(crate) fn session() -> io::Result<Connection> {
+        let address = env::var("DBUS_SESSION_BUS_ADDRESS")
+            .ok()
+            .filter(|address| !address.is_empty())
+            .or_else(|| {
+                env::var("XDG_RUNTIME_DIR")
+                    .ok()
+                    .map(|dir| format!("unix:path={}/bus", dir))
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no session bus address"))?;
+        Connection::open(&address)
+    }
+
+    // Connects to the first of the `;`-separated addresses that can be connected to,
+    // authenticating and registering with the bus
+    pub(crate) fn open(address: &str) -> io::Result<Connection> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no supported bus address");
+        for address in address.split(';') {
+            match connect(address) {
+                Ok(Some(stream)) => {
+                    let mut connection = Connection { stream, serial: 0 };
+                    connection.authenticate()?;
+                    connection.call(Message::method_call(
+                        "org.freedesktop.DBus",
+                        "/org/freedesktop/DBus",
+                        "org.freedesktop.DBus",
+                        "Hello",
+                    ))?;
+                    return Ok(connection);
+                }
+                Ok(None) => {}
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn authenticate(&mut self) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(TIMEOUT))?;
+        let uid = unsafe { libc::getuid() }.to_string();
+        let hex_uid = uid
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        write!(self.stream, "\0AUTH EXTERNAL {}\r\n", hex_uid)?;
+        let reply = read_line(&mut self.stream)?;
+        if !reply.starts_with("OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("authentication was rejected: {}", reply),
+            ));
+        }
+        self.stream.write_all(b"BEGIN\r\n")
+    }
+
+    // Sends a method call and waits for its reply, skipping any signals received in between.
+    // Error replies are returned as errors containing the D-Bus error name.
+    pub(crate) fn call(&mut self, message: Message) -> io::Result<Message> {
+        self.serial += 1;
+        let serial = self.serial;
+        self.stream
+            .write_all(&Message { serial, ..message }.encode())?;
+
+        loop {
+            let reply = Message::read_from(&mut self.stream)?;
+            if reply.reply_serial != Some(serial) {
+                continue;
+            }
+            return match reply.kind {
+                METHOD_RETURN => Ok(reply),
+                ERROR => {
+                    let name = reply.error_name.clone().unwrap_or_default();
+                    let text = match reply.signature.as_str() {
+                        "s" => Reader::new(&reply.body).string().unwrap_or_default(),
+                        _ => String::new(),
+                    };
+                    Err(io::Error::other(format!("{}: {}", name, text)))
+                }
+                _ => Err(invalid("unexpected reply")),
+            };
+        }
+    }
+}
+
+// Returns the host paths of the documents with the given IDs in the document portal. Documents
+// which are not known to the portal are omitted.
+pub(crate) fn get_host_paths(
+    connection: &mut Connection,
+    ids: &[&str],
+) -> io::Result<HashMap<String, PathBuf>> {
+    let mut body = Writer::default();
+    body.array(4, |w| {
+        for id in ids {
+            w.string(id);
+        }
+    });
+    let call = Message::method_call(
+        "org.freedesktop.portal.Documents",
+        "/org/freedesktop/portal/documents",
+        "org.freedesktop.portal.Documents",
+        "GetHostPaths",
+    )
+    .with_body("as", body);
+    let reply = connection.call(call)?;
+    if reply.signature != "a{say}" {
+        return Err(invalid("unexpected reply signature"));
+    }
+
+    let mut host_paths = HashMap::new();
+    let mut reader = Reader::new(&reply.body);
+    reader.array(8, |r| {
+        r.dict_entry()?;
+        let id = r.string()?;
+        let mut path = r.bytes()?;
+        // Paths are returned as null-terminated byte strings
+        if path.last() == Some(&0) {
+            path.pop();
+        }
+        host_paths.insert(id, path_from_bytes(path));
+        Ok(())
+    })?;
+    Ok(host_paths)
+}
+
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+// Connects to a single bus address of the form `unix:path=/run/user/1000/bus`, returning `None`
+// for transports other than unix sockets
+fn connect(address: &str) -> io::Result<Option<UnixStream>> {
+    let params = match address.strip_prefix("unix:") {
+        Some(params) => params,
+        None => return Ok(None),
+    };
+    for param in params.split(',') {
+        let mut parts = param.splitn(2, '=');
+        let key = parts.next().unwrap_or_default();
+        let value = parts.next().unwrap_or_default();
+        let value = uri::percent_decode(value).ok_or_else(|| invalid("malformed bus address"))?;
+        match key {
+            "path" => return UnixStream::connect(path_from_bytes(value)).map(Some),
+            #[cfg(target_os = "linux")]
+            "abstract" => {
+                use std::os::linux::net::SocketAddrExt;
+                use std::os::unix::net::SocketAddr;
+
+                let addr = SocketAddr::from_abstract_name(value)?;
+                return UnixStream::connect_addr(&addr).map(Some);
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+// Reads a line of the authentication protocol, which ends with `\r\n`
+fn read_line<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut line = vec![];
+    let mut byte = [0];
+    while !line.ends_with(b"\r\n") {
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+        if line.len() > 4096 {
+            return Err(invalid("authentication line is too long"));
+        }
+    }
+    line.truncate(line.len() - 2);
+    String::from_utf8(line).map_err(|_| invalid("authentication line is not UTF-8"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::process;
+    use std::thread::{self, JoinHandle};
+
+    // A stand-in for the session bus and the document portal, listening on a private socket.
+    // Returns the address of the bus and the thread serving a single connection, which returns
+    // the messages that it received.
+    pub(crate) fn fake_bus(
+        name: &str,
+        documents: Vec<(&'static str, &'static [u8])>,
+    ) -> (String, JoinHandle<Vec<Message>>) {
+        let path = env::temp_dir().join(format!("wfd-dbus-{}-{}", name, process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let address = format!("unix:path={},guid=0123", path.display());
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let mut nul = [0];
+            stream.read_exact(&mut nul).unwrap();
+            let auth = read_line(&mut stream).unwrap();
+            let uid = unsafe { libc::getuid() }.to_string();
+            let hex_uid = uid
+                .bytes()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            assert_eq!(auth, format!("AUTH EXTERNAL {}", hex_uid));
+            stream.write_all(b"OK 0123456789abcdef\r\n").unwrap();
+            assert_eq!(read_line(&mut stream).unwrap(), "BEGIN");
+
+            let mut received = vec![];
+            let mut serial = 0;
+            while let Ok(message) = Message::read_from(&mut stream) {
+                let mut reply = Message {
+                    kind: METHOD_RETURN,
+                    reply_serial: Some(message.serial),
+                    ..Default::default()
+                };
+                match message.member.as_deref() {
+                    Some("Hello") => {
+                        // A signal sent before the reply, which the client must skip
+                        serial += 1;
+                        let signal = Message {
+                            kind: SIGNAL,
+                            serial,
+                            member: Some("NameAcquired".to_string()),
+                            ..Default::default()
+                        };
+                        stream.write_all(&signal.encode()).unwrap();
+
+                        let mut body = Writer::default();
+                        body.string(":1.42");
+                        reply = reply.with_body("s", body);
+                    }
+                    Some("GetHostPaths") => {
+                        let mut ids = vec![];
+                        Reader::new(&message.body)
+                            .array(4, |r| r.string().map(|id| ids.push(id)))
+                            .unwrap();
+                        let mut body = Writer::default();
+                        body.array(8, |w| {
+                            for (id, host_path) in &documents {
+                                if ids.iter().any(|i| i == id) {
+                                    w.dict_entry();
+                                    w.string(id);
+                                    w.bytes(host_path);
+                                }
+                            }
+                        });
+                        reply = reply.with_body("a{say}", body);
+                    }
+                    _ => {
+                        let mut body = Writer::default();
+                        body.string("Unknown method");
+                        reply = Message {
+                            kind: ERROR,
+                            error_name: Some("org.freedesktop.DBus.Error.UnknownMethod".into()),
+                            ..reply
+                        }
+                        .with_body("s", body);
+                    }
+                }
+                serial += 1;
+                reply.serial = serial;
+                stream.write_all(&reply.encode()).unwrap();
+                received.push(message);
+            }
+            received
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let mut body = Writer::default();
+        body.array(4, |w| {
+            w.string("abc");
+            w.string("de");
+        });
+        let message = Message {
+            serial: 7,
+            ..Message::method_call("org.example", "/org/example", "org.example.Iface", "Method")
+                .with_body("as", body)
+        };
+
+        let encoded = message.encode();
+        assert_eq!(&encoded[..4], &[b'l', METHOD_CALL, 0, 1]);
+        assert_eq!(encoded.len() % 8, (message.body.len()) % 8);
+        let decoded = Message::read_from(&mut &encoded[..]).unwrap();
+        assert_eq!(decoded, message);
+
+        let mut strings = vec![];
+        Reader::new(&decoded.body)
+            .array(4, |r| r.string().map(|s| strings.push(s)))
+            .unwrap();
+        assert_eq!(strings, vec!["abc", "de"]);
+    }
+
+    #[test]
+    fn truncated_messages() {
+        let encoded = Message::method_call("a", "/a", "a.b", "C").encode();
+        for len in [0, 15, encoded.len() - 1] {
+            assert!(Message::read_from(&mut &encoded[..len]).is_err());
+        }
+        assert!(Reader::new(&[3, 0, 0, 0, b'a']).string().is_err());
+        assert!(Reader::new(&[8, 0, 0, 0, 1])
+            .array(1, |r| r.u8().map(drop))
+            .is_err());
+    }
+
+    #[test]
+    fn host_paths_from_fake_portal() {
+        let (address, server) = fake_bus(
+            "host-paths",
+            vec![
+                ("a1b2c3", b"/home/ben/report.odt\0"),
+                ("d4e5f6", b"/home/ben/\xffraw"),
+            ],
+        );
+
+        let mut connection = Connection::open(&format!("tcp:host=localhost;{}", address)).unwrap();
+        let host_paths = get_host_paths(&mut connection, &["a1b2c3", "d4e5f6", "missing"]).unwrap();
+        assert_eq!(host_paths.len(), 2);
+        assert_eq!(host_paths["a1b2c3"], PathBuf::from("/home/ben/report.odt"));
+        assert_eq!(
+            host_paths["d4e5f6"],
+            path_from_bytes(b"/home/ben/\xffraw".to_vec())
+        );
+
+        let error = connection
+            .call(Message::method_call(
+                "org.example",
+                "/",
+                "org.example",
+                "Missing",
+            ))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "org.freedesktop.DBus.Error.UnknownMethod: Unknown method"
+        );
+
+        drop(connection);
+        let received = server.join().unwrap();
+        let members = received
+            .iter()
+            .map(|message| message.member.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(members, vec!["Hello", "GetHostPaths", "Missing"]);
+        assert_eq!(
+            received[1].destination.as_deref(),
+            Some("org.freedesktop.portal.Documents")
+        );
+    }
+}
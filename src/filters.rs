@@ -0,0 +1,637 @@
+//! Presets for common file types that can be used for [`DialogParams::file_types`]
+//!
+//! # Examples
+//!
+//! ```
+//! use wfd::filters::{self, FileTypeFilter, LabelStyle};
+//! use wfd::DialogParams;
+//!
+//! let media = FileTypeFilter::combine(
+//!     "Media",
+//!     &[filters::IMAGES, filters::VIDEO],
+//!     LabelStyle::Plain,
+//! );
+//!
+//! let mut file_types = vec![media.as_file_type()];
+//! file_types.extend(filters::file_types(
+//!     &[filters::IMAGES, filters::VIDEO, filters::ALL_FILES],
+//!     LabelStyle::WithPatterns,
+//! ));
+//!
+//! let params = DialogParams {
+//!     file_types,
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! [`DialogParams::file_types`]: ../struct.DialogParams.html#structfield.file_types
+
+use crate::locale::{self, localized_message, Message};
+use crate::mime::{MimeDatabase, MimeType};
+
+// Defines a preset from the message of its label and its patterns, producing the pattern list at
+// compile time
+macro_rules! preset {
+    ($(#[$attr:meta])* $name:ident, $label:ident, $first:literal $(, $pattern:literal)*) => {
+        $(#[$attr])*
+        pub const $name: FilterPreset = FilterPreset {
+            label: Message::$label,
+            pattern: concat!($first $(, ";", $pattern)*),
+        };
+    };
+}
+
+preset!(
+    /// Common raster and vector image formats
+    IMAGES, ImagesFilter, "*.png", "*.jpg", "*.jpeg", "*.gif", "*.bmp", "*.webp", "*.tif", "*.tiff",
+    "*.ico", "*.svg"
+);
+preset!(
+    /// Common compressed and uncompressed audio formats
+    AUDIO, AudioFilter, "*.mp3", "*.wav", "*.flac", "*.ogg", "*.opus", "*.m4a", "*.aac", "*.wma"
+);
+preset!(
+    /// Common video container formats
+    VIDEO, VideoFilter, "*.mp4", "*.mkv", "*.webm", "*.avi", "*.mov", "*.wmv", "*.m4v", "*.mpg",
+    "*.mpeg"
+);
+preset!(
+    /// Word processor, PDF and plain text documents
+    DOCUMENTS, DocumentsFilter, "*.pdf", "*.doc", "*.docx", "*.odt", "*.rtf", "*.txt", "*.md"
+);
+preset!(
+    /// Spreadsheets and delimited data files
+    SPREADSHEETS, SpreadsheetsFilter, "*.xls", "*.xlsx", "*.ods", "*.csv", "*.tsv"
+);
+preset!(
+    /// Slide show presentations
+    PRESENTATIONS, PresentationsFilter, "*.ppt", "*.pptx", "*.odp"
+);
+preset!(
+    /// Compressed archives
+    ARCHIVES, ArchivesFilter, "*.zip", "*.7z", "*.rar", "*.tar", "*.gz", "*.tgz", "*.bz2", "*.xz"
+);
+preset!(
+    /// Source code of common programming languages
+    SOURCE_CODE, SourceCodeFilter, "*.rs", "*.c", "*.h", "*.cpp", "*.hpp", "*.cs", "*.java", "*.py",
+    "*.js", "*.ts", "*.go", "*.rb", "*.sh"
+);
+preset!(
+    /// Plain text files
+    TEXT, TextFilter, "*.txt", "*.log", "*.md"
+);
+preset!(
+    /// All files, regardless of their extension. Labelled with the same text as the default
+    /// `file_types` of `DialogParams`, which already includes the pattern.
+    ALL_FILES, AllFilesFilter, "*.*"
+);
+
+/// Determines how the label of a filter is rendered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LabelStyle {
+    /// Only the name of the filter, i.e. `"Images"`
+    #[default]
+    Plain,
+    /// The name of the filter followed by its patterns, i.e. `"Images (*.png;*.jpg)"`
+    WithPatterns,
+}
+
+/// Where an "All supported files" filter, matching the patterns of all other file types, is
+/// inserted into the file types of a dialog, see [`DialogParams::aggregate_filter`]. The filter
+/// is labelled with [`Message::AllSupportedFiles`] in the current locale.
+///
+/// Patterns that match all files, `*.*` and `*`, are not included in the aggregate filter, so it
+/// can be combined with a separate "All files" filter.
+///
+/// [`DialogParams::aggregate_filter`]: ../struct.DialogParams.html#structfield.aggregate_filter
+/// [`Message::AllSupportedFiles`]: ../enum.Message.html#variant.AllSupportedFiles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AggregateFilter {
+    /// No aggregate filter is added
+    #[default]
+    Disabled,
+    /// The aggregate filter is the first file type
+    First,
+    /// The aggregate filter is the last file type
+    Last,
+    /// The aggregate filter is inserted at the given 1-based position, or is the last file type if
+    /// the position is beyond the end of the file types
+    At(u32),
+}
+
+// The file types displayed by a dialog, which include an aggregate filter if one is enabled, and
+// the mapping between their indices and those of `DialogParams::file_types`
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct FileTypeLayout {
+    // The 0-based position of the aggregate filter among the displayed file types, its label and
+    // its pattern
+    aggregate: Option<(usize, String, String)>,
+}
+
+impl FileTypeLayout {
+    pub(crate) fn new(file_types: &[(&str, &str)], aggregate_filter: AggregateFilter) -> Self {
+        let position = match aggregate_filter {
+            AggregateFilter::Disabled => return FileTypeLayout { aggregate: None },
+            AggregateFilter::First => 0,
+            AggregateFilter::Last => file_types.len(),
+            AggregateFilter::At(position) => (position.max(1) as usize - 1).min(file_types.len()),
+        };
+
+        let pattern = join_patterns(
+            file_types
+                .iter()
+                .flat_map(|(_, pattern)| pattern.split(';'))
+                .filter(|pattern| !matches!(pattern.trim(), "*.*" | "*")),
+        );
+        FileTypeLayout {
+            aggregate: if pattern.is_empty() {
+                None
+            } else {
+                let label = localized_message(Message::AllSupportedFiles).into_owned();
+                Some((position, label, pattern))
+            },
+        }
+    }
+
+    // Returns the file types to display in the dialog. Only used by the native Windows dialog.
+    #[cfg_attr(not(all(windows, feature = "native-windows")), allow(dead_code))]
+    pub(crate) fn file_types<'a>(
+        &'a self,
+        file_types: &[(&'a str, &'a str)],
+    ) -> Vec<(&'a str, &'a str)> {
+        let mut displayed = file_types.to_vec();
+        if let Some((position, label, pattern)) = &self.aggregate {
+            displayed.insert(*position, (label.as_str(), pattern.as_str()));
+        }
+        displayed
+    }
+
+    // Converts a 1-based index into `DialogParams::file_types` into the index of the displayed
+    // file type. An index of 0 refers to the aggregate filter.
+    pub(crate) fn dialog_index(&self, index: u32) -> u32 {
+        match &self.aggregate {
+            None => index,
+            Some((position, _, _)) if index == 0 => *position as u32 + 1,
+            Some((position, _, _)) if index as usize > *position => index + 1,
+            Some(_) => index,
+        }
+    }
+
+    // Converts the 1-based index of a displayed file type into an index into
+    // `DialogParams::file_types`, returning 0 for the aggregate filter
+    pub(crate) fn caller_index(&self, dialog_index: u32) -> u32 {
+        match &self.aggregate {
+            None => dialog_index,
+            Some(_) if dialog_index == 0 => 0,
+            Some((position, _, _)) if dialog_index as usize == *position + 1 => 0,
+            Some((position, _, _)) if dialog_index as usize > *position + 1 => dialog_index - 1,
+            Some(_) => dialog_index,
+        }
+    }
+}
+
+/// A predefined file type filter, see the constants in this module. Labels are in the current
+/// locale, see [`localized_message`].
+///
+/// [`localized_message`]: ../fn.localized_message.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterPreset {
+    label: Message,
+    pattern: &'static str,
+}
+
+impl FilterPreset {
+    /// The name of the filter in the current locale, i.e. `"Images"`
+    pub fn label(&self) -> &'static str {
+        locale::static_message(self.label)
+    }
+
+    /// The patterns of the filter separated by a semi-colon, i.e. `"*.png;*.jpg"`
+    pub fn pattern(&self) -> &'static str {
+        self.pattern
+    }
+
+    /// Returns the label and pattern in the form expected by `DialogParams::file_types`
+    pub fn file_type(&self, style: LabelStyle) -> (&'static str, &'static str) {
+        let label = match style {
+            LabelStyle::Plain => self.label(),
+            // The label of all files already includes its pattern
+            LabelStyle::WithPatterns if self.label == Message::AllFilesFilter => self.label(),
+            LabelStyle::WithPatterns => {
+                locale::intern(format!("{} ({})", self.label(), self.pattern).into())
+            }
+        };
+        (label, self.pattern)
+    }
+}
+
+/// Returns the label and pattern of each preset in the form expected by
+/// `DialogParams::file_types`
+pub fn file_types(
+    presets: &[FilterPreset],
+    style: LabelStyle,
+) -> Vec<(&'static str, &'static str)> {
+    presets
+        .iter()
+        .map(|preset| preset.file_type(style))
+        .collect()
+}
+
+/// A file type filter that is built at runtime, for example by combining several presets into a
+/// single entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileTypeFilter {
+    label: String,
+    pattern: String,
+}
+
+impl FileTypeFilter {
+    /// Creates a filter from a label and patterns separated by a semi-colon. If the style is
+    /// [`LabelStyle::WithPatterns`] the patterns are appended to the label.
+    ///
+    /// [`LabelStyle::WithPatterns`]: enum.LabelStyle.html#variant.WithPatterns
+    pub fn new(label: &str, pattern: &str, style: LabelStyle) -> Self {
+        let pattern = join_patterns(pattern.split(';'));
+        let label = match style {
+            LabelStyle::Plain => label.to_string(),
+            LabelStyle::WithPatterns => format!("{} ({})", label, pattern),
+        };
+        FileTypeFilter { label, pattern }
+    }
+
+    /// Creates a single filter that matches the files of all of the presets. Patterns that
+    /// appear in more than one preset are only included once.
+    pub fn combine(label: &str, presets: &[FilterPreset], style: LabelStyle) -> Self {
+        let patterns = presets
+            .iter()
+            .map(|preset| preset.pattern)
+            .collect::<Vec<_>>()
+            .join(";");
+        FileTypeFilter::new(label, &patterns, style)
+    }
+
+    /// The label of the filter
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The patterns of the filter separated by a semi-colon
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns the label and pattern in the form expected by `DialogParams::file_types`
+    pub fn as_file_type(&self) -> (&str, &str) {
+        (&self.label, &self.pattern)
+    }
+}
+
+impl FileTypeFilter {
+    /// Creates a single filter that matches the files of all of the MIME types, such as
+    /// `"image/png"`, or MIME type wildcards, such as `"image/*"`. Returns `None` if none of the
+    /// types are known or have file name patterns.
+    ///
+    /// The patterns are read from the shared-mime-info database on Linux and other Unix-like
+    /// platforms, and from the content type associations in the registry on Windows.
+    pub fn from_mime_types(label: &str, mime_types: &[&str], style: LabelStyle) -> Option<Self> {
+        combine_mime_types(&MimeDatabase::system(), label, mime_types, style)
+    }
+}
+
+/// Returns a filter for each of the MIME types, with MIME type wildcards such as `"image/*"`
+/// producing a filter for each matching type. Filters are labelled with the description of the
+/// type in the current locale, or the MIME type itself if it has no description. Unknown types
+/// and types without file name patterns are skipped.
+///
+/// See [`FileTypeFilter::from_mime_types`] for where the types are read from.
+///
+/// [`FileTypeFilter::from_mime_types`]: struct.FileTypeFilter.html#method.from_mime_types
+pub fn mime_type_filters(mime_types: &[&str], style: LabelStyle) -> Vec<FileTypeFilter> {
+    filters_for_mime_types(&MimeDatabase::system(), mime_types, style)
+}
+
+// Returns the types of the database matching any of the MIME types, in the order that the MIME
+// types are given and without duplicates
+fn resolve_mime_types<'a>(database: &'a MimeDatabase, mime_types: &[&str]) -> Vec<&'a MimeType> {
+    let mut resolved: Vec<&MimeType> = Vec::new();
+    for mime_type in mime_types.iter().flat_map(|m| database.lookup(m)) {
+        if !mime_type.globs.is_empty() && !resolved.iter().any(|r| r.name == mime_type.name) {
+            resolved.push(mime_type);
+        }
+    }
+    resolved
+}
+
+fn combine_mime_types(
+    database: &MimeDatabase,
+    label: &str,
+    mime_types: &[&str],
+    style: LabelStyle,
+) -> Option<FileTypeFilter> {
+    let resolved = resolve_mime_types(database, mime_types);
+    if resolved.is_empty() {
+        return None;
+    }
+    let patterns = resolved
+        .iter()
+        .flat_map(|mime_type| mime_type.globs.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(";");
+    Some(FileTypeFilter::new(label, &patterns, style))
+}
+
+fn filters_for_mime_types(
+    database: &MimeDatabase,
+    mime_types: &[&str],
+    style: LabelStyle,
+) -> Vec<FileTypeFilter> {
+    resolve_mime_types(database, mime_types)
+        .into_iter()
+        .map(|mime_type| {
+            let label = mime_type.description.as_ref().unwrap_or(&mime_type.name);
+            FileTypeFilter::new(label, &mime_type.globs.join(";"), style)
+        })
+        .collect()
+}
+
+impl From<FilterPreset> for FileTypeFilter {
+    fn from(preset: FilterPreset) -> Self {
+        FileTypeFilter {
+            label: preset.label().to_string(),
+            pattern: preset.pattern.to_string(),
+        }
+    }
+}
+
+// Joins patterns with a semi-colon, skipping empty and duplicate patterns. Patterns are compared
+// case-insensitively since file names are matched case-insensitively by the dialog.
+fn join_patterns<'a, I: Iterator<Item = &'a str>>(patterns: I) -> String {
+    let mut unique: Vec<&str> = Vec::new();
+    for pattern in patterns.map(str::trim).filter(|p| !p.is_empty()) {
+        if !unique.iter().any(|p| p.eq_ignore_ascii_case(pattern)) {
+            unique.push(pattern);
+        }
+    }
+    unique.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_PRESETS: [FilterPreset; 10] = [
+        IMAGES,
+        AUDIO,
+        VIDEO,
+        DOCUMENTS,
+        SPREADSHEETS,
+        PRESENTATIONS,
+        ARCHIVES,
+        SOURCE_CODE,
+        TEXT,
+        ALL_FILES,
+    ];
+
+    // The labels are in the locale of the environment that the tests run in
+    fn label(message: Message) -> String {
+        localized_message(message).into_owned()
+    }
+
+    #[test]
+    fn preset_labels() {
+        assert_eq!(SPREADSHEETS.label(), label(Message::SpreadsheetsFilter));
+        assert_eq!(SPREADSHEETS.pattern(), "*.xls;*.xlsx;*.ods;*.csv;*.tsv");
+        assert_eq!(
+            SPREADSHEETS.file_type(LabelStyle::WithPatterns),
+            (
+                format!(
+                    "{} (*.xls;*.xlsx;*.ods;*.csv;*.tsv)",
+                    label(Message::SpreadsheetsFilter)
+                )
+                .as_str(),
+                "*.xls;*.xlsx;*.ods;*.csv;*.tsv"
+            )
+        );
+
+        // All files uses the same label as the default file types of `DialogParams`
+        let default_file_types = crate::DialogParams::default().file_types;
+        assert_eq!(
+            ALL_FILES.file_type(LabelStyle::Plain),
+            default_file_types[0]
+        );
+        assert_eq!(
+            ALL_FILES.file_type(LabelStyle::WithPatterns),
+            default_file_types[0]
+        );
+
+        // Labels with patterns are only allocated once
+        assert!(std::ptr::eq(
+            IMAGES.file_type(LabelStyle::WithPatterns).0,
+            IMAGES.file_type(LabelStyle::WithPatterns).0
+        ));
+    }
+
+    #[test]
+    fn preset_patterns_are_well_formed() {
+        for preset in ALL_PRESETS.iter() {
+            for pattern in preset.pattern().split(';') {
+                assert!(pattern.starts_with("*."), "{}", pattern);
+                assert_eq!(pattern, pattern.to_ascii_lowercase());
+            }
+            assert_eq!(
+                join_patterns(preset.pattern().split(';')),
+                preset.pattern(),
+                "{} contains duplicate patterns",
+                preset.label()
+            );
+            // The label of all files already includes its pattern
+            if *preset != ALL_FILES {
+                assert_eq!(
+                    preset.file_type(LabelStyle::WithPatterns).0,
+                    FileTypeFilter::new(preset.label(), preset.pattern(), LabelStyle::WithPatterns)
+                        .label()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn file_types_of_presets() {
+        assert_eq!(
+            file_types(&[TEXT, ALL_FILES], LabelStyle::Plain),
+            vec![
+                (TEXT.label(), "*.txt;*.log;*.md"),
+                (ALL_FILES.label(), "*.*")
+            ]
+        );
+        assert!(file_types(&[], LabelStyle::WithPatterns).is_empty());
+    }
+
+    #[test]
+    fn combine_presets() {
+        let filter = FileTypeFilter::combine("Writing", &[DOCUMENTS, TEXT], LabelStyle::Plain);
+        assert_eq!(
+            filter.as_file_type(),
+            ("Writing", "*.pdf;*.doc;*.docx;*.odt;*.rtf;*.txt;*.md;*.log")
+        );
+
+        let filter = FileTypeFilter::combine(
+            "Tables",
+            &[SPREADSHEETS, PRESENTATIONS],
+            LabelStyle::WithPatterns,
+        );
+        assert_eq!(
+            filter.label(),
+            "Tables (*.xls;*.xlsx;*.ods;*.csv;*.tsv;*.ppt;*.pptx;*.odp)"
+        );
+
+        let filter = FileTypeFilter::combine("Nothing", &[], LabelStyle::WithPatterns);
+        assert_eq!(filter.as_file_type(), ("Nothing ()", ""));
+    }
+
+    fn fixture_database() -> MimeDatabase {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("mime");
+        MimeDatabase::load(&[fixtures.join("user"), fixtures.join("system")], "de")
+    }
+
+    #[test]
+    fn combine_mime_types_into_filter() {
+        let database = fixture_database();
+        let filter = combine_mime_types(
+            &database,
+            "Bilder",
+            &["image/*", "application/pdf", "image/png"],
+            LabelStyle::WithPatterns,
+        )
+        .unwrap();
+        assert_eq!(
+            filter.as_file_type(),
+            (
+                "Bilder (*.jpg;*.jpeg;*.jpe;*.png;*.apng;*.scene;*.pdf)",
+                "*.jpg;*.jpeg;*.jpe;*.png;*.apng;*.scene;*.pdf"
+            )
+        );
+
+        assert_eq!(
+            combine_mime_types(&database, "None", &["audio/*", "x/y"], LabelStyle::Plain),
+            None
+        );
+    }
+
+    #[test]
+    fn filters_for_each_mime_type() {
+        let database = fixture_database();
+        let filters = filters_for_mime_types(
+            &database,
+            &["text/*", "image/pjpeg", "application/x-unknown"],
+            LabelStyle::Plain,
+        );
+        assert_eq!(
+            filters
+                .iter()
+                .map(FileTypeFilter::as_file_type)
+                .collect::<Vec<_>>(),
+            vec![
+                ("Kommagetrennte Werte", "*.csv;*.ourcsv"),
+                ("Einfaches Textdokument", "*.txt;*.asc;*,v"),
+                ("Makefile", "makefile;GNUmakefile;*.mk"),
+                ("JPEG-Bild", "*.jpg;*.jpeg;*.jpe"),
+            ]
+        );
+    }
+
+    const FILE_TYPES: [(&str, &str); 3] = [
+        ("Images", "*.png;*.JPG"),
+        ("Photos", "*.jpg;*.jpeg;*.*"),
+        ("All files", "*.*"),
+    ];
+
+    #[test]
+    fn aggregate_filter_patterns() {
+        let layout = FileTypeLayout::new(&FILE_TYPES, AggregateFilter::First);
+        let file_types = layout.file_types(&FILE_TYPES);
+        assert_eq!(file_types.len(), 4);
+        assert_eq!(file_types[0].1, "*.png;*.JPG;*.jpeg");
+        assert_eq!(&file_types[1..], &FILE_TYPES[..]);
+
+        // Without any patterns other than those matching all files there is no aggregate filter
+        let all_files = [("All files", "*.*"), ("Everything", " * ")];
+        let layout = FileTypeLayout::new(&all_files, AggregateFilter::First);
+        assert_eq!(layout, FileTypeLayout { aggregate: None });
+        assert_eq!(layout.file_types(&all_files), all_files.to_vec());
+        let layout = FileTypeLayout::new(&[], AggregateFilter::Last);
+        assert!(layout.file_types(&[]).is_empty());
+    }
+
+    #[test]
+    fn aggregate_filter_positions() {
+        let position = |aggregate_filter| {
+            FileTypeLayout::new(&FILE_TYPES, aggregate_filter)
+                .aggregate
+                .map(|(position, _, _)| position)
+        };
+        assert_eq!(position(AggregateFilter::Disabled), None);
+        assert_eq!(position(AggregateFilter::First), Some(0));
+        assert_eq!(position(AggregateFilter::Last), Some(3));
+        assert_eq!(position(AggregateFilter::At(0)), Some(0));
+        assert_eq!(position(AggregateFilter::At(1)), Some(0));
+        assert_eq!(position(AggregateFilter::At(3)), Some(2));
+        assert_eq!(position(AggregateFilter::At(4)), Some(3));
+        assert_eq!(position(AggregateFilter::At(100)), Some(3));
+    }
+
+    #[test]
+    fn aggregate_filter_index_mapping() {
+        let disabled = FileTypeLayout::new(&FILE_TYPES, AggregateFilter::Disabled);
+        for index in 0..5 {
+            assert_eq!(disabled.dialog_index(index), index);
+            assert_eq!(disabled.caller_index(index), index);
+        }
+
+        // Displayed: Images, Photos, All supported files, All files
+        let layout = FileTypeLayout::new(&FILE_TYPES, AggregateFilter::At(3));
+        assert_eq!(layout.dialog_index(0), 3);
+        assert_eq!(layout.dialog_index(1), 1);
+        assert_eq!(layout.dialog_index(2), 2);
+        assert_eq!(layout.dialog_index(3), 4);
+        assert_eq!(layout.caller_index(0), 0);
+        assert_eq!(layout.caller_index(1), 1);
+        assert_eq!(layout.caller_index(2), 2);
+        assert_eq!(layout.caller_index(3), 0);
+        assert_eq!(layout.caller_index(4), 3);
+
+        for aggregate_filter in [
+            AggregateFilter::First,
+            AggregateFilter::Last,
+            AggregateFilter::At(2),
+        ]
+        .iter()
+        {
+            let layout = FileTypeLayout::new(&FILE_TYPES, *aggregate_filter);
+            let displayed = layout.file_types(&FILE_TYPES);
+            for index in 0..=FILE_TYPES.len() as u32 {
+                let dialog_index = layout.dialog_index(index);
+                assert_eq!(layout.caller_index(dialog_index), index);
+                if index > 0 {
+                    assert_eq!(
+                        displayed[dialog_index as usize - 1],
+                        FILE_TYPES[index as usize - 1]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn custom_filters() {
+        let filter = FileTypeFilter::new("Logs", "*.log; *.LOG;;*.out", LabelStyle::WithPatterns);
+        assert_eq!(filter.as_file_type(), ("Logs (*.log;*.out)", "*.log;*.out"));
+        assert_eq!(
+            FileTypeFilter::from(IMAGES).as_file_type(),
+            IMAGES.file_type(LabelStyle::Plain)
+        );
+    }
+}
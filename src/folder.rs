@@ -0,0 +1,434 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::uri::{self, UriStyle};
+use crate::DialogError;
+
+/// A folder to open a dialog in, used by the `default_folder` and `folder` fields of
+/// [`DialogParams`]. String literals, `Path`s and `OsStr`s can be converted into a
+/// `FolderSpec::Path` with `into()`.
+///
+/// # Examples
+///
+/// ```
+/// use wfd::{DialogParams, FolderSpec, KnownFolder};
+///
+/// let params = DialogParams {
+///     default_folder: FolderSpec::Known(KnownFolder::Documents),
+///     folder: FolderSpec::Template(r"%APPDATA%\OurApp\exports"),
+///     ..Default::default()
+/// };
+/// ```
+///
+/// [`DialogParams`]: struct.DialogParams.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FolderSpec<'a> {
+    /// A literal path to a folder. An empty path means that the field is not set. Paths that
+    /// aren't valid Unicode are passed to the dialog without loss.
+    Path(&'a Path),
+    /// One of the well-known user folders, resolved using `SHGetKnownFolderPath` on Windows and
+    /// the XDG user directories on other platforms
+    Known(KnownFolder),
+    /// A path containing a leading `~` to refer to the user's home directory and/or environment
+    /// variables in either `%VAR%`, `$VAR` or `${VAR}` form, i.e. `"%APPDATA%\OurApp\exports"`.
+    /// Variables that are not defined are left unexpanded.
+    Template(&'a str),
+    /// A `file://` URI referring to a folder, i.e. `"file:///home/ben/exports"`. String literals
+    /// starting with `file:` are converted into this variant with `into()`.
+    Uri(&'a str),
+}
+
+impl<'a> FolderSpec<'a> {
+    /// Returns true if this is an empty `FolderSpec::Path`, `FolderSpec::Template` or
+    /// `FolderSpec::Uri`
+    pub fn is_empty(&self) -> bool {
+        match self {
+            FolderSpec::Path(path) => path.as_os_str().is_empty(),
+            FolderSpec::Template(template) | FolderSpec::Uri(template) => template.is_empty(),
+            FolderSpec::Known(_) => false,
+        }
+    }
+
+    // Resolves this spec to the path of a folder. Only used by the native Windows dialog.
+    #[cfg_attr(not(all(windows, feature = "native-windows")), allow(dead_code))]
+    pub(crate) fn resolve(&self) -> Result<PathBuf, DialogError> {
+        match self {
+            FolderSpec::Path(path) => Ok(path.to_path_buf()),
+            FolderSpec::Known(known_folder) => known_folder.path(),
+            FolderSpec::Template(template) => Ok(PathBuf::from(expand_template(
+                template,
+                home_dir().as_deref(),
+                |name| env::var(name).ok(),
+            ))),
+            FolderSpec::Uri(uri) => uri::file_uri_to_path(uri, UriStyle::Native),
+        }
+    }
+}
+
+impl<'a> Default for FolderSpec<'a> {
+    fn default() -> Self {
+        FolderSpec::Path(Path::new(""))
+    }
+}
+
+impl<'a> From<&'a str> for FolderSpec<'a> {
+    fn from(path: &'a str) -> Self {
+        if uri::is_file_uri(path) {
+            FolderSpec::Uri(path)
+        } else {
+            FolderSpec::Path(Path::new(path))
+        }
+    }
+}
+
+impl<'a> From<&'a OsStr> for FolderSpec<'a> {
+    fn from(path: &'a OsStr) -> Self {
+        FolderSpec::Path(Path::new(path))
+    }
+}
+
+impl<'a> From<&'a Path> for FolderSpec<'a> {
+    fn from(path: &'a Path) -> Self {
+        FolderSpec::Path(path)
+    }
+}
+
+impl<'a> From<KnownFolder> for FolderSpec<'a> {
+    fn from(known_folder: KnownFolder) -> Self {
+        FolderSpec::Known(known_folder)
+    }
+}
+
+impl<'a> fmt::Display for FolderSpec<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FolderSpec::Path(path) => write!(f, "{}", path.display()),
+            FolderSpec::Template(template) | FolderSpec::Uri(template) => f.write_str(template),
+            FolderSpec::Known(known_folder) => write!(f, "{:?}", known_folder),
+        }
+    }
+}
+
+/// The well-known user folders that can be used in a [`FolderSpec`]
+///
+/// [`FolderSpec`]: enum.FolderSpec.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KnownFolder {
+    /// The user's Documents folder
+    Documents,
+    /// The user's Downloads folder
+    Downloads,
+    /// The user's Pictures folder
+    Pictures,
+    /// The user's Desktop folder
+    Desktop,
+    /// The user's Music folder
+    Music,
+    /// The user's Videos folder
+    Videos,
+    /// The user's home (profile) folder
+    Home,
+}
+
+impl KnownFolder {
+    #[cfg(windows)]
+    fn path(self) -> Result<PathBuf, DialogError> {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::OsStringExt;
+        use std::ptr::null_mut;
+        use std::slice;
+
+        use libc::wcslen;
+        use winapi::shared::minwindef::LPVOID;
+        use winapi::shared::ntdef::PWSTR;
+        use winapi::um::combaseapi::CoTaskMemFree;
+        use winapi::um::knownfolders::{
+            FOLDERID_Desktop, FOLDERID_Documents, FOLDERID_Downloads, FOLDERID_Music,
+            FOLDERID_Pictures, FOLDERID_Profile, FOLDERID_Videos,
+        };
+        use winapi::um::shlobj::SHGetKnownFolderPath;
+
+        let folder_id = match self {
+            KnownFolder::Documents => &FOLDERID_Documents,
+            KnownFolder::Downloads => &FOLDERID_Downloads,
+            KnownFolder::Pictures => &FOLDERID_Pictures,
+            KnownFolder::Desktop => &FOLDERID_Desktop,
+            KnownFolder::Music => &FOLDERID_Music,
+            KnownFolder::Videos => &FOLDERID_Videos,
+            KnownFolder::Home => &FOLDERID_Profile,
+        };
+
+        let mut path: PWSTR = null_mut();
+        let result = com!(
+            SHGetKnownFolderPath(folder_id, 0, null_mut(), &mut path),
+            "SHGetKnownFolderPath"
+        )
+        .map(|_| {
+            let slice = unsafe { slice::from_raw_parts(path, wcslen(path)) };
+            PathBuf::from(OsString::from_wide(slice))
+        });
+
+        // The returned buffer must be freed even if the call fails
+        unsafe { CoTaskMemFree(path as LPVOID) };
+
+        result
+    }
+
+    #[cfg(not(windows))]
+    fn path(self) -> Result<PathBuf, DialogError> {
+        use std::fs;
+
+        let home = home_dir().unwrap_or_default();
+        let (key, default_name) = match self {
+            KnownFolder::Documents => ("XDG_DOCUMENTS_DIR", "Documents"),
+            KnownFolder::Downloads => ("XDG_DOWNLOAD_DIR", "Downloads"),
+            KnownFolder::Pictures => ("XDG_PICTURES_DIR", "Pictures"),
+            KnownFolder::Desktop => ("XDG_DESKTOP_DIR", "Desktop"),
+            KnownFolder::Music => ("XDG_MUSIC_DIR", "Music"),
+            KnownFolder::Videos => ("XDG_VIDEOS_DIR", "Videos"),
+            KnownFolder::Home => return Ok(PathBuf::from(home)),
+        };
+
+        let config_home = env::var("XDG_CONFIG_HOME")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(&home).join(".config"));
+        let user_dir = fs::read_to_string(config_home.join("user-dirs.dirs"))
+            .ok()
+            .and_then(|contents| {
+                parse_user_dirs(&contents, &home)
+                    .into_iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, dir)| PathBuf::from(dir))
+            });
+
+        Ok(user_dir.unwrap_or_else(|| PathBuf::from(&home).join(default_name)))
+    }
+}
+
+#[cfg(windows)]
+fn home_dir() -> Option<String> {
+    env::var("USERPROFILE").ok()
+}
+
+#[cfg(not(windows))]
+fn home_dir() -> Option<String> {
+    env::var("HOME").ok()
+}
+
+// Parses the contents of an XDG `user-dirs.dirs` file, returning the key and expanded path of
+// each entry. Entries have the form `XDG_DOCUMENTS_DIR="$HOME/Documents"`, where the value is
+// either an absolute path or a path relative to `$HOME`.
+#[cfg(any(not(windows), test))]
+fn parse_user_dirs(contents: &str, home: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            let value = value.strip_prefix('"')?.strip_suffix('"')?;
+            let value = unescape_shell(value);
+
+            let path = if value == "$HOME" {
+                home.to_string()
+            } else if let Some(relative) = value.strip_prefix("$HOME/") {
+                format!("{}/{}", home.trim_end_matches('/'), relative)
+            } else if value.starts_with('/') {
+                value
+            } else {
+                // The spec only allows absolute paths or paths relative to $HOME
+                return None;
+            };
+
+            Some((key.to_string(), path))
+        })
+        .collect()
+}
+
+// Removes the backslash escapes permitted within the double-quoted values of `user-dirs.dirs`
+#[cfg(any(not(windows), test))]
+fn unescape_shell(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// Expands a leading `~` to the home directory and any `%VAR%`, `$VAR` and `${VAR}` environment
+// variable references using `lookup`. References to variables that are not defined, and a `~`
+// when the home directory is not known, are left as-is.
+fn expand_template<F>(template: &str, home: Option<&str>, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    if let Some(home) = home {
+        if let Some(after_tilde) = rest.strip_prefix('~') {
+            if after_tilde.is_empty() || after_tilde.starts_with(['/', '\\']) {
+                result.push_str(home);
+                rest = after_tilde;
+            }
+        }
+    }
+
+    while let Some(index) = rest.find(['%', '$']) {
+        result.push_str(&rest[..index]);
+        let (expanded, remainder) = expand_variable(&rest[index..], &lookup);
+        result.push_str(&expanded);
+        rest = remainder;
+    }
+    result.push_str(rest);
+
+    result
+}
+
+// Expands the variable reference at the start of `input`, which begins with either `%` or `$`,
+// returning the expanded text and the remaining unprocessed input
+fn expand_variable<'a, F>(input: &'a str, lookup: &F) -> (String, &'a str)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let (name, reference_len) = if let Some(after) = input.strip_prefix('%') {
+        match after.find('%') {
+            Some(end) if end > 0 => (&after[..end], end + 2),
+            _ => return (String::from("%"), after),
+        }
+    } else if let Some(after) = input.strip_prefix("${") {
+        match after.find('}') {
+            Some(end) if end > 0 => (&after[..end], end + 3),
+            _ => return (String::from("$"), &input[1..]),
+        }
+    } else {
+        let after = &input[1..];
+        let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+        if end == 0 {
+            return (String::from("$"), after);
+        }
+        (&after[..end], end + 1)
+    };
+
+    let expanded = lookup(name).unwrap_or_else(|| input[..reference_len].to_string());
+    (expanded, &input[reference_len..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "APPDATA" => Some(r"C:\Users\ben\AppData\Roaming".to_string()),
+            "XDG_DATA_HOME" => Some("/home/ben/.local/share".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn expand(template: &str) -> String {
+        expand_template(template, Some("/home/ben"), lookup)
+    }
+
+    #[test]
+    fn expand_template_windows_variables() {
+        assert_eq!(
+            expand(r"%APPDATA%\OurApp\exports"),
+            r"C:\Users\ben\AppData\Roaming\OurApp\exports"
+        );
+    }
+
+    #[test]
+    fn expand_template_unix_variables() {
+        assert_eq!(
+            expand("$XDG_DATA_HOME/ourapp"),
+            "/home/ben/.local/share/ourapp"
+        );
+        assert_eq!(
+            expand("${XDG_DATA_HOME}ourapp"),
+            "/home/ben/.local/shareourapp"
+        );
+        assert_eq!(expand("a${EMPTY}b$EMPTY/c"), "ab/c");
+    }
+
+    #[test]
+    fn expand_template_home() {
+        assert_eq!(expand("~"), "/home/ben");
+        assert_eq!(expand("~/exports"), "/home/ben/exports");
+        assert_eq!(expand(r"~\exports"), r"/home/ben\exports");
+        assert_eq!(expand("~other/exports"), "~other/exports");
+        assert_eq!(expand("exports/~"), "exports/~");
+        assert_eq!(expand_template("~/exports", None, lookup), "~/exports");
+    }
+
+    #[test]
+    fn expand_template_leaves_unknown_and_malformed_references() {
+        assert_eq!(expand(r"%UNDEFINED%\x"), r"%UNDEFINED%\x");
+        assert_eq!(expand("$UNDEFINED/x"), "$UNDEFINED/x");
+        assert_eq!(expand("${UNDEFINED}/x"), "${UNDEFINED}/x");
+        assert_eq!(expand("100%"), "100%");
+        assert_eq!(expand("%%APPDATA%"), r"%C:\Users\ben\AppData\Roaming");
+        assert_eq!(expand("$/x$"), "$/x$");
+        assert_eq!(expand("${/x"), "${/x");
+        assert_eq!(expand("${}"), "${}");
+    }
+
+    #[test]
+    fn parse_user_dirs_file() {
+        let contents = r#"
+# This file is written by xdg-user-dirs-update
+# If you want to change or add directories, just edit the line you're
+# interested in.
+XDG_DESKTOP_DIR="$HOME/Desktop"
+XDG_DOWNLOAD_DIR="$HOME/Downloads"
+XDG_DOCUMENTS_DIR="$HOME/Dokumente"
+XDG_MUSIC_DIR="/mnt/media/Music"
+XDG_PICTURES_DIR="$HOME/My \"Pictures\""
+XDG_VIDEOS_DIR="$HOME"
+XDG_TEMPLATES_DIR=relative/not/allowed
+XDG_PUBLICSHARE_DIR="relative/not/allowed"
+"#;
+        assert_eq!(
+            parse_user_dirs(contents, "/home/ben/"),
+            vec![
+                (
+                    "XDG_DESKTOP_DIR".to_string(),
+                    "/home/ben/Desktop".to_string()
+                ),
+                (
+                    "XDG_DOWNLOAD_DIR".to_string(),
+                    "/home/ben/Downloads".to_string()
+                ),
+                (
+                    "XDG_DOCUMENTS_DIR".to_string(),
+                    "/home/ben/Dokumente".to_string()
+                ),
+                ("XDG_MUSIC_DIR".to_string(), "/mnt/media/Music".to_string()),
+                (
+                    "XDG_PICTURES_DIR".to_string(),
+                    "/home/ben/My \"Pictures\"".to_string()
+                ),
+                ("XDG_VIDEOS_DIR".to_string(), "/home/ben/".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_user_dirs_empty_file() {
+        assert!(parse_user_dirs("", "/home/ben").is_empty());
+        assert!(parse_user_dirs("# only a comment\n\n", "/home/ben").is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+// The `item_filter` of `DialogParams`, which hides items from the dialog and rejects hidden items
+// that are selected regardless, for example by typing their name into the file name box
+
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+
+use crate::selection::{RejectionReason, SelectionRejection};
+
+/// An item displayed by a dialog, passed to an [`ItemFilter`] to decide whether it is shown
+///
+/// [`ItemFilter`]: struct.ItemFilter.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterItem<'a> {
+    /// The file system path of the item
+    pub path: &'a Path,
+    /// True if the item is a folder
+    pub is_folder: bool,
+    /// True if the item is hidden, i.e. it has the hidden attribute on Windows or its name starts
+    /// with a dot on other platforms
+    pub is_hidden: bool,
+    /// True if the item is read-only
+    pub is_read_only: bool,
+}
+
+impl<'a> FilterItem<'a> {
+    // Reads the attributes of an item from the file system
+    fn from_metadata(path: &'a Path, metadata: &Metadata) -> Self {
+        FilterItem {
+            path,
+            is_folder: metadata.is_dir(),
+            is_hidden: is_hidden(path, metadata),
+            is_read_only: metadata.permissions().readonly(),
+        }
+    }
+}
+
+/// Whether an [`ItemFilter`] shows an item in the dialog
+///
+/// [`ItemFilter`]: struct.ItemFilter.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemVisibility {
+    /// Show the item
+    Include,
+    /// Hide the item, and reject it if it is selected regardless
+    Hide,
+}
+
+/// A closure deciding which items a dialog shows, see `DialogParams::item_filter`.
+///
+/// On Windows the filter is installed with `IFileDialog::SetFilter`, and is called for each file
+/// and folder that the dialog displays. Items that are not in the file system, such as libraries,
+/// are always shown. Hiding a folder prevents navigating into it. As the shell does not consult
+/// the filter in every view, and a hidden item can still be selected by typing its name, the
+/// selected items are also checked once the dialog has closed. A selected item that is hidden is
+/// rejected with [`RejectionReason::HiddenByFilter`], in the same way as the `selection_rules`.
+///
+/// # Examples
+///
+/// ```
+/// use wfd::{DialogParams, ItemFilter, ItemVisibility};
+///
+/// let params = DialogParams {
+///     item_filter: Some(ItemFilter::new(|item| {
+///         if item.is_hidden || item.path.extension().map_or(false, |ext| ext == "tmp") {
+///             ItemVisibility::Hide
+///         } else {
+///             ItemVisibility::Include
+///         }
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+///
+/// [`RejectionReason::HiddenByFilter`]: enum.RejectionReason.html#variant.HiddenByFilter
+pub struct ItemFilter<'a> {
+    filter: Box<dyn Fn(&FilterItem) -> ItemVisibility + 'a>,
+}
+
+impl<'a> ItemFilter<'a> {
+    /// Creates a filter from a closure that is called with each item
+    pub fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&FilterItem) -> ItemVisibility + 'a,
+    {
+        ItemFilter {
+            filter: Box::new(filter),
+        }
+    }
+
+    pub(crate) fn visibility(&self, item: &FilterItem) -> ItemVisibility {
+        (self.filter)(item)
+    }
+
+    // Checks the selected paths against the filter, returning a rejection for each item that it
+    // hides. Paths that do not exist, such as a new file in a Save dialog, are not checked as the
+    // dialog could not have displayed them.
+    pub(crate) fn evaluate(&self, paths: &[PathBuf]) -> Vec<SelectionRejection> {
+        paths
+            .iter()
+            .filter(|path| match fs::metadata(path) {
+                Ok(metadata) => {
+                    let item = FilterItem::from_metadata(path, &metadata);
+                    self.visibility(&item) == ItemVisibility::Hide
+                }
+                Err(_) => false,
+            })
+            .map(|path| SelectionRejection {
+                path: Some(path.clone()),
+                reason: RejectionReason::HiddenByFilter,
+            })
+            .collect()
+    }
+}
+
+impl<'a> fmt::Debug for ItemFilter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ItemFilter(..)")
+    }
+}
+
+#[cfg(windows)]
+fn is_hidden(_path: &Path, metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+#[cfg(not(windows))]
+fn is_hidden(path: &Path, _metadata: &Metadata) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::env;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wfd-item-filter-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rejects_hidden_items() {
+        let dir = temp_dir("rejects");
+        let shown = dir.join("report.txt");
+        let hidden = dir.join("draft.tmp");
+        fs::write(&shown, "report").unwrap();
+        fs::write(&hidden, "draft").unwrap();
+
+        let filter = ItemFilter::new(|item| match item.path.extension() {
+            Some(ext) if ext == "tmp" => ItemVisibility::Hide,
+            _ => ItemVisibility::Include,
+        });
+        let rejections = filter.evaluate(&[shown, hidden.clone(), dir.join("new.tmp")]);
+
+        assert_eq!(rejections.len(), 1);
+        assert_eq!(rejections[0].path, Some(hidden));
+        assert!(matches!(
+            rejections[0].reason,
+            RejectionReason::HiddenByFilter
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn passes_item_attributes() {
+        let dir = temp_dir("attributes");
+        let file = dir.join("notes.txt");
+        let read_only = dir.join("locked.txt");
+        fs::write(&file, "notes").unwrap();
+        fs::write(&read_only, "locked").unwrap();
+        let mut permissions = fs::metadata(&read_only).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&read_only, permissions).unwrap();
+
+        let items = RefCell::new(vec![]);
+        let filter = ItemFilter::new(|item| {
+            items.borrow_mut().push((
+                item.path.to_path_buf(),
+                item.is_folder,
+                item.is_hidden,
+                item.is_read_only,
+            ));
+            ItemVisibility::Include
+        });
+        let paths = vec![dir.clone(), file.clone(), read_only.clone()];
+        assert!(filter.evaluate(&paths).is_empty());
+        drop(filter);
+        assert_eq!(
+            items.into_inner(),
+            vec![
+                (dir.clone(), true, false, false),
+                (file, false, false, false),
+                (read_only.clone(), false, false, true),
+            ]
+        );
+
+        let mut permissions = fs::metadata(&read_only).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&read_only, permissions).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn dot_files_are_hidden() {
+        let dir = temp_dir("dot-files");
+        let hidden = dir.join(".config");
+        fs::write(&hidden, "").unwrap();
+
+        let filter = ItemFilter::new(|item| {
+            if item.is_hidden {
+                ItemVisibility::Hide
+            } else {
+                ItemVisibility::Include
+            }
+        });
+        assert_eq!(filter.evaluate(&[hidden]).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn debug_format() {
+        let filter = ItemFilter::new(|_| ItemVisibility::Include);
+        assert_eq!(format!("{:?}", filter), "ItemFilter(..)");
+    }
+}
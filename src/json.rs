@@ -0,0 +1,439 @@
+// A minimal JSON reader and writer, sufficient for the single-line documents that wfd writes and
+// reads back such as the entries of a dialog recording. Numbers keep their literal text so that
+// 64-bit integers round-trip without loss, and objects keep the order of their members.
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    // Returns the member of an object with the given name
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+macro_rules! number_from {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Value::Number(value.to_string())
+                }
+            }
+        )*
+    };
+}
+
+number_from!(i32, i64, u32, u64, usize);
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+// Builds an object from its members, i.e. `object(vec![("name", "value".into())])`
+pub(crate) fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ParseError(pub(crate) String);
+
+// Parses a document containing a single value
+pub(crate) fn parse(input: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser { input, pos: 0 };
+    parser.skip_whitespace();
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < input.len() {
+        return Err(parser.error("unexpected content after the value"));
+    }
+    Ok(value)
+}
+
+// Writes a value without any whitespace, so that it fits on a single line
+pub(crate) fn write(value: &Value) -> String {
+    let mut output = String::new();
+    write_value(&mut output, value);
+    output
+}
+
+fn write_value(output: &mut String, value: &Value) {
+    match value {
+        Value::Null => output.push_str("null"),
+        Value::Bool(value) => output.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => output.push_str(number),
+        Value::String(value) => write_string(output, value),
+        Value::Array(values) => {
+            output.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_value(output, value);
+            }
+            output.push(']');
+        }
+        Value::Object(members) => {
+            output.push('{');
+            for (i, (name, value)) in members.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_string(output, name);
+                output.push(':');
+                write_value(output, value);
+            }
+            output.push('}');
+        }
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+// Nested arrays and objects deeper than this are rejected rather than overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError(format!("{} at offset {}", message, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        let trimmed = rest.trim_start_matches([' ', '\t', '\n', '\r']);
+        self.pos += rest.len() - trimmed.len();
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ParseError> {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", s)))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Value, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+
+        match self.rest().chars().next() {
+            Some('n') => self.expect("null").map(|_| Value::Null),
+            Some('t') => self.expect("true").map(|_| Value::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some('"') => self.parse_string().map(Value::String),
+            Some('[') => self.parse_array(depth),
+            Some('{') => self.parse_object(depth),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, ParseError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+        if number.parse::<f64>().is_err() {
+            return Err(self.error("invalid number"));
+        }
+        self.pos += len;
+        Ok(Value::Number(number.to_string()))
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        self.expect("\"")?;
+        let mut value = String::new();
+        loop {
+            let rest = self.rest();
+            let c = match rest.chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(value),
+                '\\' => value.push(self.parse_escape()?),
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.rest().chars().next() {
+            Some(c) => c,
+            None => return Err(self.error("unterminated escape")),
+        };
+        self.pos += c.len_utf8();
+        match c {
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            '/' => Ok('/'),
+            'b' => Ok('\u{8}'),
+            'f' => Ok('\u{c}'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            'u' => {
+                let high = self.parse_hex4()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    return char::from_u32(high).ok_or_else(|| self.error("invalid escape"));
+                }
+
+                // A high surrogate must be followed by an escaped low surrogate
+                self.expect("\\u")?;
+                let low = self.parse_hex4()?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err(self.error("invalid surrogate pair"));
+                }
+                char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                    .ok_or_else(|| self.error("invalid escape"))
+            }
+            _ => Err(self.error("invalid escape")),
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self.rest().get(..4).unwrap_or("");
+        let value = u32::from_str_radix(digits, 16)
+            .ok()
+            .filter(|_| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.expect("[")?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.rest().starts_with(']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            self.skip_whitespace();
+            values.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            if self.rest().starts_with(',') {
+                self.pos += 1;
+            } else {
+                self.expect("]")?;
+                return Ok(Value::Array(values));
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.rest().starts_with('}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            self.skip_whitespace();
+            members.push((name, self.parse_value(depth + 1)?));
+            self.skip_whitespace();
+            if self.rest().starts_with(',') {
+                self.pos += 1;
+            } else {
+                self.expect("}")?;
+                return Ok(Value::Object(members));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_document() {
+        let value = parse(
+            r#" {"name": "report.txt", "size": 18446744073709551615, "tags": [true, null, -1.5e3],
+                "nested": {}} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("name").and_then(Value::as_str),
+            Some("report.txt")
+        );
+        assert_eq!(value.get("size").and_then(Value::as_u64), Some(u64::MAX));
+        assert_eq!(
+            value.get("tags").and_then(Value::as_array),
+            Some(
+                &[
+                    Value::Bool(true),
+                    Value::Null,
+                    Value::Number("-1.5e3".to_string())
+                ][..]
+            )
+        );
+        assert_eq!(value.get("nested"), Some(&Value::Object(vec![])));
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let value = parse(r#""C:\\Users\\ben\n\"\u00e9\ud83d\ude00\/""#).unwrap();
+        assert_eq!(value.as_str(), Some("C:\\Users\\ben\n\"\u{e9}\u{1f600}/"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("{").is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+        assert!(parse("[1,]").is_err());
+        assert!(parse(r#""unterminated"#).is_err());
+        assert!(parse(r#""\ud83d""#).is_err());
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse("tru").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("-").is_err());
+        assert!(parse(&"[".repeat(MAX_DEPTH + 2)).is_err());
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let value = object(vec![
+            ("path", r"C:\Users\ben\report.txt".into()),
+            ("text", "line\n\ttab \"quoted\" \u{1} \u{e9}".into()),
+            ("index", 2u32.into()),
+            ("hresult", (-2147467259i32).into()),
+            ("missing", Option::<u32>::None.into()),
+            ("paths", vec!["a", "b"].into()),
+            ("flag", false.into()),
+        ]);
+        let written = write(&value);
+        assert!(!written.contains('\n'));
+        assert_eq!(
+            written,
+            r#"{"path":"C:\\Users\\ben\\report.txt","text":"line\n\ttab \"quoted\" \u0001 é","index":2,"hresult":-2147467259,"missing":null,"paths":["a","b"],"flag":false}"#
+        );
+        assert_eq!(parse(&written), Ok(value));
+    }
+}
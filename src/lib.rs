@@ -1,38 +1,33 @@
 //! This crate provides safe methods for using Open and Save dialog boxes on Windows.
+//!
+//! The crate builds on every platform, so that it can be used in cross-platform workspaces. The
+//! native dialogs are provided by the `native-windows` feature, which is enabled by default. On
+//! other platforms, or without the feature, [`open_dialog`] and [`save_dialog`] return
+//! [`DialogError::Unsupported`] unless the dialog is answered through the environment or replayed
+//! from a recording.
+//!
+//! [`open_dialog`]: fn.open_dialog.html
+//! [`save_dialog`]: fn.save_dialog.html
+//! [`DialogError::Unsupported`]: enum.DialogError.html#variant.Unsupported
 extern crate libc;
+#[cfg(windows)]
 extern crate winapi;
 
-use crate::winapi::Interface;
-
-use std::ffi::OsString;
-use std::os::windows::ffi::OsStringExt;
-use std::path::PathBuf;
-use std::ptr::null_mut;
-use std::slice;
-
-use libc::wcslen;
-use winapi::{
-    ctypes::c_void,
-    shared::{
-        minwindef::LPVOID,
-        ntdef::LPWSTR,
-        winerror::{HRESULT, SUCCEEDED},
-    },
-    um::{
-        combaseapi::{CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL},
-        objbase::{COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE},
-        shobjidl::{IFileDialog, IFileOpenDialog, IFileSaveDialog, IShellItemArray},
-        shobjidl_core::{
-            CLSID_FileOpenDialog, CLSID_FileSaveDialog, IShellItem, SHCreateItemFromParsingName,
-            SFGAOF, SIGDN_FILESYSPATH,
-        },
-        shtypes::COMDLG_FILTERSPEC,
-    },
-};
+use crate::backend::{DialogKind, Selection};
+use crate::filters::AggregateFilter;
+use crate::noninteractive::{Answers, EnvBackend};
+
+#[cfg(windows)]
+use std::ffi::OsStr;
+use std::io;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+#[cfg(any(windows, test))]
+use std::time::Instant;
 
 // Re-exports
-pub use winapi::shared::windef::HWND;
-pub use winapi::um::shobjidl::{
+pub use crate::options::{
     FOS_ALLNONSTORAGEITEMS, FOS_ALLOWMULTISELECT, FOS_CREATEPROMPT, FOS_DEFAULTNOMINIMODE,
     FOS_DONTADDTORECENT, FOS_FILEMUSTEXIST, FOS_FORCEFILESYSTEM, FOS_FORCEPREVIEWPANEON,
     FOS_FORCESHOWHIDDEN, FOS_HIDEMRUPLACES, FOS_HIDEPINNEDPLACES, FOS_NOCHANGEDIR,
@@ -40,53 +35,199 @@ pub use winapi::um::shobjidl::{
     FOS_OVERWRITEPROMPT, FOS_PATHMUSTEXIST, FOS_PICKFOLDERS, FOS_SHAREAWARE, FOS_STRICTFILETYPES,
     FOS_SUPPORTSTREAMABLEITEMS,
 };
+#[cfg(windows)]
+pub use winapi::shared::windef::HWND;
+
+/// A handle to the window that owns a dialog. Windows are only used as owners on Windows, where
+/// this is the `HWND` type of `winapi`.
+#[cfg(not(windows))]
+pub type HWND = *mut std::os::raw::c_void;
 
+#[cfg(windows)]
 macro_rules! com {
     ($com_expr:expr, $method_name:expr ) => {
-        com(|| unsafe { $com_expr }, $method_name)
+        $crate::com(|| unsafe { $com_expr }, $method_name)
     };
 }
 
+mod backend;
+mod broker;
+#[cfg(all(windows, feature = "native-windows"))]
+mod com_dialog;
+#[cfg(all(windows, feature = "native-windows"))]
+mod com_events;
+#[cfg(all(windows, feature = "native-windows"))]
+mod com_filter;
+#[cfg(all(windows, feature = "native-windows"))]
+mod com_object;
+#[cfg(all(unix, not(target_os = "macos")))]
+mod dbus;
+pub mod filters;
+mod folder;
+mod item_filter;
+mod json;
+mod locale;
+mod message;
+mod mime;
+mod noninteractive;
+mod options;
+mod overwrite;
+mod path_form;
+mod pick;
+mod recent;
+mod replay;
+mod sandbox;
+mod sanitize;
+mod save_target;
+mod selection;
+mod shortcut;
+mod trace;
+mod uri;
+// On Windows the XML reader is only used by the tests of the shared-mime-info parser
+#[cfg(any(not(windows), test))]
+#[cfg_attr(windows, allow(dead_code))]
+mod xml;
+
+#[cfg(unix)]
+pub use crate::broker::serve_unix_socket;
+pub use crate::broker::{serve_stdio, DialogHandler, NativeDialogs};
+pub use crate::folder::{FolderSpec, KnownFolder};
+pub use crate::item_filter::{FilterItem, ItemFilter, ItemVisibility};
+pub use crate::locale::{
+    current_locale, localized_message, set_locale, set_message_override, Message, MessageOverride,
+};
+pub use crate::message::{
+    message_dialog, MessageButtons, MessageLevel, MessageParams, MessageResponse,
+};
+pub use crate::overwrite::OverwritePolicy;
+pub use crate::path_form::PathForm;
+pub use crate::pick::{
+    pick, pick_file, pick_files, pick_files_and_folders, pick_folder, pick_folders, save_file,
+    PickOptions, PickParams, PickedItem, SelectionKind,
+};
+pub use crate::replay::{set_session_mode, SessionMode};
+pub use crate::sandbox::{current_sandbox, Sandbox};
+pub use crate::sanitize::{sanitize_file_name, CharReplacement, SanitizeOptions};
+pub use crate::save_target::{Backup, SaveTarget};
+pub use crate::selection::{
+    RejectionReason, SelectionPredicate, SelectionRejection, SelectionRules,
+};
+pub use crate::shortcut::ShortcutResolution;
+#[cfg(feature = "tracing")]
+pub use crate::trace::{set_path_redaction, PathRedaction};
+pub use crate::uri::{file_uri_to_path, path_to_file_uri, UriErrorReason, UriStyle};
+
+#[cfg(windows)]
 trait NullTermUTF16 {
     fn as_null_term_utf16(&self) -> Vec<u16>;
+
+    // Converts the value of a `DialogParams` field, failing if the value contains a null
+    // character which would otherwise silently truncate it
+    fn to_null_term_utf16(&self, field: &str) -> Result<Vec<u16>, DialogError> {
+        let wide = self.as_null_term_utf16();
+        if wide[..wide.len() - 1].contains(&0) {
+            Err(DialogError::InteriorNul {
+                field: field.to_string(),
+            })
+        } else {
+            Ok(wide)
+        }
+    }
 }
 
+#[cfg(windows)]
 impl NullTermUTF16 for str {
     fn as_null_term_utf16(&self) -> Vec<u16> {
         self.encode_utf16().chain(Some(0)).collect()
     }
 }
 
-const SFGAO_FILESYSTEM: u32 = 0x4000_0000;
+// Paths are encoded without loss, including unpaired surrogates
+#[cfg(windows)]
+impl NullTermUTF16 for OsStr {
+    fn as_null_term_utf16(&self) -> Vec<u16> {
+        self.encode_wide().chain(Some(0)).collect()
+    }
+}
 
-type FileExtensionFilterPair<'a> = (&'a str, &'a str);
+/// Determines what happens when one of the path fields of [`DialogParams`] (`default_folder`,
+/// `folder` or `save_as_item`) cannot be resolved, for example because a remembered folder has
+/// since been deleted or renamed.
+///
+/// [`DialogParams`]: struct.DialogParams.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InvalidPathBehavior {
+    /// Fail with a [`DialogError::InvalidPath`] error without showing the dialog
+    ///
+    /// [`DialogError::InvalidPath`]: enum.DialogError.html#variant.InvalidPath
+    #[default]
+    Error,
+    /// Use the nearest ancestor directory of the path that can be resolved instead. When used for
+    /// `save_as_item` the ancestor directory becomes the initial folder and the file name of the
+    /// item is pre-populated in the dialog. If no ancestor can be resolved the field is ignored.
+    NearestAncestor,
+    /// Ignore the field as if it had not been set
+    Ignore,
+}
 
 /// The parameters used when displaying a dialog box. All fields are optional and have appropriate
 /// default values
 #[derive(Debug)]
 pub struct DialogParams<'a> {
+    /// Adds the selected files to the recently used documents list of the OS, so that they appear
+    /// in places such as the Start menu jump lists on Windows or the "Recent" location of file
+    /// managers on Linux. Failures to update the list are ignored. Defaults to `false`.
+    pub add_to_recent: bool,
+    /// Adds an "All supported files" filter matching the patterns of all of the `file_types` at
+    /// the given position. The indices in `file_type_index` and in the dialog results continue
+    /// to refer to `file_types`, with an index of 0 referring to the aggregate filter. Defaults to
+    /// no aggregate filter, see [`AggregateFilter`].
+    ///
+    /// [`AggregateFilter`]: filters/enum.AggregateFilter.html
+    pub aggregate_filter: AggregateFilter,
     /// The default file extension to add to the returned file name when a file extension
     /// is not entered. Note that if this is not set no extensions will be present on returned
     /// filenames even when a specific file type filter is selected.
     pub default_extension: &'a str,
-    /// The path to the default folder that the dialog will navigate to on first usage. Subsequent
-    /// usages of the dialog will remember the directory of the last selected file/folder.
-    pub default_folder: &'a str,
+    /// The default folder that the dialog will navigate to on first usage. Subsequent usages of
+    /// the dialog will remember the directory of the last selected file/folder. This can be a
+    /// literal path, a known folder such as the user's Documents or a path template containing
+    /// environment variables, see [`FolderSpec`].
+    ///
+    /// [`FolderSpec`]: enum.FolderSpec.html
+    pub default_folder: FolderSpec<'a>,
     /// The filename to pre-populate in the dialog box
     pub file_name: &'a str,
     /// The label to display to the left of the filename input box in the dialog
     pub file_name_label: &'a str,
-    /// Specifies the (1-based) index of the file type that is selected by default.
+    /// Specifies the (1-based) index of the file type that is selected by default. When an
+    /// `aggregate_filter` is enabled an index of 0 selects the aggregate filter.
     pub file_type_index: u32,
     /// The file types that are displayed in the File Type dropdown box in the dialog. The first
     /// element is the text description, i.e `"Text Files (*.txt)"` and the second element is the
     /// file extension filter pattern, with multiple entries separated by a semi-colon
-    /// i.e `"*.txt;*.log"`
+    /// i.e `"*.txt;*.log"`. Defaults to a single filter matching all files, labelled in the
+    /// current locale, see [`localized_message`].
+    ///
+    /// [`localized_message`]: fn.localized_message.html
     pub file_types: Vec<(&'a str, &'a str)>,
-    /// The path to the folder that is always selected when a dialog is opened, regardless of
-    /// previous user action. This is not recommended for general use, instead `default_folder`
-    /// should be used.
-    pub folder: &'a str,
+    /// The folder that is always selected when a dialog is opened, regardless of previous user
+    /// action. This is not recommended for general use, instead `default_folder` should be used.
+    /// See [`FolderSpec`] for the ways in which the folder can be specified.
+    ///
+    /// [`FolderSpec`]: enum.FolderSpec.html
+    pub folder: FolderSpec<'a>,
+    /// Determines what happens when `default_folder`, `folder` or `save_as_item` cannot be
+    /// resolved. Defaults to failing with [`DialogError::InvalidPath`].
+    ///
+    /// [`DialogError::InvalidPath`]: enum.DialogError.html#variant.InvalidPath
+    pub invalid_path_behavior: InvalidPathBehavior,
+    /// A filter deciding which files and folders the dialog shows. Selected items that the filter
+    /// hides are rejected, as with the `selection_rules`. Defaults to showing all items, see
+    /// [`ItemFilter`].
+    ///
+    /// [`ItemFilter`]: struct.ItemFilter.html
+    pub item_filter: Option<ItemFilter<'a>>,
     /// The text label to replace the default "Open" or "Save" text on the "OK" button of the dialog
     pub ok_button_label: &'a str,
     /// A set of bit flags to apply to the dialog. Setting invalid flags will result in the dialog
@@ -98,13 +239,55 @@ pub struct DialogParams<'a> {
     /// Flags should be a combination of `FOS_*` constants, the documentation for which can be found
     /// [here](https://docs.microsoft.com/en-us/windows/win32/api/shobjidl_core/ne-shobjidl_core-_fileopendialogoptions)
     pub un_options: u32,
+    /// Determines what happens when the file selected in a Save dialog already exists. Defaults to
+    /// the shell asking the user to confirm that the file should be replaced. Not used by Open
+    /// dialogs.
+    pub overwrite_policy: OverwritePolicy,
+    /// The form in which the selected paths are returned, for example to consistently receive
+    /// paths without a verbatim `\\?\` prefix. Defaults to returning paths exactly as provided
+    /// by the dialog.
+    pub path_form: PathForm,
+    /// Resolves the selected Windows shortcut (`.lnk`) files to the items that they refer to,
+    /// either replacing them in the result or returning their targets alongside them. Defaults to
+    /// returning shortcuts as they were selected, see [`ShortcutResolution`].
+    ///
+    /// [`ShortcutResolution`]: enum.ShortcutResolution.html
+    pub resolve_shortcuts: ShortcutResolution,
     /// The HWND of the window that the dialog will be owned by. If not provided the dialog will be
     /// an independent top-level window.
     pub owner: Option<HWND>,
+    /// Rules that the paths selected in an Open dialog must satisfy, such as the number of
+    /// selected items or the maximum size of each file. Not used by Save dialogs.
+    pub selection_rules: SelectionRules<'a>,
+    /// Requires that the file selected in a Save dialog can be written, for example by refusing a
+    /// file in a read-only folder. The file is checked when the OK button is clicked, and the
+    /// dialog stays open after telling the user why the file cannot be written. Not used by Open
+    /// dialogs.
+    pub require_writable: bool,
     /// The path to the existing file to use when opening a Save As dialog. Acts as a combination of
     /// `folder` and `file_name`, displaying the file name in the edit box, and selecting the
-    /// containing folder as the initial folder in the dialog.
-    pub save_as_item: &'a str,
+    /// containing folder as the initial folder in the dialog. Paths that aren't valid Unicode are
+    /// passed to the dialog without loss, and `file://` URIs are converted into paths.
+    pub save_as_item: &'a Path,
+    /// Makes the `file_name` displayed in the dialog, and the name of the file selected in a Save
+    /// dialog, valid on Windows and other platforms, for example when the `file_name` is made
+    /// from the title of a document. Only the name of the selected file is changed, as its
+    /// folders already exist. Defaults to using the names unchanged, see [`sanitize_file_name`].
+    /// The shell's overwrite prompt only asks about the name that was entered, so when a Save
+    /// dialog prompts before overwriting, a sanitized name that belongs to an existing file is
+    /// refused and the dialog shown again.
+    ///
+    /// [`sanitize_file_name`]: fn.sanitize_file_name.html
+    pub sanitize_file_name: Option<SanitizeOptions>,
+    /// Allows files and folders to be selected together in an Open dialog. Clicking the OK button
+    /// returns the selected items instead of opening a selected folder, which double-clicking it
+    /// still does. If no items are selected the current folder is returned, for which the file
+    /// name box holds `.` while no files are selected. Requires `FOS_ALLOWMULTISELECT` to select more than one item
+    /// and cannot be combined with `FOS_PICKFOLDERS`, see [`SelectionKind::FilesAndFolders`].
+    /// Not used by Save dialogs.
+    ///
+    /// [`SelectionKind::FilesAndFolders`]: enum.SelectionKind.html#variant.FilesAndFolders
+    pub select_files_and_folders: bool,
     /// The text displayed in the title bar of the dialog box
     pub title: &'a str,
 }
@@ -112,18 +295,29 @@ pub struct DialogParams<'a> {
 impl<'a> Default for DialogParams<'a> {
     fn default() -> Self {
         DialogParams {
+            add_to_recent: false,
+            aggregate_filter: AggregateFilter::Disabled,
             default_extension: "",
-            default_folder: "",
+            default_folder: FolderSpec::default(),
             file_name: "",
             file_name_label: "",
             file_type_index: 1,
-            file_types: vec![("All types (*.*)", "*.*")],
-            folder: "",
+            file_types: vec![(locale::static_message(Message::AllFilesFilter), "*.*")],
+            folder: FolderSpec::default(),
+            invalid_path_behavior: InvalidPathBehavior::Error,
+            item_filter: None,
             ok_button_label: "",
             options: 0,
             un_options: 0,
+            overwrite_policy: OverwritePolicy::ShellPrompt,
+            path_form: PathForm::Unchanged,
             owner: None,
-            save_as_item: "",
+            require_writable: false,
+            resolve_shortcuts: ShortcutResolution::Off,
+            sanitize_file_name: None,
+            save_as_item: Path::new(""),
+            select_files_and_folders: false,
+            selection_rules: SelectionRules::default(),
             title: "",
         }
     }
@@ -139,8 +333,41 @@ pub struct OpenDialogResult {
     /// The file paths that the user selected. Will only ever contain a single file path if
     /// `FOS_ALLOWMULTISELECT` is not enabled.
     pub selected_file_paths: Vec<PathBuf>,
-    /// The 1-based index of the file type that was selected in the File Type dropdown
+    /// The 1-based index of the file type that was selected in the File Type dropdown, or 0 if
+    /// the `aggregate_filter` was selected
     pub selected_file_type_index: u32,
+    /// The application sandbox that the process was running in when the dialog was shown
+    pub sandbox: Sandbox,
+    /// The path of each selected file on the host, in the same order as `selected_file_paths`.
+    /// When sandboxed, files outside the sandbox are returned through the document store of the
+    /// document portal, and this contains the paths that the portal resolves them to. Paths that
+    /// are not in the document store, or cannot be resolved, are the same as the selected path.
+    pub selected_host_paths: Vec<PathBuf>,
+    /// The target of each selected path when `resolve_shortcuts` is
+    /// [`ShortcutResolution::Annotate`], in the same order as `selected_file_paths`. Paths that
+    /// are not shortcuts, or whose target cannot be read, have no target, and shortcuts whose
+    /// target does not exist have the first path recorded in them. Empty when shortcuts are not
+    /// annotated.
+    ///
+    /// [`ShortcutResolution::Annotate`]: enum.ShortcutResolution.html#variant.Annotate
+    pub shortcut_targets: Vec<Option<PathBuf>>,
+}
+
+impl OpenDialogResult {
+    /// Returns the selected file paths as `file://` URIs, using the conventions of the platform
+    ///
+    /// # Errors
+    /// If a path cannot be represented as a URI the [`UriConversion`] error is returned, see
+    /// [`path_to_file_uri`]
+    ///
+    /// [`UriConversion`]: enum.DialogError.html#variant.UriConversion
+    /// [`path_to_file_uri`]: fn.path_to_file_uri.html
+    pub fn selected_file_uris(&self) -> Result<Vec<String>, DialogError> {
+        self.selected_file_paths
+            .iter()
+            .map(|path| path_to_file_uri(path, UriStyle::Native))
+            .collect()
+    }
 }
 
 /// The result of a Save Dialog after the user has selected a file
@@ -148,8 +375,43 @@ pub struct OpenDialogResult {
 pub struct SaveDialogResult {
     /// The file path that the user selected
     pub selected_file_path: PathBuf,
-    /// The 1-based index of the file type that was selected in the File Type dropdown
+    /// The 1-based index of the file type that was selected in the File Type dropdown, or 0 if
+    /// the `aggregate_filter` was selected
     pub selected_filter_index: u32,
+    /// The application sandbox that the process was running in when the dialog was shown
+    pub sandbox: Sandbox,
+    /// The path of the selected file on the host. See
+    /// [`OpenDialogResult::selected_host_paths`] for how it is resolved.
+    ///
+    /// [`OpenDialogResult::selected_host_paths`]: struct.OpenDialogResult.html#structfield.selected_host_paths
+    pub selected_host_path: PathBuf,
+    /// The target of the selected path when it is a shortcut and `resolve_shortcuts` is
+    /// [`ShortcutResolution::Annotate`]
+    ///
+    /// [`ShortcutResolution::Annotate`]: enum.ShortcutResolution.html#variant.Annotate
+    pub shortcut_target: Option<PathBuf>,
+}
+
+impl SaveDialogResult {
+    /// Returns a [`SaveTarget`] for the selected file, which can be used to write the file
+    /// atomically and optionally keep a backup of its previous contents
+    ///
+    /// [`SaveTarget`]: struct.SaveTarget.html
+    pub fn save_target(&self) -> SaveTarget {
+        SaveTarget::new(self.selected_file_path.clone())
+    }
+
+    /// Returns the selected file path as a `file://` URI, using the conventions of the platform
+    ///
+    /// # Errors
+    /// If the path cannot be represented as a URI the [`UriConversion`] error is returned, see
+    /// [`path_to_file_uri`]
+    ///
+    /// [`UriConversion`]: enum.DialogError.html#variant.UriConversion
+    /// [`path_to_file_uri`]: fn.path_to_file_uri.html
+    pub fn selected_file_uri(&self) -> Result<String, DialogError> {
+        path_to_file_uri(&self.selected_file_path, UriStyle::Native)
+    }
 }
 
 /// Error returned when showing a dialog fails
@@ -169,17 +431,91 @@ pub enum DialogError {
         /// The HRESULT error code
         hresult: i32,
     },
+    /// One of the path fields of [`DialogParams`] (`default_folder`, `folder` or `save_as_item`)
+    /// could not be resolved, usually because the path does not exist. This error is only returned
+    /// when `invalid_path_behavior` is set to [`InvalidPathBehavior::Error`].
+    ///
+    /// [`DialogParams`]: struct.DialogParams.html
+    /// [`InvalidPathBehavior::Error`]: enum.InvalidPathBehavior.html#variant.Error
+    InvalidPath {
+        /// The name of the `DialogParams` field containing the path, i.e. `"default_folder"`
+        field: String,
+        /// The path that could not be resolved
+        path: String,
+        /// The error that occurred when resolving the path
+        cause: Box<DialogError>,
+    },
+    /// The items selected in an Open dialog did not satisfy the `selection_rules` of the
+    /// [`DialogParams`], or a selected item is hidden by its `item_filter`. Also returned when
+    /// `WFD_SELECT` provides several paths to a dialog that can only select one. Contains the
+    /// reason for each rule that was not satisfied.
+    ///
+    /// [`DialogParams`]: struct.DialogParams.html
+    SelectionRejected {
+        /// The reasons that the selection was rejected
+        rejections: Vec<SelectionRejection>,
+    },
+    /// A file system operation performed on a selected path failed, such as reserving a new file
+    /// name for [`OverwritePolicy::AutoRename`]
+    ///
+    /// [`OverwritePolicy::AutoRename`]: enum.OverwritePolicy.html#variant.AutoRename
+    Io {
+        /// The path that the operation was performed on
+        path: PathBuf,
+        /// The underlying I/O error
+        error: io::Error,
+    },
+    /// The dialog could not be shown because no user can answer it, and no answer was provided
+    /// through the environment. Dialogs are non-interactive when `WFD_NONINTERACTIVE` is set, or
+    /// when there is no display to show them on, such as in CI jobs or services. On Windows this
+    /// is the case in session 0, where services run, and in non-interactive window stations.
+    /// Answers can be provided by setting `WFD_SELECT` to the paths to select, separated in the
+    /// same way as the `PATH` of the platform, and `WFD_FILTER_INDEX` to the index of the
+    /// selected file type. More than one path can only be selected in an Open dialog with
+    /// `FOS_ALLOWMULTISELECT`, otherwise a [`SelectionRejected`] error is returned.
+    ///
+    /// [`SelectionRejected`]: enum.DialogError.html#variant.SelectionRejected
+    NonInteractive,
+    /// A text or path field of the [`DialogParams`] or [`MessageParams`] contains a null
+    /// character, which cannot be passed to the dialog without truncating the value
+    ///
+    /// [`DialogParams`]: struct.DialogParams.html
+    /// [`MessageParams`]: struct.MessageParams.html
+    InteriorNul {
+        /// The name of the field containing the null character, i.e. `"title"`
+        field: String,
+    },
+    /// A path could not be converted into a `file://` URI, or a URI into a path, see
+    /// [`path_to_file_uri`] and [`file_uri_to_path`]
+    ///
+    /// [`path_to_file_uri`]: fn.path_to_file_uri.html
+    /// [`file_uri_to_path`]: fn.file_uri_to_path.html
+    UriConversion {
+        /// The path or URI that could not be converted
+        input: String,
+        /// The reason that the conversion failed
+        reason: UriErrorReason,
+    },
+    /// The dialog cannot be shown because there is no native implementation for the platform, or
+    /// because wfd was built without the `native-windows` feature. Dialogs can still be answered
+    /// through the environment, see [`NonInteractive`].
+    ///
+    /// [`NonInteractive`]: enum.DialogError.html#variant.NonInteractive
+    Unsupported {
+        /// A description of why the dialog is not supported
+        reason: String,
+    },
 }
 
 /// Displays an Open Dialog using the provided parameters.
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// // An entirely default Open File dialog box with no customization
 /// let result = wfd::open_dialog(Default::default());
 /// ```
-/// ```
+/// ```no_run
 /// // A folder-picker Open dialog box with a custom dialog title
 /// # use std::io;
 /// # fn main() -> Result<(), wfd::DialogError> {
@@ -193,7 +529,7 @@ pub enum DialogError {
 /// #    Ok(())
 /// # }
 /// ```
-/// ```
+/// ```no_run
 /// // An Open dialog box with a custom dialog title and file types
 /// # use std::io;
 /// # fn main() -> Result<(), wfd::DialogError> {
@@ -209,105 +545,113 @@ pub enum DialogError {
 /// ```
 ///
 /// # Errors
-/// If a user cancels the dialog, the [`UserCancelled`] error is returned. The only other kinds of
-/// errors that can be retured are COM [`HRESULT`] failure codes - usually as the result of invalid
-/// combinations of options. These are returned in a [`HResultFailed`] error
+/// If a user cancels the dialog, the [`UserCancelled`] error is returned. If the selected items do
+/// not satisfy the `selection_rules` of the params or are hidden by its `item_filter`, the
+/// [`SelectionRejected`] error is returned.
+/// The only other kinds of errors that can be retured are COM [`HRESULT`] failure codes - usually
+/// as the result of invalid combinations of options. These are returned in a [`HResultFailed`]
+/// error
 ///
-/// [`UserCancelled`]: enum.FileDialogError.html#variant.UserCancelled
-/// [`HResultFailed`]: enum.FileDialogError.html#variant.HResultFailed
+/// When recording dialogs, a failure to write the recording is returned in an [`Io`] error. If no
+/// user can answer the dialog and no answer is provided through the environment, the
+/// [`NonInteractive`] error is returned instead of showing the dialog. On platforms without a
+/// native dialog the [`Unsupported`] error is returned.
+///
+/// # Panics
+/// Panics when replaying a recording that the dialog diverges from, see [`set_session_mode`]
+///
+/// [`UserCancelled`]: enum.DialogError.html#variant.UserCancelled
+/// [`SelectionRejected`]: enum.DialogError.html#variant.SelectionRejected
+/// [`HResultFailed`]: enum.DialogError.html#variant.HResultFailed
 /// [`HRESULT`]: https://en.wikipedia.org/wiki/HRESULT
+/// [`Io`]: enum.DialogError.html#variant.Io
+/// [`NonInteractive`]: enum.DialogError.html#variant.NonInteractive
+/// [`Unsupported`]: enum.DialogError.html#variant.Unsupported
+/// [`set_session_mode`]: fn.set_session_mode.html
 pub fn open_dialog(params: DialogParams) -> Result<OpenDialogResult, DialogError> {
-    // Initialize COM
-    com!(
-        CoInitializeEx(
-            null_mut(),
-            COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE,
-        ),
-        "CoInitializeEx"
-    )?;
-
-    // Create IFileOpenDialog instance
-    let mut file_open_dialog: *mut IFileOpenDialog = null_mut();
-    com!(
-        CoCreateInstance(
-            &CLSID_FileOpenDialog,
-            null_mut(),
-            CLSCTX_ALL,
-            &IFileOpenDialog::uuidof(),
-            &mut file_open_dialog as *mut *mut IFileOpenDialog as *mut *mut c_void,
-        ),
-        "CoCreateInstance - IFileOpenDialog"
-    )?;
-    let file_open_dialog = unsafe { &*file_open_dialog };
-
-    // Perform non open-specific dialog configuration
-    configure_file_dialog(file_open_dialog, &params)?;
-
-    show_dialog(file_open_dialog, params.owner)?;
-
-    // Get the item(s) that the user selected in the dialog
-    // IFileOpenDialog::GetResults
-    let mut shell_item_array: *mut IShellItemArray = null_mut();
-    com!(
-        file_open_dialog.GetResults(&mut shell_item_array),
-        "IFileOpenDialog::GetResults"
-    )?;
-
-    let shell_item_array = unsafe { &*shell_item_array };
-
-    // IShellItemArray::GetCount
-    let mut item_count: u32 = 0;
-    com!(
-        shell_item_array.GetCount(&mut item_count),
-        "IShellItemArray::GetCount"
-    )?;
-
-    let mut file_paths: Vec<PathBuf> = vec![];
-    for i in 0..item_count {
-        // IShellItemArray::GetItemAt
-        let mut shell_item: *mut IShellItem = null_mut();
-        com!(
-            shell_item_array.GetItemAt(i, &mut shell_item),
-            "IShellItemArray::GetItemAt"
-        )?;
-        let shell_item = unsafe { &*shell_item };
-
-        // Fetch the SFGAO_FILESYSTEM attribute for the file
-        let mut attribs: SFGAOF = 0;
-        // IShellItem::GetAttributes
-        com!(
-            shell_item.GetAttributes(SFGAO_FILESYSTEM, &mut attribs),
-            "IShellItem::GetAttributes"
-        )?;
-
-        // Ignore shell items that do not have the SFGAO_FILESYSTEM attribute
-        // which indicates that they represent a valid path to a file or folder
-        if attribs & SFGAO_FILESYSTEM == 0 {
-            continue;
-        }
+    replay::open_dialog(&params, || run_open_dialog(&params))
+}
 
-        let file_name = get_shell_item_display_name(shell_item)?;
-        file_paths.push(PathBuf::from(file_name));
+fn run_open_dialog(params: &DialogParams) -> Result<OpenDialogResult, DialogError> {
+    let noninteractive = noninteractive::is_enabled();
+    loop {
+        let result = show_open_dialog(params, noninteractive)?;
 
-        // Free non-owned allocation
-        unsafe { shell_item.Release() };
+        let mut rejections = params.selection_rules.evaluate(&result.selected_file_paths);
+        if let Some(item_filter) = &params.item_filter {
+            rejections.extend(item_filter.evaluate(&result.selected_file_paths));
+        }
+        if rejections.is_empty() {
+            if params.add_to_recent {
+                recent::add_to_recent(&result.selected_file_paths);
+            }
+            return Ok(result);
+        } else if !params.selection_rules.reshow_on_rejection || noninteractive {
+            // Non-interactive answers would be the same if the dialog was shown again
+            return Err(DialogError::SelectionRejected { rejections });
+        }
+        report_rejection(params, &rejections);
     }
+}
 
-    // IFileDialog::GetFileTypeIndex
-    let selected_filter_index = get_file_type_index(file_open_dialog)?;
+// Tells the user why their selection was not accepted before the dialog is shown again, so that
+// the dialog does not appear to ignore the OK button
+fn report_rejection<T: ToString>(params: &DialogParams, reasons: &[T]) {
+    let text = reasons
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    show_rejection(params.owner, params.title, &text);
+}
 
-    // Un-initialize COM
-    unsafe {
-        CoUninitialize();
-    }
+// Shows why a selection was not accepted in a warning owned by the given window. A message that
+// cannot be shown is skipped.
+pub(crate) fn show_rejection(owner: Option<HWND>, title: &str, text: &str) {
+    let _ = message_dialog(MessageParams {
+        level: MessageLevel::Warning,
+        owner,
+        text,
+        title,
+        ..Default::default()
+    });
+}
 
+// Describes why the file selected in a Save dialog cannot be written
+pub(crate) fn unwritable_message(path: &Path, error: &io::Error) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    locale::format_message(
+        &localized_message(Message::FileNotWritable),
+        &[("name", &name), ("error", error)],
+    )
+}
+
+fn show_open_dialog(
+    params: &DialogParams,
+    noninteractive: bool,
+) -> Result<OpenDialogResult, DialogError> {
+    let Selection {
+        paths: file_paths,
+        file_type_index,
+    } = if noninteractive {
+        let mut backend = EnvBackend::new(Answers::from_env());
+        backend::run(&mut backend, DialogKind::Open, params)?
+    } else {
+        run_native_dialog(DialogKind::Open, params)?
+    };
+
+    let (file_paths, shortcut_targets) = params.resolve_shortcuts.apply(file_paths);
+    let selected_host_paths = sandbox::host_paths(&file_paths);
     file_paths
         .first()
         .cloned()
         .map(|x| OpenDialogResult {
             selected_file_path: x,
             selected_file_paths: file_paths,
-            selected_file_type_index: selected_filter_index,
+            selected_file_type_index: file_type_index,
+            sandbox: current_sandbox(),
+            selected_host_paths,
+            shortcut_targets,
         })
         .ok_or(DialogError::UnsupportedFilepath)
 }
@@ -316,14 +660,14 @@ pub fn open_dialog(params: DialogParams) -> Result<OpenDialogResult, DialogError
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// # fn main() -> Result<(), wfd::DialogError> {
 /// // An entirely default Save File dialog box with no customization
 /// let result = wfd::save_dialog(Default::default())?;
 /// #    Ok(())
 /// # }
 /// ```
-/// ```
+/// ```no_run
 /// # fn main() -> Result<(), wfd::DialogError> {
 /// // A Save File dialog box with a custom dialog title and file types///
 /// let params = wfd::DialogParams {
@@ -337,307 +681,200 @@ pub fn open_dialog(params: DialogParams) -> Result<OpenDialogResult, DialogError
 /// ```
 ///
 /// # Errors
-/// If a user cancels the dialog, the [`UserCancelled`] error is returned. The only other kinds of
-/// errors that can be retured are COM [`HRESULT`] failure codes - usually as the result of invalid
-/// combinations of options. These are returned in a [`HResultFailed`] error
+/// If a user cancels the dialog, the [`UserCancelled`] error is returned. If the `overwrite_policy`
+/// is [`OverwritePolicy::AutoRename`] and a new file name cannot be reserved, the [`Io`] error is
+/// returned. The only other kinds of errors that can be retured are COM [`HRESULT`] failure codes -
+/// usually as the result of invalid combinations of options. These are returned in a
+/// [`HResultFailed`] error. When recording dialogs, a failure to write the recording is also
+/// returned in an [`Io`] error. If no user can answer the dialog and no answer is provided through
+/// the environment, the [`NonInteractive`] error is returned instead of showing the dialog, a
+/// selected file that is hidden by the `item_filter` is returned in a [`SelectionRejected`] error
+/// rather than showing the dialog again, and so is an existing file that is refused by the
+/// `overwrite_policy`, in an [`Io`] error. On platforms without a native dialog the
+/// [`Unsupported`] error is returned.
 ///
-/// [`UserCancelled`]: enum.FileDialogError.html#variant.UserCancelled
-/// [`HResultFailed`]: enum.FileDialogError.html#variant.HResultFailed
+/// # Panics
+/// Panics when replaying a recording that the dialog diverges from, see [`set_session_mode`]
+///
+/// [`UserCancelled`]: enum.DialogError.html#variant.UserCancelled
+/// [`SelectionRejected`]: enum.DialogError.html#variant.SelectionRejected
+/// [`OverwritePolicy::AutoRename`]: enum.OverwritePolicy.html#variant.AutoRename
+/// [`Io`]: enum.DialogError.html#variant.Io
+/// [`NonInteractive`]: enum.DialogError.html#variant.NonInteractive
+/// [`Unsupported`]: enum.DialogError.html#variant.Unsupported
+/// [`HResultFailed`]: enum.DialogError.html#variant.HResultFailed
 /// [`HRESULT`]: https://en.wikipedia.org/wiki/HRESULT
+/// [`set_session_mode`]: fn.set_session_mode.html
 pub fn save_dialog(params: DialogParams) -> Result<SaveDialogResult, DialogError> {
-    // Initialize COM
-    com!(
-        CoInitializeEx(
-            null_mut(),
-            COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE,
-        ),
-        "CoInitializeEx"
-    )?;
-
-    // Create IFileSaveDialog instance
-    let mut file_save_dialog: *mut IFileSaveDialog;
-    file_save_dialog = null_mut();
-    com!(
-        CoCreateInstance(
-            &CLSID_FileSaveDialog,
-            null_mut(),
-            CLSCTX_ALL,
-            &IFileSaveDialog::uuidof(),
-            &mut file_save_dialog as *mut *mut IFileSaveDialog as *mut *mut c_void,
-        ),
-        "CoCreateInstance - FileSaveDialog"
-    )?;
-    let file_save_dialog = unsafe { &*file_save_dialog };
-
-    // IFileDialog::SetSaveAsItem
-    if !params.save_as_item.is_empty() {
-        let mut item: *mut IShellItem = null_mut();
-        let path = params.save_as_item.as_null_term_utf16();
-        com!(
-            SHCreateItemFromParsingName(
-                path.as_ptr(),
-                null_mut(),
-                &IShellItem::uuidof(),
-                &mut item as *mut *mut IShellItem as *mut *mut c_void
-            ),
-            "SHCreateItemFromParsingName"
-        )?;
-        com!(
-            file_save_dialog.SetSaveAsItem(item),
-            "IFileDialog::SetSaveAsItem"
-        )?;
-        unsafe {
-            let item = &*item;
-            item.Release();
-        }
-    }
-
-    // Perform non save-specific dialog configuration
-    configure_file_dialog(file_save_dialog, &params)?;
-
-    show_dialog(file_save_dialog, params.owner)?;
-
-    // IFileDialog::GetResult
-    let mut shell_item: *mut IShellItem = null_mut();
-    com!(
-        file_save_dialog.GetResult(&mut shell_item),
-        "IFileDialog::GetResult"
-    )?;
-    let shell_item = unsafe { &*shell_item };
-    let file_name = get_shell_item_display_name(shell_item)?;
-    unsafe { shell_item.Release() };
-
-    // IFileDialog::GetFileTypeIndex
-    let selected_filter_index = get_file_type_index(file_save_dialog)?;
-
-    // Un-initialize COM
-    unsafe {
-        CoUninitialize();
-    }
-
-    let result = SaveDialogResult {
-        selected_filter_index,
-        selected_file_path: PathBuf::from(file_name),
-    };
-
-    Ok(result)
+    replay::save_dialog(&params, || run_save_dialog(&params))
 }
 
-#[allow(overflowing_literals)]
-#[allow(unused_comparisons)]
-fn show_dialog(file_dialog: &IFileDialog, owner: Option<HWND>) -> Result<(), DialogError> {
-    let owner_hwnd = owner.unwrap_or(null_mut());
-
-    // IModalWindow::Show
-    let result = com!(file_dialog.Show(owner_hwnd), "IModalWindow::Show");
-
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => match e {
-            DialogError::HResultFailed { hresult, .. } => {
-                if hresult == 0x8007_04C7 {
-                    Err(DialogError::UserCancelled)
-                } else {
-                    Err(e)
-                }
-            }
-            _ => Err(e),
-        },
-    }
+fn run_save_dialog(params: &DialogParams) -> Result<SaveDialogResult, DialogError> {
+    let noninteractive = noninteractive::is_enabled();
+    select_save_path(params, noninteractive, || {
+        if noninteractive {
+            let mut backend = EnvBackend::new(Answers::from_env());
+            backend::run(&mut backend, DialogKind::Save, params)
+        } else {
+            run_native_dialog(DialogKind::Save, params)
+        }
+    })
 }
 
-fn configure_file_dialog(
-    file_dialog: &IFileDialog,
+// Shows a Save dialog with `show` until the selected file is accepted
+fn select_save_path<F>(
     params: &DialogParams,
-) -> Result<(), DialogError> {
-    // IFileDialog::SetDefaultExtension
-    if !params.default_extension.is_empty() {
-        let default_extension = params.default_extension.as_null_term_utf16();
-        com!(
-            file_dialog.SetDefaultExtension(default_extension.as_ptr()),
-            "IFileDialog::SetDefaultExtension"
-        )?;
-    }
-
-    // IFileDialog::SetDefaultFolder
-    if !params.default_folder.is_empty() {
-        let mut default_folder: *mut IShellItem = null_mut();
-        let path = params.default_folder.as_null_term_utf16();
-        com!(
-            SHCreateItemFromParsingName(
-                path.as_ptr(),
-                null_mut(),
-                &IShellItem::uuidof(),
-                &mut default_folder as *mut *mut IShellItem as *mut *mut c_void
-            ),
-            "SHCreateItemFromParsingName"
-        )?;
-        com!(
-            file_dialog.SetDefaultFolder(default_folder),
-            "IFileDialog::SetDefaultFolder"
-        )?;
-        unsafe {
-            let default_folder = &*default_folder;
-            default_folder.Release();
+    noninteractive: bool,
+    mut show: F,
+) -> Result<SaveDialogResult, DialogError>
+where
+    F: FnMut() -> Result<Selection, DialogError>,
+{
+    let result = loop {
+        let (mut result, sanitized) = save_result(params, show()?)?;
+
+        // Non-interactive answers would be the same if the dialog was shown again, so the reason
+        // that the dialog would be shown again is returned instead
+        let path = &result.selected_file_path;
+        if let Some(item_filter) = &params.item_filter {
+            let rejections = item_filter.evaluate(std::slice::from_ref(path));
+            if !rejections.is_empty() {
+                if noninteractive {
+                    return Err(DialogError::SelectionRejected { rejections });
+                }
+                report_rejection(params, &rejections);
+                continue;
+            }
         }
-    }
 
-    // IFileDialog::SetFolder
-    if !params.folder.is_empty() {
-        let mut folder: *mut IShellItem = null_mut();
-        let path = params.folder.as_null_term_utf16();
-        com!(
-            SHCreateItemFromParsingName(
-                path.as_ptr(),
-                null_mut(),
-                &IShellItem::uuidof(),
-                &mut folder as *mut *mut IShellItem as *mut *mut c_void
-            ),
-            "SHCreateItemFromParsingName"
-        )?;
-        com!(file_dialog.SetFolder(folder), "IFileDialog::SetFolder")?;
-        unsafe {
-            let folder = &*folder;
-            folder.Release();
+        if params.require_writable {
+            if let Err(error) = result.save_target().check_writable() {
+                if noninteractive {
+                    return Err(DialogError::Io {
+                        path: path.clone(),
+                        error,
+                    });
+                }
+                // The native dialog checks the file before it closes, but the sanitized name or
+                // the folder may have changed since
+                report_rejection(params, &[unwritable_message(path, &error)]);
+                continue;
+            }
         }
-    }
-
-    // IFileDialog::SetFileName
-    if !params.file_name.is_empty() {
-        let initial_file_name = params.file_name.as_null_term_utf16();
-        com!(
-            file_dialog.SetFileName(initial_file_name.as_ptr()),
-            "IFileDialog::SetFileName"
-        )?;
-    }
-
-    // IFileDialog::SetFileNameLabel
-    if !params.file_name_label.is_empty() {
-        let file_name_label = params.file_name_label.as_null_term_utf16();
-        com!(
-            file_dialog.SetFileNameLabel(file_name_label.as_ptr()),
-            "IFileDialog::SetFileNameLabel"
-        )?;
-    }
-
-    if !params.file_types.is_empty() {
-        add_filters(file_dialog, &params.file_types)?;
-    }
-
-    // IFileDialog::SetFileTypeIndex
-    if !params.file_types.is_empty() && params.file_type_index > 0 {
-        com!(
-            file_dialog.SetFileTypeIndex(params.file_type_index),
-            "IFileDialog::SetFileTypeIndex"
-        )?;
-    }
-
-    // IFileDialog::SetOkButtonLabel
-    if !params.ok_button_label.is_empty() {
-        let ok_button_label = params.ok_button_label.as_null_term_utf16();
-        com!(
-            file_dialog.SetOkButtonLabel(ok_button_label.as_ptr()),
-            "IFileDialog::SetOkButtonLabel"
-        )?;
-    }
 
-    if params.options > 0 || params.un_options > 0 {
-        // IFileDialog::GetOptions
-        let mut existing_options: u32 = 0;
-        com!(
-            file_dialog.GetOptions(&mut existing_options),
-            "IFileDialog::GetOptions"
-        )?;
-
-        existing_options &= !params.un_options;
-        existing_options |= params.options;
-
-        // IFileDialog::SetOptions
-        com!(
-            file_dialog.SetOptions(existing_options),
-            "IFileDialog::SetOptions"
-        )?;
-    }
+        // The shell's overwrite prompt only asked about the name before it was sanitized, so a
+        // sanitized name that exists is refused rather than overwritten without asking
+        let refuse_existing = match params.overwrite_policy {
+            OverwritePolicy::Refuse => true,
+            OverwritePolicy::ShellPrompt => {
+                sanitized && params.un_options & FOS_OVERWRITEPROMPT == 0
+            }
+            OverwritePolicy::Allow | OverwritePolicy::AutoRename => false,
+        };
+        match params.overwrite_policy {
+            _ if refuse_existing && path.exists() && noninteractive => {
+                return Err(DialogError::Io {
+                    path: path.clone(),
+                    error: io::Error::new(io::ErrorKind::AlreadyExists, "the file already exists"),
+                })
+            }
+            _ if refuse_existing && path.exists() => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let text = locale::format_message(
+                    &localized_message(Message::FileAlreadyExists),
+                    &[("name", &name)],
+                );
+                report_rejection(params, &[text]);
+                continue;
+            }
+            OverwritePolicy::AutoRename if path.exists() => {
+                result.selected_file_path =
+                    overwrite::reserve_available_path(path).map_err(|error| DialogError::Io {
+                        path: path.clone(),
+                        error,
+                    })?;
+                break result;
+            }
+            _ => break result,
+        }
+    };
 
-    // IFileDialog::SetTitle
-    if !params.title.is_empty() {
-        let title = params.title.as_null_term_utf16();
-        com!(
-            file_dialog.SetTitle(title.as_ptr()),
-            "IFileDialog::SetTitle"
-        )?;
+    if params.add_to_recent {
+        recent::add_to_recent(std::slice::from_ref(&result.selected_file_path));
     }
-
-    Ok(())
+    Ok(result)
 }
 
-fn add_filters(
-    dialog: &IFileDialog,
-    filters: &[FileExtensionFilterPair],
-) -> Result<(), DialogError> {
-    // Create a vec holding the UTF-16 string pairs for the filter - we need
-    // to have these in a vec since we need to be able to pass a pointer to them
-    // in the COMDLG_FILTERSPEC structs passed to SetFileTypes.
-    let temp_filters = filters
-        .iter()
-        .map(|filter| {
-            let name = filter.0.as_null_term_utf16();
-            let pattern = filter.1.as_null_term_utf16();
-            (name, pattern)
-        })
-        .collect::<Vec<(Vec<u16>, Vec<u16>)>>();
-
-    let filter_specs = temp_filters
-        .iter()
-        .map(|x| COMDLG_FILTERSPEC {
-            pszName: x.0.as_ptr(),
-            pszSpec: x.1.as_ptr(),
+// Returns the result of a Save dialog from its selection, and whether sanitizing changed the name
+// of the selected file
+fn save_result(
+    params: &DialogParams,
+    selection: Selection,
+) -> Result<(SaveDialogResult, bool), DialogError> {
+    let Selection {
+        paths,
+        file_type_index,
+    } = selection;
+
+    let mut sanitized = false;
+    let paths = match params.sanitize_file_name {
+        Some(options) => paths
+            .into_iter()
+            .map(|path| {
+                let sanitized_path = options.apply(path.clone());
+                sanitized |= sanitized_path != path;
+                sanitized_path
+            })
+            .collect(),
+        None => paths,
+    };
+    let (paths, shortcut_targets) = params.resolve_shortcuts.apply(paths);
+    paths
+        .into_iter()
+        .next()
+        .map(|x| SaveDialogResult {
+            selected_host_path: sandbox::host_paths(std::slice::from_ref(&x)).remove(0),
+            selected_file_path: x,
+            selected_filter_index: file_type_index,
+            sandbox: current_sandbox(),
+            shortcut_target: shortcut_targets.into_iter().next().flatten(),
         })
-        .collect::<Vec<COMDLG_FILTERSPEC>>();
-
-    // IFileDialog::SetFileTypes
-    com!(
-        dialog.SetFileTypes(filter_specs.len() as u32, filter_specs.as_ptr()),
-        "IFileDialog::SetFileTypes"
-    )?;
-
-    Ok(())
+        .map(|result| (result, sanitized))
+        .ok_or(DialogError::UnsupportedFilepath)
 }
 
-fn get_file_type_index(file_dialog: &IFileDialog) -> Result<u32, DialogError> {
-    // IFileDialog::GetFileTypeIndex
-    let mut selected_filter_index: u32 = 0;
-    com!(
-        file_dialog.GetFileTypeIndex(&mut selected_filter_index),
-        "IFileDialog::GetFileTypeIndex"
-    )?;
-    Ok(selected_filter_index)
+// Shows a dialog using the native implementation of the platform
+#[cfg(all(windows, feature = "native-windows"))]
+fn run_native_dialog(kind: DialogKind, params: &DialogParams) -> Result<Selection, DialogError> {
+    backend::run(&mut com_dialog::ComDialog::new(), kind, params)
 }
 
-fn get_shell_item_display_name(shell_item: &IShellItem) -> Result<OsString, DialogError> {
-    let mut display_name: LPWSTR = null_mut();
-    // IShellItem::GetDisplayName
-    com!(
-        shell_item.GetDisplayName(SIGDN_FILESYSPATH, &mut display_name),
-        "IShellItem::GetDisplayName"
-    )?;
-    let slice = unsafe { slice::from_raw_parts(display_name, wcslen(display_name)) };
-    let result = OsString::from_wide(slice);
-
-    // Free non-owned allocation
-    unsafe { CoTaskMemFree(display_name as LPVOID) };
-
-    Ok(result)
+#[cfg(not(all(windows, feature = "native-windows")))]
+fn run_native_dialog(_kind: DialogKind, _params: &DialogParams) -> Result<Selection, DialogError> {
+    let reason = if cfg!(windows) {
+        "wfd was built without the `native-windows` feature".to_string()
+    } else {
+        format!(
+            "there is no native file dialog on {}, only answers provided through the environment \
+             are supported",
+            std::env::consts::OS
+        )
+    };
+    Err(DialogError::Unsupported { reason })
 }
 
 // This wrapper method makes working with COM methods much simpler by
 // returning Err if the HRESULT for a call does not return success.
+#[cfg(any(windows, test))]
 fn com<F>(mut f: F, method: &str) -> Result<(), DialogError>
 where
-    F: FnMut() -> HRESULT,
+    F: FnMut() -> i32,
 {
+    let start = Instant::now();
     let hresult = f();
-    if !SUCCEEDED(hresult) {
+    trace::com_call(method, hresult, start.elapsed());
+    // Failure codes have the severity bit set
+    if hresult < 0 {
         Err(DialogError::HResultFailed {
             hresult,
             error_method: method.to_string(),
@@ -646,3 +883,82 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(all(windows, feature = "native-windows")))]
+    #[test]
+    fn native_dialog_unsupported() {
+        match run_native_dialog(DialogKind::Open, &DialogParams::default()) {
+            Err(DialogError::Unsupported { reason }) => assert!(!reason.is_empty()),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sanitized_save_name_is_not_overwritten() {
+        let dir = std::env::temp_dir().join(format!("wfd-lib-sanitized-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a_b.txt"), "existing").unwrap();
+
+        let select = |params: &DialogParams, name: &str| {
+            select_save_path(params, true, || {
+                Ok(Selection {
+                    paths: vec![dir.join(name)],
+                    file_type_index: 1,
+                })
+            })
+            .map(|result| result.selected_file_path)
+        };
+        let params = DialogParams {
+            sanitize_file_name: Some(SanitizeOptions::default()),
+            ..Default::default()
+        };
+
+        // The shell only asked about overwriting `a:b.txt`
+        match select(&params, "a:b.txt") {
+            Err(DialogError::Io { path, error }) => {
+                assert_eq!(path, dir.join("a_b.txt"));
+                assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        // The shell asked about overwriting the existing name itself
+        assert_eq!(select(&params, "a_b.txt").unwrap(), dir.join("a_b.txt"));
+        assert_eq!(select(&params, "c:d.txt").unwrap(), dir.join("c_d.txt"));
+
+        // Without the prompt existing files are overwritten whatever their name
+        let params = DialogParams {
+            un_options: FOS_OVERWRITEPROMPT,
+            ..params
+        };
+        assert_eq!(select(&params, "a:b.txt").unwrap(), dir.join("a_b.txt"));
+
+        assert_eq!(std::fs::read(dir.join("a_b.txt")).unwrap(), b"existing");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn null_term_utf16() {
+        assert_eq!(
+            "ab".to_null_term_utf16("title").unwrap(),
+            vec![0x61, 0x62, 0]
+        );
+        assert_eq!(
+            OsStr::new("a").to_null_term_utf16("folder").unwrap(),
+            vec![0x61, 0]
+        );
+        assert!(matches!(
+            "a\0b".to_null_term_utf16("title"),
+            Err(DialogError::InteriorNul { field }) if field == "title"
+        ));
+        assert!(matches!(
+            OsStr::new("C:\\a\0").to_null_term_utf16("save_as_item"),
+            Err(DialogError::InteriorNul { field }) if field == "save_as_item"
+        ));
+    }
+}
@@ -0,0 +1,724 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// A hook that replaces the built-in text of a [`Message`], see [`set_message_override`]. It is
+/// called with each form of the current locale from the most to the least specific, i.e. `"de-AT"`
+/// and then `"de"`, and returns `None` to use the built-in text for that form. Texts can be
+/// borrowed for the lifetime of the program or created at runtime, such as translations loaded
+/// from a file.
+///
+/// The texts of [`Message::AllFilesFilter`] and of the labels of the presets in the [`filters`]
+/// module are used as labels of `DialogParams::file_types`, which borrow them for the rest of the
+/// program, so each distinct text created at runtime for them is kept in memory until the program
+/// exits. Hooks should therefore return texts for them from a fixed set, such as a loaded
+/// translation, rather than a text that differs on every call. The texts of other messages are
+/// not kept.
+///
+/// [`Message::AllFilesFilter`]: enum.Message.html#variant.AllFilesFilter
+/// [`filters`]: filters/index.html
+/// [`Message`]: enum.Message.html
+/// [`set_message_override`]: fn.set_message_override.html
+pub type MessageOverride = Box<dyn Fn(&str, Message) -> Option<Cow<'static, str>> + Send + Sync>;
+
+// A registered hook, which is cloned out of its lock before it is called so that the hook can
+// itself change the settings
+type SharedOverride = Arc<dyn Fn(&str, Message) -> Option<Cow<'static, str>> + Send + Sync>;
+
+// The settings changed by `set_locale` and `set_message_override`
+struct Settings {
+    // The locale used instead of the detected locale
+    locale: RwLock<Option<String>>,
+    message_override: RwLock<Option<SharedOverride>>,
+}
+
+impl Settings {
+    const fn new() -> Self {
+        Settings {
+            locale: RwLock::new(None),
+            message_override: RwLock::new(None),
+        }
+    }
+
+    fn current_locale(&self) -> String {
+        let locale = self
+            .locale
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        locale
+            .or_else(detect_locale)
+            .unwrap_or_else(|| String::from("en"))
+    }
+
+    fn set_locale(&self, locale: Option<&str>) {
+        *self.locale.write().unwrap_or_else(|e| e.into_inner()) = locale.map(normalize_locale);
+    }
+
+    fn set_message_override(&self, hook: Option<MessageOverride>) {
+        *self
+            .message_override
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = hook.map(SharedOverride::from);
+    }
+
+    fn localized_message(&self, message: Message) -> Cow<'static, str> {
+        let hook = self
+            .message_override
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let locale = self.current_locale();
+        match hook {
+            Some(hook) => lookup(&locale, message, |locale| hook(locale, message)),
+            None => lookup(&locale, message, |_| None),
+        }
+    }
+}
+
+static SETTINGS: Settings = Settings::new();
+
+// The texts returned by `intern` that were created at runtime
+static INTERNED_MESSAGES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// The built-in strings that wfd displays or returns, which are translated into the language of
+/// the user. Messages that describe values contain named placeholders such as `{count}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// The label of the file type filter matching all files, used by `DialogParams::default()`
+    AllFilesFilter,
+    /// The label of the aggregate filter matching all other file types, see `AggregateFilter`
+    AllSupportedFiles,
+    /// The label of the `filters::IMAGES` preset
+    ImagesFilter,
+    /// The label of the `filters::AUDIO` preset
+    AudioFilter,
+    /// The label of the `filters::VIDEO` preset
+    VideoFilter,
+    /// The label of the `filters::DOCUMENTS` preset
+    DocumentsFilter,
+    /// The label of the `filters::SPREADSHEETS` preset
+    SpreadsheetsFilter,
+    /// The label of the `filters::PRESENTATIONS` preset
+    PresentationsFilter,
+    /// The label of the `filters::ARCHIVES` preset
+    ArchivesFilter,
+    /// The label of the `filters::SOURCE_CODE` preset
+    SourceCodeFilter,
+    /// The label of the `filters::TEXT` preset
+    TextFilter,
+    /// Too few items were selected, with the placeholders `{min}` and `{count}`
+    TooFewItems,
+    /// Too many items were selected, with the placeholders `{max}` and `{count}`
+    TooManyItems,
+    /// A selected file is too large, with the placeholders `{max}` and `{size}` in bytes
+    TooLarge,
+    /// A selected item cannot be read
+    NotReadable,
+    /// A selected item is not a writable folder
+    NotWritableDirectory,
+    /// A selected item is not located in one of the allowed folders
+    OutsideAllowedRoots,
+    /// A selected item is hidden by the item filter of the dialog
+    HiddenByFilter,
+    /// The file selected in a Save dialog already exists and cannot be replaced, with the
+    /// placeholder `{name}` for its file name
+    FileAlreadyExists,
+    /// The file selected in a Save dialog cannot be written, with the placeholders `{name}` for
+    /// its file name and `{error}` for the reason
+    FileNotWritable,
+    /// The label of the OK button of a message dialog
+    OkButton,
+    /// The label of the Cancel button of a message dialog
+    CancelButton,
+    /// The label of the Yes button of a message dialog
+    YesButton,
+    /// The label of the No button of a message dialog
+    NoButton,
+    /// The prompt of a message dialog shown in a terminal, with the placeholders `{count}` for
+    /// the number of buttons and `{default}` for the number of the default button
+    ChoicePrompt,
+}
+
+/// Returns the text of a message in the current locale, see [`current_locale`].
+///
+/// Translations are looked up from the most to the least specific form of the locale, falling
+/// back to English, so `"de-AT"` uses the German text. A hook registered with
+/// [`set_message_override`] takes precedence over the built-in translation for the same form of
+/// the locale.
+///
+/// [`current_locale`]: fn.current_locale.html
+/// [`set_message_override`]: fn.set_message_override.html
+pub fn localized_message(message: Message) -> Cow<'static, str> {
+    SETTINGS.localized_message(message)
+}
+
+// Returns the text of a message in the current locale for the places that need a text that
+// lives for the rest of the program, such as the default `file_types` of `DialogParams`. Only
+// messages used as labels of file types may use this, see `intern`.
+pub(crate) fn static_message(message: Message) -> &'static str {
+    intern(localized_message(message))
+}
+
+// Returns a text that lives for the rest of the program. Texts created at runtime are leaked once
+// for each distinct text, so that repeated calls do not allocate again. The memory used is only
+// bounded if the texts come from a fixed set, as documented on `MessageOverride`.
+pub(crate) fn intern(text: Cow<'static, str>) -> &'static str {
+    let text = match text {
+        Cow::Borrowed(text) => return text,
+        Cow::Owned(text) => text,
+    };
+    let mut interned = INTERNED_MESSAGES.lock().unwrap_or_else(|e| e.into_inner());
+    match interned.iter().find(|interned| **interned == text) {
+        Some(interned) => interned,
+        None => {
+            let text: &'static str = Box::leak(text.into_boxed_str());
+            interned.push(text);
+            text
+        }
+    }
+}
+
+/// Returns the locale used for wfd's built-in strings as a BCP 47 language tag, i.e. `"ja-JP"`.
+///
+/// This is the locale set with [`set_locale`] or, if none was set, the user's UI language on
+/// Windows and the locale given by the `LANGUAGE`, `LC_ALL`, `LC_MESSAGES` and `LANG`
+/// environment variables elsewhere. Defaults to `"en"` if no locale can be determined.
+///
+/// [`set_locale`]: fn.set_locale.html
+pub fn current_locale() -> String {
+    SETTINGS.current_locale()
+}
+
+/// Sets the locale used for wfd's built-in strings, i.e. `"de"` or `"ja-JP"`, instead of the
+/// locale of the user. Passing `None` restores the detected locale.
+pub fn set_locale(locale: Option<&str>) {
+    SETTINGS.set_locale(locale);
+}
+
+/// Registers a hook that replaces the built-in text of messages, for example to add a language
+/// that has no built-in translation or to match the wording used by an application. Passing
+/// `None` removes the hook. See [`MessageOverride`] for the texts that are kept in memory.
+///
+/// [`MessageOverride`]: type.MessageOverride.html
+///
+/// # Examples
+///
+/// ```
+/// use wfd::{set_message_override, Message};
+///
+/// set_message_override(Some(Box::new(|locale, message| {
+///     match (locale, message) {
+///         ("nl", Message::AllFilesFilter) => Some("Alle bestanden (*.*)".into()),
+///         _ => None,
+///     }
+/// })));
+/// ```
+pub fn set_message_override(hook: Option<MessageOverride>) {
+    SETTINGS.set_message_override(hook);
+}
+
+// Replaces the `{name}` placeholders in a message with the values of the arguments
+pub(crate) fn format_message(template: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut formatted = template.to_string();
+    for (name, value) in args {
+        formatted = formatted.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    formatted
+}
+
+// Returns the text of a message for the most specific form of the locale that has a
+// translation, falling back to English. Texts returned by the hook take precedence over the
+// built-in translation for the same form of the locale.
+fn lookup<F>(locale: &str, message: Message, hook: F) -> Cow<'static, str>
+where
+    F: Fn(&str) -> Option<Cow<'static, str>>,
+{
+    fallback_chain(locale)
+        .iter()
+        .find_map(|locale| hook(locale).or_else(|| catalog(locale, message).map(Cow::Borrowed)))
+        .or_else(|| hook("en"))
+        .unwrap_or_else(|| Cow::Borrowed(english(message)))
+}
+
+// Returns the forms of a locale from the most to the least specific, i.e. `zh-Hant-TW`,
+// `zh-Hant` and `zh`
+pub(crate) fn fallback_chain(locale: &str) -> Vec<String> {
+    let locale = normalize_locale(locale);
+    let mut chain = vec![locale.clone()];
+    let mut current = locale.as_str();
+    while let Some(index) = current.rfind('-') {
+        current = &current[..index];
+        chain.push(current.to_string());
+    }
+    chain
+}
+
+// Converts a POSIX locale such as `de_AT.UTF-8@euro` into a language tag such as `de-AT`. The
+// language is lower-cased and a two letter region upper-cased.
+pub(crate) fn normalize_locale(locale: &str) -> String {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    locale
+        .split(['_', '-'])
+        .filter(|part| !part.is_empty())
+        .enumerate()
+        .map(|(i, part)| match (i, part.len()) {
+            (0, _) => part.to_ascii_lowercase(),
+            (_, 2) => part.to_ascii_uppercase(),
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(windows)]
+fn detect_locale() -> Option<String> {
+    use winapi::um::winnls::{GetUserDefaultUILanguage, LCIDToLocaleName};
+    use winapi::um::winnt::LOCALE_NAME_MAX_LENGTH;
+
+    let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH];
+    let length = unsafe {
+        // A LANGID with the default sort order is a valid LCID
+        let lcid = u32::from(GetUserDefaultUILanguage());
+        LCIDToLocaleName(lcid, buffer.as_mut_ptr(), buffer.len() as i32, 0)
+    };
+    if length <= 1 {
+        return None;
+    }
+    Some(normalize_locale(&String::from_utf16_lossy(
+        &buffer[..length as usize - 1],
+    )))
+}
+
+#[cfg(not(windows))]
+fn detect_locale() -> Option<String> {
+    locale_from_env(|name| std::env::var(name).ok())
+}
+
+// Determines the locale for messages from the environment variables in the order used by
+// gettext. `LANGUAGE` can contain a list of locales but only its first entry is used, and it is
+// ignored when the locale is `C` or `POSIX` since messages are then not translated.
+#[cfg(any(not(windows), test))]
+fn locale_from_env<F: Fn(&str) -> Option<String>>(lookup: F) -> Option<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| lookup(name))
+        .find(|value| !value.is_empty())?;
+    if locale == "C" || locale == "POSIX" || locale.starts_with("C.") {
+        return None;
+    }
+
+    let language = lookup("LANGUAGE")
+        .and_then(|language| language.split(':').next().map(String::from))
+        .filter(|language| !language.is_empty());
+    Some(normalize_locale(&language.unwrap_or(locale)))
+}
+
+fn catalog(locale: &str, message: Message) -> Option<&'static str> {
+    match locale {
+        "en" => Some(english(message)),
+        "de" => Some(german(message)),
+        "fr" => Some(french(message)),
+        "ja" => Some(japanese(message)),
+        _ => None,
+    }
+}
+
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::AllFilesFilter => "All types (*.*)",
+        Message::AllSupportedFiles => "All supported files",
+        Message::ImagesFilter => "Images",
+        Message::AudioFilter => "Audio",
+        Message::VideoFilter => "Video",
+        Message::DocumentsFilter => "Documents",
+        Message::SpreadsheetsFilter => "Spreadsheets",
+        Message::PresentationsFilter => "Presentations",
+        Message::ArchivesFilter => "Archives",
+        Message::SourceCodeFilter => "Source code",
+        Message::TextFilter => "Text files",
+        Message::TooFewItems => {
+            "at least {min} items must be selected, but the selection contains {count}"
+        }
+        Message::TooManyItems => {
+            "at most {max} items can be selected, but the selection contains {count}"
+        }
+        Message::TooLarge => "the file is {size} bytes, which exceeds the limit of {max} bytes",
+        Message::NotReadable => "the item cannot be read",
+        Message::NotWritableDirectory => "the item is not a writable folder",
+        Message::OutsideAllowedRoots => "the item is not located in an allowed folder",
+        Message::HiddenByFilter => "the item is not available for selection",
+        Message::FileAlreadyExists => "{name} already exists. Choose a different name.",
+        Message::FileNotWritable => "{name} cannot be saved: {error}",
+        Message::OkButton => "OK",
+        Message::CancelButton => "Cancel",
+        Message::YesButton => "Yes",
+        Message::NoButton => "No",
+        Message::ChoicePrompt => "Choose an option (1-{count}) [{default}]: ",
+    }
+}
+
+fn german(message: Message) -> &'static str {
+    match message {
+        Message::AllFilesFilter => "Alle Dateien (*.*)",
+        Message::AllSupportedFiles => "Alle unterstützten Dateien",
+        Message::ImagesFilter => "Bilder",
+        Message::AudioFilter => "Audio",
+        Message::VideoFilter => "Video",
+        Message::DocumentsFilter => "Dokumente",
+        Message::SpreadsheetsFilter => "Tabellen",
+        Message::PresentationsFilter => "Präsentationen",
+        Message::ArchivesFilter => "Archive",
+        Message::SourceCodeFilter => "Quellcode",
+        Message::TextFilter => "Textdateien",
+        Message::TooFewItems => {
+            "mindestens {min} Elemente müssen ausgewählt werden, die Auswahl enthält aber {count}"
+        }
+        Message::TooManyItems => {
+            "höchstens {max} Elemente können ausgewählt werden, die Auswahl enthält aber {count}"
+        }
+        Message::TooLarge => {
+            "die Datei ist {size} Bytes groß und überschreitet das Limit von {max} Bytes"
+        }
+        Message::NotReadable => "das Element kann nicht gelesen werden",
+        Message::NotWritableDirectory => "das Element ist kein beschreibbarer Ordner",
+        Message::OutsideAllowedRoots => {
+            "das Element befindet sich nicht in einem zulässigen Ordner"
+        }
+        Message::HiddenByFilter => "das Element steht nicht zur Auswahl",
+        Message::FileAlreadyExists => {
+            "{name} ist bereits vorhanden. Wählen Sie einen anderen Namen."
+        }
+        Message::FileNotWritable => "{name} kann nicht gespeichert werden: {error}",
+        Message::OkButton => "OK",
+        Message::CancelButton => "Abbrechen",
+        Message::YesButton => "Ja",
+        Message::NoButton => "Nein",
+        Message::ChoicePrompt => "Wählen Sie eine Option (1-{count}) [{default}]: ",
+    }
+}
+
+fn french(message: Message) -> &'static str {
+    match message {
+        Message::AllFilesFilter => "Tous les fichiers (*.*)",
+        Message::AllSupportedFiles => "Tous les fichiers pris en charge",
+        Message::ImagesFilter => "Images",
+        Message::AudioFilter => "Audio",
+        Message::VideoFilter => "Vidéo",
+        Message::DocumentsFilter => "Documents",
+        Message::SpreadsheetsFilter => "Feuilles de calcul",
+        Message::PresentationsFilter => "Présentations",
+        Message::ArchivesFilter => "Archives",
+        Message::SourceCodeFilter => "Code source",
+        Message::TextFilter => "Fichiers texte",
+        Message::TooFewItems => {
+            "au moins {min} éléments doivent être sélectionnés, mais la sélection en contient {count}"
+        }
+        Message::TooManyItems => {
+            "au plus {max} éléments peuvent être sélectionnés, mais la sélection en contient {count}"
+        }
+        Message::TooLarge => {
+            "le fichier fait {size} octets, ce qui dépasse la limite de {max} octets"
+        }
+        Message::NotReadable => "l'élément ne peut pas être lu",
+        Message::NotWritableDirectory => "l'élément n'est pas un dossier accessible en écriture",
+        Message::OutsideAllowedRoots => "l'élément ne se trouve pas dans un dossier autorisé",
+        Message::HiddenByFilter => "l'élément ne peut pas être sélectionné",
+        Message::FileAlreadyExists => "{name} existe déjà. Choisissez un autre nom.",
+        Message::FileNotWritable => "{name} ne peut pas être enregistré : {error}",
+        Message::OkButton => "OK",
+        Message::CancelButton => "Annuler",
+        Message::YesButton => "Oui",
+        Message::NoButton => "Non",
+        Message::ChoicePrompt => "Choisissez une option (1-{count}) [{default}] : ",
+    }
+}
+
+fn japanese(message: Message) -> &'static str {
+    match message {
+        Message::AllFilesFilter => "すべてのファイル (*.*)",
+        Message::AllSupportedFiles => "サポートされているすべてのファイル",
+        Message::ImagesFilter => "画像",
+        Message::AudioFilter => "オーディオ",
+        Message::VideoFilter => "ビデオ",
+        Message::DocumentsFilter => "ドキュメント",
+        Message::SpreadsheetsFilter => "スプレッドシート",
+        Message::PresentationsFilter => "プレゼンテーション",
+        Message::ArchivesFilter => "アーカイブ",
+        Message::SourceCodeFilter => "ソースコード",
+        Message::TextFilter => "テキスト ファイル",
+        Message::TooFewItems => {
+            "少なくとも {min} 個の項目を選択する必要がありますが、{count} 個が選択されました"
+        }
+        Message::TooManyItems => "選択できる項目は最大 {max} 個ですが、{count} 個が選択されました",
+        Message::TooLarge => {
+            "ファイルのサイズは {size} バイトで、上限の {max} バイトを超えています"
+        }
+        Message::NotReadable => "項目を読み取れません",
+        Message::NotWritableDirectory => "項目は書き込み可能なフォルダーではありません",
+        Message::OutsideAllowedRoots => "項目は許可されたフォルダー内にありません",
+        Message::HiddenByFilter => "この項目は選択できません",
+        Message::FileAlreadyExists => "{name} は既に存在します。別の名前を選択してください。",
+        Message::FileNotWritable => "{name} を保存できません: {error}",
+        Message::OkButton => "OK",
+        Message::CancelButton => "キャンセル",
+        Message::YesButton => "はい",
+        Message::NoButton => "いいえ",
+        Message::ChoicePrompt => "選択肢を選んでください (1-{count}) [{default}]: ",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const ALL_MESSAGES: [Message; 25] = [
+        Message::AllFilesFilter,
+        Message::AllSupportedFiles,
+        Message::ImagesFilter,
+        Message::AudioFilter,
+        Message::VideoFilter,
+        Message::DocumentsFilter,
+        Message::SpreadsheetsFilter,
+        Message::PresentationsFilter,
+        Message::ArchivesFilter,
+        Message::SourceCodeFilter,
+        Message::TextFilter,
+        Message::TooFewItems,
+        Message::TooManyItems,
+        Message::TooLarge,
+        Message::NotReadable,
+        Message::NotWritableDirectory,
+        Message::OutsideAllowedRoots,
+        Message::HiddenByFilter,
+        Message::FileAlreadyExists,
+        Message::FileNotWritable,
+        Message::OkButton,
+        Message::CancelButton,
+        Message::YesButton,
+        Message::NoButton,
+        Message::ChoicePrompt,
+    ];
+
+    fn env(vars: &[(&str, &str)]) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        locale_from_env(|name| vars.get(name).cloned())
+    }
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut names = text
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split('}').next())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn normalize_locales() {
+        assert_eq!(normalize_locale("de_AT.UTF-8@euro"), "de-AT");
+        assert_eq!(normalize_locale("ja_JP.eucJP"), "ja-JP");
+        assert_eq!(normalize_locale("EN-us"), "en-US");
+        assert_eq!(normalize_locale("zh-Hant-TW"), "zh-Hant-TW");
+        assert_eq!(normalize_locale("sr_RS@latin"), "sr-RS");
+        assert_eq!(normalize_locale(""), "");
+    }
+
+    #[test]
+    fn fallback_chains() {
+        assert_eq!(fallback_chain("de_AT.UTF-8"), vec!["de-AT", "de"]);
+        assert_eq!(
+            fallback_chain("zh-Hant-TW"),
+            vec!["zh-Hant-TW", "zh-Hant", "zh"]
+        );
+        assert_eq!(fallback_chain("ja"), vec!["ja"]);
+    }
+
+    #[test]
+    fn lookup_uses_fallback_chain() {
+        assert_eq!(
+            lookup("de", Message::AllFilesFilter, |_| None),
+            "Alle Dateien (*.*)"
+        );
+        assert_eq!(
+            lookup("de-AT", Message::AllFilesFilter, |_| None),
+            "Alle Dateien (*.*)"
+        );
+        assert_eq!(
+            lookup("de_CH.UTF-8", Message::NotReadable, |_| None),
+            german(Message::NotReadable)
+        );
+        assert_eq!(
+            lookup("ja-JP", Message::AllFilesFilter, |_| None),
+            "すべてのファイル (*.*)"
+        );
+        assert_eq!(
+            lookup("fr-CA", Message::AllFilesFilter, |_| None),
+            "Tous les fichiers (*.*)"
+        );
+    }
+
+    #[test]
+    fn lookup_falls_back_to_english() {
+        assert_eq!(
+            lookup("pt-BR", Message::AllFilesFilter, |_| None),
+            "All types (*.*)"
+        );
+        assert_eq!(
+            lookup("", Message::AllFilesFilter, |_| None),
+            "All types (*.*)"
+        );
+        assert_eq!(
+            lookup("en-GB", Message::NotReadable, |_| None),
+            "the item cannot be read"
+        );
+    }
+
+    #[test]
+    fn lookup_prefers_hook_for_same_locale() {
+        let hook = |locale: &str| match locale {
+            "de-AT" => Some("Alle Dateien (österreichisch)".into()),
+            "en" => Some(String::from("Everything").into()),
+            _ => None,
+        };
+        assert_eq!(
+            lookup("de-AT", Message::AllFilesFilter, hook),
+            "Alle Dateien (österreichisch)"
+        );
+        // The built-in translation of a more specific form wins over the hook's English text
+        assert_eq!(
+            lookup("de-DE", Message::AllFilesFilter, hook),
+            "Alle Dateien (*.*)"
+        );
+        assert_eq!(lookup("pt", Message::AllFilesFilter, hook), "Everything");
+        assert_eq!(lookup("en-US", Message::AllFilesFilter, hook), "Everything");
+    }
+
+    #[test]
+    fn catalogs_use_the_english_placeholders() {
+        for message in ALL_MESSAGES.iter().copied() {
+            let expected = placeholders(english(message));
+            for locale in &["de", "fr", "ja"] {
+                let text = catalog(locale, message).unwrap();
+                assert_eq!(placeholders(text), expected, "{} {:?}", locale, message);
+            }
+        }
+    }
+
+    #[test]
+    fn locale_from_environment() {
+        assert_eq!(env(&[]), None);
+        assert_eq!(env(&[("LANG", "de_DE.UTF-8")]), Some("de-DE".into()));
+        assert_eq!(
+            env(&[("LANG", "de_DE.UTF-8"), ("LC_MESSAGES", "ja_JP.UTF-8")]),
+            Some("ja-JP".into())
+        );
+        assert_eq!(
+            env(&[("LC_ALL", "fr_FR"), ("LC_MESSAGES", "ja_JP.UTF-8")]),
+            Some("fr-FR".into())
+        );
+        assert_eq!(
+            env(&[("LC_ALL", ""), ("LANG", "de_AT.UTF-8")]),
+            Some("de-AT".into())
+        );
+    }
+
+    #[test]
+    fn locale_from_language_variable() {
+        assert_eq!(
+            env(&[("LANG", "en_US.UTF-8"), ("LANGUAGE", "de_AT:de:en")]),
+            Some("de-AT".into())
+        );
+        assert_eq!(
+            env(&[("LANG", "en_US.UTF-8"), ("LANGUAGE", "")]),
+            Some("en-US".into())
+        );
+        // LANGUAGE is ignored when messages are not translated
+        assert_eq!(env(&[("LANG", "C.UTF-8"), ("LANGUAGE", "de")]), None);
+        assert_eq!(env(&[("LC_ALL", "POSIX"), ("LANGUAGE", "de")]), None);
+        // LANGUAGE alone does not enable translations
+        assert_eq!(env(&[("LANGUAGE", "de")]), None);
+    }
+
+    #[test]
+    fn format_messages() {
+        assert_eq!(
+            format_message(
+                &lookup("de", Message::TooFewItems, |_| None),
+                &[("min", &2), ("count", &1)]
+            ),
+            "mindestens 2 Elemente müssen ausgewählt werden, die Auswahl enthält aber 1"
+        );
+        assert_eq!(
+            format_message("{a} {b} {a}", &[("a", &"x"), ("c", &"y")]),
+            "x {b} x"
+        );
+    }
+
+    // The global settings are used by other tests running in parallel, so the tests use their
+    // own settings instead
+    #[test]
+    fn settings_locale_and_message_override() {
+        let settings = Settings::new();
+        settings.set_locale(Some("ja_JP.UTF-8"));
+        assert_eq!(settings.current_locale(), "ja-JP");
+        assert_eq!(
+            settings.localized_message(Message::AllFilesFilter),
+            "すべてのファイル (*.*)"
+        );
+
+        settings.set_message_override(Some(Box::new(|locale, message| match (locale, message) {
+            ("ja-JP", Message::AllFilesFilter) => Some("全ファイル".into()),
+            ("nl", Message::AllFilesFilter) => Some(format!("Alle {} (*.*)", "bestanden").into()),
+            _ => None,
+        })));
+        assert_eq!(
+            settings.localized_message(Message::AllFilesFilter),
+            "全ファイル"
+        );
+        assert_eq!(
+            settings.localized_message(Message::NotReadable),
+            "項目を読み取れません"
+        );
+
+        settings.set_locale(Some("nl-NL"));
+        assert_eq!(
+            settings.localized_message(Message::AllFilesFilter),
+            "Alle bestanden (*.*)"
+        );
+
+        settings.set_message_override(None);
+        settings.set_locale(None);
+        assert_eq!(
+            settings.current_locale(),
+            detect_locale().unwrap_or_else(|| "en".into())
+        );
+    }
+
+    #[test]
+    fn message_override_can_change_settings() {
+        static SETTINGS: Settings = Settings::new();
+        SETTINGS.set_locale(Some("de"));
+        SETTINGS.set_message_override(Some(Box::new(|_, _| {
+            // Removes itself and switches to French, which would deadlock if the hook was
+            // called while the settings were locked
+            SETTINGS.set_message_override(None);
+            SETTINGS.set_locale(Some("fr"));
+            None
+        })));
+
+        assert_eq!(
+            SETTINGS.localized_message(Message::AllFilesFilter),
+            "Alle Dateien (*.*)"
+        );
+        assert_eq!(
+            SETTINGS.localized_message(Message::AllFilesFilter),
+            "Tous les fichiers (*.*)"
+        );
+    }
+}
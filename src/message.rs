@@ -0,0 +1,823 @@
+// Message dialogs, such as confirmation prompts and error reports, shown using the native message
+// box of the platform or in the terminal when no native message box is available
+
+use std::borrow::Cow;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::locale::{format_message, localized_message, Message};
+use crate::noninteractive;
+use crate::{DialogError, HWND};
+
+/// The kind of message shown in a message dialog, which determines its icon
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageLevel {
+    /// An informational message
+    #[default]
+    Info,
+    /// A warning, such as a file about to be replaced
+    Warning,
+    /// An error, such as a failed import
+    Error,
+    /// A question for the user to answer
+    Question,
+}
+
+/// The buttons displayed in a message dialog
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum MessageButtons<'a> {
+    /// A single OK button
+    #[default]
+    Ok,
+    /// OK and Cancel buttons
+    OkCancel,
+    /// Yes and No buttons
+    YesNo,
+    /// Yes, No and Cancel buttons
+    YesNoCancel,
+    /// Buttons with the given labels, in order. An empty list displays a single OK button.
+    Custom(Vec<&'a str>),
+}
+
+impl<'a> MessageButtons<'a> {
+    // Returns the response given by each button, in order
+    fn responses(&self) -> Vec<MessageResponse> {
+        match self {
+            MessageButtons::Ok => vec![MessageResponse::Ok],
+            MessageButtons::OkCancel => vec![MessageResponse::Ok, MessageResponse::Cancel],
+            MessageButtons::YesNo => vec![MessageResponse::Yes, MessageResponse::No],
+            MessageButtons::YesNoCancel => vec![
+                MessageResponse::Yes,
+                MessageResponse::No,
+                MessageResponse::Cancel,
+            ],
+            MessageButtons::Custom(labels) if labels.is_empty() => vec![MessageResponse::Ok],
+            MessageButtons::Custom(labels) => {
+                (0..labels.len()).map(MessageResponse::Custom).collect()
+            }
+        }
+    }
+
+    // Returns the label of the button that gives the response, in the current locale for the
+    // standard buttons
+    fn label(&self, response: MessageResponse) -> Cow<'a, str> {
+        match (self, response) {
+            (MessageButtons::Custom(labels), MessageResponse::Custom(index)) => {
+                Cow::Borrowed(labels[index])
+            }
+            (_, MessageResponse::Ok) | (_, MessageResponse::Custom(_)) => {
+                localized_message(Message::OkButton)
+            }
+            (_, MessageResponse::Cancel) => localized_message(Message::CancelButton),
+            (_, MessageResponse::Yes) => localized_message(Message::YesButton),
+            (_, MessageResponse::No) => localized_message(Message::NoButton),
+        }
+    }
+}
+
+/// The button that was pressed to close a message dialog
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageResponse {
+    /// The OK button was pressed
+    Ok,
+    /// The Cancel button was pressed, or the dialog was closed without pressing a button
+    Cancel,
+    /// The Yes button was pressed
+    Yes,
+    /// The No button was pressed
+    No,
+    /// The button at the given index of `MessageButtons::Custom` was pressed
+    Custom(usize),
+}
+
+/// The parameters used when displaying a message dialog. All fields other than `text` are
+/// optional and have appropriate default values
+#[derive(Debug)]
+pub struct MessageParams<'a> {
+    /// The buttons displayed in the dialog. Defaults to a single OK button.
+    pub buttons: MessageButtons<'a>,
+    /// The (0-based) index of the button that is selected by default. Defaults to the first button.
+    pub default_button: usize,
+    /// The kind of message, which determines the icon displayed in the dialog. Defaults to
+    /// [`MessageLevel::Info`].
+    ///
+    /// [`MessageLevel::Info`]: enum.MessageLevel.html#variant.Info
+    pub level: MessageLevel,
+    /// The HWND of the window that the dialog will be owned by. If not provided the dialog will be
+    /// an independent top-level window. Only used on Windows.
+    pub owner: Option<HWND>,
+    /// The message displayed in the dialog
+    pub text: &'a str,
+    /// The text displayed in the title bar of the dialog box
+    pub title: &'a str,
+}
+
+impl<'a> Default for MessageParams<'a> {
+    fn default() -> Self {
+        MessageParams {
+            buttons: MessageButtons::Ok,
+            default_button: 0,
+            level: MessageLevel::Info,
+            owner: None,
+            text: "",
+            title: "",
+        }
+    }
+}
+
+/// Displays a message dialog using the provided parameters, returning the button that was
+/// pressed.
+///
+/// On Windows the dialog is a task dialog when version 6 of the common controls is in use, i.e.
+/// when the application has a manifest enabling visual styles, and a message box otherwise. On
+/// Linux the dialog is shown using `zenity` or `kdialog`. If neither is available, or there is no
+/// display, the message is shown in the terminal and the user is asked to enter their choice.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> Result<(), wfd::DialogError> {
+/// let params = wfd::MessageParams {
+///     title: "Export",
+///     text: "report.csv already exists. Do you want to replace it?",
+///     level: wfd::MessageLevel::Warning,
+///     buttons: wfd::MessageButtons::Custom(vec!["Replace", "Keep both", "Cancel"]),
+///     ..Default::default()
+/// };
+/// if wfd::message_dialog(params)? == wfd::MessageResponse::Custom(0) {
+///     // Replace the file
+/// }
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// If the dialog is closed without pressing a button and it has neither a Cancel button nor only
+/// an OK button, the [`UserCancelled`] error is returned. If the message cannot be shown because
+/// there is neither a display nor a terminal, or dialogs are non-interactive, the
+/// [`NonInteractive`] error is returned. Errors from the native dialog are returned in a
+/// [`HResultFailed`] error, including a missing `TaskDialogIndirect` when custom buttons are used
+/// without version 6 of the common controls.
+///
+/// [`UserCancelled`]: enum.DialogError.html#variant.UserCancelled
+/// [`NonInteractive`]: enum.DialogError.html#variant.NonInteractive
+/// [`HResultFailed`]: enum.DialogError.html#variant.HResultFailed
+pub fn message_dialog(params: MessageParams) -> Result<MessageResponse, DialogError> {
+    let responses = params.buttons.responses();
+    let default_button = params.default_button.min(responses.len() - 1);
+
+    if !noninteractive::is_enabled() {
+        if let Some(result) = show_native(&params, &responses, default_button) {
+            return result;
+        }
+    }
+
+    let terminal = io::stdin().is_terminal() && io::stderr().is_terminal();
+    if noninteractive::forced() == Some(true) || !terminal {
+        return Err(DialogError::NonInteractive);
+    }
+
+    let labels = responses
+        .iter()
+        .map(|response| params.buttons.label(*response))
+        .collect::<Vec<_>>();
+    let labels = labels
+        .iter()
+        .map(|label| label.as_ref())
+        .collect::<Vec<_>>();
+    let stdin = io::stdin();
+    let choice = prompt(
+        &mut stdin.lock(),
+        &mut io::stderr(),
+        &params,
+        &labels,
+        default_button,
+    );
+    match choice {
+        Ok(Some(index)) => Ok(responses[index]),
+        Ok(None) => dismissed(&responses),
+        Err(_) => Err(DialogError::NonInteractive),
+    }
+}
+
+// Returns the response for a dialog that was closed without pressing a button
+fn dismissed(responses: &[MessageResponse]) -> Result<MessageResponse, DialogError> {
+    if responses.contains(&MessageResponse::Cancel) {
+        Ok(MessageResponse::Cancel)
+    } else if responses == [MessageResponse::Ok] {
+        Ok(MessageResponse::Ok)
+    } else {
+        Err(DialogError::UserCancelled)
+    }
+}
+
+// Shows the message in a terminal and reads the number or label of the chosen button, returning
+// `None` if the input ends without a valid choice
+fn prompt<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    params: &MessageParams,
+    labels: &[&str],
+    default_button: usize,
+) -> io::Result<Option<usize>> {
+    if !params.title.is_empty() {
+        writeln!(output, "{}", params.title)?;
+    }
+    writeln!(output, "{}", params.text)?;
+    for (i, label) in labels.iter().enumerate() {
+        writeln!(output, "  {}) {}", i + 1, label)?;
+    }
+
+    let count = labels.len();
+    let default_number = default_button + 1;
+    let prompt = format_message(
+        &localized_message(Message::ChoicePrompt),
+        &[("count", &count), ("default", &default_number)],
+    );
+    loop {
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let answer = line.trim();
+        if answer.is_empty() {
+            return Ok(Some(default_button));
+        }
+        if let Ok(number) = answer.parse::<usize>() {
+            if (1..=count).contains(&number) {
+                return Ok(Some(number - 1));
+            }
+        }
+        if let Some(index) = labels
+            .iter()
+            .position(|label| label.to_lowercase() == answer.to_lowercase())
+        {
+            return Ok(Some(index));
+        }
+    }
+}
+
+#[cfg(windows)]
+fn show_native(
+    params: &MessageParams,
+    responses: &[MessageResponse],
+    default_button: usize,
+) -> Option<Result<MessageResponse, DialogError>> {
+    Some(windows::show(params, responses, default_button))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn show_native(
+    params: &MessageParams,
+    responses: &[MessageResponse],
+    default_button: usize,
+) -> Option<Result<MessageResponse, DialogError>> {
+    use std::process::{Command, Stdio};
+
+    let labels = responses
+        .iter()
+        .map(|response| params.buttons.label(*response))
+        .collect::<Vec<_>>();
+    let labels = labels
+        .iter()
+        .map(|label| label.as_ref())
+        .collect::<Vec<_>>();
+    let run = |program: &str, args: &[String]| {
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+    };
+
+    if let Some(output) = run("zenity", &desktop::zenity_args(params, &labels)) {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Some(desktop::zenity_response(
+            output.status.code(),
+            &stdout,
+            &labels,
+            responses,
+        ));
+    }
+
+    let args = desktop::kdialog_args(params, &labels, default_button)?;
+    run("kdialog", &args).map(|output| desktop::kdialog_response(output.status.code(), responses))
+}
+
+#[cfg(target_os = "macos")]
+fn show_native(
+    _params: &MessageParams,
+    _responses: &[MessageResponse],
+    _default_button: usize,
+) -> Option<Result<MessageResponse, DialogError>> {
+    None
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::mem;
+    use std::ptr::null_mut;
+
+    use winapi::shared::minwindef::{BOOL, FARPROC};
+    use winapi::shared::ntdef::LPCWSTR;
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::commctrl::{
+        TASKDIALOGCONFIG, TASKDIALOG_BUTTON, TDCBF_CANCEL_BUTTON, TDCBF_NO_BUTTON, TDCBF_OK_BUTTON,
+        TDCBF_YES_BUTTON, TDF_ALLOW_DIALOG_CANCELLATION, TDF_POSITION_RELATIVE_TO_WINDOW,
+        TDF_USE_HICON_MAIN, TD_ERROR_ICON, TD_INFORMATION_ICON, TD_WARNING_ICON,
+    };
+    use winapi::um::libloaderapi::{GetProcAddress, LoadLibraryW};
+    use winapi::um::winuser::{
+        LoadIconW, MessageBoxW, IDCANCEL, IDI_QUESTION, IDNO, IDOK, IDYES, MB_DEFBUTTON2,
+        MB_DEFBUTTON3, MB_ICONERROR, MB_ICONINFORMATION, MB_ICONQUESTION, MB_ICONWARNING, MB_OK,
+        MB_OKCANCEL, MB_YESNO, MB_YESNOCANCEL,
+    };
+
+    use super::{dismissed, MessageButtons, MessageLevel, MessageParams, MessageResponse};
+    use crate::{DialogError, NullTermUTF16};
+
+    // The IDs of custom task dialog buttons are offset so that they don't clash with IDOK etc.
+    const CUSTOM_BUTTON_ID: i32 = 100;
+
+    // HRESULT_FROM_WIN32(ERROR_PROC_NOT_FOUND)
+    const PROC_NOT_FOUND: HRESULT = 0x8007_007F_u32 as HRESULT;
+
+    type TaskDialogIndirect = unsafe extern "system" fn(
+        *const TASKDIALOGCONFIG,
+        *mut i32,
+        *mut i32,
+        *mut BOOL,
+    ) -> HRESULT;
+
+    pub(super) fn show(
+        params: &MessageParams,
+        responses: &[MessageResponse],
+        default_button: usize,
+    ) -> Result<MessageResponse, DialogError> {
+        match (task_dialog_indirect(), &params.buttons) {
+            (Some(task_dialog), _) => {
+                show_task_dialog(task_dialog, params, responses, default_button)
+            }
+            (None, MessageButtons::Custom(labels)) if !labels.is_empty() => {
+                Err(DialogError::HResultFailed {
+                    error_method: "TaskDialogIndirect".to_string(),
+                    hresult: PROC_NOT_FOUND,
+                })
+            }
+            (None, _) => show_message_box(params, responses, default_button),
+        }
+    }
+
+    // TaskDialogIndirect is only exported by version 6 of the common controls, which is loaded
+    // when the application's manifest requests it, so it is looked up at runtime
+    fn task_dialog_indirect() -> Option<TaskDialogIndirect> {
+        let module_name = "comctl32.dll".as_null_term_utf16();
+        unsafe {
+            let module = LoadLibraryW(module_name.as_ptr());
+            if module.is_null() {
+                return None;
+            }
+            let function = GetProcAddress(module, b"TaskDialogIndirect\0".as_ptr() as *const i8);
+            if function.is_null() {
+                None
+            } else {
+                Some(mem::transmute::<FARPROC, TaskDialogIndirect>(function))
+            }
+        }
+    }
+
+    fn show_task_dialog(
+        task_dialog: TaskDialogIndirect,
+        params: &MessageParams,
+        responses: &[MessageResponse],
+        default_button: usize,
+    ) -> Result<MessageResponse, DialogError> {
+        let title = params.title.to_null_term_utf16("title")?;
+        let text = params.text.to_null_term_utf16("text")?;
+        let labels = match &params.buttons {
+            MessageButtons::Custom(labels) => labels
+                .iter()
+                .map(|label| label.to_null_term_utf16("buttons"))
+                .collect::<Result<Vec<_>, DialogError>>()?,
+            _ => vec![],
+        };
+        let buttons = labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| TASKDIALOG_BUTTON {
+                nButtonID: CUSTOM_BUTTON_ID + i as i32,
+                pszButtonText: label.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut config: TASKDIALOGCONFIG = unsafe { mem::zeroed() };
+        config.cbSize = mem::size_of::<TASKDIALOGCONFIG>() as u32;
+        config.hwndParent = params.owner.unwrap_or(null_mut());
+        config.dwFlags = TDF_ALLOW_DIALOG_CANCELLATION;
+        if params.owner.is_some() {
+            config.dwFlags |= TDF_POSITION_RELATIVE_TO_WINDOW;
+        }
+        if !params.title.is_empty() {
+            config.pszWindowTitle = title.as_ptr();
+        }
+        config.pszContent = text.as_ptr();
+        unsafe {
+            match params.level {
+                MessageLevel::Info => *config.u1.pszMainIcon_mut() = TD_INFORMATION_ICON,
+                MessageLevel::Warning => *config.u1.pszMainIcon_mut() = TD_WARNING_ICON,
+                MessageLevel::Error => *config.u1.pszMainIcon_mut() = TD_ERROR_ICON,
+                // There is no predefined question icon for task dialogs
+                MessageLevel::Question => {
+                    config.dwFlags |= TDF_USE_HICON_MAIN;
+                    *config.u1.hMainIcon_mut() = LoadIconW(null_mut(), IDI_QUESTION);
+                }
+            }
+        }
+
+        for response in responses {
+            config.dwCommonButtons |= match response {
+                MessageResponse::Ok => TDCBF_OK_BUTTON,
+                MessageResponse::Cancel => TDCBF_CANCEL_BUTTON,
+                MessageResponse::Yes => TDCBF_YES_BUTTON,
+                MessageResponse::No => TDCBF_NO_BUTTON,
+                MessageResponse::Custom(_) => 0,
+            };
+        }
+        config.cButtons = buttons.len() as u32;
+        config.pButtons = buttons.as_ptr();
+        config.nDefaultButton = button_id(responses[default_button]);
+
+        let mut button_id = 0;
+        com!(
+            task_dialog(&config, &mut button_id, null_mut(), null_mut()),
+            "TaskDialogIndirect"
+        )?;
+        response_for_id(button_id, responses)
+    }
+
+    fn show_message_box(
+        params: &MessageParams,
+        responses: &[MessageResponse],
+        default_button: usize,
+    ) -> Result<MessageResponse, DialogError> {
+        let title = params.title.to_null_term_utf16("title")?;
+        let text = params.text.to_null_term_utf16("text")?;
+
+        let mut flags = match params.buttons {
+            MessageButtons::OkCancel => MB_OKCANCEL,
+            MessageButtons::YesNo => MB_YESNO,
+            MessageButtons::YesNoCancel => MB_YESNOCANCEL,
+            MessageButtons::Ok | MessageButtons::Custom(_) => MB_OK,
+        };
+        flags |= match params.level {
+            MessageLevel::Info => MB_ICONINFORMATION,
+            MessageLevel::Warning => MB_ICONWARNING,
+            MessageLevel::Error => MB_ICONERROR,
+            MessageLevel::Question => MB_ICONQUESTION,
+        };
+        flags |= match default_button {
+            0 => 0,
+            1 => MB_DEFBUTTON2,
+            _ => MB_DEFBUTTON3,
+        };
+
+        let title_ptr: LPCWSTR = if params.title.is_empty() {
+            null_mut()
+        } else {
+            title.as_ptr()
+        };
+        let owner = params.owner.unwrap_or(null_mut());
+        let button_id = unsafe { MessageBoxW(owner, text.as_ptr(), title_ptr, flags) };
+        if button_id == 0 {
+            let code = io::Error::last_os_error().raw_os_error().unwrap_or(0) as u32;
+            return Err(DialogError::HResultFailed {
+                error_method: "MessageBoxW".to_string(),
+                hresult: (0x8007_0000 | (code & 0xFFFF)) as HRESULT,
+            });
+        }
+        response_for_id(button_id, responses)
+    }
+
+    fn button_id(response: MessageResponse) -> i32 {
+        match response {
+            MessageResponse::Ok => IDOK,
+            MessageResponse::Cancel => IDCANCEL,
+            MessageResponse::Yes => IDYES,
+            MessageResponse::No => IDNO,
+            MessageResponse::Custom(index) => CUSTOM_BUTTON_ID + index as i32,
+        }
+    }
+
+    fn response_for_id(
+        button_id: i32,
+        responses: &[MessageResponse],
+    ) -> Result<MessageResponse, DialogError> {
+        // IDCANCEL is returned when the dialog is closed, even if it has no Cancel button
+        match responses
+            .iter()
+            .find(|response| self::button_id(**response) == button_id)
+        {
+            Some(response) => Ok(*response),
+            None => dismissed(responses),
+        }
+    }
+}
+
+// Message dialogs shown using the `zenity` and `kdialog` command line tools
+#[cfg(any(not(any(windows, target_os = "macos")), test))]
+mod desktop {
+    use super::{dismissed, MessageLevel, MessageParams, MessageResponse};
+    use crate::DialogError;
+
+    // zenity prints the label of the pressed button when using `--switch`
+    pub(crate) fn zenity_args(params: &MessageParams, labels: &[&str]) -> Vec<String> {
+        let mut args = vec![];
+        if labels.len() == 1 {
+            args.push(
+                match params.level {
+                    MessageLevel::Info | MessageLevel::Question => "--info",
+                    MessageLevel::Warning => "--warning",
+                    MessageLevel::Error => "--error",
+                }
+                .to_string(),
+            );
+            args.push(format!("--ok-label={}", labels[0]));
+        } else {
+            args.push("--question".to_string());
+            args.push("--switch".to_string());
+            args.extend(
+                labels
+                    .iter()
+                    .map(|label| format!("--extra-button={}", label)),
+            );
+        }
+
+        let icon = match params.level {
+            MessageLevel::Info => "dialog-information",
+            MessageLevel::Warning => "dialog-warning",
+            MessageLevel::Error => "dialog-error",
+            MessageLevel::Question => "dialog-question",
+        };
+        args.push(format!("--icon-name={}", icon));
+        if !params.title.is_empty() {
+            args.push(format!("--title={}", params.title));
+        }
+        args.push("--no-markup".to_string());
+        args.push(format!("--text={}", params.text));
+        args
+    }
+
+    pub(crate) fn zenity_response(
+        exit_code: Option<i32>,
+        stdout: &str,
+        labels: &[&str],
+        responses: &[MessageResponse],
+    ) -> Result<MessageResponse, DialogError> {
+        if labels.len() == 1 && exit_code == Some(0) {
+            return Ok(responses[0]);
+        }
+
+        let pressed = stdout.trim_end_matches('\n');
+        match labels.iter().position(|label| *label == pressed) {
+            Some(index) if !pressed.is_empty() => Ok(responses[index]),
+            _ => dismissed(responses),
+        }
+    }
+
+    // kdialog only supports up to three buttons, returning `None` for more
+    pub(crate) fn kdialog_args(
+        params: &MessageParams,
+        labels: &[&str],
+        default_button: usize,
+    ) -> Option<Vec<String>> {
+        let warning = matches!(params.level, MessageLevel::Warning | MessageLevel::Error);
+        let mut args = vec![];
+        match labels.len() {
+            1 => args.push(
+                match params.level {
+                    MessageLevel::Info | MessageLevel::Question => "--msgbox",
+                    MessageLevel::Warning => "--sorry",
+                    MessageLevel::Error => "--error",
+                }
+                .to_string(),
+            ),
+            2 => {
+                args.push(if warning { "--warningyesno" } else { "--yesno" }.to_string());
+                args.push(format!("--yes-label={}", labels[0]));
+                args.push(format!("--no-label={}", labels[1]));
+            }
+            3 => {
+                let kind = if warning {
+                    "--warningyesnocancel"
+                } else {
+                    "--yesnocancel"
+                };
+                args.push(kind.to_string());
+                args.push(format!("--yes-label={}", labels[0]));
+                args.push(format!("--no-label={}", labels[1]));
+                args.push(format!("--cancel-label={}", labels[2]));
+            }
+            _ => return None,
+        }
+        args.push(params.text.to_string());
+
+        if !params.title.is_empty() {
+            args.push(format!("--title={}", params.title));
+        }
+        if labels.len() > 1 && default_button > 0 {
+            args.push(format!("--default={}", labels[default_button]));
+        }
+        Some(args)
+    }
+
+    pub(crate) fn kdialog_response(
+        exit_code: Option<i32>,
+        responses: &[MessageResponse],
+    ) -> Result<MessageResponse, DialogError> {
+        // The exit code is the index of the pressed button
+        match exit_code {
+            Some(code) if code >= 0 && (code as usize) < responses.len() => {
+                Ok(responses[code as usize])
+            }
+            _ => dismissed(responses),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::desktop::*;
+    use super::*;
+    use std::io::Cursor;
+
+    fn question(buttons: MessageButtons) -> MessageParams {
+        MessageParams {
+            buttons,
+            level: MessageLevel::Question,
+            text: "Replace report.csv?",
+            title: "Export",
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn button_responses() {
+        assert_eq!(MessageButtons::Ok.responses(), vec![MessageResponse::Ok]);
+        assert_eq!(
+            MessageButtons::YesNoCancel.responses(),
+            vec![
+                MessageResponse::Yes,
+                MessageResponse::No,
+                MessageResponse::Cancel
+            ]
+        );
+        assert_eq!(
+            MessageButtons::Custom(vec!["Replace", "Skip"]).responses(),
+            vec![MessageResponse::Custom(0), MessageResponse::Custom(1)]
+        );
+        assert_eq!(
+            MessageButtons::Custom(vec![]).responses(),
+            vec![MessageResponse::Ok]
+        );
+        assert_eq!(
+            MessageButtons::Custom(vec!["Replace"]).label(MessageResponse::Custom(0)),
+            "Replace"
+        );
+    }
+
+    #[test]
+    fn dismissed_responses() {
+        assert_eq!(
+            dismissed(&[MessageResponse::Ok]).unwrap(),
+            MessageResponse::Ok
+        );
+        assert_eq!(
+            dismissed(&MessageButtons::OkCancel.responses()).unwrap(),
+            MessageResponse::Cancel
+        );
+        assert!(matches!(
+            dismissed(&MessageButtons::YesNo.responses()),
+            Err(DialogError::UserCancelled)
+        ));
+        assert!(matches!(
+            dismissed(&[MessageResponse::Custom(0)]),
+            Err(DialogError::UserCancelled)
+        ));
+    }
+
+    #[test]
+    fn zenity() {
+        let params = question(MessageButtons::Custom(vec!["Replace", "Keep both"]));
+        let labels = ["Replace", "Keep both"];
+        assert_eq!(
+            zenity_args(&params, &labels),
+            vec![
+                "--question",
+                "--switch",
+                "--extra-button=Replace",
+                "--extra-button=Keep both",
+                "--icon-name=dialog-question",
+                "--title=Export",
+                "--no-markup",
+                "--text=Replace report.csv?",
+            ]
+        );
+
+        let responses = params.buttons.responses();
+        assert_eq!(
+            zenity_response(Some(1), "Keep both\n", &labels, &responses).unwrap(),
+            MessageResponse::Custom(1)
+        );
+        assert!(zenity_response(Some(1), "", &labels, &responses).is_err());
+
+        let params = MessageParams {
+            level: MessageLevel::Error,
+            text: "Import failed",
+            ..Default::default()
+        };
+        assert_eq!(
+            zenity_args(&params, &["OK"]),
+            vec![
+                "--error",
+                "--ok-label=OK",
+                "--icon-name=dialog-error",
+                "--no-markup",
+                "--text=Import failed",
+            ]
+        );
+        assert_eq!(
+            zenity_response(Some(0), "", &["OK"], &[MessageResponse::Ok]).unwrap(),
+            MessageResponse::Ok
+        );
+    }
+
+    #[test]
+    fn kdialog() {
+        let params = MessageParams {
+            level: MessageLevel::Warning,
+            ..question(MessageButtons::YesNoCancel)
+        };
+        assert_eq!(
+            kdialog_args(&params, &["Yes", "No", "Cancel"], 1).unwrap(),
+            vec![
+                "--warningyesnocancel",
+                "--yes-label=Yes",
+                "--no-label=No",
+                "--cancel-label=Cancel",
+                "Replace report.csv?",
+                "--title=Export",
+                "--default=No",
+            ]
+        );
+        assert!(kdialog_args(&params, &["1", "2", "3", "4"], 0).is_none());
+
+        let responses = params.buttons.responses();
+        assert_eq!(
+            kdialog_response(Some(1), &responses).unwrap(),
+            MessageResponse::No
+        );
+        assert_eq!(
+            kdialog_response(Some(2), &responses).unwrap(),
+            MessageResponse::Cancel
+        );
+        assert_eq!(
+            kdialog_response(None, &responses).unwrap(),
+            MessageResponse::Cancel
+        );
+    }
+
+    fn run_prompt(input: &str, default_button: usize) -> (Option<usize>, String) {
+        let params = question(MessageButtons::YesNoCancel);
+        let mut output = vec![];
+        let choice = prompt(
+            &mut Cursor::new(input),
+            &mut output,
+            &params,
+            &["Yes", "No", "Cancel"],
+            default_button,
+        )
+        .unwrap();
+        (choice, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn terminal_prompt() {
+        let (choice, output) = run_prompt("2\n", 0);
+        assert_eq!(choice, Some(1));
+        assert!(output.starts_with("Export\nReplace report.csv?\n  1) Yes\n  2) No\n  3) Cancel\n"));
+
+        assert_eq!(run_prompt("cancel\n", 0).0, Some(2));
+        assert_eq!(run_prompt("\n", 1).0, Some(1));
+        assert_eq!(run_prompt("", 0).0, None);
+
+        // Invalid answers are asked again
+        let (choice, output) = run_prompt("7\nmaybe\nyes\n", 0);
+        assert_eq!(choice, Some(0));
+        assert_eq!(output.matches("(1-3)").count(), 3);
+    }
+}
@@ -0,0 +1,671 @@
+// Resolution of MIME types to the glob patterns and descriptions of the files that they match.
+// On Windows these come from the content type associations in the registry, elsewhere from the
+// package files of the freedesktop.org shared-mime-info database, see
+// https://specifications.freedesktop.org/shared-mime-info-spec/latest/
+
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MimeType {
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) globs: Vec<String>,
+    pub(crate) aliases: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MimeDatabase {
+    types: Vec<MimeType>,
+}
+
+impl MimeDatabase {
+    // Returns the types matching a MIME type such as `image/png`, which can also be one of its
+    // aliases, or a wildcard such as `image/*` or `*/*`. Types are returned in alphabetical order.
+    pub(crate) fn lookup(&self, pattern: &str) -> Vec<&MimeType> {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        let mut types = self
+            .types
+            .iter()
+            .filter(|mime_type| matches(&pattern, mime_type))
+            .collect::<Vec<_>>();
+        types.sort_by(|a, b| a.name.cmp(&b.name));
+        types
+    }
+
+    // Adds a type, merging it with an existing definition of the same type. A description in
+    // the new definition replaces the existing one and its globs are added, unless `replace_globs`
+    // is set in which case they replace the existing globs.
+    fn merge(&mut self, mime_type: MimeType, replace_globs: bool) {
+        let existing = self
+            .types
+            .iter_mut()
+            .find(|existing| existing.name == mime_type.name);
+        let existing = match existing {
+            Some(existing) => existing,
+            None => {
+                self.types.push(mime_type);
+                return;
+            }
+        };
+
+        if mime_type.description.is_some() {
+            existing.description = mime_type.description;
+        }
+        if replace_globs {
+            existing.globs.clear();
+        }
+        for glob in mime_type.globs {
+            if !existing.globs.contains(&glob) {
+                existing.globs.push(glob);
+            }
+        }
+        for alias in mime_type.aliases {
+            if !existing.aliases.contains(&alias) {
+                existing.aliases.push(alias);
+            }
+        }
+    }
+}
+
+// Only used for the recently used list, which is not read from the database on Windows and macOS
+#[cfg(any(not(any(windows, target_os = "macos")), test))]
+impl MimeDatabase {
+    // Returns the type with a glob matching the file name. As in the shared-mime-info spec, the
+    // longest matching glob wins and globs are matched case-insensitively when no glob matches
+    // the exact case.
+    pub(crate) fn type_for_file_name(&self, file_name: &str) -> Option<&MimeType> {
+        let longest = |matched: &dyn Fn(&str) -> bool| {
+            self.types
+                .iter()
+                .flat_map(|mime_type| mime_type.globs.iter().map(move |glob| (mime_type, glob)))
+                .filter(|(_, glob)| matched(glob))
+                .max_by_key(|(_, glob)| glob.len())
+                .map(|(mime_type, _)| mime_type)
+        };
+
+        let lowercase = file_name.to_lowercase();
+        longest(&|glob| glob_matches(glob.as_bytes(), file_name.as_bytes())).or_else(|| {
+            longest(&|glob| glob_matches(glob.to_lowercase().as_bytes(), lowercase.as_bytes()))
+        })
+    }
+}
+
+// Matches a file name against a glob in which `*` matches any sequence of characters and `?`
+// any single byte. Other characters, including the rarely used `[...]` classes, match literally.
+#[cfg(any(not(any(windows, target_os = "macos")), test))]
+fn glob_matches(glob: &[u8], name: &[u8]) -> bool {
+    match (glob.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_matches(&glob[1..], name) || (!name.is_empty() && glob_matches(glob, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_matches(&glob[1..], &name[1..]),
+        (Some(g), Some(n)) if g == n => glob_matches(&glob[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+impl MimeDatabase {
+    // Returns the database of the system. It is read on first use and shared afterwards, and only
+    // read again when the locale of the descriptions changes.
+    pub(crate) fn system() -> Arc<MimeDatabase> {
+        static SYSTEM: Mutex<Option<(String, Arc<MimeDatabase>)>> = Mutex::new(None);
+
+        let locale = crate::current_locale();
+        let mut system = SYSTEM.lock().unwrap_or_else(|e| e.into_inner());
+        match &*system {
+            Some((system_locale, database)) if *system_locale == locale => Arc::clone(database),
+            _ => {
+                let database = Arc::new(MimeDatabase::read_system(&locale));
+                *system = Some((locale, Arc::clone(&database)));
+                database
+            }
+        }
+    }
+}
+
+fn matches(pattern: &str, mime_type: &MimeType) -> bool {
+    if pattern == "*" || pattern == "*/*" {
+        return true;
+    }
+    match pattern.strip_suffix("/*") {
+        Some(media_type) => mime_type
+            .name
+            .split('/')
+            .next()
+            .is_some_and(|name| name == media_type),
+        None => mime_type.name == pattern || mime_type.aliases.iter().any(|a| a == pattern),
+    }
+}
+
+#[cfg(windows)]
+impl MimeDatabase {
+    // Reads the content types registered under `HKEY_CLASSES_ROOT\MIME\Database\Content Type`.
+    // Each content type has a single associated extension, whose description is the name of the
+    // ProgID that the extension is associated with, i.e. `.png` -> `pngfile` -> `PNG File`. The
+    // descriptions are in the language of the registry, so the locale is not used.
+    fn read_system(_locale: &str) -> Self {
+        use winapi::um::winreg::HKEY_CLASSES_ROOT;
+
+        let mut database = MimeDatabase::default();
+        let content_types =
+            match registry::Key::open(HKEY_CLASSES_ROOT, r"MIME\Database\Content Type") {
+                Some(key) => key,
+                None => return database,
+            };
+
+        for name in content_types.subkey_names() {
+            let extension = content_types
+                .open_subkey(&name)
+                .and_then(|key| key.string_value(Some("Extension")))
+                .filter(|extension| extension.starts_with('.') && extension.len() > 1);
+            let extension = match extension {
+                Some(extension) => extension,
+                None => continue,
+            };
+
+            let description = registry::Key::open(HKEY_CLASSES_ROOT, &extension)
+                .and_then(|key| key.string_value(None))
+                .and_then(|prog_id| registry::Key::open(HKEY_CLASSES_ROOT, &prog_id))
+                .and_then(|key| key.string_value(None))
+                .filter(|description| !description.is_empty());
+
+            database.merge(
+                MimeType {
+                    name: name.to_ascii_lowercase(),
+                    description,
+                    globs: vec![format!("*{}", extension.to_ascii_lowercase())],
+                    aliases: Vec::new(),
+                },
+                false,
+            );
+        }
+        database
+    }
+}
+
+#[cfg(windows)]
+mod registry {
+    use std::ptr::{null, null_mut};
+
+    use winapi::shared::minwindef::{DWORD, HKEY};
+    use winapi::shared::winerror::ERROR_SUCCESS;
+    use winapi::um::winnt::{KEY_READ, REG_EXPAND_SZ, REG_SZ};
+    use winapi::um::winreg::{RegCloseKey, RegEnumKeyExW, RegOpenKeyExW, RegQueryValueExW};
+
+    use crate::NullTermUTF16;
+
+    // The maximum length of a registry key name, including the null terminator
+    const MAX_KEY_LENGTH: usize = 256;
+
+    // An open registry key that is closed when dropped
+    pub(super) struct Key(HKEY);
+
+    impl Key {
+        pub(super) fn open(parent: HKEY, name: &str) -> Option<Key> {
+            let name = name.as_null_term_utf16();
+            let mut key: HKEY = null_mut();
+            let result = unsafe { RegOpenKeyExW(parent, name.as_ptr(), 0, KEY_READ, &mut key) };
+            if result == ERROR_SUCCESS as i32 {
+                Some(Key(key))
+            } else {
+                None
+            }
+        }
+
+        pub(super) fn open_subkey(&self, name: &str) -> Option<Key> {
+            Key::open(self.0, name)
+        }
+
+        pub(super) fn subkey_names(&self) -> Vec<String> {
+            let mut names = Vec::new();
+            let mut buffer = [0u16; MAX_KEY_LENGTH];
+            for index in 0.. {
+                let mut length = buffer.len() as DWORD;
+                let result = unsafe {
+                    RegEnumKeyExW(
+                        self.0,
+                        index,
+                        buffer.as_mut_ptr(),
+                        &mut length,
+                        null_mut(),
+                        null_mut(),
+                        null_mut(),
+                        null_mut(),
+                    )
+                };
+                if result != ERROR_SUCCESS as i32 {
+                    break;
+                }
+                names.push(String::from_utf16_lossy(&buffer[..length as usize]));
+            }
+            names
+        }
+
+        // Reads a string value, or the default value of the key if `name` is `None`
+        pub(super) fn string_value(&self, name: Option<&str>) -> Option<String> {
+            let name = name.map(|name| name.as_null_term_utf16());
+            let name_ptr = name.as_ref().map_or(null(), |name| name.as_ptr());
+
+            let mut value_type: DWORD = 0;
+            let mut size: DWORD = 0;
+            let result = unsafe {
+                RegQueryValueExW(
+                    self.0,
+                    name_ptr,
+                    null_mut(),
+                    &mut value_type,
+                    null_mut(),
+                    &mut size,
+                )
+            };
+            if result != ERROR_SUCCESS as i32
+                || (value_type != REG_SZ && value_type != REG_EXPAND_SZ)
+            {
+                return None;
+            }
+
+            let mut buffer = vec![0u16; (size as usize).div_ceil(2)];
+            let result = unsafe {
+                RegQueryValueExW(
+                    self.0,
+                    name_ptr,
+                    null_mut(),
+                    null_mut(),
+                    buffer.as_mut_ptr() as *mut u8,
+                    &mut size,
+                )
+            };
+            if result != ERROR_SUCCESS as i32 {
+                return None;
+            }
+
+            // The stored value is not guaranteed to be null terminated
+            buffer.truncate(size as usize / 2);
+            while buffer.last() == Some(&0) {
+                buffer.pop();
+            }
+            Some(String::from_utf16_lossy(&buffer))
+        }
+    }
+
+    impl Drop for Key {
+        fn drop(&mut self) {
+            unsafe { RegCloseKey(self.0) };
+        }
+    }
+}
+
+#[cfg(not(windows))]
+impl MimeDatabase {
+    // Reads the package files of the shared-mime-info database in the XDG data directories
+    fn read_system(locale: &str) -> Self {
+        let mime_dirs = data_dirs(|name| std::env::var_os(name))
+            .into_iter()
+            .map(|dir| dir.join("mime"))
+            .collect::<Vec<_>>();
+        MimeDatabase::load(&mime_dirs, locale)
+    }
+}
+
+#[cfg(any(not(windows), test))]
+mod packages {
+    use std::ffi::OsString;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{MimeDatabase, MimeType};
+    use crate::locale;
+    use crate::xml::{self, Element, ParseError};
+
+    // Returns the XDG data directories in order of decreasing priority, starting with
+    // `$XDG_DATA_HOME` followed by the entries of `$XDG_DATA_DIRS`
+    pub(crate) fn data_dirs<F: Fn(&str) -> Option<OsString>>(lookup: F) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        match lookup("XDG_DATA_HOME").filter(|dir| Path::new(dir).is_absolute()) {
+            Some(data_home) => dirs.push(PathBuf::from(data_home)),
+            None => {
+                if let Some(home) = lookup("HOME") {
+                    dirs.push(PathBuf::from(home).join(".local").join("share"));
+                }
+            }
+        }
+
+        let data_dirs = lookup("XDG_DATA_DIRS")
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| OsString::from("/usr/local/share:/usr/share"));
+        dirs.extend(std::env::split_paths(&data_dirs).filter(|dir| dir.is_absolute()));
+        dirs
+    }
+
+    impl MimeDatabase {
+        // Loads the `packages/*.xml` files of each of the MIME directories, given in order of
+        // decreasing priority. Definitions in higher priority directories are merged into those
+        // of lower priority directories, and descriptions are read in the given locale if they
+        // have been translated. Files that cannot be read or parsed are skipped.
+        pub(crate) fn load(mime_dirs: &[PathBuf], locale: &str) -> Self {
+            let mut database = MimeDatabase::default();
+            for dir in mime_dirs.iter().rev() {
+                let mut files = match fs::read_dir(dir.join("packages")) {
+                    Ok(entries) => entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|path| path.extension().is_some_and(|e| e == "xml"))
+                        .collect::<Vec<_>>(),
+                    Err(_) => continue,
+                };
+                files.sort();
+
+                for file in files {
+                    if let Ok(contents) = fs::read_to_string(&file) {
+                        let _ = database.add_package(&contents, locale);
+                    }
+                }
+            }
+            database
+        }
+
+        // Adds the types defined by the contents of a package file
+        pub(crate) fn add_package(
+            &mut self,
+            contents: &str,
+            locale: &str,
+        ) -> Result<(), ParseError> {
+            let root = xml::parse(contents)?;
+            if root.name != "mime-info" {
+                return Err(ParseError(String::from(
+                    "the root element is not 'mime-info'",
+                )));
+            }
+
+            for element in root.elements().filter(|e| e.name == "mime-type") {
+                let name = match element.attribute("type") {
+                    Some(name) if name.contains('/') => name.to_ascii_lowercase(),
+                    _ => continue,
+                };
+                let replace_globs = element.child("glob-deleteall").is_some();
+                let mime_type = MimeType {
+                    name,
+                    description: description(element, locale),
+                    globs: element
+                        .elements()
+                        .filter(|e| e.name == "glob")
+                        .filter_map(|glob| glob.attribute("pattern"))
+                        .filter(|pattern| !pattern.is_empty())
+                        .map(String::from)
+                        .collect(),
+                    aliases: element
+                        .elements()
+                        .filter(|e| e.name == "alias")
+                        .filter_map(|alias| alias.attribute("type"))
+                        .map(str::to_ascii_lowercase)
+                        .collect(),
+                };
+                self.merge(mime_type, replace_globs);
+            }
+            Ok(())
+        }
+    }
+
+    // Returns the comment of a type in the most specific form of the locale that it has been
+    // translated into, or the untranslated comment
+    fn description(mime_type: &Element, locale: &str) -> Option<String> {
+        let comments = mime_type
+            .elements()
+            .filter(|e| e.name == "comment")
+            .map(|comment| {
+                let lang = comment.attribute("xml:lang").map(locale::normalize_locale);
+                (lang, comment.text().trim().to_string())
+            })
+            .filter(|(_, text)| !text.is_empty())
+            .collect::<Vec<_>>();
+
+        locale::fallback_chain(locale)
+            .iter()
+            .find_map(|locale| {
+                comments
+                    .iter()
+                    .find(|(lang, _)| lang.as_ref() == Some(locale))
+            })
+            .or_else(|| comments.iter().find(|(lang, _)| lang.is_none()))
+            .map(|(_, text)| text.clone())
+    }
+}
+
+#[cfg(any(not(windows), test))]
+use packages::data_dirs;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("mime")
+            .join(name)
+    }
+
+    fn fixture_database(locale: &str) -> MimeDatabase {
+        MimeDatabase::load(&[fixture_dir("user"), fixture_dir("system")], locale)
+    }
+
+    fn names(types: Vec<&MimeType>) -> Vec<&str> {
+        types.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_package() {
+        let mut database = MimeDatabase::default();
+        database
+            .add_package(
+                include_str!("../tests/fixtures/mime/system/packages/freedesktop.org.xml"),
+                "en",
+            )
+            .unwrap();
+
+        assert_eq!(
+            database.lookup("image/jpeg"),
+            vec![&MimeType {
+                name: String::from("image/jpeg"),
+                description: Some(String::from("JPEG image")),
+                globs: vec![
+                    String::from("*.jpg"),
+                    String::from("*.jpeg"),
+                    String::from("*.jpe")
+                ],
+                aliases: vec![String::from("image/pjpeg")],
+            }]
+        );
+        assert_eq!(
+            database.lookup("text/x-makefile")[0].globs,
+            vec!["makefile", "GNUmakefile", "*.mk"]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_package() {
+        let mut database = MimeDatabase::default();
+        assert!(database.add_package("<mime-info>", "en").is_err());
+        assert!(database.add_package("<other/>", "en").is_err());
+        assert!(database
+            .add_package(
+                r#"<mime-info><mime-type><glob pattern="*.x"/></mime-type></mime-info>"#,
+                "en"
+            )
+            .is_ok());
+        assert!(database.lookup("*/*").is_empty());
+    }
+
+    #[test]
+    fn lookup_types_and_aliases() {
+        let database = fixture_database("en");
+        assert_eq!(
+            names(database.lookup("application/pdf")),
+            vec!["application/pdf"]
+        );
+        assert_eq!(
+            names(database.lookup(" Application/PDF ")),
+            vec!["application/pdf"]
+        );
+        assert_eq!(names(database.lookup("image/pjpeg")), vec!["image/jpeg"]);
+        assert!(database.lookup("application/x-unknown").is_empty());
+    }
+
+    #[test]
+    fn lookup_wildcards() {
+        let database = fixture_database("en");
+        assert_eq!(
+            names(database.lookup("image/*")),
+            vec!["image/jpeg", "image/png", "image/x-ourapp-scene"]
+        );
+        assert_eq!(database.lookup("*/*").len(), database.lookup("*").len());
+        assert_eq!(
+            names(database.lookup("text/*")),
+            vec!["text/csv", "text/plain", "text/x-makefile"]
+        );
+        assert!(database.lookup("imag/*").is_empty());
+    }
+
+    #[test]
+    fn load_merges_directories() {
+        let database = fixture_database("en");
+
+        // The user package adds a new type
+        let scene = database.lookup("image/x-ourapp-scene");
+        assert_eq!(scene[0].description.as_deref(), Some("OurApp scene"));
+        assert_eq!(scene[0].globs, vec!["*.scene"]);
+
+        // and adds a glob to an existing type, keeping its description
+        let png = database.lookup("image/png");
+        assert_eq!(png[0].description.as_deref(), Some("PNG image"));
+        assert_eq!(png[0].globs, vec!["*.png", "*.apng"]);
+
+        // and replaces the globs of another with glob-deleteall
+        let csv = database.lookup("text/csv");
+        assert_eq!(
+            csv[0].description.as_deref(),
+            Some("Comma separated values")
+        );
+        assert_eq!(csv[0].globs, vec!["*.csv", "*.ourcsv"]);
+
+        // Packages that cannot be parsed are skipped
+        assert_eq!(database.lookup("*/*").len(), 7);
+    }
+
+    #[test]
+    fn load_translated_descriptions() {
+        let german = fixture_database("de_AT.UTF-8");
+        assert_eq!(
+            german.lookup("image/png")[0].description.as_deref(),
+            Some("PNG-Bild")
+        );
+        assert_eq!(
+            german.lookup("application/pdf")[0].description.as_deref(),
+            Some("PDF-Dokument")
+        );
+
+        let brazilian = fixture_database("pt-BR");
+        assert_eq!(
+            brazilian.lookup("image/png")[0].description.as_deref(),
+            Some("Imagem PNG")
+        );
+        let portuguese = fixture_database("pt");
+        assert_eq!(
+            portuguese.lookup("image/png")[0].description.as_deref(),
+            Some("Imagem PNG (Portugal)")
+        );
+
+        let japanese = fixture_database("ja");
+        assert_eq!(
+            japanese.lookup("text/plain")[0].description.as_deref(),
+            Some("プレーンテキストドキュメント")
+        );
+        assert_eq!(
+            japanese.lookup("image/jpeg")[0].description.as_deref(),
+            Some("JPEG image")
+        );
+    }
+
+    #[test]
+    fn type_for_file_names() {
+        let database = fixture_database("en");
+        let name = |file_name| {
+            database
+                .type_for_file_name(file_name)
+                .map(|mime_type| mime_type.name.as_str())
+        };
+        assert_eq!(name("report.pdf"), Some("application/pdf"));
+        assert_eq!(name("photo.JPG"), Some("image/jpeg"));
+        assert_eq!(name("data.ourcsv"), Some("text/csv"));
+        assert_eq!(name("GNUmakefile"), Some("text/x-makefile"));
+        assert_eq!(name("rules.mk"), Some("text/x-makefile"));
+        assert_eq!(name("archive.unknown"), None);
+        assert_eq!(name("pdf"), None);
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_matches(b"*.txt", b"a.txt"));
+        assert!(glob_matches(b"*.txt", b".txt"));
+        assert!(glob_matches(b"*.tar.*", b"a.tar.gz"));
+        assert!(glob_matches(b"README?", b"README1"));
+        assert!(!glob_matches(b"*.txt", b"a.txt.bak"));
+        assert!(!glob_matches(b"README?", b"README"));
+    }
+
+    #[test]
+    fn system_database_is_shared() {
+        assert!(Arc::ptr_eq(
+            &MimeDatabase::system(),
+            &MimeDatabase::system()
+        ));
+    }
+
+    #[test]
+    fn load_missing_directories() {
+        let database = MimeDatabase::load(&[fixture_dir("does-not-exist")], "en");
+        assert!(database.lookup("*/*").is_empty());
+    }
+
+    #[test]
+    fn xdg_data_dirs() {
+        let lookup = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+
+        assert_eq!(
+            data_dirs(lookup(&[("HOME", "/home/ben")])),
+            vec![
+                PathBuf::from("/home/ben/.local/share"),
+                PathBuf::from("/usr/local/share"),
+                PathBuf::from("/usr/share")
+            ]
+        );
+        assert_eq!(
+            data_dirs(lookup(&[
+                ("HOME", "/home/ben"),
+                ("XDG_DATA_HOME", "/data"),
+                ("XDG_DATA_DIRS", "/opt/share:relative:/usr/share")
+            ])),
+            vec![
+                PathBuf::from("/data"),
+                PathBuf::from("/opt/share"),
+                PathBuf::from("/usr/share")
+            ]
+        );
+        assert_eq!(
+            data_dirs(lookup(&[("XDG_DATA_HOME", "relative")])),
+            vec![
+                PathBuf::from("/usr/local/share"),
+                PathBuf::from("/usr/share")
+            ]
+        );
+    }
+}
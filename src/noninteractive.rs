@@ -0,0 +1,287 @@
+// Detection of environments in which no user can answer a dialog, such as CI jobs and services,
+// and a backend that answers dialogs from environment variables instead of showing them
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::backend::{Backend, DialogKind, Selection};
+use crate::filters::FileTypeLayout;
+use crate::selection::{RejectionReason, SelectionRejection};
+use crate::{DialogError, DialogParams, FOS_ALLOWMULTISELECT, HWND};
+
+const NONINTERACTIVE_VAR: &str = "WFD_NONINTERACTIVE";
+const SELECT_VAR: &str = "WFD_SELECT";
+const FILTER_INDEX_VAR: &str = "WFD_FILTER_INDEX";
+
+// Returns true if dialogs cannot be answered by a user. `WFD_NONINTERACTIVE` forces the mode on,
+// or off when set to `0`, `false` or `no`, otherwise it is detected from the platform.
+pub(crate) fn is_enabled() -> bool {
+    forced().unwrap_or_else(platform_is_noninteractive)
+}
+
+// Returns the mode set with `WFD_NONINTERACTIVE`, if any
+pub(crate) fn forced() -> Option<bool> {
+    from_var(env::var_os(NONINTERACTIVE_VAR))
+}
+
+fn from_var(value: Option<OsString>) -> Option<bool> {
+    let value = value?;
+    let value = value.to_string_lossy();
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" => None,
+        "0" | "false" | "no" => Some(false),
+        _ => Some(true),
+    }
+}
+
+// Services run in session 0, where no user is logged on, and processes running in a
+// non-interactive window station cannot show windows. Whether the process has a console is not
+// checked, as GUI applications have no console but can show dialogs.
+#[cfg(windows)]
+fn platform_is_noninteractive() -> bool {
+    use std::mem;
+    use std::ptr::null_mut;
+    use winapi::um::processthreadsapi::{GetCurrentProcessId, ProcessIdToSessionId};
+    use winapi::um::winuser::{
+        GetProcessWindowStation, GetUserObjectInformationW, UOI_FLAGS, USEROBJECTFLAGS, WSF_VISIBLE,
+    };
+
+    unsafe {
+        let mut session = 0;
+        if ProcessIdToSessionId(GetCurrentProcessId(), &mut session) != 0 && session == 0 {
+            return true;
+        }
+
+        let station = GetProcessWindowStation();
+        if station.is_null() {
+            return false;
+        }
+
+        let mut flags: USEROBJECTFLAGS = mem::zeroed();
+        let succeeded = GetUserObjectInformationW(
+            station as _,
+            UOI_FLAGS as i32,
+            &mut flags as *mut USEROBJECTFLAGS as *mut _,
+            mem::size_of::<USEROBJECTFLAGS>() as u32,
+            null_mut(),
+        );
+        succeeded != 0 && flags.dwFlags & WSF_VISIBLE == 0
+    }
+}
+
+// Dialogs need either an X11 or a Wayland display to be shown on
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_is_noninteractive() -> bool {
+    let is_set = |name| env::var_os(name).is_some_and(|value| !value.is_empty());
+    !is_set("DISPLAY") && !is_set("WAYLAND_DISPLAY")
+}
+
+#[cfg(target_os = "macos")]
+fn platform_is_noninteractive() -> bool {
+    false
+}
+
+// The answers to a dialog provided through the environment
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Answers {
+    // The paths in `WFD_SELECT`, separated in the same way as the `PATH` of the platform
+    pub(crate) paths: Vec<PathBuf>,
+    // The index in `WFD_FILTER_INDEX`, in the same form as `DialogParams::file_type_index`
+    pub(crate) file_type_index: Option<u32>,
+}
+
+impl Answers {
+    pub(crate) fn from_env() -> Answers {
+        Answers::parse(env::var_os(SELECT_VAR), env::var_os(FILTER_INDEX_VAR))
+    }
+
+    fn parse(select: Option<OsString>, filter_index: Option<OsString>) -> Answers {
+        Answers {
+            paths: select
+                .map(|select| {
+                    env::split_paths(&select)
+                        .filter(|path| !path.as_os_str().is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            file_type_index: filter_index
+                .and_then(|index| index.to_str().and_then(|i| i.trim().parse().ok())),
+        }
+    }
+}
+
+// Answers dialogs from the environment, failing with `DialogError::NonInteractive` if no paths
+// were provided, or with `DialogError::SelectionRejected` if several paths were provided to a
+// dialog that can only select one
+pub(crate) struct EnvBackend {
+    answers: Answers,
+    kind: DialogKind,
+    // Whether the dialog can select more than one item
+    multiselect: bool,
+    // The dialog index of the selected file type
+    file_type_index: u32,
+}
+
+impl EnvBackend {
+    pub(crate) fn new(answers: Answers) -> Self {
+        EnvBackend {
+            answers,
+            kind: DialogKind::Open,
+            multiselect: false,
+            file_type_index: 0,
+        }
+    }
+}
+
+impl Backend for EnvBackend {
+    fn create(&mut self, kind: DialogKind) -> Result<(), DialogError> {
+        self.kind = kind;
+        Ok(())
+    }
+
+    fn configure(&mut self, params: &DialogParams) -> Result<FileTypeLayout, DialogError> {
+        let layout = FileTypeLayout::new(&params.file_types, params.aggregate_filter);
+        let index = self
+            .answers
+            .file_type_index
+            .unwrap_or(params.file_type_index);
+        self.file_type_index = layout.dialog_index(index);
+        // Save dialogs always select a single item, as the native dialog does
+        self.multiselect = self.kind == DialogKind::Open
+            && params.options & FOS_ALLOWMULTISELECT != 0
+            && params.un_options & FOS_ALLOWMULTISELECT == 0;
+        Ok(layout)
+    }
+
+    fn show(&mut self, _owner: Option<HWND>) -> Result<(), DialogError> {
+        let count = self.answers.paths.len();
+        if count == 0 {
+            Err(DialogError::NonInteractive)
+        } else if count > 1 && !self.multiselect {
+            Err(DialogError::SelectionRejected {
+                rejections: vec![SelectionRejection {
+                    path: None,
+                    reason: RejectionReason::TooManyItems { max: 1, count },
+                }],
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn collect(&mut self) -> Result<Selection, DialogError> {
+        Ok(Selection {
+            paths: self.answers.paths.clone(),
+            file_type_index: self.file_type_index,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::filters::AggregateFilter;
+
+    #[test]
+    fn noninteractive_var() {
+        assert_eq!(from_var(None), None);
+        assert_eq!(from_var(Some("".into())), None);
+        assert_eq!(from_var(Some("1".into())), Some(true));
+        assert_eq!(from_var(Some("true".into())), Some(true));
+        assert_eq!(from_var(Some("0".into())), Some(false));
+        assert_eq!(from_var(Some("False".into())), Some(false));
+        assert_eq!(from_var(Some("no".into())), Some(false));
+    }
+
+    #[test]
+    fn parse_answers() {
+        let select = env::join_paths(["report.txt", "", "data.csv"]).unwrap();
+        let answers = Answers::parse(Some(select), Some(" 2 ".into()));
+        assert_eq!(
+            answers,
+            Answers {
+                paths: vec![PathBuf::from("report.txt"), PathBuf::from("data.csv")],
+                file_type_index: Some(2),
+            }
+        );
+
+        assert_eq!(Answers::parse(None, Some("x".into())), Answers::default());
+    }
+
+    #[test]
+    fn env_backend_selection() {
+        let params = DialogParams {
+            file_types: vec![("Text", "*.txt"), ("CSV", "*.csv")],
+            aggregate_filter: AggregateFilter::First,
+            file_type_index: 1,
+            ..Default::default()
+        };
+
+        let mut backend = EnvBackend::new(Answers {
+            paths: vec![PathBuf::from("data.csv")],
+            file_type_index: Some(2),
+        });
+        let selection = backend::run(&mut backend, DialogKind::Open, &params).unwrap();
+        assert_eq!(selection.paths, vec![PathBuf::from("data.csv")]);
+        assert_eq!(selection.file_type_index, 2);
+
+        // Without an index the default from the params is used
+        let mut backend = EnvBackend::new(Answers {
+            paths: vec![PathBuf::from("data.csv")],
+            file_type_index: None,
+        });
+        let selection = backend::run(&mut backend, DialogKind::Save, &params).unwrap();
+        assert_eq!(selection.file_type_index, 1);
+    }
+
+    #[test]
+    fn env_backend_multiple_paths() {
+        let answers = || Answers {
+            paths: vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")],
+            file_type_index: None,
+        };
+        let multiselect = DialogParams {
+            options: FOS_ALLOWMULTISELECT,
+            ..Default::default()
+        };
+
+        let mut backend = EnvBackend::new(answers());
+        let selection = backend::run(&mut backend, DialogKind::Open, &multiselect).unwrap();
+        assert_eq!(selection.paths, answers().paths);
+
+        let single_select = [
+            (DialogKind::Open, DialogParams::default()),
+            (
+                DialogKind::Open,
+                DialogParams {
+                    options: FOS_ALLOWMULTISELECT,
+                    un_options: FOS_ALLOWMULTISELECT,
+                    ..Default::default()
+                },
+            ),
+            (DialogKind::Save, multiselect),
+        ];
+        for (kind, params) in &single_select {
+            let mut backend = EnvBackend::new(answers());
+            match backend::run(&mut backend, *kind, params) {
+                Err(DialogError::SelectionRejected { rejections }) => assert_eq!(
+                    rejections
+                        .into_iter()
+                        .map(|rejection| (rejection.path, rejection.reason))
+                        .collect::<Vec<_>>(),
+                    [(None, RejectionReason::TooManyItems { max: 1, count: 2 })]
+                ),
+                result => panic!("unexpected result {:?}", result.map(|s| s.paths)),
+            }
+        }
+    }
+
+    #[test]
+    fn env_backend_without_answers() {
+        let mut backend = EnvBackend::new(Answers::default());
+        let result = backend::run(&mut backend, DialogKind::Open, &DialogParams::default());
+        assert!(matches!(result, Err(DialogError::NonInteractive)));
+    }
+}
@@ -0,0 +1,87 @@
+// The FOS_* flags of `DialogParams::options` and `un_options`, defined here with the values of the
+// Windows SDK so that they are available on every platform
+
+/// Prompts for confirmation when the file selected in a Save dialog already exists
+pub const FOS_OVERWRITEPROMPT: u32 = 0x2;
+/// Only allows files with one of the extensions of the selected file type to be returned
+pub const FOS_STRICTFILETYPES: u32 = 0x4;
+/// Doesn't change the current working directory of the process
+pub const FOS_NOCHANGEDIR: u32 = 0x8;
+/// Selects folders rather than files
+pub const FOS_PICKFOLDERS: u32 = 0x20;
+/// Ensures that the returned items are file system items
+pub const FOS_FORCEFILESYSTEM: u32 = 0x40;
+/// Allows items that are not file system items to be selected
+pub const FOS_ALLNONSTORAGEITEMS: u32 = 0x80;
+/// Doesn't check for situations that would prevent the selected file from being opened
+pub const FOS_NOVALIDATE: u32 = 0x100;
+/// Allows multiple items to be selected in an Open dialog
+pub const FOS_ALLOWMULTISELECT: u32 = 0x200;
+/// Requires the selected item to be in an existing folder
+pub const FOS_PATHMUSTEXIST: u32 = 0x800;
+/// Requires the selected item to exist
+pub const FOS_FILEMUSTEXIST: u32 = 0x1000;
+/// Prompts for confirmation before creating a file that does not exist
+pub const FOS_CREATEPROMPT: u32 = 0x2000;
+/// Lets the application handle sharing violations for the selected file
+pub const FOS_SHAREAWARE: u32 = 0x4000;
+/// Doesn't return read-only items
+pub const FOS_NOREADONLYRETURN: u32 = 0x8000;
+/// Doesn't test whether the selected file can be created
+pub const FOS_NOTESTFILECREATE: u32 = 0x10000;
+/// Hides the list of recently used places
+pub const FOS_HIDEMRUPLACES: u32 = 0x20000;
+/// Hides the items shown in the navigation pane by default
+pub const FOS_HIDEPINNEDPLACES: u32 = 0x40000;
+/// Returns shortcut files themselves rather than the items that they refer to
+pub const FOS_NODEREFERENCELINKS: u32 = 0x100000;
+/// Doesn't add the selected item to the list of recently used documents
+pub const FOS_DONTADDTORECENT: u32 = 0x2000000;
+/// Shows hidden and system items
+pub const FOS_FORCESHOWHIDDEN: u32 = 0x10000000;
+/// Shows the dialog in its default mode rather than in a minimal mode
+pub const FOS_DEFAULTNOMINIMODE: u32 = 0x20000000;
+/// Shows the preview pane
+pub const FOS_FORCEPREVIEWPANEON: u32 = 0x40000000;
+/// Allows items that can be streamed but are not file system items to be selected
+pub const FOS_SUPPORTSTREAMABLEITEMS: u32 = 0x80000000;
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+    use winapi::um::shobjidl;
+
+    #[test]
+    fn windows_sdk_values() {
+        let options = [
+            (FOS_OVERWRITEPROMPT, shobjidl::FOS_OVERWRITEPROMPT),
+            (FOS_STRICTFILETYPES, shobjidl::FOS_STRICTFILETYPES),
+            (FOS_NOCHANGEDIR, shobjidl::FOS_NOCHANGEDIR),
+            (FOS_PICKFOLDERS, shobjidl::FOS_PICKFOLDERS),
+            (FOS_FORCEFILESYSTEM, shobjidl::FOS_FORCEFILESYSTEM),
+            (FOS_ALLNONSTORAGEITEMS, shobjidl::FOS_ALLNONSTORAGEITEMS),
+            (FOS_NOVALIDATE, shobjidl::FOS_NOVALIDATE),
+            (FOS_ALLOWMULTISELECT, shobjidl::FOS_ALLOWMULTISELECT),
+            (FOS_PATHMUSTEXIST, shobjidl::FOS_PATHMUSTEXIST),
+            (FOS_FILEMUSTEXIST, shobjidl::FOS_FILEMUSTEXIST),
+            (FOS_CREATEPROMPT, shobjidl::FOS_CREATEPROMPT),
+            (FOS_SHAREAWARE, shobjidl::FOS_SHAREAWARE),
+            (FOS_NOREADONLYRETURN, shobjidl::FOS_NOREADONLYRETURN),
+            (FOS_NOTESTFILECREATE, shobjidl::FOS_NOTESTFILECREATE),
+            (FOS_HIDEMRUPLACES, shobjidl::FOS_HIDEMRUPLACES),
+            (FOS_HIDEPINNEDPLACES, shobjidl::FOS_HIDEPINNEDPLACES),
+            (FOS_NODEREFERENCELINKS, shobjidl::FOS_NODEREFERENCELINKS),
+            (FOS_DONTADDTORECENT, shobjidl::FOS_DONTADDTORECENT),
+            (FOS_FORCESHOWHIDDEN, shobjidl::FOS_FORCESHOWHIDDEN),
+            (FOS_DEFAULTNOMINIMODE, shobjidl::FOS_DEFAULTNOMINIMODE),
+            (FOS_FORCEPREVIEWPANEON, shobjidl::FOS_FORCEPREVIEWPANEON),
+            (
+                FOS_SUPPORTSTREAMABLEITEMS,
+                shobjidl::FOS_SUPPORTSTREAMABLEITEMS,
+            ),
+        ];
+        for (value, sdk_value) in options.iter() {
+            assert_eq!(value, sdk_value);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The maximum number of numbered names tried before giving up on finding an unused name
+const MAX_RENAME_ATTEMPTS: u32 = 10_000;
+
+/// Determines what happens when the file selected in a Save dialog already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// The dialog asks the user to confirm that the existing file should be replaced, using the
+    /// shell's `FOS_OVERWRITEPROMPT` behaviour. Removing `FOS_OVERWRITEPROMPT` with `un_options`
+    /// disables the prompt.
+    #[default]
+    ShellPrompt,
+    /// Existing files cannot be selected. The user is told that the file already exists and the
+    /// dialog is shown again until a file that does not exist is selected.
+    Refuse,
+    /// Existing files are returned without asking the user for confirmation
+    Allow,
+    /// When an existing file is selected, a numbered name such as `name (1).ext` that does not
+    /// exist is returned instead. An empty file is created with the returned name to reserve it,
+    /// so that the same name cannot be handed out to another writer before the caller writes it.
+    AutoRename,
+}
+
+// Atomically creates an empty file with the first numbered variant of `path` that does not
+// exist, returning its path. Creating the file with `create_new` means that a name taken by
+// another process between checking and creating it is skipped rather than overwritten.
+pub(crate) fn reserve_available_path(path: &Path) -> io::Result<PathBuf> {
+    for candidate in numbered_paths(path).take(MAX_RENAME_ATTEMPTS as usize) {
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no unused numbered file name is available",
+    ))
+}
+
+// Returns an iterator over the numbered variants of `path`. If the file stem already ends with a
+// number in brackets, i.e. `name (3).ext`, numbering continues from that number. The numbers stop
+// at `u32::MAX`, the largest that is recognised as a suffix.
+fn numbered_paths(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    // The name is built from the stem and extension as they are, so that names which aren't
+    // valid Unicode are numbered without loss
+    let stem = path.file_stem().unwrap_or_default();
+    let (base, first) = match split_number_suffix(stem) {
+        Some((base, number)) => (base, u64::from(number) + 1),
+        None => (stem, 1),
+    };
+
+    (first..=u64::from(u32::MAX)).map(move |number| {
+        let mut file_name = base.to_os_string();
+        file_name.push(format!(" ({})", number));
+        if let Some(extension) = path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        path.with_file_name(file_name)
+    })
+}
+
+// Splits a trailing ` (n)` from a file stem, returning the remaining stem and the number
+fn split_number_suffix(stem: &OsStr) -> Option<(&OsStr, u32)> {
+    let inner = stem.as_encoded_bytes().strip_suffix(b")")?;
+    let open = inner.windows(2).rposition(|pair| pair == b" (")?;
+    let digits = &inner[open + 2..];
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) || open == 0 {
+        return None;
+    }
+    let number = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    // The stem is split before an ASCII character, which keeps the encoding valid
+    let base = unsafe { OsStr::from_encoded_bytes_unchecked(&inner[..open]) };
+    Some((base, number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn next_available(path: &str, existing: &[&str]) -> Option<PathBuf> {
+        let existing = existing.iter().map(PathBuf::from).collect::<HashSet<_>>();
+        numbered_paths(Path::new(path)).find(|candidate| !existing.contains(candidate))
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wfd-overwrite-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn numbered_paths_numbers_files() {
+        assert_eq!(
+            next_available("dir/report.txt", &[]),
+            Some(PathBuf::from("dir/report (1).txt"))
+        );
+        assert_eq!(
+            next_available(
+                "dir/report.txt",
+                &["dir/report (1).txt", "dir/report (2).txt"]
+            ),
+            Some(PathBuf::from("dir/report (3).txt"))
+        );
+        assert_eq!(
+            next_available("dir/report.txt", &["dir/report (2).txt"]),
+            Some(PathBuf::from("dir/report (1).txt"))
+        );
+    }
+
+    #[test]
+    fn numbered_paths_continues_existing_numbering() {
+        assert_eq!(
+            next_available("report (1).txt", &[]),
+            Some(PathBuf::from("report (2).txt"))
+        );
+        assert_eq!(
+            next_available("report (9).txt", &["report (10).txt"]),
+            Some(PathBuf::from("report (11).txt"))
+        );
+        assert_eq!(
+            next_available("report (draft).txt", &[]),
+            Some(PathBuf::from("report (draft) (1).txt"))
+        );
+        assert_eq!(
+            next_available("(3).txt", &[]),
+            Some(PathBuf::from("(3) (1).txt"))
+        );
+    }
+
+    #[test]
+    fn numbered_paths_extensions() {
+        assert_eq!(
+            next_available("README", &[]),
+            Some(PathBuf::from("README (1)"))
+        );
+        assert_eq!(
+            next_available("archive.tar.gz", &[]),
+            Some(PathBuf::from("archive.tar (1).gz"))
+        );
+        assert_eq!(
+            next_available(".gitignore", &[]),
+            Some(PathBuf::from(".gitignore (1)"))
+        );
+    }
+
+    #[test]
+    fn numbered_paths_stop_at_largest_number() {
+        assert_eq!(
+            next_available("report (4294967294).txt", &[]),
+            Some(PathBuf::from("report (4294967295).txt"))
+        );
+        assert_eq!(
+            next_available("report (4294967294).txt", &["report (4294967295).txt"]),
+            None
+        );
+        assert_eq!(next_available("report (4294967295).txt", &[]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn numbered_paths_keep_non_unicode_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"dir/r\xe9sum\xe9 (2).txt"));
+        assert_eq!(
+            numbered_paths(path).next().unwrap(),
+            Path::new(OsStr::from_bytes(b"dir/r\xe9sum\xe9 (3).txt"))
+        );
+    }
+
+    #[test]
+    fn reserve_available_path_creates_placeholder() {
+        let dir = temp_dir("reserve");
+        let path = dir.join("export.csv");
+        fs::write(&path, "existing").unwrap();
+        fs::write(dir.join("export (1).csv"), "existing").unwrap();
+
+        let reserved = reserve_available_path(&path).unwrap();
+        assert_eq!(reserved, dir.join("export (2).csv"));
+        assert_eq!(fs::read(&reserved).unwrap(), b"");
+
+        // The reserved name is not handed out again
+        let reserved_again = reserve_available_path(&path).unwrap();
+        assert_eq!(reserved_again, dir.join("export (3).csv"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reserve_available_path_runs_out_of_numbers() {
+        let dir = temp_dir("exhausted");
+        for name in &["export (4294967294).csv", "export (4294967295).csv"] {
+            fs::write(dir.join(name), "existing").unwrap();
+        }
+
+        for name in &["export (4294967294).csv", "export (4294967295).csv"] {
+            let error = reserve_available_path(&dir.join(name)).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reserve_available_path_missing_directory() {
+        let dir = temp_dir("missing");
+        let path = dir.join("does-not-exist").join("export.csv");
+        assert!(reserve_available_path(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
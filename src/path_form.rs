@@ -0,0 +1,429 @@
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+// The number of UTF-16 code units, including the terminating null, that fit within a path
+// buffer for APIs that do not support long paths
+const MAX_PATH: usize = 260;
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+const DEVICE_PREFIX: &str = r"\\.\";
+const UNC_PREFIX: &str = r"\\";
+
+/// The form in which the paths selected in a dialog are returned
+///
+/// A verbatim path with a name that has no plain form, such as a name ending in a dot or a space,
+/// or a reserved device name like `NUL` or `aux.txt`, is always returned in verbatim form, as the
+/// plain form would refer to a different item.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathForm {
+    /// Paths are returned exactly as provided by the dialog
+    #[default]
+    Unchanged,
+    /// Paths are returned without a verbatim (`\\?\`) prefix, i.e. `C:\dir\file.txt` and
+    /// `\\server\share\file.txt`
+    Plain,
+    /// Paths are returned in plain form, unless they are too long to fit within `MAX_PATH` in
+    /// which case they are returned in verbatim form, i.e. `\\?\C:\...` or `\\?\UNC\server\...`
+    VerbatimWhenLong,
+    /// The same as `VerbatimWhenLong`, except that UNC paths are always returned in plain
+    /// `\\server\share` form regardless of their length
+    PreserveUnc,
+}
+
+impl PathForm {
+    // Converts a path returned from a dialog into this form. Paths which aren't valid Unicode are
+    // converted without loss, since only the ASCII prefix is changed.
+    pub(crate) fn apply(self, path: PathBuf) -> PathBuf {
+        if self == PathForm::Unchanged {
+            return path;
+        }
+
+        let converted = self.apply_bytes(path.as_os_str().as_encoded_bytes());
+        // Only the ASCII prefix is added, removed or replaced, which keeps the encoding valid
+        PathBuf::from(unsafe { OsString::from_encoded_bytes_unchecked(converted) })
+    }
+
+    fn apply_bytes(self, path: &[u8]) -> Vec<u8> {
+        let plain = to_plain_bytes(path);
+        // The plain form of a verbatim path with such names would refer to a different item
+        if plain.len() != path.len() && has_verbatim_only_name(&plain) {
+            return path.to_vec();
+        }
+
+        let is_long = utf16_len(&plain) >= MAX_PATH;
+        match self {
+            PathForm::Unchanged => path.to_vec(),
+            PathForm::Plain => plain,
+            PathForm::VerbatimWhenLong if is_long => to_verbatim(&plain),
+            PathForm::PreserveUnc if is_long && !plain.starts_with(UNC_PREFIX.as_bytes()) => {
+                to_verbatim(&plain)
+            }
+            PathForm::VerbatimWhenLong | PathForm::PreserveUnc => plain,
+        }
+    }
+}
+
+// Normalizes a path provided as input to a dialog into the plain form understood by the shell.
+// Forward slashes are converted to backslashes, verbatim prefixes are removed and repeated
+// separators are collapsed. A verbatim path is kept as it is if it cannot be written in plain
+// form, because it is too long to fit within `MAX_PATH` or has a name that only exists in
+// verbatim form, see `has_verbatim_only_name`. Shell parsing names such as `shell:Downloads` or `::{GUID}` are
+// returned unchanged. Paths which aren't valid Unicode are normalized without loss, since only
+// ASCII characters are changed.
+pub(crate) fn normalize_input(path: &OsStr) -> OsString {
+    let normalized = normalize_input_bytes(path.as_encoded_bytes());
+    // Only ASCII characters are added, removed or replaced, which keeps the encoding valid
+    unsafe { OsString::from_encoded_bytes_unchecked(normalized) }
+}
+
+fn normalize_input_bytes(path: &[u8]) -> Vec<u8> {
+    if is_shell_parsing_name(path) {
+        return path.to_vec();
+    }
+
+    let path = path
+        .iter()
+        .map(|&b| if b == b'/' { b'\\' } else { b })
+        .collect::<Vec<_>>();
+    let plain = to_plain_bytes(&path);
+    if plain.len() != path.len() && !is_plain_round_trip(&plain) {
+        return path;
+    }
+    let path = plain;
+    if path.starts_with(DEVICE_PREFIX.as_bytes()) {
+        return path;
+    }
+
+    // Keep the leading double separator of UNC paths, but collapse any other repeated separators
+    let prefix_len = if path.starts_with(UNC_PREFIX.as_bytes()) {
+        UNC_PREFIX.len()
+    } else {
+        0
+    };
+    let mut normalized = Vec::with_capacity(path.len());
+    normalized.extend_from_slice(&path[..prefix_len]);
+    for &b in &path[prefix_len..] {
+        if !(b == b'\\' && normalized.ends_with(b"\\") && normalized.len() > prefix_len) {
+            normalized.push(b);
+        }
+    }
+    normalized
+}
+
+// Returns true if a path that had a verbatim prefix refers to the same item in plain form
+fn is_plain_round_trip(plain: &[u8]) -> bool {
+    utf16_len(plain) < MAX_PATH && !has_verbatim_only_name(plain)
+}
+
+// Returns true if the path has a name that can only be used in verbatim form. In plain form a
+// name ending in a dot or a space is trimmed, including `..` which then refers to the parent, and
+// a reserved device name such as `NUL` or `aux.txt` refers to the device.
+fn has_verbatim_only_name(path: &[u8]) -> bool {
+    path.split(|&b| b == b'\\')
+        .any(|name| name.ends_with(b".") || name.ends_with(b" ") || is_device_name(name))
+}
+
+// Returns true for `CON`, `PRN`, `AUX`, `NUL`, `COM1` to `COM9` and `LPT1` to `LPT9`, in any case
+// and with any extension
+fn is_device_name(name: &[u8]) -> bool {
+    let stem = name.split(|&b| b == b'.').next().unwrap_or_default();
+    let stem = stem.to_ascii_uppercase();
+    match stem.as_slice() {
+        b"CON" | b"PRN" | b"AUX" | b"NUL" => true,
+        [b'C', b'O', b'M', digit] | [b'L', b'P', b'T', digit] => (b'1'..=b'9').contains(digit),
+        _ => false,
+    }
+}
+
+// Counts the UTF-16 code units of a path in the encoding of `OsStr`, where characters outside of
+// the Basic Multilingual Plane take four bytes and two code units
+fn utf16_len(path: &[u8]) -> usize {
+    path.iter()
+        .map(|&b| match b {
+            0x80..=0xBF => 0,
+            0xF0..=0xFF => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+// Returns true if the path is a shell parsing name rather than a filesystem path, i.e.
+// `shell:Downloads` or `::{20D04FE0-3AEA-1069-A2D8-08002B30309D}`
+fn is_shell_parsing_name(path: &[u8]) -> bool {
+    path.starts_with(b"::")
+        || path.iter().position(|&b| b == b':').is_some_and(|index| {
+            index > 1 && !path[..index].iter().any(|&b| b == b'\\' || b == b'/')
+        })
+}
+
+// Removes the verbatim prefix from drive and UNC paths
+fn to_plain_bytes(path: &[u8]) -> Vec<u8> {
+    if let Some(rest) = strip_prefix_ignore_case(path, VERBATIM_UNC_PREFIX.as_bytes()) {
+        [UNC_PREFIX.as_bytes(), rest].concat()
+    } else if let Some(rest) = path
+        .strip_prefix(VERBATIM_PREFIX.as_bytes())
+        .filter(|r| is_drive_path(r))
+    {
+        rest.to_vec()
+    } else {
+        path.to_vec()
+    }
+}
+
+// Adds a verbatim prefix to absolute drive and UNC paths
+fn to_verbatim(path: &[u8]) -> Vec<u8> {
+    if path.starts_with(VERBATIM_PREFIX.as_bytes()) || path.starts_with(DEVICE_PREFIX.as_bytes()) {
+        path.to_vec()
+    } else if let Some(rest) = path.strip_prefix(UNC_PREFIX.as_bytes()) {
+        [VERBATIM_UNC_PREFIX.as_bytes(), rest].concat()
+    } else if is_drive_path(path) {
+        [VERBATIM_PREFIX.as_bytes(), path].concat()
+    } else {
+        path.to_vec()
+    }
+}
+
+// Returns true for absolute paths starting with a drive letter, i.e. `C:\`
+fn is_drive_path(path: &[u8]) -> bool {
+    path.len() >= 3 && path[0].is_ascii_alphabetic() && path[1] == b':' && path[2] == b'\\'
+}
+
+fn strip_prefix_ignore_case<'a>(path: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
+    let candidate = path.get(..prefix.len())?;
+    if candidate.eq_ignore_ascii_case(prefix) {
+        Some(&path[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn long_path(prefix: &str) -> String {
+        let mut path = prefix.to_string();
+        while path.len() < MAX_PATH {
+            path.push_str(r"\directory");
+        }
+        path
+    }
+
+    fn normalize(path: &str) -> String {
+        normalize_input(OsStr::new(path)).into_string().unwrap()
+    }
+
+    impl PathForm {
+        fn apply_str(self, path: &str) -> String {
+            String::from_utf8(self.apply_bytes(path.as_bytes())).unwrap()
+        }
+    }
+
+    #[test]
+    fn normalize_input_paths() {
+        assert_eq!(normalize(r"C:\Users\ben"), r"C:\Users\ben");
+        assert_eq!(normalize("C:/Users/ben/"), r"C:\Users\ben\");
+        assert_eq!(normalize(r"C:\\Users\\\ben"), r"C:\Users\ben");
+        assert_eq!(normalize(r"\\?\C:\Users\ben"), r"C:\Users\ben");
+        assert_eq!(normalize("//?/C:/Users/ben"), r"C:\Users\ben");
+        assert_eq!(
+            normalize(r"\\?\UNC\server\share\dir"),
+            r"\\server\share\dir"
+        );
+        assert_eq!(normalize(r"\\?\unc\server\share"), r"\\server\share");
+        assert_eq!(normalize("//server/share//dir"), r"\\server\share\dir");
+        assert_eq!(normalize(r"\\server\share"), r"\\server\share");
+        assert_eq!(normalize(r"\\.\PhysicalDrive0"), r"\\.\PhysicalDrive0");
+        assert_eq!(normalize(r"\\?\Volume{guid}\dir"), r"\\?\Volume{guid}\dir");
+        assert_eq!(normalize("relative/dir"), r"relative\dir");
+        assert_eq!(normalize("C:relative"), "C:relative");
+    }
+
+    #[test]
+    fn normalize_input_keeps_long_verbatim_paths() {
+        let long = long_path(r"C:");
+        let verbatim = format!(r"\\?\{}", long);
+        assert_eq!(normalize(&verbatim), verbatim);
+        assert_eq!(normalize(&verbatim.replace('\\', "/")), verbatim);
+
+        let long_unc = format!(r"\\?\UNC\{}", &long_path(r"\\server\share")[2..]);
+        assert_eq!(normalize(&long_unc), long_unc);
+
+        // The length is counted in UTF-16 code units rather than bytes
+        let mut short = String::from(r"C:\");
+        short.push_str(&"é".repeat(MAX_PATH - 1 - short.len()));
+        assert_eq!(normalize(&format!(r"\\?\{}", short)), short);
+        short.push('😀');
+        let verbatim = format!(r"\\?\{}", short);
+        assert_eq!(normalize(&verbatim), verbatim);
+    }
+
+    #[test]
+    fn normalize_input_keeps_verbatim_trailing_dots_and_spaces() {
+        for path in &[
+            r"\\?\C:\dir\name.",
+            r"\\?\C:\dir\name ",
+            r"\\?\C:\dir. \file.txt",
+            r"\\?\UNC\server\share\name.",
+            r"\\?\C:\dir\..\file.txt",
+            r"\\?\C:\dir\NUL",
+            r"\\?\C:\dir\aux.txt",
+        ] {
+            assert_eq!(normalize(path), *path);
+        }
+        // Plain paths are normalized as usual
+        assert_eq!(normalize(r"C:\dir\\name."), r"C:\dir\name.");
+    }
+
+    #[test]
+    fn normalize_input_leaves_shell_parsing_names() {
+        assert_eq!(normalize("shell:Downloads"), "shell:Downloads");
+        assert_eq!(normalize("shell:Libraries/Music"), "shell:Libraries/Music");
+        assert_eq!(
+            normalize("::{20D04FE0-3AEA-1069-A2D8-08002B30309D}"),
+            "::{20D04FE0-3AEA-1069-A2D8-08002B30309D}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalize_input_preserves_invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = OsStr::from_bytes(b"//?/C:/Users//\xff\xfeben/");
+        assert_eq!(
+            normalize_input(path).as_bytes(),
+            b"C:\\Users\\\xff\xfeben\\"
+        );
+    }
+
+    #[test]
+    fn apply_unchanged() {
+        for path in &[r"\\?\C:\dir", r"\\server\share", r"C:\dir"] {
+            assert_eq!(PathForm::Unchanged.apply_str(path), *path);
+        }
+    }
+
+    #[test]
+    fn apply_plain() {
+        assert_eq!(PathForm::Plain.apply_str(r"\\?\C:\dir"), r"C:\dir");
+        assert_eq!(
+            PathForm::Plain.apply_str(r"\\?\UNC\server\share"),
+            r"\\server\share"
+        );
+        assert_eq!(
+            PathForm::Plain.apply_str(r"\\server\share"),
+            r"\\server\share"
+        );
+        assert_eq!(PathForm::Plain.apply_str(r"C:\dir"), r"C:\dir");
+
+        let long = long_path(r"C:");
+        let verbatim = format!(r"\\?\{}", long);
+        assert_eq!(PathForm::Plain.apply_str(&verbatim), long);
+    }
+
+    #[test]
+    fn apply_verbatim_when_long() {
+        let form = PathForm::VerbatimWhenLong;
+        assert_eq!(form.apply_str(r"\\?\C:\dir"), r"C:\dir");
+        assert_eq!(form.apply_str(r"\\server\share"), r"\\server\share");
+
+        let long = long_path(r"C:");
+        assert_eq!(form.apply_str(&long), format!(r"\\?\{}", long));
+        assert_eq!(
+            form.apply_str(&format!(r"\\?\{}", long)),
+            format!(r"\\?\{}", long)
+        );
+
+        let long_unc = long_path(r"\\server\share");
+        assert_eq!(
+            form.apply_str(&long_unc),
+            format!(r"\\?\UNC\{}", &long_unc[2..])
+        );
+    }
+
+    #[test]
+    fn apply_preserve_unc() {
+        let form = PathForm::PreserveUnc;
+        assert_eq!(form.apply_str(r"\\?\UNC\server\share"), r"\\server\share");
+
+        let long = long_path(r"C:");
+        assert_eq!(form.apply_str(&long), format!(r"\\?\{}", long));
+
+        let long_unc = long_path(r"\\server\share");
+        assert_eq!(form.apply_str(&long_unc), long_unc);
+        assert_eq!(
+            form.apply_str(&format!(r"\\?\UNC\{}", &long_unc[2..])),
+            long_unc
+        );
+    }
+
+    #[test]
+    fn apply_keeps_verbatim_only_names() {
+        for path in &[
+            r"\\?\C:\dir\name.",
+            r"\\?\C:\dir\name ",
+            r"\\?\C:\dir\..\file.txt",
+            r"\\?\UNC\server\share\name.",
+            r"\\?\C:\dir\con",
+            r"\\?\C:\dir\Com1.log",
+            r"\\?\C:\dir\lpt9",
+        ] {
+            for form in &[
+                PathForm::Plain,
+                PathForm::VerbatimWhenLong,
+                PathForm::PreserveUnc,
+            ] {
+                assert_eq!(form.apply_str(path), *path);
+            }
+        }
+
+        // Names that only look like device names have a plain form
+        for name in &["auxiliary.txt", "COM10", "LPT0", "nul-device"] {
+            assert_eq!(
+                PathForm::Plain.apply_str(&format!(r"\\?\C:\dir\{}", name)),
+                format!(r"C:\dir\{}", name)
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_converts_invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"\\\\?\\C:\\Users\\\xff\\file"));
+        assert_eq!(
+            PathForm::Plain.apply(path).as_os_str().as_bytes(),
+            b"C:\\Users\\\xff\\file"
+        );
+
+        let mut long = b"C:".to_vec();
+        while long.len() < MAX_PATH {
+            long.extend_from_slice(b"\\\xff\xfe");
+        }
+        let path = PathBuf::from(OsStr::from_bytes(&long));
+        assert_eq!(
+            PathForm::VerbatimWhenLong
+                .apply(path)
+                .as_os_str()
+                .as_bytes(),
+            [b"\\\\?\\".as_ref(), &long].concat()
+        );
+    }
+
+    #[test]
+    fn apply_length_threshold() {
+        let mut path = String::from(r"C:\");
+        path.push_str(&"a".repeat(MAX_PATH - 1 - path.len()));
+        assert_eq!(path.len(), MAX_PATH - 1);
+        assert_eq!(PathForm::VerbatimWhenLong.apply_str(&path), path);
+
+        path.push('a');
+        assert_eq!(
+            PathForm::VerbatimWhenLong.apply_str(&path),
+            format!(r"\\?\{}", path)
+        );
+    }
+}
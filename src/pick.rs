@@ -0,0 +1,490 @@
+// Purpose-specific entry points that set the options needed to pick files or folders and return
+// only the selected paths
+
+use std::fs;
+use std::ops::{BitOr, BitOrAssign};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    open_dialog, save_dialog, AggregateFilter, DialogError, DialogParams, FolderSpec,
+    InvalidPathBehavior, ItemFilter, OverwritePolicy, PathForm, SanitizeOptions, SelectionRules,
+    ShortcutResolution, FOS_ALLNONSTORAGEITEMS, FOS_ALLOWMULTISELECT, FOS_CREATEPROMPT,
+    FOS_DEFAULTNOMINIMODE, FOS_DONTADDTORECENT, FOS_FILEMUSTEXIST, FOS_FORCEFILESYSTEM,
+    FOS_FORCEPREVIEWPANEON, FOS_FORCESHOWHIDDEN, FOS_HIDEMRUPLACES, FOS_HIDEPINNEDPLACES,
+    FOS_NOCHANGEDIR, FOS_NODEREFERENCELINKS, FOS_NOREADONLYRETURN, FOS_NOTESTFILECREATE,
+    FOS_NOVALIDATE, FOS_OVERWRITEPROMPT, FOS_PATHMUSTEXIST, FOS_PICKFOLDERS, FOS_SHAREAWARE,
+    FOS_STRICTFILETYPES, FOS_SUPPORTSTREAMABLEITEMS, HWND,
+};
+
+/// The kind of items selected in an Open dialog by [`pick`]
+///
+/// [`pick`]: fn.pick.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionKind {
+    /// A single file
+    #[default]
+    File,
+    /// One or more files
+    Files,
+    /// A single folder
+    Folder,
+    /// One or more folders
+    Folders,
+    /// One or more items, each either a file or a folder. A selected folder is returned rather
+    /// than opened when the OK button is clicked, and is opened by double-clicking it. The
+    /// current folder is returned if no items are selected, for which the file name box holds `.`
+    /// while no files are selected. Use [`pick_files_and_folders`] to tell the files and folders
+    /// apart.
+    ///
+    /// [`pick_files_and_folders`]: fn.pick_files_and_folders.html
+    FilesAndFolders,
+}
+
+impl SelectionKind {
+    // The options that the dialog must be shown with
+    fn required_options(self) -> u32 {
+        match self {
+            SelectionKind::File => 0,
+            SelectionKind::Files => FOS_ALLOWMULTISELECT,
+            SelectionKind::Folder => FOS_PICKFOLDERS,
+            SelectionKind::Folders => FOS_PICKFOLDERS | FOS_ALLOWMULTISELECT,
+            SelectionKind::FilesAndFolders => FOS_ALLOWMULTISELECT,
+        }
+    }
+}
+
+/// A set of `FOS_*` flags for the `options` and `un_options` of [`PickParams`]. The flags that
+/// decide the kind of selection, `FOS_PICKFOLDERS` and `FOS_ALLOWMULTISELECT`, are set by the
+/// function that shows the dialog and cannot be included. Flags are combined with `|`.
+///
+/// # Examples
+///
+/// ```
+/// use wfd::PickOptions;
+///
+/// let options = PickOptions::FILE_MUST_EXIST | PickOptions::FORCE_SHOW_HIDDEN;
+/// assert_eq!(options.bits(), wfd::FOS_FILEMUSTEXIST | wfd::FOS_FORCESHOWHIDDEN);
+/// ```
+///
+/// [`PickParams`]: struct.PickParams.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PickOptions(u32);
+
+impl PickOptions {
+    /// No flags
+    pub const NONE: PickOptions = PickOptions(0);
+    /// `FOS_OVERWRITEPROMPT`
+    pub const OVERWRITE_PROMPT: PickOptions = PickOptions(FOS_OVERWRITEPROMPT);
+    /// `FOS_STRICTFILETYPES`
+    pub const STRICT_FILE_TYPES: PickOptions = PickOptions(FOS_STRICTFILETYPES);
+    /// `FOS_NOCHANGEDIR`
+    pub const NO_CHANGE_DIR: PickOptions = PickOptions(FOS_NOCHANGEDIR);
+    /// `FOS_FORCEFILESYSTEM`
+    pub const FORCE_FILE_SYSTEM: PickOptions = PickOptions(FOS_FORCEFILESYSTEM);
+    /// `FOS_ALLNONSTORAGEITEMS`
+    pub const ALL_NON_STORAGE_ITEMS: PickOptions = PickOptions(FOS_ALLNONSTORAGEITEMS);
+    /// `FOS_NOVALIDATE`
+    pub const NO_VALIDATE: PickOptions = PickOptions(FOS_NOVALIDATE);
+    /// `FOS_PATHMUSTEXIST`
+    pub const PATH_MUST_EXIST: PickOptions = PickOptions(FOS_PATHMUSTEXIST);
+    /// `FOS_FILEMUSTEXIST`
+    pub const FILE_MUST_EXIST: PickOptions = PickOptions(FOS_FILEMUSTEXIST);
+    /// `FOS_CREATEPROMPT`
+    pub const CREATE_PROMPT: PickOptions = PickOptions(FOS_CREATEPROMPT);
+    /// `FOS_SHAREAWARE`
+    pub const SHARE_AWARE: PickOptions = PickOptions(FOS_SHAREAWARE);
+    /// `FOS_NOREADONLYRETURN`
+    pub const NO_READ_ONLY_RETURN: PickOptions = PickOptions(FOS_NOREADONLYRETURN);
+    /// `FOS_NOTESTFILECREATE`
+    pub const NO_TEST_FILE_CREATE: PickOptions = PickOptions(FOS_NOTESTFILECREATE);
+    /// `FOS_HIDEMRUPLACES`
+    pub const HIDE_MRU_PLACES: PickOptions = PickOptions(FOS_HIDEMRUPLACES);
+    /// `FOS_HIDEPINNEDPLACES`
+    pub const HIDE_PINNED_PLACES: PickOptions = PickOptions(FOS_HIDEPINNEDPLACES);
+    /// `FOS_NODEREFERENCELINKS`
+    pub const NO_DEREFERENCE_LINKS: PickOptions = PickOptions(FOS_NODEREFERENCELINKS);
+    /// `FOS_DONTADDTORECENT`
+    pub const DONT_ADD_TO_RECENT: PickOptions = PickOptions(FOS_DONTADDTORECENT);
+    /// `FOS_FORCESHOWHIDDEN`
+    pub const FORCE_SHOW_HIDDEN: PickOptions = PickOptions(FOS_FORCESHOWHIDDEN);
+    /// `FOS_DEFAULTNOMINIMODE`
+    pub const DEFAULT_NO_MINI_MODE: PickOptions = PickOptions(FOS_DEFAULTNOMINIMODE);
+    /// `FOS_FORCEPREVIEWPANEON`
+    pub const FORCE_PREVIEW_PANE_ON: PickOptions = PickOptions(FOS_FORCEPREVIEWPANEON);
+    /// `FOS_SUPPORTSTREAMABLEITEMS`
+    pub const SUPPORTS_STREAMABLE_ITEMS: PickOptions = PickOptions(FOS_SUPPORTSTREAMABLEITEMS);
+
+    /// The `FOS_*` flags of the set
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for PickOptions {
+    type Output = PickOptions;
+
+    fn bitor(self, other: PickOptions) -> PickOptions {
+        PickOptions(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for PickOptions {
+    fn bitor_assign(&mut self, other: PickOptions) {
+        self.0 |= other.0;
+    }
+}
+
+/// The parameters of [`pick`] and the other purpose-specific functions. The fields are the same
+/// as those of [`DialogParams`], except that `options` and `un_options` cannot change the kind of
+/// selection, see [`PickOptions`], and the selection of files and folders together is requested
+/// with [`SelectionKind::FilesAndFolders`].
+///
+/// [`pick`]: fn.pick.html
+/// [`DialogParams`]: struct.DialogParams.html
+/// [`PickOptions`]: struct.PickOptions.html
+/// [`SelectionKind::FilesAndFolders`]: enum.SelectionKind.html#variant.FilesAndFolders
+#[derive(Debug)]
+pub struct PickParams<'a> {
+    /// See `DialogParams::add_to_recent`
+    pub add_to_recent: bool,
+    /// See `DialogParams::aggregate_filter`
+    pub aggregate_filter: AggregateFilter,
+    /// See `DialogParams::default_extension`
+    pub default_extension: &'a str,
+    /// See `DialogParams::default_folder`
+    pub default_folder: FolderSpec<'a>,
+    /// See `DialogParams::file_name`
+    pub file_name: &'a str,
+    /// See `DialogParams::file_name_label`
+    pub file_name_label: &'a str,
+    /// See `DialogParams::file_type_index`
+    pub file_type_index: u32,
+    /// See `DialogParams::file_types`
+    pub file_types: Vec<(&'a str, &'a str)>,
+    /// See `DialogParams::folder`
+    pub folder: FolderSpec<'a>,
+    /// See `DialogParams::invalid_path_behavior`
+    pub invalid_path_behavior: InvalidPathBehavior,
+    /// See `DialogParams::item_filter`
+    pub item_filter: Option<ItemFilter<'a>>,
+    /// See `DialogParams::ok_button_label`
+    pub ok_button_label: &'a str,
+    /// The flags to add to the options of the dialog, in addition to those that the kind of
+    /// selection requires
+    pub options: PickOptions,
+    /// The flags to remove from the default options of the dialog
+    pub un_options: PickOptions,
+    /// See `DialogParams::overwrite_policy`
+    pub overwrite_policy: OverwritePolicy,
+    /// See `DialogParams::path_form`
+    pub path_form: PathForm,
+    /// See `DialogParams::resolve_shortcuts`
+    pub resolve_shortcuts: ShortcutResolution,
+    /// See `DialogParams::owner`
+    pub owner: Option<HWND>,
+    /// See `DialogParams::selection_rules`
+    pub selection_rules: SelectionRules<'a>,
+    /// See `DialogParams::require_writable`
+    pub require_writable: bool,
+    /// See `DialogParams::save_as_item`
+    pub save_as_item: &'a Path,
+    /// See `DialogParams::sanitize_file_name`
+    pub sanitize_file_name: Option<SanitizeOptions>,
+    /// See `DialogParams::title`
+    pub title: &'a str,
+}
+
+impl<'a> Default for PickParams<'a> {
+    fn default() -> Self {
+        let params = DialogParams::default();
+        PickParams {
+            add_to_recent: params.add_to_recent,
+            aggregate_filter: params.aggregate_filter,
+            default_extension: params.default_extension,
+            default_folder: params.default_folder,
+            file_name: params.file_name,
+            file_name_label: params.file_name_label,
+            file_type_index: params.file_type_index,
+            file_types: params.file_types,
+            folder: params.folder,
+            invalid_path_behavior: params.invalid_path_behavior,
+            item_filter: params.item_filter,
+            ok_button_label: params.ok_button_label,
+            options: PickOptions::NONE,
+            un_options: PickOptions::NONE,
+            overwrite_policy: params.overwrite_policy,
+            path_form: params.path_form,
+            resolve_shortcuts: params.resolve_shortcuts,
+            owner: params.owner,
+            selection_rules: params.selection_rules,
+            require_writable: params.require_writable,
+            save_as_item: params.save_as_item,
+            sanitize_file_name: params.sanitize_file_name,
+            title: params.title,
+        }
+    }
+}
+
+impl<'a> PickParams<'a> {
+    // Converts the params into those of a dialog with the options required by a kind of
+    // selection. The required options cannot be removed, as `un_options` cannot contain them.
+    fn into_dialog_params(self, required_options: u32, kind: SelectionKind) -> DialogParams<'a> {
+        DialogParams {
+            add_to_recent: self.add_to_recent,
+            aggregate_filter: self.aggregate_filter,
+            default_extension: self.default_extension,
+            default_folder: self.default_folder,
+            file_name: self.file_name,
+            file_name_label: self.file_name_label,
+            file_type_index: self.file_type_index,
+            file_types: self.file_types,
+            folder: self.folder,
+            invalid_path_behavior: self.invalid_path_behavior,
+            item_filter: self.item_filter,
+            ok_button_label: self.ok_button_label,
+            options: self.options.bits() | required_options,
+            un_options: self.un_options.bits(),
+            overwrite_policy: self.overwrite_policy,
+            path_form: self.path_form,
+            resolve_shortcuts: self.resolve_shortcuts,
+            owner: self.owner,
+            selection_rules: self.selection_rules,
+            require_writable: self.require_writable,
+            save_as_item: self.save_as_item,
+            sanitize_file_name: self.sanitize_file_name,
+            select_files_and_folders: kind == SelectionKind::FilesAndFolders,
+            title: self.title,
+        }
+    }
+}
+
+/// Displays an Open dialog for selecting the given kind of items, returning the selected paths.
+/// The options needed for the kind of selection are added to the `options` of the params.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> Result<(), wfd::DialogError> {
+/// let folders = wfd::pick(wfd::SelectionKind::Folders, Default::default())?;
+/// for folder in folders {
+///     println!("{}", folder.display());
+/// }
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// A cancelled dialog returns no paths rather than an error. All other errors are those of
+/// [`open_dialog`].
+///
+/// [`open_dialog`]: fn.open_dialog.html
+pub fn pick(kind: SelectionKind, params: PickParams) -> Result<Vec<PathBuf>, DialogError> {
+    match open_dialog(params.into_dialog_params(kind.required_options(), kind)) {
+        Ok(result) => Ok(result.selected_file_paths),
+        Err(DialogError::UserCancelled) => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Displays an Open dialog for selecting a single file, returning `None` if the dialog was
+/// cancelled. See [`pick`] for the errors that can be returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> Result<(), wfd::DialogError> {
+/// let params = wfd::PickParams {
+///     file_types: vec![("PDF Files", "*.pdf")],
+///     ..Default::default()
+/// };
+/// if let Some(path) = wfd::pick_file(params)? {
+///     println!("{}", path.display());
+/// }
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// [`pick`]: fn.pick.html
+pub fn pick_file(params: PickParams) -> Result<Option<PathBuf>, DialogError> {
+    pick(SelectionKind::File, params).map(|paths| paths.into_iter().next())
+}
+
+/// Displays an Open dialog for selecting one or more files, returning no paths if the dialog was
+/// cancelled. See [`pick`] for the errors that can be returned.
+///
+/// [`pick`]: fn.pick.html
+pub fn pick_files(params: PickParams) -> Result<Vec<PathBuf>, DialogError> {
+    pick(SelectionKind::Files, params)
+}
+
+/// Displays an Open dialog for selecting a single folder, returning `None` if the dialog was
+/// cancelled. See [`pick`] for the errors that can be returned.
+///
+/// [`pick`]: fn.pick.html
+pub fn pick_folder(params: PickParams) -> Result<Option<PathBuf>, DialogError> {
+    pick(SelectionKind::Folder, params).map(|paths| paths.into_iter().next())
+}
+
+/// Displays an Open dialog for selecting one or more folders, returning no paths if the dialog
+/// was cancelled. See [`pick`] for the errors that can be returned.
+///
+/// [`pick`]: fn.pick.html
+pub fn pick_folders(params: PickParams) -> Result<Vec<PathBuf>, DialogError> {
+    pick(SelectionKind::Folders, params)
+}
+
+/// An item selected with [`pick_files_and_folders`]
+///
+/// [`pick_files_and_folders`]: fn.pick_files_and_folders.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PickedItem {
+    /// A file, or an item that no longer exists
+    File(PathBuf),
+    /// A folder
+    Folder(PathBuf),
+}
+
+impl PickedItem {
+    // Classifies a selected path by reading its metadata
+    fn from_path(path: PathBuf) -> Self {
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => PickedItem::Folder(path),
+            _ => PickedItem::File(path),
+        }
+    }
+
+    /// The path of the item
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            PickedItem::File(path) | PickedItem::Folder(path) => path,
+        }
+    }
+}
+
+/// Displays an Open dialog for selecting files and folders together, returning no items if the
+/// dialog was cancelled. See [`SelectionKind::FilesAndFolders`] for how the dialog behaves, and
+/// [`pick`] for the errors that can be returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> Result<(), wfd::DialogError> {
+/// use wfd::PickedItem;
+///
+/// for item in wfd::pick_files_and_folders(Default::default())? {
+///     match item {
+///         PickedItem::File(path) => println!("file: {}", path.display()),
+///         PickedItem::Folder(path) => println!("folder: {}", path.display()),
+///     }
+/// }
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// [`SelectionKind::FilesAndFolders`]: enum.SelectionKind.html#variant.FilesAndFolders
+/// [`pick`]: fn.pick.html
+pub fn pick_files_and_folders(params: PickParams) -> Result<Vec<PickedItem>, DialogError> {
+    let paths = pick(SelectionKind::FilesAndFolders, params)?;
+    Ok(paths.into_iter().map(PickedItem::from_path).collect())
+}
+
+/// Displays a Save dialog, returning the selected path or `None` if the dialog was cancelled.
+///
+/// # Errors
+/// The errors are those of [`save_dialog`].
+///
+/// [`save_dialog`]: fn.save_dialog.html
+pub fn save_file(params: PickParams) -> Result<Option<PathBuf>, DialogError> {
+    match save_dialog(params.into_dialog_params(0, SelectionKind::File)) {
+        Ok(result) => Ok(Some(result.selected_file_path)),
+        Err(DialogError::UserCancelled) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn selection_options_are_required() {
+        let params = PickParams {
+            options: PickOptions::FILE_MUST_EXIST | PickOptions::FORCE_SHOW_HIDDEN,
+            un_options: PickOptions::NO_VALIDATE,
+            ..Default::default()
+        };
+        let kind = SelectionKind::Folders;
+        let params = params.into_dialog_params(kind.required_options(), kind);
+        assert_eq!(
+            params.options,
+            FOS_FILEMUSTEXIST | FOS_FORCESHOWHIDDEN | FOS_PICKFOLDERS | FOS_ALLOWMULTISELECT
+        );
+        assert_eq!(params.un_options, FOS_NOVALIDATE);
+        assert!(!params.select_files_and_folders);
+
+        let kind = SelectionKind::FilesAndFolders;
+        let params = PickParams::default().into_dialog_params(kind.required_options(), kind);
+        assert_eq!(params.options, FOS_ALLOWMULTISELECT);
+        assert!(params.select_files_and_folders);
+    }
+
+    #[test]
+    fn pick_options_exclude_selection_options() {
+        let options = [
+            PickOptions::OVERWRITE_PROMPT,
+            PickOptions::STRICT_FILE_TYPES,
+            PickOptions::NO_CHANGE_DIR,
+            PickOptions::FORCE_FILE_SYSTEM,
+            PickOptions::ALL_NON_STORAGE_ITEMS,
+            PickOptions::NO_VALIDATE,
+            PickOptions::PATH_MUST_EXIST,
+            PickOptions::FILE_MUST_EXIST,
+            PickOptions::CREATE_PROMPT,
+            PickOptions::SHARE_AWARE,
+            PickOptions::NO_READ_ONLY_RETURN,
+            PickOptions::NO_TEST_FILE_CREATE,
+            PickOptions::HIDE_MRU_PLACES,
+            PickOptions::HIDE_PINNED_PLACES,
+            PickOptions::NO_DEREFERENCE_LINKS,
+            PickOptions::DONT_ADD_TO_RECENT,
+            PickOptions::FORCE_SHOW_HIDDEN,
+            PickOptions::DEFAULT_NO_MINI_MODE,
+            PickOptions::FORCE_PREVIEW_PANE_ON,
+            PickOptions::SUPPORTS_STREAMABLE_ITEMS,
+        ];
+        let mut all = PickOptions::NONE;
+        for option in options.iter() {
+            assert_eq!(option.bits().count_ones(), 1);
+            all |= *option;
+        }
+        assert_eq!(all.bits().count_ones(), options.len() as u32);
+        assert_eq!(all.bits() & (FOS_PICKFOLDERS | FOS_ALLOWMULTISELECT), 0);
+    }
+
+    #[test]
+    fn picked_items_are_classified() {
+        let dir = env::temp_dir().join(format!("wfd-pick-classified-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("archive.txt");
+        fs::write(&file, "contents").unwrap();
+        let missing = dir.join("missing.txt");
+
+        assert_eq!(
+            PickedItem::from_path(dir.clone()),
+            PickedItem::Folder(dir.clone())
+        );
+        assert_eq!(
+            PickedItem::from_path(file.clone()),
+            PickedItem::File(file.clone())
+        );
+        assert_eq!(
+            PickedItem::from_path(missing.clone()),
+            PickedItem::File(missing.clone())
+        );
+        assert_eq!(PickedItem::Folder(dir.clone()).path(), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,622 @@
+// Registers the paths selected in a dialog with the recently used documents list of the OS. On
+// Windows this uses `SHAddToRecentDocs`, on other freedesktop.org platforms an entry is added to
+// the `recently-used.xbel` file shared by desktop applications.
+
+use std::path::PathBuf;
+
+// Adds the paths to the recently used documents list. This is best effort, failures are ignored
+// since the paths have already been selected successfully.
+#[cfg(windows)]
+pub(crate) fn add_to_recent(paths: &[PathBuf]) {
+    use crate::NullTermUTF16;
+    use winapi::ctypes::c_void;
+    use winapi::shared::minwindef::UINT;
+
+    const SHARD_PATHW: UINT = 0x0000_0003;
+
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SHAddToRecentDocs(uFlags: UINT, pv: *const c_void);
+    }
+
+    for path in paths {
+        let path = path.as_os_str().as_null_term_utf16();
+        unsafe { SHAddToRecentDocs(SHARD_PATHW, path.as_ptr() as *const c_void) };
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn add_to_recent(paths: &[PathBuf]) {
+    use std::env;
+    use std::time::SystemTime;
+
+    let data_home = match xbel::data_home(env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+        Some(data_home) => data_home,
+        None => return,
+    };
+    let _ = xbel::add_bookmarks(
+        &data_home.join("recently-used.xbel"),
+        paths,
+        &xbel::Application::current(),
+        &crate::mime::MimeDatabase::system(),
+        SystemTime::now(),
+    );
+}
+
+// macOS and the platforms without a freedesktop.org desktop have no list that is updated
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+pub(crate) fn add_to_recent(_paths: &[PathBuf]) {}
+
+// Reading and writing of the XML Bookmark Exchange Language files used for the freedesktop.org
+// recently used list, see https://www.freedesktop.org/wiki/Specifications/desktop-bookmark-spec/
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod xbel {
+    use std::env;
+    use std::ffi::OsString;
+    use std::fs::{self, File, Permissions};
+    use std::io::{self, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::AsRawFd;
+    use std::path::{Path, PathBuf};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::mime::MimeDatabase;
+    use crate::uri::{self, UriStyle};
+    use crate::xml::{self, Element};
+    use crate::SaveTarget;
+
+    const BOOKMARK_NAMESPACE: &str = "http://www.freedesktop.org/standards/desktop-bookmarks";
+    const MIME_NAMESPACE: &str = "http://www.freedesktop.org/standards/shared-mime-info";
+    const METADATA_OWNER: &str = "http://freedesktop.org";
+
+    // The application that is recorded as having used a bookmarked file
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub(crate) struct Application {
+        pub(crate) name: String,
+        pub(crate) exec: String,
+    }
+
+    impl Application {
+        // The currently running application, identified by the name of its executable
+        pub(crate) fn current() -> Self {
+            let name = env::current_exe()
+                .ok()
+                .and_then(|exe| exe.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| String::from("wfd"));
+            Application {
+                exec: format!("'{} %u'", name),
+                name,
+            }
+        }
+    }
+
+    // A bookmark read from an XBEL file
+    #[cfg(test)]
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub(crate) struct Bookmark {
+        pub(crate) path: PathBuf,
+        pub(crate) mime_type: Option<String>,
+        pub(crate) modified: Option<String>,
+        // The name and usage count of each application that used the file
+        pub(crate) applications: Vec<(String, u32)>,
+    }
+
+    // Returns the base directory for user data files, `$XDG_DATA_HOME` or `$HOME/.local/share`
+    pub(crate) fn data_home(
+        xdg_data_home: Option<OsString>,
+        home: Option<OsString>,
+    ) -> Option<PathBuf> {
+        xdg_data_home
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| PathBuf::from(home).join(".local").join("share")))
+    }
+
+    // Reads the file-based bookmarks from the contents of an XBEL file
+    #[cfg(test)]
+    pub(crate) fn read_bookmarks(contents: &str) -> io::Result<Vec<Bookmark>> {
+        let root = parse(contents)?;
+        Ok(root
+            .elements()
+            .filter(|element| element.name == "bookmark")
+            .filter_map(|bookmark| {
+                let path =
+                    uri::file_uri_to_path(bookmark.attribute("href")?, UriStyle::Unix).ok()?;
+                let metadata = metadata(bookmark);
+                let mime_type = metadata
+                    .and_then(|m| m.child("mime:mime-type"))
+                    .and_then(|m| m.attribute("type"))
+                    .map(String::from);
+                let applications = metadata
+                    .and_then(|m| m.child("bookmark:applications"))
+                    .map(|applications| {
+                        applications
+                            .elements()
+                            .filter(|e| e.name == "bookmark:application")
+                            .filter_map(|application| {
+                                let name = application.attribute("name")?.to_string();
+                                let count = application
+                                    .attribute("count")
+                                    .and_then(|count| count.parse().ok())
+                                    .unwrap_or(1);
+                                Some((name, count))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                Some(Bookmark {
+                    path,
+                    mime_type,
+                    modified: bookmark.attribute("modified").map(String::from),
+                    applications,
+                })
+            })
+            .collect())
+    }
+
+    // Adds or updates the bookmarks for the paths in the XBEL file, creating it if it does not
+    // exist. The file is replaced atomically so that other applications never read a partially
+    // written file. As the file is a private browsing history, a new file is only readable by
+    // the user, as when it is created by GLib.
+    pub(crate) fn add_bookmarks(
+        file: &Path,
+        paths: &[PathBuf],
+        application: &Application,
+        mime_database: &MimeDatabase,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        let dir = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir)?;
+        let _lock = lock_directory(dir)?;
+
+        let (mut root, is_new) = match fs::read_to_string(file) {
+            Ok(contents) => (parse(&contents)?, false),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (new_document(), true),
+            Err(e) => return Err(e),
+        };
+
+        let timestamp = format_timestamp(now);
+        for path in paths {
+            add_bookmark(&mut root, path, application, mime_database, &timestamp);
+        }
+
+        let contents = xml::write(&root);
+        SaveTarget::new(file).write_atomically(|w| {
+            // The permissions of an existing file are kept when it is replaced
+            if is_new {
+                w.get_ref().set_permissions(Permissions::from_mode(0o600))?;
+            }
+            w.write_all(contents.as_bytes())
+        })
+    }
+
+    // Locks the directory of the file for the read, modify and replace of the file, so that
+    // concurrent writers in other threads and processes do not lose each other's entries. The
+    // file itself cannot be locked as it is replaced by a new file. The lock is released when the
+    // returned directory is closed.
+    fn lock_directory(dir: &Path) -> io::Result<File> {
+        let dir = File::open(dir)?;
+        if unsafe { libc::flock(dir.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(dir)
+    }
+
+    fn add_bookmark(
+        root: &mut Element,
+        path: &Path,
+        application: &Application,
+        mime_database: &MimeDatabase,
+        timestamp: &str,
+    ) {
+        // Only absolute paths can be bookmarked
+        let href = match uri::path_to_file_uri(path, UriStyle::Unix) {
+            Ok(href) => href,
+            Err(_) => return,
+        };
+        let existing = root.children.iter().position(|node| match node {
+            xml::Node::Element(element) => {
+                element.name == "bookmark"
+                    && element
+                        .attribute("href")
+                        .and_then(|href| uri::file_uri_to_path(href, UriStyle::Unix).ok())
+                        .is_some_and(|bookmark_path| bookmark_path == path)
+            }
+            _ => false,
+        });
+        let index = existing.unwrap_or_else(|| {
+            let bookmark = Element::new("bookmark")
+                .with_attribute("href", &href)
+                .with_attribute("added", timestamp);
+            root.children.push(xml::Node::Element(bookmark));
+            root.children.len() - 1
+        });
+        let bookmark = match &mut root.children[index] {
+            xml::Node::Element(bookmark) => bookmark,
+            _ => unreachable!("the index refers to a bookmark element"),
+        };
+        bookmark.set_attribute("modified", timestamp);
+        bookmark.set_attribute("visited", timestamp);
+
+        let metadata = bookmark.child_or_insert("info");
+        if !metadata
+            .elements()
+            .any(|e| e.name == "metadata" && e.attribute("owner") == Some(METADATA_OWNER))
+        {
+            metadata.children.push(xml::Node::Element(
+                Element::new("metadata").with_attribute("owner", METADATA_OWNER),
+            ));
+        }
+        let metadata = metadata
+            .elements_mut()
+            .find(|e| e.name == "metadata" && e.attribute("owner") == Some(METADATA_OWNER))
+            .expect("metadata element exists");
+
+        // The type recorded by the application that added the bookmark is kept, as it may know
+        // the type of a file that the database does not
+        let recorded_type = metadata.child_or_insert("mime:mime-type");
+        if recorded_type.attribute("type").is_none() {
+            recorded_type.set_attribute("type", &mime_type(path, mime_database));
+        }
+
+        let applications = metadata.child_or_insert("bookmark:applications");
+        let existing = applications
+            .elements_mut()
+            .find(|e| e.attribute("name") == Some(application.name.as_str()));
+        match existing {
+            Some(existing) => {
+                let count = existing
+                    .attribute("count")
+                    .and_then(|count| count.parse::<u32>().ok())
+                    .unwrap_or(0);
+                existing.set_attribute("modified", timestamp);
+                existing.set_attribute("count", &count.saturating_add(1).to_string());
+            }
+            None => applications.children.push(xml::Node::Element(
+                Element::new("bookmark:application")
+                    .with_attribute("name", &application.name)
+                    .with_attribute("exec", &application.exec)
+                    .with_attribute("modified", timestamp)
+                    .with_attribute("count", "1"),
+            )),
+        }
+    }
+
+    #[cfg(test)]
+    fn metadata(bookmark: &Element) -> Option<&Element> {
+        bookmark.child("info")?.elements().find(|e| {
+            e.name == "metadata" && e.attribute("owner").map_or(true, |o| o == METADATA_OWNER)
+        })
+    }
+
+    fn parse(contents: &str) -> io::Result<Element> {
+        let root =
+            xml::parse(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.0))?;
+        if root.name != "xbel" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the root element is not 'xbel'",
+            ));
+        }
+        Ok(root)
+    }
+
+    fn new_document() -> Element {
+        Element::new("xbel")
+            .with_attribute("version", "1.0")
+            .with_attribute("xmlns:bookmark", BOOKMARK_NAMESPACE)
+            .with_attribute("xmlns:mime", MIME_NAMESPACE)
+    }
+
+    // Guesses the MIME type of a path from its file name, using the types of the shared-mime-info
+    // database. Files that the database has no type for are treated as generic binary files.
+    fn mime_type(path: &Path, mime_database: &MimeDatabase) -> String {
+        if path.is_dir() {
+            return String::from("inode/directory");
+        }
+        path.file_name()
+            .and_then(|name| mime_database.type_for_file_name(&name.to_string_lossy()))
+            .map_or_else(
+                || String::from("application/octet-stream"),
+                |mime_type| mime_type.name.clone(),
+            )
+    }
+
+    // Formats a time as an ISO 8601 UTC timestamp with microseconds, as written by GLib
+    pub(crate) fn format_timestamp(time: SystemTime) -> String {
+        let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = duration.as_secs();
+        let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+        let seconds_of_day = seconds % 86_400;
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            year,
+            month,
+            day,
+            seconds_of_day / 3600,
+            seconds_of_day / 60 % 60,
+            seconds_of_day % 60,
+            duration.subsec_micros()
+        )
+    }
+
+    // Converts a number of days since 1970-01-01 into a (year, month, day) date in the proleptic
+    // Gregorian calendar, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    fn civil_from_days(days: i64) -> (i64, u32, u32) {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::process;
+        use std::time::Duration;
+
+        const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0"
+      xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks"
+      xmlns:mime="http://www.freedesktop.org/standards/shared-mime-info"
+>
+  <bookmark href="file:///home/ben/Documents/report%20final.odt" added="2023-05-01T09:00:00.000000Z" modified="2023-05-01T09:00:00.000000Z" visited="2023-05-01T09:00:00.000000Z">
+    <info>
+      <metadata owner="http://freedesktop.org">
+        <mime:mime-type type="application/vnd.oasis.opendocument.text"/>
+        <bookmark:applications>
+          <bookmark:application name="LibreOffice" exec="&apos;soffice %u&apos;" modified="2023-05-01T09:00:00.000000Z" count="3"/>
+        </bookmark:applications>
+      </metadata>
+    </info>
+  </bookmark>
+  <bookmark href="https://example.com/not-a-file" added="2023-05-01T09:00:00Z" modified="2023-05-01T09:00:00Z" visited="2023-05-01T09:00:00Z"/>
+</xbel>
+"#;
+
+        fn application() -> Application {
+            Application {
+                name: String::from("exporter"),
+                exec: String::from("'exporter %u'"),
+            }
+        }
+
+        fn mime_database() -> MimeDatabase {
+            let fixtures = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join("fixtures")
+                .join("mime");
+            MimeDatabase::load(&[fixtures.join("user"), fixtures.join("system")], "en")
+        }
+
+        // 2024-02-29T12:34:56.789000Z
+        fn now() -> SystemTime {
+            UNIX_EPOCH + Duration::from_micros(1_709_210_096_789_000)
+        }
+
+        fn temp_home(name: &str) -> PathBuf {
+            let home = env::temp_dir().join(format!("wfd-recent-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&home);
+            fs::create_dir_all(&home).unwrap();
+            home
+        }
+
+        #[test]
+        fn format_timestamps() {
+            assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+            assert_eq!(format_timestamp(now()), "2024-02-29T12:34:56.789000Z");
+            assert_eq!(
+                format_timestamp(UNIX_EPOCH + Duration::from_secs(4_102_444_799)),
+                "2099-12-31T23:59:59.000000Z"
+            );
+        }
+
+        #[test]
+        fn data_home_locations() {
+            assert_eq!(
+                data_home(Some("/xdg/data".into()), Some("/home/ben".into())),
+                Some(PathBuf::from("/xdg/data"))
+            );
+            assert_eq!(
+                data_home(Some("relative".into()), Some("/home/ben".into())),
+                Some(PathBuf::from("/home/ben/.local/share"))
+            );
+            assert_eq!(
+                data_home(None, Some("/home/ben".into())),
+                Some(PathBuf::from("/home/ben/.local/share"))
+            );
+            assert_eq!(data_home(None, None), None);
+        }
+
+        #[test]
+        fn read_sample_file() {
+            assert_eq!(
+                read_bookmarks(SAMPLE).unwrap(),
+                vec![Bookmark {
+                    path: PathBuf::from("/home/ben/Documents/report final.odt"),
+                    mime_type: Some(String::from("application/vnd.oasis.opendocument.text")),
+                    modified: Some(String::from("2023-05-01T09:00:00.000000Z")),
+                    applications: vec![(String::from("LibreOffice"), 3)],
+                }]
+            );
+        }
+
+        #[test]
+        fn read_invalid_file() {
+            assert!(read_bookmarks("<xbel>").is_err());
+            assert!(read_bookmarks("<other/>").is_err());
+        }
+
+        #[test]
+        fn add_bookmarks_creates_file() {
+            let home = temp_home("create");
+            let file = data_home(None, Some(home.clone().into()))
+                .unwrap()
+                .join("recently-used.xbel");
+            let document = home.join("My Exports").join("données #1.csv");
+
+            add_bookmarks(
+                &file,
+                std::slice::from_ref(&document),
+                &application(),
+                &mime_database(),
+                now(),
+            )
+            .unwrap();
+
+            let contents = fs::read_to_string(&file).unwrap();
+            assert!(contents.contains(&format!(
+                "href=\"file://{}/My%20Exports/donn%C3%A9es%20%231.csv\"",
+                home.display()
+            )));
+            assert!(contents.contains(
+                "xmlns:bookmark=\"http://www.freedesktop.org/standards/desktop-bookmarks\""
+            ));
+            assert_eq!(
+                read_bookmarks(&contents).unwrap(),
+                vec![Bookmark {
+                    path: document,
+                    mime_type: Some(String::from("text/csv")),
+                    modified: Some(String::from("2024-02-29T12:34:56.789000Z")),
+                    applications: vec![(String::from("exporter"), 1)],
+                }]
+            );
+            fs::remove_dir_all(&home).unwrap();
+        }
+
+        #[test]
+        fn add_bookmarks_updates_sample_file() {
+            let home = temp_home("update");
+            let file = home.join(".local/share/recently-used.xbel");
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, SAMPLE).unwrap();
+
+            let existing = PathBuf::from("/home/ben/Documents/report final.odt");
+            let new = PathBuf::from("/home/ben/Pictures/photo.JPG");
+            add_bookmarks(
+                &file,
+                &[existing.clone(), new.clone()],
+                &application(),
+                &mime_database(),
+                now(),
+            )
+            .unwrap();
+            add_bookmarks(
+                &file,
+                std::slice::from_ref(&new),
+                &application(),
+                &mime_database(),
+                now(),
+            )
+            .unwrap();
+
+            let contents = fs::read_to_string(&file).unwrap();
+            let bookmarks = read_bookmarks(&contents).unwrap();
+            assert_eq!(
+                bookmarks,
+                vec![
+                    Bookmark {
+                        path: existing,
+                        mime_type: Some(String::from("application/vnd.oasis.opendocument.text")),
+                        modified: Some(String::from("2024-02-29T12:34:56.789000Z")),
+                        applications: vec![
+                            (String::from("LibreOffice"), 3),
+                            (String::from("exporter"), 1)
+                        ],
+                    },
+                    Bookmark {
+                        path: new,
+                        mime_type: Some(String::from("image/jpeg")),
+                        modified: Some(String::from("2024-02-29T12:34:56.789000Z")),
+                        applications: vec![(String::from("exporter"), 2)],
+                    },
+                ]
+            );
+
+            // Unrelated bookmarks and attributes are preserved
+            assert!(contents.contains("href=\"https://example.com/not-a-file\""));
+            assert!(contents.contains("added=\"2023-05-01T09:00:00.000000Z\""));
+            assert!(contents.contains("exec=\"&apos;soffice %u&apos;\""));
+            fs::remove_dir_all(&home).unwrap();
+        }
+
+        #[test]
+        fn add_bookmarks_creates_private_file() {
+            let home = temp_home("private");
+            let file = home.join("recently-used.xbel");
+            let add = || {
+                add_bookmarks(
+                    &file,
+                    &[home.join("a.bin")],
+                    &application(),
+                    &mime_database(),
+                    now(),
+                )
+                .unwrap()
+            };
+
+            add();
+            let metadata = fs::metadata(&file).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+            let contents = fs::read_to_string(&file).unwrap();
+            assert!(contents.contains("type=\"application/octet-stream\""));
+
+            // The permissions chosen by the user for an existing file are kept
+            fs::set_permissions(&file, Permissions::from_mode(0o640)).unwrap();
+            add();
+            let metadata = fs::metadata(&file).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+            fs::remove_dir_all(&home).unwrap();
+        }
+
+        #[test]
+        fn add_bookmarks_concurrently() {
+            let home = temp_home("concurrent");
+            let file = home.join("recently-used.xbel");
+            let mime_database = mime_database();
+
+            std::thread::scope(|scope| {
+                for i in 0..8 {
+                    let (file, home, mime_database) = (&file, &home, &mime_database);
+                    scope.spawn(move || {
+                        let path = home.join(format!("{}.txt", i));
+                        add_bookmarks(file, &[path], &application(), mime_database, now()).unwrap();
+                    });
+                }
+            });
+
+            let contents = fs::read_to_string(&file).unwrap();
+            assert_eq!(read_bookmarks(&contents).unwrap().len(), 8);
+            fs::remove_dir_all(&home).unwrap();
+        }
+
+        #[test]
+        fn add_bookmarks_rejects_invalid_file() {
+            let home = temp_home("invalid");
+            let file = home.join("recently-used.xbel");
+            fs::write(&file, "not xml").unwrap();
+
+            let result = add_bookmarks(
+                &file,
+                &[home.join("a.txt")],
+                &application(),
+                &mime_database(),
+                now(),
+            );
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+            assert_eq!(fs::read_to_string(&file).unwrap(), "not xml");
+            fs::remove_dir_all(&home).unwrap();
+        }
+    }
+}
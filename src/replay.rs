@@ -0,0 +1,1158 @@
+// Recording of the parameters and outcomes of dialogs to a JSON Lines file, and replaying of the
+// recorded outcomes without showing any dialogs, for use in automated UI tests
+
+use std::convert::TryInto;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::backend::DialogKind;
+use crate::json::{self, object, Value};
+use crate::{
+    AggregateFilter, CharReplacement, DialogError, DialogParams, FolderSpec, InvalidPathBehavior,
+    KnownFolder, OpenDialogResult, OverwritePolicy, PathForm, RejectionReason, Sandbox,
+    SanitizeOptions, SaveDialogResult, SelectionRejection, ShortcutResolution, UriErrorReason,
+};
+
+const RECORD_VAR: &str = "WFD_RECORD";
+const REPLAY_VAR: &str = "WFD_REPLAY";
+
+/// Determines whether dialogs are shown normally, recorded or replayed, see [`set_session_mode`]
+///
+/// [`set_session_mode`]: fn.set_session_mode.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionMode {
+    /// Dialogs are shown normally
+    Live,
+    /// Dialogs are shown normally, and the parameters and outcome of each dialog are appended to
+    /// the given JSON Lines file
+    Record(PathBuf),
+    /// No dialogs are shown. Instead the outcomes in the given JSON Lines file, as written by
+    /// `Record`, are returned in order.
+    Replay(PathBuf),
+}
+
+enum Session {
+    // The mode has not been set yet, and is taken from the environment when the first dialog is
+    // shown
+    Unset,
+    Live,
+    Record(PathBuf),
+    Replay(Replay),
+}
+
+static SESSION: Mutex<Session> = Mutex::new(Session::Unset);
+
+/// Sets whether dialogs are shown normally, recorded or replayed. This overrides the mode taken
+/// from the environment, where `WFD_REPLAY=<file>` replays the outcomes in the given file and
+/// `WFD_RECORD=<file>` records outcomes to it. `WFD_REPLAY` takes precedence if both are set.
+///
+/// When replaying, the parameters of each dialog must be the same as those in the recording,
+/// other than `owner`. If they differ, or a dialog of a different kind is requested, or there are
+/// no more recorded outcomes, the dialog function panics so that the divergence cannot go
+/// unnoticed. Replayed outcomes have no side effects, so for example files are not added to the
+/// recently used list.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// wfd::set_session_mode(wfd::SessionMode::Replay("tests/dialogs.jsonl".into()))?;
+/// let result = wfd::open_dialog(Default::default());
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// Returns an error if the file to be replayed cannot be read or is not a valid recording
+pub fn set_session_mode(mode: SessionMode) -> io::Result<()> {
+    let session = match mode {
+        SessionMode::Live => Session::Live,
+        SessionMode::Record(path) => Session::Record(path),
+        SessionMode::Replay(path) => Session::Replay(Replay::load(&path)?),
+    };
+    *SESSION.lock().unwrap_or_else(|e| e.into_inner()) = session;
+    Ok(())
+}
+
+// Shows an Open dialog using `show`, or replays its outcome, according to the session mode
+pub(crate) fn open_dialog<F>(
+    params: &DialogParams,
+    show: F,
+) -> Result<OpenDialogResult, DialogError>
+where
+    F: FnOnce() -> Result<OpenDialogResult, DialogError>,
+{
+    run(DialogKind::Open, params, show, encode_open, decode_open)
+}
+
+// Shows a Save dialog using `show`, or replays its outcome, according to the session mode
+pub(crate) fn save_dialog<F>(
+    params: &DialogParams,
+    show: F,
+) -> Result<SaveDialogResult, DialogError>
+where
+    F: FnOnce() -> Result<SaveDialogResult, DialogError>,
+{
+    run(DialogKind::Save, params, show, encode_save, decode_save)
+}
+
+fn run<T, F>(
+    kind: DialogKind,
+    params: &DialogParams,
+    show: F,
+    encode: fn(&T) -> Value,
+    decode: fn(&Value) -> Option<T>,
+) -> Result<T, DialogError>
+where
+    F: FnOnce() -> Result<T, DialogError>,
+{
+    // The lock is not held while a dialog is shown, so that other threads can show dialogs
+    let record_path = {
+        let mut session = SESSION.lock().unwrap_or_else(|e| e.into_inner());
+        if let Session::Unset = *session {
+            *session = Session::from_env();
+        }
+        match &mut *session {
+            Session::Unset | Session::Live => None,
+            Session::Record(path) => Some(path.clone()),
+            Session::Replay(replay) => {
+                let (line, outcome) = replay.next(kind, params);
+                return decode_outcome(&outcome, decode)
+                    .unwrap_or_else(|| replay.invalid(line, "the outcome is not valid"));
+            }
+        }
+    };
+
+    let result = show();
+    if let Some(path) = record_path {
+        let outcome = encode_outcome(&result, encode);
+        append_entry(&path, kind, params, outcome)
+            .map_err(|error| DialogError::Io { path, error })?;
+    }
+    result
+}
+
+impl Session {
+    fn from_env() -> Session {
+        if let Some(path) = env::var_os(REPLAY_VAR).filter(|path| !path.is_empty()) {
+            let path = PathBuf::from(path);
+            match Replay::load(&path) {
+                Ok(replay) => Session::Replay(replay),
+                Err(e) => panic!(
+                    "{} is set but {} cannot be replayed: {}",
+                    REPLAY_VAR,
+                    path.display(),
+                    e
+                ),
+            }
+        } else if let Some(path) = env::var_os(RECORD_VAR).filter(|path| !path.is_empty()) {
+            Session::Record(PathBuf::from(path))
+        } else {
+            Session::Live
+        }
+    }
+}
+
+// The entries of a recording that are yet to be replayed
+struct Replay {
+    path: PathBuf,
+    // The line number and value of each entry
+    entries: Vec<(usize, Value)>,
+    next: usize,
+}
+
+impl Replay {
+    fn load(path: &Path) -> io::Result<Replay> {
+        let contents = fs::read_to_string(path)?;
+        Replay::parse(path, &contents)
+    }
+
+    fn parse(path: &Path, contents: &str) -> io::Result<Replay> {
+        let mut entries = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = json::parse(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", i + 1, e.0),
+                )
+            })?;
+            entries.push((i + 1, entry));
+        }
+
+        Ok(Replay {
+            path: path.to_path_buf(),
+            entries,
+            next: 0,
+        })
+    }
+
+    // Returns the line number and outcome of the next entry, panicking if it was recorded for a
+    // different kind of dialog or different parameters
+    fn next(&mut self, kind: DialogKind, params: &DialogParams) -> (usize, Value) {
+        let (line, entry) = match self.entries.get(self.next) {
+            Some((line, entry)) => (*line, entry.clone()),
+            None => panic!(
+                "wfd replay of {} diverged: a {} dialog was shown after all {} recorded dialogs \
+                 were replayed",
+                self.path.display(),
+                kind_name(kind),
+                self.entries.len()
+            ),
+        };
+        self.next += 1;
+
+        let recorded_kind = entry.get("dialog").and_then(Value::as_str);
+        if recorded_kind != Some(kind_name(kind)) {
+            self.diverged(
+                line,
+                "dialog",
+                &recorded_kind.unwrap_or_default().into(),
+                &kind_name(kind).into(),
+            );
+        }
+
+        let recorded_params = match entry.get("params") {
+            Some(Value::Object(members)) => members,
+            _ => self.invalid(line, "the params are missing"),
+        };
+        if let Value::Object(members) = encode_params(params) {
+            for (name, value) in &members {
+                let recorded = recorded_params
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map_or(&Value::Null, |(_, value)| value);
+                if recorded != value {
+                    self.diverged(line, &format!("params.{}", name), recorded, value);
+                }
+            }
+        }
+
+        match entry.get("outcome") {
+            Some(outcome) => (line, outcome.clone()),
+            None => self.invalid(line, "the outcome is missing"),
+        }
+    }
+
+    fn diverged(&self, line: usize, field: &str, recorded: &Value, actual: &Value) -> ! {
+        panic!(
+            "wfd replay of {} diverged at line {}: `{}` was {} when recorded but is {}",
+            self.path.display(),
+            line,
+            field,
+            json::write(recorded),
+            json::write(actual)
+        )
+    }
+
+    fn invalid(&self, line: usize, reason: &str) -> ! {
+        panic!(
+            "wfd replay of {} failed at line {}: {}",
+            self.path.display(),
+            line,
+            reason
+        )
+    }
+}
+
+fn append_entry(
+    path: &Path,
+    kind: DialogKind,
+    params: &DialogParams,
+    outcome: Value,
+) -> io::Result<()> {
+    let entry = object(vec![
+        ("dialog", kind_name(kind).into()),
+        ("params", encode_params(params)),
+        ("outcome", outcome),
+    ]);
+    let mut line = json::write(&entry);
+    line.push('\n');
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())
+}
+
+fn kind_name(kind: DialogKind) -> &'static str {
+    match kind {
+        DialogKind::Open => "open",
+        DialogKind::Save => "save",
+    }
+}
+
+// Encodes the parameters that affect the outcome of a dialog. The `owner` window is left out as
+// it differs between runs, and predicates and item filters are only recorded as being present.
+// Enums are written with explicit names rather than their `Debug` output, so that recordings stay
+// valid when the `Debug` output of a type changes.
+fn encode_params(params: &DialogParams) -> Value {
+    let rules = &params.selection_rules;
+    object(vec![
+        ("add_to_recent", params.add_to_recent.into()),
+        (
+            "aggregate_filter",
+            encode_aggregate_filter(params.aggregate_filter),
+        ),
+        ("default_extension", params.default_extension.into()),
+        ("default_folder", encode_folder(params.default_folder)),
+        ("file_name", params.file_name.into()),
+        ("file_name_label", params.file_name_label.into()),
+        ("file_type_index", params.file_type_index.into()),
+        (
+            "file_types",
+            params
+                .file_types
+                .iter()
+                .map(|(label, pattern)| Value::from(vec![*label, *pattern]))
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        ("folder", encode_folder(params.folder)),
+        (
+            "invalid_path_behavior",
+            encode_invalid_path_behavior(params.invalid_path_behavior),
+        ),
+        ("item_filter", params.item_filter.is_some().into()),
+        ("ok_button_label", params.ok_button_label.into()),
+        ("options", params.options.into()),
+        ("un_options", params.un_options.into()),
+        (
+            "overwrite_policy",
+            encode_overwrite_policy(params.overwrite_policy),
+        ),
+        ("path_form", encode_path_form(params.path_form)),
+        (
+            "selection_rules",
+            object(vec![
+                ("min_count", rules.min_count.into()),
+                ("max_count", rules.max_count.into()),
+                ("max_file_size", rules.max_file_size.into()),
+                ("must_be_readable", rules.must_be_readable.into()),
+                (
+                    "must_be_writable_directory",
+                    rules.must_be_writable_directory.into(),
+                ),
+                (
+                    "allowed_roots",
+                    Value::Array(
+                        rules
+                            .allowed_roots
+                            .iter()
+                            .map(|root| encode_path(root))
+                            .collect(),
+                    ),
+                ),
+                ("predicate", rules.predicate.is_some().into()),
+                ("reshow_on_rejection", rules.reshow_on_rejection.into()),
+            ]),
+        ),
+        ("require_writable", params.require_writable.into()),
+        (
+            "resolve_shortcuts",
+            encode_shortcut_resolution(params.resolve_shortcuts),
+        ),
+        (
+            "sanitize_file_name",
+            encode_sanitize_options(params.sanitize_file_name.as_ref()),
+        ),
+        ("save_as_item", encode_path(params.save_as_item)),
+        (
+            "select_files_and_folders",
+            params.select_files_and_folders.into(),
+        ),
+        ("title", params.title.into()),
+    ])
+}
+
+fn encode_aggregate_filter(aggregate_filter: AggregateFilter) -> Value {
+    match aggregate_filter {
+        AggregateFilter::Disabled => "disabled".into(),
+        AggregateFilter::First => "first".into(),
+        AggregateFilter::Last => "last".into(),
+        AggregateFilter::At(position) => format!("at:{}", position).into(),
+    }
+}
+
+// Encodes a folder as its kind followed by its value, i.e. `known:documents` or `path:/tmp`
+fn encode_folder(folder: FolderSpec) -> Value {
+    match folder {
+        FolderSpec::Path(path) => format!("path:{}", path.to_string_lossy()),
+        FolderSpec::Known(known_folder) => format!("known:{}", known_folder_name(known_folder)),
+        FolderSpec::Template(template) => format!("template:{}", template),
+        FolderSpec::Uri(uri) => format!("uri:{}", uri),
+    }
+    .into()
+}
+
+fn known_folder_name(known_folder: KnownFolder) -> &'static str {
+    match known_folder {
+        KnownFolder::Documents => "documents",
+        KnownFolder::Downloads => "downloads",
+        KnownFolder::Pictures => "pictures",
+        KnownFolder::Desktop => "desktop",
+        KnownFolder::Music => "music",
+        KnownFolder::Videos => "videos",
+        KnownFolder::Home => "home",
+    }
+}
+
+fn encode_invalid_path_behavior(behavior: InvalidPathBehavior) -> Value {
+    match behavior {
+        InvalidPathBehavior::Error => "error",
+        InvalidPathBehavior::NearestAncestor => "nearest_ancestor",
+        InvalidPathBehavior::Ignore => "ignore",
+    }
+    .into()
+}
+
+fn encode_overwrite_policy(policy: OverwritePolicy) -> Value {
+    match policy {
+        OverwritePolicy::ShellPrompt => "shell_prompt",
+        OverwritePolicy::Refuse => "refuse",
+        OverwritePolicy::Allow => "allow",
+        OverwritePolicy::AutoRename => "auto_rename",
+    }
+    .into()
+}
+
+fn encode_path_form(path_form: PathForm) -> Value {
+    match path_form {
+        PathForm::Unchanged => "unchanged",
+        PathForm::Plain => "plain",
+        PathForm::VerbatimWhenLong => "verbatim_when_long",
+        PathForm::PreserveUnc => "preserve_unc",
+    }
+    .into()
+}
+
+fn encode_shortcut_resolution(resolution: ShortcutResolution) -> Value {
+    match resolution {
+        ShortcutResolution::Off => "off",
+        ShortcutResolution::Replace => "replace",
+        ShortcutResolution::Annotate => "annotate",
+    }
+    .into()
+}
+
+// Encodes the options for sanitizing file names as the replacement followed by the maximum
+// length, i.e. `char:_:255` or `lookalike:255`, or `off` if names are not sanitized
+fn encode_sanitize_options(options: Option<&SanitizeOptions>) -> Value {
+    let options = match options {
+        Some(options) => options,
+        None => return "off".into(),
+    };
+    let replacement = match options.replacement {
+        CharReplacement::Char(c) => format!("char:{}", c),
+        CharReplacement::Remove => "remove".to_string(),
+        CharReplacement::Lookalike => "lookalike".to_string(),
+    };
+    format!("{}:{}", replacement, options.max_length).into()
+}
+
+fn encode_outcome<T>(result: &Result<T, DialogError>, encode: fn(&T) -> Value) -> Value {
+    match result {
+        Ok(value) => object(vec![("ok", encode(value))]),
+        Err(error) => object(vec![("error", encode_error(error))]),
+    }
+}
+
+fn decode_outcome<T>(
+    outcome: &Value,
+    decode: fn(&Value) -> Option<T>,
+) -> Option<Result<T, DialogError>> {
+    match (outcome.get("ok"), outcome.get("error")) {
+        (Some(value), None) => decode(value).map(Ok),
+        (None, Some(error)) => decode_error(error).map(Err),
+        _ => None,
+    }
+}
+
+fn encode_path(path: &Path) -> Value {
+    path.to_string_lossy().into_owned().into()
+}
+
+fn decode_path(value: &Value) -> Option<PathBuf> {
+    value.as_str().map(PathBuf::from)
+}
+
+fn encode_optional_path(path: Option<&Path>) -> Value {
+    path.map_or(Value::Null, encode_path)
+}
+
+// Decodes a path that may be null, returning `None` if the value is neither
+fn decode_optional_path(value: &Value) -> Option<Option<PathBuf>> {
+    if value.is_null() {
+        Some(None)
+    } else {
+        decode_path(value).map(Some)
+    }
+}
+
+pub(crate) fn encode_open(result: &OpenDialogResult) -> Value {
+    object(vec![
+        (
+            "paths",
+            Value::Array(
+                result
+                    .selected_file_paths
+                    .iter()
+                    .map(|path| encode_path(path))
+                    .collect(),
+            ),
+        ),
+        ("file_type_index", result.selected_file_type_index.into()),
+        ("sandbox", encode_sandbox(result.sandbox)),
+        (
+            "host_paths",
+            Value::Array(
+                result
+                    .selected_host_paths
+                    .iter()
+                    .map(|path| encode_path(path))
+                    .collect(),
+            ),
+        ),
+        (
+            "shortcut_targets",
+            Value::Array(
+                result
+                    .shortcut_targets
+                    .iter()
+                    .map(|target| encode_optional_path(target.as_deref()))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+fn decode_open(value: &Value) -> Option<OpenDialogResult> {
+    let paths = value
+        .get("paths")?
+        .as_array()?
+        .iter()
+        .map(decode_path)
+        .collect::<Option<Vec<_>>>()?;
+    // Sessions recorded before sandboxes were detected have no host paths
+    let selected_host_paths = match value.get("host_paths") {
+        Some(host_paths) => host_paths
+            .as_array()?
+            .iter()
+            .map(decode_path)
+            .collect::<Option<Vec<_>>>()?,
+        None => paths.clone(),
+    };
+    // Sessions recorded before shortcuts were resolved have no targets
+    let shortcut_targets = match value.get("shortcut_targets") {
+        Some(targets) => targets
+            .as_array()?
+            .iter()
+            .map(decode_optional_path)
+            .collect::<Option<Vec<_>>>()?,
+        None => vec![],
+    };
+    Some(OpenDialogResult {
+        selected_file_path: paths.first()?.clone(),
+        selected_file_paths: paths,
+        selected_file_type_index: decode_u32(value.get("file_type_index")?)?,
+        sandbox: decode_sandbox(value.get("sandbox"))?,
+        selected_host_paths,
+        shortcut_targets,
+    })
+}
+
+pub(crate) fn encode_save(result: &SaveDialogResult) -> Value {
+    object(vec![
+        ("path", encode_path(&result.selected_file_path)),
+        ("file_type_index", result.selected_filter_index.into()),
+        ("sandbox", encode_sandbox(result.sandbox)),
+        ("host_path", encode_path(&result.selected_host_path)),
+        (
+            "shortcut_target",
+            encode_optional_path(result.shortcut_target.as_deref()),
+        ),
+    ])
+}
+
+fn decode_save(value: &Value) -> Option<SaveDialogResult> {
+    let selected_file_path = decode_path(value.get("path")?)?;
+    let selected_host_path = match value.get("host_path") {
+        Some(host_path) => decode_path(host_path)?,
+        None => selected_file_path.clone(),
+    };
+    let shortcut_target = match value.get("shortcut_target") {
+        Some(target) => decode_optional_path(target)?,
+        None => None,
+    };
+    Some(SaveDialogResult {
+        selected_file_path,
+        selected_filter_index: decode_u32(value.get("file_type_index")?)?,
+        sandbox: decode_sandbox(value.get("sandbox"))?,
+        selected_host_path,
+        shortcut_target,
+    })
+}
+
+fn encode_sandbox(sandbox: Sandbox) -> Value {
+    match sandbox {
+        Sandbox::None => "none",
+        Sandbox::Flatpak => "flatpak",
+        Sandbox::Snap => "snap",
+    }
+    .into()
+}
+
+fn decode_sandbox(value: Option<&Value>) -> Option<Sandbox> {
+    match value.map(Value::as_str) {
+        None => Some(Sandbox::None),
+        Some(Some("none")) => Some(Sandbox::None),
+        Some(Some("flatpak")) => Some(Sandbox::Flatpak),
+        Some(Some("snap")) => Some(Sandbox::Snap),
+        Some(_) => None,
+    }
+}
+
+fn decode_u32(value: &Value) -> Option<u32> {
+    value.as_u64().and_then(|value| value.try_into().ok())
+}
+
+fn decode_usize(value: &Value) -> Option<usize> {
+    value.as_u64().and_then(|value| value.try_into().ok())
+}
+
+pub(crate) fn encode_error(error: &DialogError) -> Value {
+    match error {
+        DialogError::UserCancelled => object(vec![("kind", "UserCancelled".into())]),
+        DialogError::UnsupportedFilepath => object(vec![("kind", "UnsupportedFilepath".into())]),
+        DialogError::NonInteractive => object(vec![("kind", "NonInteractive".into())]),
+        DialogError::InteriorNul { field } => object(vec![
+            ("kind", "InteriorNul".into()),
+            ("field", field.as_str().into()),
+        ]),
+        DialogError::UriConversion { input, reason } => {
+            let (reason, detail) = match reason {
+                UriErrorReason::MissingScheme => ("MissingScheme", None),
+                UriErrorReason::UnsupportedScheme(scheme) => ("UnsupportedScheme", Some(scheme)),
+                UriErrorReason::RemoteHost(host) => ("RemoteHost", Some(host)),
+                UriErrorReason::MalformedEscape => ("MalformedEscape", None),
+                UriErrorReason::NullCharacter => ("NullCharacter", None),
+                UriErrorReason::NotAbsolute => ("NotAbsolute", None),
+                UriErrorReason::InvalidUnicode => ("InvalidUnicode", None),
+            };
+            object(vec![
+                ("kind", "UriConversion".into()),
+                ("input", input.as_str().into()),
+                ("reason", reason.into()),
+                ("detail", detail.map(String::as_str).into()),
+            ])
+        }
+        DialogError::Unsupported { reason } => object(vec![
+            ("kind", "Unsupported".into()),
+            ("reason", reason.as_str().into()),
+        ]),
+        DialogError::HResultFailed {
+            error_method,
+            hresult,
+        } => object(vec![
+            ("kind", "HResultFailed".into()),
+            ("method", error_method.as_str().into()),
+            ("hresult", (*hresult).into()),
+        ]),
+        DialogError::InvalidPath { field, path, cause } => object(vec![
+            ("kind", "InvalidPath".into()),
+            ("field", field.as_str().into()),
+            ("path", path.as_str().into()),
+            ("cause", encode_error(cause)),
+        ]),
+        DialogError::SelectionRejected { rejections } => object(vec![
+            ("kind", "SelectionRejected".into()),
+            (
+                "rejections",
+                Value::Array(rejections.iter().map(encode_rejection).collect()),
+            ),
+        ]),
+        DialogError::Io { path, error } => object(vec![
+            ("kind", "Io".into()),
+            ("path", encode_path(path)),
+            ("error_kind", encode_io_error_kind(error.kind()).into()),
+            ("os_error", error.raw_os_error().into()),
+            ("message", error.to_string().into()),
+        ]),
+    }
+}
+
+fn decode_error(value: &Value) -> Option<DialogError> {
+    let error = match value.get("kind")?.as_str()? {
+        "UserCancelled" => DialogError::UserCancelled,
+        "UnsupportedFilepath" => DialogError::UnsupportedFilepath,
+        "NonInteractive" => DialogError::NonInteractive,
+        "InteriorNul" => DialogError::InteriorNul {
+            field: value.get("field")?.as_str()?.to_string(),
+        },
+        "UriConversion" => {
+            let detail = || value.get("detail")?.as_str().map(String::from);
+            DialogError::UriConversion {
+                input: value.get("input")?.as_str()?.to_string(),
+                reason: match value.get("reason")?.as_str()? {
+                    "MissingScheme" => UriErrorReason::MissingScheme,
+                    "UnsupportedScheme" => UriErrorReason::UnsupportedScheme(detail()?),
+                    "RemoteHost" => UriErrorReason::RemoteHost(detail()?),
+                    "MalformedEscape" => UriErrorReason::MalformedEscape,
+                    "NullCharacter" => UriErrorReason::NullCharacter,
+                    "NotAbsolute" => UriErrorReason::NotAbsolute,
+                    "InvalidUnicode" => UriErrorReason::InvalidUnicode,
+                    _ => return None,
+                },
+            }
+        }
+        "Unsupported" => DialogError::Unsupported {
+            reason: value.get("reason")?.as_str()?.to_string(),
+        },
+        "HResultFailed" => DialogError::HResultFailed {
+            error_method: value.get("method")?.as_str()?.to_string(),
+            hresult: value.get("hresult")?.as_i64()?.try_into().ok()?,
+        },
+        "InvalidPath" => DialogError::InvalidPath {
+            field: value.get("field")?.as_str()?.to_string(),
+            path: value.get("path")?.as_str()?.to_string(),
+            cause: Box::new(decode_error(value.get("cause")?)?),
+        },
+        "SelectionRejected" => DialogError::SelectionRejected {
+            rejections: value
+                .get("rejections")?
+                .as_array()?
+                .iter()
+                .map(decode_rejection)
+                .collect::<Option<Vec<_>>>()?,
+        },
+        "Io" => DialogError::Io {
+            path: decode_path(value.get("path")?)?,
+            error: decode_io_error(value)?,
+        },
+        _ => return None,
+    };
+    Some(error)
+}
+
+// Names the kinds of I/O errors that `decode_io_error` distinguishes, all others are `Other`
+fn encode_io_error_kind(kind: io::ErrorKind) -> &'static str {
+    match kind {
+        io::ErrorKind::NotFound => "NotFound",
+        io::ErrorKind::PermissionDenied => "PermissionDenied",
+        io::ErrorKind::AlreadyExists => "AlreadyExists",
+        io::ErrorKind::InvalidInput => "InvalidInput",
+        io::ErrorKind::InvalidData => "InvalidData",
+        io::ErrorKind::UnexpectedEof => "UnexpectedEof",
+        _ => "Other",
+    }
+}
+
+fn decode_io_error(value: &Value) -> Option<io::Error> {
+    let os_error = value.get("os_error")?;
+    if !os_error.is_null() {
+        return Some(io::Error::from_raw_os_error(
+            os_error.as_i64()?.try_into().ok()?,
+        ));
+    }
+
+    let kind = match value.get("error_kind")?.as_str()? {
+        "NotFound" => io::ErrorKind::NotFound,
+        "PermissionDenied" => io::ErrorKind::PermissionDenied,
+        "AlreadyExists" => io::ErrorKind::AlreadyExists,
+        "InvalidInput" => io::ErrorKind::InvalidInput,
+        "InvalidData" => io::ErrorKind::InvalidData,
+        "UnexpectedEof" => io::ErrorKind::UnexpectedEof,
+        _ => io::ErrorKind::Other,
+    };
+    Some(io::Error::new(kind, value.get("message")?.as_str()?))
+}
+
+fn encode_rejection(rejection: &SelectionRejection) -> Value {
+    let mut members = vec![(
+        "path",
+        rejection.path.as_deref().map_or(Value::Null, encode_path),
+    )];
+    match &rejection.reason {
+        RejectionReason::TooFewItems { min, count } => members.extend(vec![
+            ("reason", "TooFewItems".into()),
+            ("min", (*min).into()),
+            ("count", (*count).into()),
+        ]),
+        RejectionReason::TooManyItems { max, count } => members.extend(vec![
+            ("reason", "TooManyItems".into()),
+            ("max", (*max).into()),
+            ("count", (*count).into()),
+        ]),
+        RejectionReason::TooLarge { max, size } => members.extend(vec![
+            ("reason", "TooLarge".into()),
+            ("max", (*max).into()),
+            ("size", (*size).into()),
+        ]),
+        RejectionReason::NotReadable => members.push(("reason", "NotReadable".into())),
+        RejectionReason::NotWritableDirectory => {
+            members.push(("reason", "NotWritableDirectory".into()))
+        }
+        RejectionReason::OutsideAllowedRoots => {
+            members.push(("reason", "OutsideAllowedRoots".into()))
+        }
+        RejectionReason::Predicate(message) => members.extend(vec![
+            ("reason", "Predicate".into()),
+            ("message", message.as_str().into()),
+        ]),
+        RejectionReason::HiddenByFilter => members.push(("reason", "HiddenByFilter".into())),
+    }
+    object(members)
+}
+
+fn decode_rejection(value: &Value) -> Option<SelectionRejection> {
+    let path = value.get("path")?;
+    let reason = match value.get("reason")?.as_str()? {
+        "TooFewItems" => RejectionReason::TooFewItems {
+            min: decode_usize(value.get("min")?)?,
+            count: decode_usize(value.get("count")?)?,
+        },
+        "TooManyItems" => RejectionReason::TooManyItems {
+            max: decode_usize(value.get("max")?)?,
+            count: decode_usize(value.get("count")?)?,
+        },
+        "TooLarge" => RejectionReason::TooLarge {
+            max: value.get("max")?.as_u64()?,
+            size: value.get("size")?.as_u64()?,
+        },
+        "NotReadable" => RejectionReason::NotReadable,
+        "NotWritableDirectory" => RejectionReason::NotWritableDirectory,
+        "OutsideAllowedRoots" => RejectionReason::OutsideAllowedRoots,
+        "Predicate" => RejectionReason::Predicate(value.get("message")?.as_str()?.to_string()),
+        "HiddenByFilter" => RejectionReason::HiddenByFilter,
+        _ => return None,
+    };
+    Some(SelectionRejection {
+        path: if path.is_null() {
+            None
+        } else {
+            Some(decode_path(path)?)
+        },
+        reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SelectionRules;
+    use std::process;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("wfd-replay-{}-{}.jsonl", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn params() -> DialogParams<'static> {
+        DialogParams {
+            file_types: vec![("Text", "*.txt"), ("All files", "*.*")],
+            title: "Export",
+            ..Default::default()
+        }
+    }
+
+    fn record<T>(
+        path: &Path,
+        kind: DialogKind,
+        params: &DialogParams,
+        result: &Result<T, DialogError>,
+        encode: fn(&T) -> Value,
+    ) {
+        append_entry(path, kind, params, encode_outcome(result, encode)).unwrap();
+    }
+
+    fn replay_open(
+        replay: &mut Replay,
+        params: &DialogParams,
+    ) -> Result<OpenDialogResult, DialogError> {
+        let (_, outcome) = replay.next(DialogKind::Open, params);
+        decode_outcome(&outcome, decode_open).unwrap()
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = temp_file("round-trip");
+        let open = OpenDialogResult {
+            selected_file_path: PathBuf::from(r"C:\Users\ben\a.txt"),
+            selected_file_paths: vec![
+                PathBuf::from(r"C:\Users\ben\a.txt"),
+                PathBuf::from(r"C:\Users\ben\b.txt"),
+            ],
+            selected_file_type_index: 1,
+            sandbox: Sandbox::None,
+            selected_host_paths: vec![
+                PathBuf::from(r"C:\Users\ben\a.txt"),
+                PathBuf::from(r"C:\Users\ben\b.txt"),
+            ],
+            shortcut_targets: vec![None, Some(PathBuf::from(r"\\fileserver\projects\b.txt"))],
+        };
+        let save = SaveDialogResult {
+            selected_file_path: PathBuf::from("/run/user/1000/doc/a1b2c3/export.csv"),
+            selected_filter_index: 2,
+            sandbox: Sandbox::Flatpak,
+            selected_host_path: PathBuf::from("/home/ben/export.csv"),
+            shortcut_target: None,
+        };
+        record(&path, DialogKind::Open, &params(), &Ok(open), encode_open);
+        record(&path, DialogKind::Save, &params(), &Ok(save), encode_save);
+        record::<OpenDialogResult>(
+            &path,
+            DialogKind::Open,
+            &params(),
+            &Err(DialogError::UserCancelled),
+            encode_open,
+        );
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        assert!(contents.starts_with(r#"{"dialog":"open","params":{"add_to_recent":false"#));
+
+        let mut replay = Replay::load(&path).unwrap();
+        let open = replay_open(&mut replay, &params()).unwrap();
+        assert_eq!(
+            open.selected_file_path,
+            PathBuf::from(r"C:\Users\ben\a.txt")
+        );
+        assert_eq!(open.selected_file_paths.len(), 2);
+        assert_eq!(open.selected_file_type_index, 1);
+        assert_eq!(open.sandbox, Sandbox::None);
+        assert_eq!(open.selected_host_paths, open.selected_file_paths);
+        assert_eq!(
+            open.shortcut_targets,
+            vec![None, Some(PathBuf::from(r"\\fileserver\projects\b.txt"))]
+        );
+
+        let (_, outcome) = replay.next(DialogKind::Save, &params());
+        let save = decode_outcome(&outcome, decode_save).unwrap().unwrap();
+        assert_eq!(
+            save.selected_file_path,
+            PathBuf::from("/run/user/1000/doc/a1b2c3/export.csv")
+        );
+        assert_eq!(save.selected_filter_index, 2);
+        assert_eq!(save.sandbox, Sandbox::Flatpak);
+        assert_eq!(
+            save.selected_host_path,
+            PathBuf::from("/home/ben/export.csv")
+        );
+        assert_eq!(save.shortcut_target, None);
+
+        assert!(matches!(
+            replay_open(&mut replay, &params()),
+            Err(DialogError::UserCancelled)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn results_without_sandbox_fields() {
+        // Sessions recorded before the sandbox was detected, or shortcuts were resolved
+        let open = json::parse(r#"{"paths":["/home/ben/a.txt"],"file_type_index":0}"#).unwrap();
+        let open = decode_open(&open).unwrap();
+        assert_eq!(open.sandbox, Sandbox::None);
+        assert_eq!(
+            open.selected_host_paths,
+            vec![PathBuf::from("/home/ben/a.txt")]
+        );
+        assert!(open.shortcut_targets.is_empty());
+
+        let save = json::parse(r#"{"path":"/home/ben/b.txt","file_type_index":1}"#).unwrap();
+        let save = decode_save(&save).unwrap();
+        assert_eq!(save.sandbox, Sandbox::None);
+        assert_eq!(save.selected_host_path, PathBuf::from("/home/ben/b.txt"));
+        assert_eq!(save.shortcut_target, None);
+
+        let open = json::parse(r#"{"paths":["/a"],"file_type_index":0,"sandbox":"jail"}"#).unwrap();
+        assert!(decode_open(&open).is_none());
+    }
+
+    #[test]
+    fn errors_round_trip() {
+        let errors = vec![
+            DialogError::UnsupportedFilepath,
+            DialogError::NonInteractive,
+            DialogError::InteriorNul {
+                field: "title".to_string(),
+            },
+            DialogError::UriConversion {
+                input: "https://example.com/report.txt".to_string(),
+                reason: UriErrorReason::UnsupportedScheme("https".to_string()),
+            },
+            DialogError::Unsupported {
+                reason: "wfd was built without the `native-windows` feature".to_string(),
+            },
+            DialogError::HResultFailed {
+                error_method: "IModalWindow::Show".to_string(),
+                hresult: 0x8000_4005_u32 as i32,
+            },
+            DialogError::InvalidPath {
+                field: "folder".to_string(),
+                path: r"C:\missing".to_string(),
+                cause: Box::new(DialogError::HResultFailed {
+                    error_method: "SHCreateItemFromParsingName".to_string(),
+                    hresult: 0x8007_0002_u32 as i32,
+                }),
+            },
+            DialogError::SelectionRejected {
+                rejections: vec![
+                    SelectionRejection {
+                        path: None,
+                        reason: RejectionReason::TooManyItems { max: 1, count: 2 },
+                    },
+                    SelectionRejection {
+                        path: Some(PathBuf::from("big.iso")),
+                        reason: RejectionReason::TooLarge {
+                            max: 1024,
+                            size: u64::MAX,
+                        },
+                    },
+                    SelectionRejection {
+                        path: Some(PathBuf::from("notes.txt")),
+                        reason: RejectionReason::Predicate("not a report".to_string()),
+                    },
+                    SelectionRejection {
+                        path: Some(PathBuf::from("draft.tmp")),
+                        reason: RejectionReason::HiddenByFilter,
+                    },
+                ],
+            },
+            DialogError::Io {
+                path: PathBuf::from("export.csv"),
+                error: io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+            },
+        ];
+
+        for error in &errors {
+            let encoded = encode_error(error);
+            let decoded = decode_error(&json::parse(&json::write(&encoded)).unwrap()).unwrap();
+            // Errors don't implement PartialEq, so compare their encoded forms
+            assert_eq!(encode_error(&decoded), encoded);
+        }
+
+        let io_error = decode_error(&encode_error(errors.last().unwrap())).unwrap();
+        match io_error {
+            DialogError::Io { error, .. } => {
+                assert_eq!(error.kind(), io::ErrorKind::PermissionDenied)
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`params.title` was \"Export\" when recorded but is \"Import\"")]
+    fn replay_panics_on_diverging_params() {
+        let path = PathBuf::from("dialogs.jsonl");
+        let mut contents = json::write(&object(vec![
+            ("dialog", "open".into()),
+            ("params", encode_params(&params())),
+            (
+                "outcome",
+                object(vec![("error", encode_error(&DialogError::UserCancelled))]),
+            ),
+        ]));
+        contents.push('\n');
+        let mut replay = Replay::parse(&path, &contents).unwrap();
+
+        let diverging = DialogParams {
+            title: "Import",
+            ..params()
+        };
+        let _ = replay_open(&mut replay, &diverging);
+    }
+
+    #[test]
+    #[should_panic(expected = "`dialog` was \"open\" when recorded but is \"save\"")]
+    fn replay_panics_on_diverging_kind() {
+        let path = temp_file("kind");
+        record::<OpenDialogResult>(
+            &path,
+            DialogKind::Open,
+            &params(),
+            &Err(DialogError::UserCancelled),
+            encode_open,
+        );
+        let mut replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        replay.next(DialogKind::Save, &params());
+    }
+
+    #[test]
+    #[should_panic(expected = "after all 0 recorded dialogs were replayed")]
+    fn replay_panics_when_exhausted() {
+        let mut replay = Replay::parse(Path::new("empty.jsonl"), "\n").unwrap();
+        replay.next(DialogKind::Open, &params());
+    }
+
+    #[test]
+    fn replay_ignores_owner() {
+        let path = temp_file("owner");
+        record::<OpenDialogResult>(
+            &path,
+            DialogKind::Open,
+            &params(),
+            &Err(DialogError::UserCancelled),
+            encode_open,
+        );
+        let mut replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let params = DialogParams {
+            owner: Some(0x1234 as crate::HWND),
+            overwrite_policy: OverwritePolicy::ShellPrompt,
+            selection_rules: SelectionRules::default(),
+            ..params()
+        };
+        assert!(replay_open(&mut replay, &params).is_err());
+    }
+
+    #[test]
+    fn params_are_encoded_by_name() {
+        let params = DialogParams {
+            aggregate_filter: AggregateFilter::At(2),
+            default_folder: FolderSpec::Known(KnownFolder::Documents),
+            folder: FolderSpec::Template("~/exports"),
+            invalid_path_behavior: InvalidPathBehavior::NearestAncestor,
+            overwrite_policy: OverwritePolicy::AutoRename,
+            path_form: PathForm::VerbatimWhenLong,
+            resolve_shortcuts: ShortcutResolution::Annotate,
+            sanitize_file_name: Some(SanitizeOptions {
+                replacement: CharReplacement::Char('-'),
+                max_length: 100,
+            }),
+            ..params()
+        };
+        let encoded = encode_params(&params);
+        let field = |name| encoded.get(name).and_then(Value::as_str).unwrap();
+        assert_eq!(field("aggregate_filter"), "at:2");
+        assert_eq!(field("default_folder"), "known:documents");
+        assert_eq!(field("folder"), "template:~/exports");
+        assert_eq!(field("invalid_path_behavior"), "nearest_ancestor");
+        assert_eq!(field("overwrite_policy"), "auto_rename");
+        assert_eq!(field("path_form"), "verbatim_when_long");
+        assert_eq!(field("resolve_shortcuts"), "annotate");
+        assert_eq!(field("sanitize_file_name"), "char:-:100");
+
+        let encoded = encode_params(&DialogParams::default());
+        let field = |name| encoded.get(name).and_then(Value::as_str).unwrap();
+        assert_eq!(field("aggregate_filter"), "disabled");
+        assert_eq!(field("default_folder"), "path:");
+        assert_eq!(field("overwrite_policy"), "shell_prompt");
+        assert_eq!(field("sanitize_file_name"), "off");
+    }
+
+    #[test]
+    fn invalid_recording() {
+        let error = Replay::parse(Path::new("broken.jsonl"), "{}\n{\"dialog\":\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 2: "));
+    }
+}
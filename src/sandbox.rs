@@ -0,0 +1,224 @@
+// Detection of application sandboxes, and mapping of the paths that the document portal exposes
+// to sandboxed applications back to the paths of the files on the host
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The application sandbox that the process is running in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sandbox {
+    /// The process is not sandboxed
+    #[default]
+    None,
+    /// The process is running in a Flatpak sandbox, which is detected by the presence of
+    /// `/.flatpak-info`
+    Flatpak,
+    /// The process is running as a Snap, which is detected by the presence of the `SNAP`
+    /// environment variable
+    Snap,
+}
+
+/// Returns the application sandbox that the process is running in
+pub fn current_sandbox() -> Sandbox {
+    if cfg!(windows) {
+        return Sandbox::None;
+    }
+    detect(Path::new("/.flatpak-info").exists(), env::var_os("SNAP"))
+}
+
+fn detect(flatpak_info_exists: bool, snap: Option<OsString>) -> Sandbox {
+    if flatpak_info_exists {
+        Sandbox::Flatpak
+    } else if snap.is_some_and(|snap| !snap.is_empty()) {
+        Sandbox::Snap
+    } else {
+        Sandbox::None
+    }
+}
+
+// Returns the path of each selected file on the host. Paths within the document store of the
+// document portal, i.e. `/run/user/1000/doc/a1b2c3/report.odt`, are resolved using the
+// portal's `GetHostPaths` method. Paths that are not in the document store, or cannot be
+// resolved, are returned unchanged.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn host_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    use crate::dbus::{self, Connection};
+
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("/run/user/{}", unsafe { libc::getuid() })));
+    let store = runtime_dir.join("doc");
+    map_host_paths(paths, &store, |ids| {
+        let mut connection = Connection::session()?;
+        dbus::get_host_paths(&mut connection, ids)
+    })
+}
+
+// Files cannot be exported by the document portal on other platforms
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn host_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths.to_vec()
+}
+
+// Maps the paths in the document store using `resolve`, which is only called if at least one
+// path is in the store and returns the host paths of the documents with the given IDs
+#[cfg(any(all(unix, not(target_os = "macos")), test))]
+fn map_host_paths<F>(paths: &[PathBuf], store: &Path, resolve: F) -> Vec<PathBuf>
+where
+    F: FnOnce(&[&str]) -> std::io::Result<std::collections::HashMap<String, PathBuf>>,
+{
+    let documents = paths
+        .iter()
+        .map(|path| document(path, store))
+        .collect::<Vec<_>>();
+    let mut ids = documents
+        .iter()
+        .filter_map(|document| document.as_ref().map(|(id, _)| *id))
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+        return paths.to_vec();
+    }
+
+    let host_paths = resolve(&ids).unwrap_or_default();
+    paths
+        .iter()
+        .zip(documents)
+        .map(|(path, document)| {
+            document
+                .and_then(|(id, rest)| {
+                    let host = host_paths.get(id)?;
+                    // Joining an empty path would add a trailing separator
+                    Some(if rest.as_os_str().is_empty() {
+                        host.clone()
+                    } else {
+                        host.join(rest)
+                    })
+                })
+                .unwrap_or_else(|| path.clone())
+        })
+        .collect()
+}
+
+// Splits a path in the document store into the ID of the document and the path within the
+// document. The first component after the ID is the name of the exported file or directory,
+// which is the last component of the host path.
+#[cfg(any(all(unix, not(target_os = "macos")), test))]
+fn document<'a>(path: &'a Path, store: &Path) -> Option<(&'a str, PathBuf)> {
+    let mut components = path.strip_prefix(store).ok()?.components();
+    let id = components.next()?.as_os_str().to_str()?;
+    components.next()?;
+    Some((id, components.as_path().to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io;
+
+    #[test]
+    fn detect_sandbox() {
+        assert_eq!(detect(true, Some("/snap/app/1".into())), Sandbox::Flatpak);
+        assert_eq!(detect(false, Some("/snap/app/1".into())), Sandbox::Snap);
+        assert_eq!(detect(false, Some("".into())), Sandbox::None);
+        assert_eq!(detect(false, None), Sandbox::None);
+    }
+
+    #[test]
+    fn document_paths() {
+        let store = Path::new("/run/user/1000/doc");
+        assert_eq!(
+            document(Path::new("/run/user/1000/doc/a1b2c3/report.odt"), store),
+            Some(("a1b2c3", PathBuf::new()))
+        );
+        assert_eq!(
+            document(
+                Path::new("/run/user/1000/doc/a1b2c3/project/src/main.rs"),
+                store
+            ),
+            Some(("a1b2c3", PathBuf::from("src/main.rs")))
+        );
+        assert_eq!(
+            document(Path::new("/run/user/1000/doc/a1b2c3"), store),
+            None
+        );
+        assert_eq!(document(Path::new("/run/user/1000/docs/a/b"), store), None);
+        assert_eq!(document(Path::new("/home/ben/report.odt"), store), None);
+    }
+
+    #[test]
+    fn map_paths_to_host() {
+        let store = Path::new("/run/user/1000/doc");
+        let paths = vec![
+            PathBuf::from("/run/user/1000/doc/a1b2c3/report.odt"),
+            PathBuf::from("/home/ben/local.txt"),
+            PathBuf::from("/run/user/1000/doc/d4e5f6/project/src/main.rs"),
+            PathBuf::from("/run/user/1000/doc/unknown/file.txt"),
+            PathBuf::from("/run/user/1000/doc/a1b2c3/report.odt"),
+        ];
+
+        let mut requested = vec![];
+        let host_paths = map_host_paths(&paths, store, |ids| {
+            requested = ids.iter().map(|id| id.to_string()).collect();
+            let mut host_paths = HashMap::new();
+            host_paths.insert("a1b2c3".to_string(), PathBuf::from("/home/ben/report.odt"));
+            host_paths.insert("d4e5f6".to_string(), PathBuf::from("/home/ben/project"));
+            Ok(host_paths)
+        });
+        assert_eq!(requested, vec!["a1b2c3", "d4e5f6", "unknown"]);
+        assert_eq!(
+            host_paths,
+            vec![
+                PathBuf::from("/home/ben/report.odt"),
+                PathBuf::from("/home/ben/local.txt"),
+                PathBuf::from("/home/ben/project/src/main.rs"),
+                PathBuf::from("/run/user/1000/doc/unknown/file.txt"),
+                PathBuf::from("/home/ben/report.odt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_paths_without_documents() {
+        let paths = vec![PathBuf::from("/home/ben/local.txt")];
+        let host_paths = map_host_paths(&paths, Path::new("/run/user/1000/doc"), |_| {
+            panic!("the portal must not be called")
+        });
+        assert_eq!(host_paths, paths);
+
+        // Paths are returned unchanged if the portal cannot be reached
+        let paths = vec![PathBuf::from("/run/user/1000/doc/a1b2c3/report.odt")];
+        let host_paths = map_host_paths(&paths, Path::new("/run/user/1000/doc"), |_| {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert_eq!(host_paths, paths);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn map_paths_with_fake_portal() {
+        use crate::dbus::{self, tests::fake_bus, Connection};
+
+        let (address, server) = fake_bus("sandbox", vec![("a1b2c3", b"/home/ben/report.odt\0")]);
+        let paths = vec![
+            PathBuf::from("/run/user/1000/doc/a1b2c3/report.odt"),
+            PathBuf::from("/run/user/1000/doc/zzz/other.odt"),
+        ];
+        let host_paths = map_host_paths(&paths, Path::new("/run/user/1000/doc"), |ids| {
+            let mut connection = Connection::open(&address)?;
+            dbus::get_host_paths(&mut connection, ids)
+        });
+        assert_eq!(
+            host_paths,
+            vec![
+                PathBuf::from("/home/ben/report.odt"),
+                PathBuf::from("/run/user/1000/doc/zzz/other.odt"),
+            ]
+        );
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,523 @@
+// The `sanitize_file_name` of `DialogParams`, which turns arbitrary text such as a document title
+// into a file name that can be saved on Windows and other platforms
+
+use std::path::PathBuf;
+
+// The characters that Windows does not allow in file names, in addition to control characters.
+// `/` is also the separator of other platforms, and `:` is reserved by macOS.
+const RESERVED_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+// The device names that Windows reserves regardless of their case or extension
+const RESERVED_NAMES: [&str; 6] = ["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"];
+
+// The numbered device names, which are followed by a digit or a superscript digit
+const RESERVED_NUMBERED_NAMES: [&str; 2] = ["COM", "LPT"];
+
+// The replacement used when a name is empty or is a reserved name, and the configured replacement
+// character cannot be used
+const FALLBACK_CHAR: char = '_';
+
+/// How [`sanitize_file_name`] replaces characters that are not allowed in file names
+///
+/// [`sanitize_file_name`]: fn.sanitize_file_name.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharReplacement {
+    /// Each invalid character is replaced with the given character, `_` by default. A character
+    /// that is itself invalid is replaced with `_` instead.
+    Char(char),
+    /// Invalid characters are removed
+    Remove,
+    /// Invalid characters are replaced with similar looking characters that are allowed, for
+    /// example `:` with the fullwidth colon `：`, and control characters are removed
+    Lookalike,
+}
+
+impl Default for CharReplacement {
+    fn default() -> Self {
+        CharReplacement::Char(FALLBACK_CHAR)
+    }
+}
+
+impl CharReplacement {
+    // Returns the replacement for an invalid character, if any
+    fn replace(self, c: char) -> Option<char> {
+        match self {
+            CharReplacement::Char(replacement) if is_invalid_char(replacement) => {
+                Some(FALLBACK_CHAR)
+            }
+            CharReplacement::Char(replacement) => Some(replacement),
+            CharReplacement::Remove => None,
+            CharReplacement::Lookalike => lookalike(c),
+        }
+    }
+
+    // The character appended to a reserved name, or used as the whole name when nothing else is
+    // left of it. It must be allowed at the end of a name.
+    fn suffix(self) -> char {
+        match self {
+            CharReplacement::Char(c) if !is_invalid_char(c) && !is_trimmed_char(c) => c,
+            _ => FALLBACK_CHAR,
+        }
+    }
+}
+
+/// Options for [`sanitize_file_name`]
+///
+/// [`sanitize_file_name`]: fn.sanitize_file_name.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SanitizeOptions {
+    /// How characters that are not allowed in file names are replaced
+    pub replacement: CharReplacement,
+    /// The maximum length of the name in bytes of UTF-8, 255 by default. This is the limit of
+    /// most file systems on Linux and macOS, and also keeps the name within the 255 UTF-16 code
+    /// units allowed by Windows.
+    pub max_length: usize,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        SanitizeOptions {
+            replacement: CharReplacement::default(),
+            max_length: 255,
+        }
+    }
+}
+
+impl SanitizeOptions {
+    // Sanitizes the file name of a path returned from a dialog. Its folders are left unchanged as
+    // they already exist, as are names which aren't valid Unicode.
+    pub(crate) fn apply(&self, path: PathBuf) -> PathBuf {
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return path,
+        };
+        let sanitized = sanitize_file_name(name, self);
+        if sanitized == name {
+            path
+        } else {
+            path.with_file_name(sanitized)
+        }
+    }
+}
+
+/// Returns a file name that can be saved on Windows and other platforms, made from the given
+/// text, such as the title of a document.
+///
+/// - The characters `<`, `>`, `:`, `"`, `/`, `\`, `|`, `?` and `*`, and control characters, are
+///   replaced as configured by the `replacement` of the options.
+/// - Trailing dots and spaces, which Windows removes from names, are removed.
+/// - Names longer than the `max_length` of the options are shortened, keeping their extension
+///   where possible.
+/// - The device names that Windows reserves, such as `CON`, `aux.txt` or `COM1.log`, have the
+///   replacement character (or `_`) appended to the part before their extension, i.e. `CON_` and
+///   `aux_.txt`.
+/// - A name that nothing is left of is replaced with the replacement character (or `_`).
+///
+/// Text that is already a valid file name is returned unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use wfd::{sanitize_file_name, CharReplacement, SanitizeOptions};
+///
+/// let options = SanitizeOptions::default();
+/// assert_eq!(sanitize_file_name("Q3: Sales/Costs?", &options), "Q3_ Sales_Costs_");
+/// assert_eq!(sanitize_file_name("aux.txt", &options), "aux_.txt");
+///
+/// let options = SanitizeOptions {
+///     replacement: CharReplacement::Lookalike,
+///     ..Default::default()
+/// };
+/// assert_eq!(sanitize_file_name("What's new?", &options), "What's new？");
+/// ```
+pub fn sanitize_file_name(name: &str, options: &SanitizeOptions) -> String {
+    let replaced: String = name
+        .chars()
+        .filter_map(|c| {
+            if is_invalid_char(c) {
+                options.replacement.replace(c)
+            } else {
+                Some(c)
+            }
+        })
+        .collect();
+
+    let suffix = options.replacement.suffix();
+    let mut name = truncate(trim_end(&replaced), options.max_length);
+    if name.is_empty() {
+        name = suffix.to_string();
+    }
+    if is_reserved_name(&name) {
+        name = escape_reserved_name(&name, suffix, options.max_length);
+    }
+    name
+}
+
+fn is_invalid_char(c: char) -> bool {
+    (c as u32) < 0x20 || RESERVED_CHARS.contains(&c)
+}
+
+// Characters that Windows removes from the end of names
+fn is_trimmed_char(c: char) -> bool {
+    c == '.' || c == ' '
+}
+
+fn trim_end(name: &str) -> &str {
+    name.trim_end_matches(is_trimmed_char)
+}
+
+// The fullwidth forms of the reserved characters, which look the same but are allowed
+fn lookalike(c: char) -> Option<char> {
+    match c {
+        '<' => Some('＜'),
+        '>' => Some('＞'),
+        ':' => Some('：'),
+        '"' => Some('＂'),
+        '/' => Some('／'),
+        '\\' => Some('＼'),
+        '|' => Some('｜'),
+        '?' => Some('？'),
+        '*' => Some('＊'),
+        _ => None,
+    }
+}
+
+// Shortens a name to at most `max_length` bytes without splitting a character. The extension is
+// kept if there is room for it and at least one character before it, and dots and spaces left at
+// the end are removed.
+fn truncate(name: &str, max_length: usize) -> String {
+    if name.len() <= max_length {
+        return name.to_string();
+    }
+
+    let extension = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot < max_length => &name[dot..],
+        _ => "",
+    };
+    let stem = &name[..name.len() - extension.len()];
+    let stem = trim_end(truncate_bytes(stem, max_length - extension.len()));
+    if stem.is_empty() {
+        trim_end(truncate_bytes(name, max_length)).to_string()
+    } else {
+        format!("{}{}", stem, extension)
+    }
+}
+
+fn truncate_bytes(s: &str, max_length: usize) -> &str {
+    let mut end = max_length.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+// Returns the part of a name that Windows compares with the reserved device names, which is the
+// part before the first dot without trailing spaces
+fn device_part(name: &str) -> &str {
+    let end = name.find('.').unwrap_or(name.len());
+    name[..end].trim_end_matches(' ')
+}
+
+fn is_reserved_name(name: &str) -> bool {
+    let device = device_part(name);
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| device.eq_ignore_ascii_case(reserved))
+    {
+        return true;
+    }
+
+    let mut chars = device.chars();
+    let digit = chars.next_back();
+    let prefix = chars.as_str();
+    RESERVED_NUMBERED_NAMES
+        .iter()
+        .any(|reserved| prefix.eq_ignore_ascii_case(reserved))
+        && matches!(digit, Some('0'..='9') | Some('¹') | Some('²') | Some('³'))
+}
+
+// Appends the suffix to the device part of a reserved name, shortening the device part if the
+// name would otherwise be longer than `max_length`
+fn escape_reserved_name(name: &str, suffix: char, max_length: usize) -> String {
+    let device = device_part(name);
+    let rest = &name[device.len()..];
+    let available = max_length.saturating_sub(rest.len() + suffix.len_utf8());
+    let escaped = format!("{}{}{}", truncate_bytes(device, available), suffix, rest);
+    if is_reserved_name(&escaped) {
+        // Only possible when the suffix completes a numbered device name, such as `COM` and `1`
+        escape_reserved_name(name, FALLBACK_CHAR, max_length)
+    } else {
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitize(name: &str) -> String {
+        sanitize_file_name(name, &SanitizeOptions::default())
+    }
+
+    fn sanitize_with(name: &str, replacement: CharReplacement, max_length: usize) -> String {
+        let options = SanitizeOptions {
+            replacement,
+            max_length,
+        };
+        sanitize_file_name(name, &options)
+    }
+
+    #[test]
+    fn valid_names_are_unchanged() {
+        for name in &[
+            "report.txt",
+            "Résumé – 2024.docx",
+            "отчёт",
+            "日本語のファイル名.pdf",
+            ".gitignore",
+            "  leading spaces",
+            "archive.tar.gz",
+        ] {
+            assert_eq!(&sanitize(name), name);
+        }
+        assert_eq!(sanitize("console.log"), "console.log");
+        assert_eq!(sanitize("COM10"), "COM10");
+        assert_eq!(sanitize("LPT"), "LPT");
+        assert_eq!(sanitize("auxiliary.txt"), "auxiliary.txt");
+        assert_eq!(sanitize(".con"), ".con");
+        assert_eq!(sanitize("my con.txt"), "my con.txt");
+    }
+
+    #[test]
+    fn reserved_characters_are_replaced() {
+        for c in RESERVED_CHARS.iter() {
+            let name = format!("a{}b", c);
+            assert_eq!(sanitize(&name), "a_b", "{:?}", name);
+            assert_eq!(sanitize_with(&name, CharReplacement::Char('-'), 255), "a-b");
+            assert_eq!(sanitize_with(&name, CharReplacement::Remove, 255), "ab");
+            let lookalike = sanitize_with(&name, CharReplacement::Lookalike, 255);
+            assert_eq!(lookalike.chars().count(), 3);
+            assert!(!lookalike.chars().any(is_invalid_char), "{:?}", lookalike);
+        }
+        assert_eq!(sanitize("Q3: Sales/Costs?"), "Q3_ Sales_Costs_");
+        assert_eq!(sanitize(r#"<"a\b|c*">"#), "__a_b_c___");
+    }
+
+    #[test]
+    fn control_characters_are_replaced() {
+        for c in (0..0x20).map(|c| char::from(c as u8)) {
+            let name = format!("a{}b", c);
+            assert_eq!(sanitize(&name), "a_b", "{:?}", name);
+            assert_eq!(sanitize_with(&name, CharReplacement::Remove, 255), "ab");
+            assert_eq!(sanitize_with(&name, CharReplacement::Lookalike, 255), "ab");
+        }
+        // Other control characters are allowed in names
+        assert_eq!(sanitize("a\u{7f}b"), "a\u{7f}b");
+    }
+
+    #[test]
+    fn lookalike_characters() {
+        assert_eq!(
+            sanitize_with(r#"<>:"/\|?*"#, CharReplacement::Lookalike, 255),
+            "＜＞：＂／＼｜？＊"
+        );
+        assert_eq!(
+            sanitize_with("What's new?", CharReplacement::Lookalike, 255),
+            "What's new？"
+        );
+    }
+
+    #[test]
+    fn invalid_replacement_characters_are_not_used() {
+        assert_eq!(sanitize_with("a:b", CharReplacement::Char('/'), 255), "a_b");
+        assert_eq!(
+            sanitize_with("a:b", CharReplacement::Char('\0'), 255),
+            "a_b"
+        );
+        // Dots and spaces can replace characters within a name, but not end one
+        assert_eq!(sanitize_with("a:b", CharReplacement::Char(' '), 255), "a b");
+        assert_eq!(sanitize_with("a:", CharReplacement::Char('.'), 255), "a");
+        assert_eq!(
+            sanitize_with("CON", CharReplacement::Char(' '), 255),
+            "CON_"
+        );
+        assert_eq!(sanitize_with("?", CharReplacement::Char('.'), 255), "_");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_removed() {
+        assert_eq!(sanitize("notes."), "notes");
+        assert_eq!(sanitize("notes . . "), "notes");
+        assert_eq!(sanitize("notes.txt  "), "notes.txt");
+        assert_eq!(sanitize("a: "), "a_");
+        assert_eq!(sanitize_with("a ?", CharReplacement::Remove, 255), "a");
+    }
+
+    #[test]
+    fn empty_names_are_replaced() {
+        assert_eq!(sanitize(""), "_");
+        assert_eq!(sanitize("."), "_");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize("   "), "_");
+        assert_eq!(sanitize_with("???", CharReplacement::Remove, 255), "_");
+        assert_eq!(sanitize_with("", CharReplacement::Char('-'), 255), "-");
+        assert_eq!(sanitize_with("\n", CharReplacement::Lookalike, 255), "_");
+    }
+
+    #[test]
+    fn reserved_names_are_escaped() {
+        let devices = ["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"]
+            .iter()
+            .map(|name| name.to_string())
+            .chain((0..=9).map(|n| format!("COM{}", n)))
+            .chain((0..=9).map(|n| format!("LPT{}", n)))
+            .chain(['¹', '²', '³'].iter().map(|n| format!("COM{}", n)))
+            .chain(['¹', '²', '³'].iter().map(|n| format!("LPT{}", n)))
+            .collect::<Vec<_>>();
+        for device in &devices {
+            for name in &[
+                device.clone(),
+                device.to_lowercase(),
+                format!("{}.txt", device),
+                format!("{}.tar.gz", device.to_lowercase()),
+                format!("{} .txt", device),
+            ] {
+                let sanitized = sanitize(name);
+                assert!(!is_reserved_name(&sanitized), "{:?}", sanitized);
+                assert_eq!(sanitized.len(), name.len() + 1, "{:?}", sanitized);
+            }
+        }
+
+        assert_eq!(sanitize("CON"), "CON_");
+        assert_eq!(sanitize("aux.txt"), "aux_.txt");
+        assert_eq!(sanitize("nul.tar.gz"), "nul_.tar.gz");
+        assert_eq!(sanitize("com1 .log"), "com1_ .log");
+        assert_eq!(sanitize("PRN."), "PRN_");
+        assert_eq!(sanitize("LPT²"), "LPT²_");
+        assert_eq!(
+            sanitize_with("CON", CharReplacement::Char('-'), 255),
+            "CON-"
+        );
+        assert_eq!(sanitize_with("aux", CharReplacement::Remove, 255), "aux_");
+        assert_eq!(
+            sanitize_with("aux", CharReplacement::Lookalike, 255),
+            "aux_"
+        );
+    }
+
+    #[test]
+    fn reserved_names_made_by_replacement() {
+        assert_eq!(sanitize("C?ON"), "C_ON");
+        assert_eq!(sanitize_with("C?ON", CharReplacement::Remove, 255), "CON_");
+        assert_eq!(sanitize_with("NUL?", CharReplacement::Remove, 255), "NUL_");
+        assert_eq!(
+            sanitize_with("COM:1", CharReplacement::Remove, 255),
+            "COM1_"
+        );
+    }
+
+    #[test]
+    fn reserved_names_within_length_limit() {
+        assert_eq!(sanitize_with("CON", CharReplacement::default(), 3), "CO_");
+        assert_eq!(
+            sanitize_with("aux.txt", CharReplacement::default(), 7),
+            "au_.txt"
+        );
+        // The suffix would complete another numbered device name
+        assert_eq!(sanitize_with("COM1", CharReplacement::Char('1'), 4), "COM_");
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        let long = "a".repeat(300);
+        assert_eq!(sanitize(&long).len(), 255);
+
+        let with_extension = format!("{}.docx", long);
+        let sanitized = sanitize(&with_extension);
+        assert_eq!(sanitized.len(), 255);
+        assert!(sanitized.ends_with("a.docx"));
+
+        assert_eq!(
+            sanitize_with("report.txt", CharReplacement::default(), 8),
+            "repo.txt"
+        );
+        assert_eq!(
+            sanitize_with("report.txt", CharReplacement::default(), 10),
+            "report.txt"
+        );
+        // An extension that leaves no room for the rest of the name is not kept
+        assert_eq!(
+            sanitize_with("a.longextension", CharReplacement::default(), 8),
+            "a.longex"
+        );
+        assert_eq!(
+            sanitize_with(".profile", CharReplacement::default(), 4),
+            ".pro"
+        );
+    }
+
+    #[test]
+    fn truncation_keeps_characters_whole() {
+        // Each character is two bytes in UTF-8
+        let name = "я".repeat(200);
+        let sanitized = sanitize(&name);
+        assert_eq!(sanitized, "я".repeat(127));
+
+        let name = format!("{}.txt", "日".repeat(100));
+        let sanitized = sanitize(&name);
+        assert_eq!(sanitized, format!("{}.txt", "日".repeat(83)));
+        assert!(sanitized.encode_utf16().count() <= 255);
+
+        let lookalikes = sanitize_with(&"?".repeat(100), CharReplacement::Lookalike, 10);
+        assert_eq!(lookalikes, "？？？");
+    }
+
+    #[test]
+    fn truncation_removes_exposed_dots_and_spaces() {
+        assert_eq!(sanitize_with("ab  cd", CharReplacement::default(), 4), "ab");
+        assert_eq!(
+            sanitize_with("ab. cd.txt", CharReplacement::default(), 7),
+            "ab.txt"
+        );
+        assert_eq!(sanitize_with("  abc", CharReplacement::default(), 2), "_");
+    }
+
+    #[test]
+    fn zero_length_limit() {
+        assert_eq!(
+            sanitize_with("report.txt", CharReplacement::default(), 0),
+            "_"
+        );
+        assert_eq!(sanitize_with("CON", CharReplacement::default(), 0), "_");
+    }
+
+    #[test]
+    fn sanitizes_file_name_of_paths() {
+        let options = SanitizeOptions::default();
+        let dir = PathBuf::from("folder").join("sub:dir");
+        assert_eq!(options.apply(dir.join("a?.txt")), dir.join("a_.txt"));
+        assert_eq!(options.apply(dir.join("aux.txt")), dir.join("aux_.txt"));
+        assert_eq!(
+            options.apply(dir.join("report.txt")),
+            dir.join("report.txt")
+        );
+        assert_eq!(options.apply(PathBuf::new()), PathBuf::new());
+    }
+
+    #[test]
+    fn results_are_stable() {
+        let long = "z".repeat(300);
+        let names = ["CON", "a:b", "x.", "COM1.txt", "?", long.as_str()];
+        for replacement in &[
+            CharReplacement::default(),
+            CharReplacement::Remove,
+            CharReplacement::Lookalike,
+            CharReplacement::Char('1'),
+        ] {
+            for name in names.iter() {
+                let once = sanitize_with(name, *replacement, 255);
+                assert_eq!(sanitize_with(&once, *replacement, 255), once);
+            }
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Used to give concurrent writes from the same process distinct temporary file names
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Determines whether the previous contents of a file are kept when it is replaced by
+/// [`SaveTarget::write_atomically`]
+///
+/// [`SaveTarget::write_atomically`]: struct.SaveTarget.html#method.write_atomically
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backup {
+    /// No backup is kept
+    #[default]
+    Disabled,
+    /// The previous file is kept as `name.ext.bak`, replacing any existing backup
+    Single,
+    /// The previous file is kept as `name.ext.1.bak`, `name.ext.2.bak`, ... using the first number
+    /// that is not already taken, so that every previous version is kept
+    Numbered,
+}
+
+/// A file selected in a Save dialog that can be written safely. Obtained from
+/// [`SaveDialogResult::save_target`], or created directly for a path.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Write;
+/// use wfd::Backup;
+///
+/// # fn main() -> std::io::Result<()> {
+/// if let Ok(result) = wfd::save_dialog(Default::default()) {
+///     let target = result.save_target().with_backup(Backup::Single);
+///     target.write_atomically(|writer| writer.write_all(b"Hello, world!"))?;
+/// }
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// [`SaveDialogResult::save_target`]: struct.SaveDialogResult.html#method.save_target
+#[derive(Clone, Debug)]
+pub struct SaveTarget {
+    path: PathBuf,
+    backup: Backup,
+}
+
+impl SaveTarget {
+    /// Creates a `SaveTarget` for the file at `path`, without backups
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        SaveTarget {
+            path: path.into(),
+            backup: Backup::Disabled,
+        }
+    }
+
+    /// Sets whether the previous contents of the file are kept when it is replaced
+    pub fn with_backup(mut self, backup: Backup) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// The path of the file that is written
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks that the file can be written, from the permissions of the containing folder and,
+    /// if the file already exists, of the file. Nothing is created in the folder and the file is
+    /// not modified.
+    ///
+    /// # Errors
+    /// Returns the I/O error that prevents the file from being written
+    pub fn check_writable(&self) -> io::Result<()> {
+        let folder = match self.path.parent() {
+            Some(folder) if !folder.as_os_str().is_empty() => folder,
+            _ => Path::new("."),
+        };
+        // Writing atomically creates a file in the folder
+        check_access(folder, true)?;
+
+        if self.path.exists() {
+            check_access(&self.path, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the file atomically. The contents written by `write` are first written to a
+    /// temporary file in the same folder, which is flushed to disk and then renamed over the file.
+    /// If `write` or any other step fails, the existing file is left unchanged and the temporary
+    /// file is removed. If a [`Backup`] is enabled, the previous file is kept as a backup before
+    /// it is replaced.
+    ///
+    /// # Errors
+    /// Returns the error returned by `write`, or the I/O error of any step that fails
+    ///
+    /// [`Backup`]: enum.Backup.html
+    pub fn write_atomically<F, T>(&self, write: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut BufWriter<File>) -> io::Result<T>,
+    {
+        let (temp_path, temp_file) = self.create_temp_file()?;
+        let result = self.write_and_replace(&temp_path, temp_file, write);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    fn write_and_replace<F, T>(&self, temp_path: &Path, temp_file: File, write: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut BufWriter<File>) -> io::Result<T>,
+    {
+        let mut writer = BufWriter::new(temp_file);
+        let value = write(&mut writer)?;
+        let temp_file = writer.into_inner().map_err(|e| e.into_error())?;
+        temp_file.sync_all()?;
+        drop(temp_file);
+
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            // Keep the permissions of the file being replaced
+            fs::set_permissions(temp_path, metadata.permissions())?;
+            self.backup_existing()?;
+        }
+
+        fs::rename(temp_path, &self.path)?;
+        sync_parent_dir(&self.path);
+
+        Ok(value)
+    }
+
+    // Keeps a copy of the existing file according to the backup policy. A hard link is used where
+    // possible so that the existing file stays in place until it is replaced.
+    fn backup_existing(&self) -> io::Result<()> {
+        match self.backup {
+            Backup::Disabled => Ok(()),
+            Backup::Single => {
+                let backup_path = self.backup_path(None);
+                if backup_path.exists() {
+                    fs::remove_file(&backup_path)?;
+                }
+                if fs::hard_link(&self.path, &backup_path).is_err() {
+                    fs::copy(&self.path, &backup_path)?;
+                }
+                Ok(())
+            }
+            Backup::Numbered => self.backup_numbered(),
+        }
+    }
+
+    // Keeps the existing file under the first free numbered backup name. Both the hard link and
+    // the copy fail rather than replace a backup that already exists, so a name taken by another
+    // process in the meantime is skipped rather than overwritten.
+    fn backup_numbered(&self) -> io::Result<()> {
+        let mut number = 1;
+        loop {
+            let backup_path = self.backup_path(Some(number));
+            number += 1;
+            match fs::hard_link(&self.path, &backup_path) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                // Hard links are not supported by every file system, so fall back to a copy
+                Err(_) => {}
+            }
+            match copy_to_new_file(&self.path, &backup_path) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn backup_path(&self, number: Option<u32>) -> PathBuf {
+        let mut file_name = self.file_name();
+        if let Some(number) = number {
+            file_name.push(format!(".{}", number));
+        }
+        file_name.push(".bak");
+        self.path.with_file_name(file_name)
+    }
+
+    fn file_name(&self) -> OsString {
+        self.path
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default()
+    }
+
+    // Creates a new, uniquely named hidden file in the same folder as the target
+    fn create_temp_file(&self) -> io::Result<(PathBuf, File)> {
+        loop {
+            let mut file_name = OsString::from(".");
+            file_name.push(self.file_name());
+            file_name.push(format!(
+                ".{}.{}.tmp",
+                process::id(),
+                TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let temp_path = self.path.with_file_name(file_name);
+
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)
+            {
+                Ok(file) => return Ok((temp_path, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Copies a file into a new file, failing with `AlreadyExists` instead of replacing the destination
+// if it exists. The destination is created with `create_new` before anything is copied into it.
+fn copy_to_new_file(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut source_file = File::open(source)?;
+    let mut destination_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)?;
+    let result = io::copy(&mut source_file, &mut destination_file)
+        .and_then(|_| destination_file.set_permissions(source_file.metadata()?.permissions()));
+    if result.is_err() {
+        drop(destination_file);
+        let _ = fs::remove_file(destination);
+    }
+    result
+}
+
+// Checks that a file can be written, or that files can be created in a folder, from their
+// permissions
+#[cfg(unix)]
+fn check_access(path: &Path, folder: bool) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // Creating a file requires write and search permission on the folder
+    let mode = if folder {
+        libc::W_OK | libc::X_OK
+    } else {
+        libc::W_OK
+    };
+    if unsafe { libc::access(path.as_ptr(), mode) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Opening the file for writing, or the folder with the right to add files, performs an access
+// check against its ACL without changing it
+#[cfg(windows)]
+fn check_access(path: &Path, folder: bool) -> io::Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use winapi::um::winbase::FILE_FLAG_BACKUP_SEMANTICS;
+    use winapi::um::winnt::{
+        FILE_ADD_FILE, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, GENERIC_WRITE,
+    };
+
+    let mut options = OpenOptions::new();
+    options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE);
+    if folder {
+        // Required to open a handle to a folder
+        options
+            .access_mode(FILE_ADD_FILE)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS);
+    } else {
+        options.access_mode(GENERIC_WRITE);
+    }
+    options.open(path).map(drop)
+}
+
+// Flushes the rename of a file to disk by syncing its directory. This is best effort, as not all
+// platforms and file systems support opening a directory.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wfd-save-target-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut entries = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn write_atomically_creates_file() {
+        let dir = temp_dir("create");
+        let target = SaveTarget::new(dir.join("doc.txt"));
+
+        let value = target
+            .write_atomically(|w| w.write_all(b"contents").map(|_| 42))
+            .unwrap();
+
+        assert_eq!(value, 42);
+        assert_eq!(fs::read(dir.join("doc.txt")).unwrap(), b"contents");
+        assert_eq!(dir_entries(&dir), vec!["doc.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_atomically_replaces_file_without_backup() {
+        let dir = temp_dir("replace");
+        fs::write(dir.join("doc.txt"), "old").unwrap();
+
+        let target = SaveTarget::new(dir.join("doc.txt"));
+        target.write_atomically(|w| w.write_all(b"new")).unwrap();
+
+        assert_eq!(fs::read(dir.join("doc.txt")).unwrap(), b"new");
+        assert_eq!(dir_entries(&dir), vec!["doc.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_atomically_failure_keeps_existing_file() {
+        let dir = temp_dir("failure");
+        fs::write(dir.join("doc.txt"), "old").unwrap();
+
+        let target = SaveTarget::new(dir.join("doc.txt")).with_backup(Backup::Single);
+        let result = target.write_atomically(|w| {
+            w.write_all(b"partial")?;
+            Err::<(), _>(io::Error::other("serialization failed"))
+        });
+
+        assert_eq!(result.unwrap_err().to_string(), "serialization failed");
+        assert_eq!(fs::read(dir.join("doc.txt")).unwrap(), b"old");
+        assert_eq!(dir_entries(&dir), vec!["doc.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_atomically_single_backup() {
+        let dir = temp_dir("single");
+        fs::write(dir.join("doc.txt"), "v1").unwrap();
+
+        let target = SaveTarget::new(dir.join("doc.txt")).with_backup(Backup::Single);
+        target.write_atomically(|w| w.write_all(b"v2")).unwrap();
+        target.write_atomically(|w| w.write_all(b"v3")).unwrap();
+
+        assert_eq!(fs::read(dir.join("doc.txt")).unwrap(), b"v3");
+        assert_eq!(fs::read(dir.join("doc.txt.bak")).unwrap(), b"v2");
+        assert_eq!(dir_entries(&dir), vec!["doc.txt", "doc.txt.bak"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_atomically_numbered_backup() {
+        let dir = temp_dir("numbered");
+        let target = SaveTarget::new(dir.join("doc.txt")).with_backup(Backup::Numbered);
+        for contents in &["v1", "v2", "v3"] {
+            target
+                .write_atomically(|w| w.write_all(contents.as_bytes()))
+                .unwrap();
+        }
+
+        assert_eq!(fs::read(dir.join("doc.txt")).unwrap(), b"v3");
+        assert_eq!(fs::read(dir.join("doc.txt.1.bak")).unwrap(), b"v1");
+        assert_eq!(fs::read(dir.join("doc.txt.2.bak")).unwrap(), b"v2");
+        assert_eq!(
+            dir_entries(&dir),
+            vec!["doc.txt", "doc.txt.1.bak", "doc.txt.2.bak"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn numbered_backup_skips_taken_names() {
+        let dir = temp_dir("numbered-taken");
+        fs::write(dir.join("doc.txt"), "v1").unwrap();
+        fs::write(dir.join("doc.txt.1.bak"), "other").unwrap();
+
+        let target = SaveTarget::new(dir.join("doc.txt")).with_backup(Backup::Numbered);
+        target.write_atomically(|w| w.write_all(b"v2")).unwrap();
+
+        assert_eq!(fs::read(dir.join("doc.txt.1.bak")).unwrap(), b"other");
+        assert_eq!(fs::read(dir.join("doc.txt.2.bak")).unwrap(), b"v1");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_to_new_file_never_replaces() {
+        let dir = temp_dir("copy-new");
+        fs::write(dir.join("source.txt"), "source").unwrap();
+        fs::write(dir.join("taken.txt"), "taken").unwrap();
+
+        let error = copy_to_new_file(&dir.join("source.txt"), &dir.join("taken.txt")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(dir.join("taken.txt")).unwrap(), b"taken");
+
+        copy_to_new_file(&dir.join("source.txt"), &dir.join("copy.txt")).unwrap();
+        assert_eq!(fs::read(dir.join("copy.txt")).unwrap(), b"source");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomically_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        fs::write(dir.join("script.sh"), "old").unwrap();
+        fs::set_permissions(dir.join("script.sh"), fs::Permissions::from_mode(0o754)).unwrap();
+
+        let target = SaveTarget::new(dir.join("script.sh"));
+        target.write_atomically(|w| w.write_all(b"new")).unwrap();
+
+        let mode = fs::metadata(dir.join("script.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o754);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_writable() {
+        let dir = temp_dir("writable");
+        assert!(SaveTarget::new(dir.join("new.txt"))
+            .check_writable()
+            .is_ok());
+
+        fs::write(dir.join("existing.txt"), "old").unwrap();
+        assert!(SaveTarget::new(dir.join("existing.txt"))
+            .check_writable()
+            .is_ok());
+        assert_eq!(fs::read(dir.join("existing.txt")).unwrap(), b"old");
+
+        let mut permissions = fs::metadata(dir.join("existing.txt"))
+            .unwrap()
+            .permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(dir.join("existing.txt"), permissions).unwrap();
+        let readonly = SaveTarget::new(dir.join("existing.txt")).check_writable();
+        let mut permissions = fs::metadata(dir.join("existing.txt"))
+            .unwrap()
+            .permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(dir.join("existing.txt"), permissions).unwrap();
+        // Root can write to read-only files, so only check the result for other users
+        if !is_root() {
+            assert!(readonly.is_err());
+        }
+
+        assert!(SaveTarget::new(dir.join("missing").join("new.txt"))
+            .check_writable()
+            .is_err());
+        assert_eq!(dir_entries(&dir), vec!["existing.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_writable_readonly_folder() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("readonly-folder");
+        fs::write(dir.join("existing.txt"), "old").unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+        let new = SaveTarget::new(dir.join("new.txt")).check_writable();
+        // The file can be written in place, but not replaced atomically
+        let existing = SaveTarget::new(dir.join("existing.txt")).check_writable();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        if !is_root() {
+            assert_eq!(new.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+            assert!(existing.is_err());
+        }
+        assert_eq!(dir_entries(&dir), vec!["existing.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn is_root() -> bool {
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    #[cfg(not(unix))]
+    fn is_root() -> bool {
+        false
+    }
+}
@@ -0,0 +1,694 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+
+use crate::locale::{format_message, localized_message, Message};
+use crate::PathForm;
+
+/// A custom selection rule, returning `Err` with the reason for rejecting the path if it is not
+/// acceptable
+pub type SelectionPredicate<'a> = Box<dyn Fn(&Path) -> Result<(), String> + 'a>;
+
+/// Rules that the paths selected in an Open dialog must satisfy. All rules are optional and
+/// disabled by default. When any rule is not satisfied the dialog either returns a
+/// [`DialogError::SelectionRejected`] error or, if `reshow_on_rejection` is set, tells the user why
+/// the selection was rejected and is shown again.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use wfd::{DialogParams, SelectionRules, FOS_ALLOWMULTISELECT};
+///
+/// let params = DialogParams {
+///     options: FOS_ALLOWMULTISELECT,
+///     selection_rules: SelectionRules {
+///         max_count: Some(10),
+///         max_file_size: Some(16 * 1024 * 1024),
+///         must_be_readable: true,
+///         allowed_roots: vec![Path::new(r"C:\Projects")],
+///         predicate: Some(Box::new(|path| match path.extension() {
+///             Some(ext) if ext == "bak" => Err("Backup files cannot be imported".to_string()),
+///             _ => Ok(()),
+///         })),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// ```
+///
+/// [`DialogError::SelectionRejected`]: enum.DialogError.html#variant.SelectionRejected
+#[derive(Default)]
+pub struct SelectionRules<'a> {
+    /// The minimum number of items that must be selected
+    pub min_count: Option<usize>,
+    /// The maximum number of items that can be selected
+    pub max_count: Option<usize>,
+    /// The maximum size in bytes of each selected file. Folders are not checked.
+    pub max_file_size: Option<u64>,
+    /// Requires that each selected file can be opened for reading, or that each selected folder
+    /// can be listed
+    pub must_be_readable: bool,
+    /// Requires that each selected item is a folder in which files can be created. This is
+    /// checked against the permissions of the folder, without creating any files in it.
+    pub must_be_writable_directory: bool,
+    /// The folders that the selected items must be located within. If empty, items can be
+    /// located anywhere. Symbolic links, junctions and `..` components are resolved before the
+    /// paths are compared, and verbatim (`\\?\`) paths are compared in their plain form.
+    pub allowed_roots: Vec<&'a Path>,
+    /// A custom rule evaluated for each selected item, returning `Err` with the reason for
+    /// rejecting the item
+    pub predicate: Option<SelectionPredicate<'a>>,
+    /// Shows the reasons for rejecting the selection in a message dialog and then shows the
+    /// dialog again, instead of returning a [`DialogError::SelectionRejected`] error
+    ///
+    /// [`DialogError::SelectionRejected`]: enum.DialogError.html#variant.SelectionRejected
+    pub reshow_on_rejection: bool,
+}
+
+impl<'a> SelectionRules<'a> {
+    // Evaluates the rules against the selected paths, returning the reasons for rejecting the
+    // selection if any rules are not satisfied
+    pub(crate) fn evaluate(&self, paths: &[PathBuf]) -> Vec<SelectionRejection> {
+        let mut rejections = vec![];
+
+        let count = paths.len();
+        if let Some(min) = self.min_count.filter(|min| count < *min) {
+            rejections.push(SelectionRejection {
+                path: None,
+                reason: RejectionReason::TooFewItems { min, count },
+            });
+        }
+        if let Some(max) = self.max_count.filter(|max| count > *max) {
+            rejections.push(SelectionRejection {
+                path: None,
+                reason: RejectionReason::TooManyItems { max, count },
+            });
+        }
+
+        for path in paths {
+            rejections.extend(self.evaluate_path(path).into_iter().map(|reason| {
+                SelectionRejection {
+                    path: Some(path.clone()),
+                    reason,
+                }
+            }));
+        }
+
+        rejections
+    }
+
+    fn evaluate_path(&self, path: &Path) -> Vec<RejectionReason> {
+        let mut reasons = vec![];
+
+        if !self.allowed_roots.is_empty()
+            && !self.allowed_roots.iter().any(|root| is_within(path, root))
+        {
+            reasons.push(RejectionReason::OutsideAllowedRoots);
+        }
+
+        if let Some(max) = self.max_file_size {
+            if let Ok(metadata) = fs::metadata(path) {
+                if metadata.is_file() && metadata.len() > max {
+                    reasons.push(RejectionReason::TooLarge {
+                        max,
+                        size: metadata.len(),
+                    });
+                }
+            }
+        }
+
+        if self.must_be_readable && !is_readable(path) {
+            reasons.push(RejectionReason::NotReadable);
+        }
+
+        if self.must_be_writable_directory && !is_writable_directory(path) {
+            reasons.push(RejectionReason::NotWritableDirectory);
+        }
+
+        if let Some(predicate) = &self.predicate {
+            if let Err(reason) = predicate(path) {
+                reasons.push(RejectionReason::Predicate(reason));
+            }
+        }
+
+        reasons
+    }
+}
+
+impl<'a> fmt::Debug for SelectionRules<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SelectionRules")
+            .field("min_count", &self.min_count)
+            .field("max_count", &self.max_count)
+            .field("max_file_size", &self.max_file_size)
+            .field("must_be_readable", &self.must_be_readable)
+            .field(
+                "must_be_writable_directory",
+                &self.must_be_writable_directory,
+            )
+            .field("allowed_roots", &self.allowed_roots)
+            .field("predicate", &self.predicate.as_ref().map(|_| ".."))
+            .field("reshow_on_rejection", &self.reshow_on_rejection)
+            .finish()
+    }
+}
+
+/// The reason that a selection was rejected by the [`SelectionRules`]. The `Display`
+/// implementation describes the rejection in the current locale, prefixed by the rejected path.
+///
+/// [`SelectionRules`]: struct.SelectionRules.html
+#[derive(Debug)]
+pub struct SelectionRejection {
+    /// The selected path that was rejected, or `None` if the rejection applies to the selection as
+    /// a whole
+    pub path: Option<PathBuf>,
+    /// The rule that was not satisfied
+    pub reason: RejectionReason,
+}
+
+impl fmt::Display for SelectionRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+/// The rules of [`SelectionRules`] that can cause a selection to be rejected. The `Display`
+/// implementation describes the reason in the current locale, see [`localized_message`].
+///
+/// [`SelectionRules`]: struct.SelectionRules.html
+/// [`localized_message`]: fn.localized_message.html
+#[derive(Debug, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// Fewer items than `min_count` were selected
+    TooFewItems {
+        /// The minimum number of items
+        min: usize,
+        /// The number of items that were selected
+        count: usize,
+    },
+    /// More items than `max_count` were selected
+    TooManyItems {
+        /// The maximum number of items
+        max: usize,
+        /// The number of items that were selected
+        count: usize,
+    },
+    /// The file is larger than `max_file_size`
+    TooLarge {
+        /// The maximum file size in bytes
+        max: u64,
+        /// The size of the file in bytes
+        size: u64,
+    },
+    /// The item could not be opened for reading
+    NotReadable,
+    /// The item is not a folder in which files can be created
+    NotWritableDirectory,
+    /// The item is not located within any of the `allowed_roots`
+    OutsideAllowedRoots,
+    /// The item was rejected by the `predicate`, with the reason it provided
+    Predicate(String),
+    /// The item is hidden by the `item_filter` of the dialog, see [`ItemFilter`]
+    ///
+    /// [`ItemFilter`]: struct.ItemFilter.html
+    HiddenByFilter,
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            RejectionReason::TooFewItems { min, count } => format_message(
+                &localized_message(Message::TooFewItems),
+                &[("min", min), ("count", count)],
+            ),
+            RejectionReason::TooManyItems { max, count } => format_message(
+                &localized_message(Message::TooManyItems),
+                &[("max", max), ("count", count)],
+            ),
+            RejectionReason::TooLarge { max, size } => format_message(
+                &localized_message(Message::TooLarge),
+                &[("max", max), ("size", size)],
+            ),
+            RejectionReason::NotReadable => localized_message(Message::NotReadable).into_owned(),
+            RejectionReason::NotWritableDirectory => {
+                localized_message(Message::NotWritableDirectory).into_owned()
+            }
+            RejectionReason::OutsideAllowedRoots => {
+                localized_message(Message::OutsideAllowedRoots).into_owned()
+            }
+            RejectionReason::Predicate(reason) => reason.clone(),
+            RejectionReason::HiddenByFilter => {
+                localized_message(Message::HiddenByFilter).into_owned()
+            }
+        };
+        f.write_str(&text)
+    }
+}
+
+fn is_readable(path: &Path) -> bool {
+    if path.is_dir() {
+        fs::read_dir(path).is_ok()
+    } else {
+        File::open(path).is_ok()
+    }
+}
+
+// Checks that a file can be created in the directory from its permissions. No file is created,
+// which would be seen by file watchers and sync clients, and could be left behind.
+#[cfg(unix)]
+fn is_writable_directory(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    if !path.is_dir() {
+        return false;
+    }
+    match CString::new(path.as_os_str().as_bytes()) {
+        // Creating a file requires write and search permission on the directory
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+// Opening the directory with the right to add files performs an access check against its ACL,
+// without creating a file in it
+#[cfg(windows)]
+fn is_writable_directory(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr::null_mut;
+    use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::winbase::FILE_FLAG_BACKUP_SEMANTICS;
+    use winapi::um::winnt::{FILE_ADD_FILE, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE};
+
+    if !path.is_dir() {
+        return false;
+    }
+    let wide = path
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<u16>>();
+    unsafe {
+        let handle = CreateFileW(
+            wide.as_ptr(),
+            FILE_ADD_FILE,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            null_mut(),
+            OPEN_EXISTING,
+            // Required to open a handle to a directory
+            FILE_FLAG_BACKUP_SEMANTICS,
+            null_mut(),
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return false;
+        }
+        CloseHandle(handle);
+    }
+    true
+}
+
+// Returns true if the path is the root or is located within it. Both paths are first brought
+// into the same form, so that `C:\Projects\..\Windows` is not within `C:\Projects`, a link within
+// the root that points elsewhere is not within it, and `\\?\C:\Projects\x` is within it.
+// Components are compared case-insensitively on Windows.
+fn is_within(path: &Path, root: &Path) -> bool {
+    let path = comparable_path(path);
+    let root = comparable_path(root);
+    let mut path_components = path.components();
+    root.components().all(|root_component| {
+        path_components
+            .next()
+            .is_some_and(|c| components_equal(c, root_component))
+    })
+}
+
+// Resolves the links and `..` components of the path, including in the part of the path that
+// does not exist yet, and removes any verbatim prefix
+fn comparable_path(path: &Path) -> PathBuf {
+    let resolved = fs::canonicalize(path).unwrap_or_else(|_| {
+        let normalized = lexically_normalize(path);
+        // The nearest existing ancestor is resolved and the rest of the path appended to it
+        normalized
+            .ancestors()
+            .find_map(|ancestor| {
+                let canonical = fs::canonicalize(ancestor).ok()?;
+                Some(canonical.join(normalized.strip_prefix(ancestor).ok()?))
+            })
+            .unwrap_or(normalized)
+    });
+    PathForm::Plain.apply(resolved)
+}
+
+// Removes `.` components, and `..` components along with the component that they follow. A `..`
+// at the root refers to the root itself.
+fn lexically_normalize(path: &Path) -> PathBuf {
+    let path = PathForm::Plain.apply(path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                Some(Component::ParentDir) | Some(Component::CurDir) | None => {
+                    normalized.push(component)
+                }
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(windows)]
+fn components_equal(a: Component, b: Component) -> bool {
+    a.as_os_str().to_string_lossy().to_lowercase() == b.as_os_str().to_string_lossy().to_lowercase()
+}
+
+#[cfg(not(windows))]
+fn components_equal(a: Component, b: Component) -> bool {
+    a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wfd-selection-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // The rejections as pairs of the rejected path and reason, in the order they were made
+    fn rejections(
+        rules: &SelectionRules,
+        paths: &[PathBuf],
+    ) -> Vec<(Option<PathBuf>, RejectionReason)> {
+        rules
+            .evaluate(paths)
+            .into_iter()
+            .map(|rejection| (rejection.path, rejection.reason))
+            .collect()
+    }
+
+    #[test]
+    fn default_rules_accept_anything() {
+        let paths = vec![PathBuf::from("/missing/a.txt"), PathBuf::from("b.txt")];
+        assert!(rejections(&SelectionRules::default(), &paths).is_empty());
+        assert!(rejections(&SelectionRules::default(), &[]).is_empty());
+    }
+
+    #[test]
+    fn item_counts() {
+        let rules = SelectionRules {
+            min_count: Some(2),
+            max_count: Some(3),
+            ..Default::default()
+        };
+        let paths = (0..4)
+            .map(|i| PathBuf::from(format!("{}.txt", i)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rejections(&rules, &paths[..1]),
+            [(None, RejectionReason::TooFewItems { min: 2, count: 1 })]
+        );
+        assert!(rejections(&rules, &paths[..2]).is_empty());
+        assert!(rejections(&rules, &paths[..3]).is_empty());
+        assert_eq!(
+            rejections(&rules, &paths),
+            [(None, RejectionReason::TooManyItems { max: 3, count: 4 })]
+        );
+    }
+
+    #[test]
+    fn file_sizes() {
+        let dir = temp_dir("sizes");
+        fs::write(dir.join("small.txt"), "12345").unwrap();
+        fs::write(dir.join("large.txt"), "123456").unwrap();
+        fs::create_dir(dir.join("folder")).unwrap();
+        let rules = SelectionRules {
+            max_file_size: Some(5),
+            ..Default::default()
+        };
+
+        // Folders and missing files are not checked
+        let paths = ["small.txt", "large.txt", "folder", "missing.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejections(&rules, &paths),
+            [(
+                Some(dir.join("large.txt")),
+                RejectionReason::TooLarge { max: 5, size: 6 }
+            )]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn readable_items() {
+        let dir = temp_dir("readable");
+        fs::write(dir.join("file.txt"), "").unwrap();
+        fs::create_dir(dir.join("folder")).unwrap();
+        let rules = SelectionRules {
+            must_be_readable: true,
+            ..Default::default()
+        };
+
+        let paths = ["file.txt", "folder", "missing.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejections(&rules, &paths),
+            [(Some(dir.join("missing.txt")), RejectionReason::NotReadable)]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn items_within_allowed_roots() {
+        let dir = temp_dir("roots");
+        let projects = dir.join("projects");
+        let shared = dir.join("shared");
+        fs::create_dir_all(projects.join("app")).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::create_dir_all(dir.join("other")).unwrap();
+        // The roots are resolved before they are compared
+        let unresolved_projects = dir.join("other").join("..").join("projects");
+        let rules = SelectionRules {
+            allowed_roots: vec![&unresolved_projects, &shared],
+            ..Default::default()
+        };
+
+        let paths = vec![
+            projects.join("app").join("main.rs"),
+            dir.join("other").join("notes.txt"),
+            shared.join("data.csv"),
+            projects.join("..").join("other").join("x.txt"),
+        ];
+        assert_eq!(
+            rejections(&rules, &paths),
+            [
+                (
+                    Some(dir.join("other").join("notes.txt")),
+                    RejectionReason::OutsideAllowedRoots
+                ),
+                (
+                    Some(projects.join("..").join("other").join("x.txt")),
+                    RejectionReason::OutsideAllowedRoots
+                ),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn items_within_symlinked_roots() {
+        let dir = temp_dir("symlinked-roots");
+        let projects = dir.join("projects");
+        fs::create_dir_all(&projects).unwrap();
+        fs::create_dir_all(dir.join("other")).unwrap();
+        std::os::unix::fs::symlink(&projects, dir.join("alias")).unwrap();
+        std::os::unix::fs::symlink(dir.join("other"), projects.join("escape")).unwrap();
+        let alias = dir.join("alias");
+        let rules = SelectionRules {
+            allowed_roots: vec![&alias],
+            ..Default::default()
+        };
+
+        let paths = vec![
+            projects.join("a.txt"),
+            alias.join("b.txt"),
+            projects.join("escape").join("c.txt"),
+        ];
+        assert_eq!(
+            rejections(&rules, &paths),
+            [(
+                Some(projects.join("escape").join("c.txt")),
+                RejectionReason::OutsideAllowedRoots
+            )]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn predicate_reasons() {
+        let rules = SelectionRules {
+            predicate: Some(Box::new(|path| match path.extension() {
+                Some(ext) if ext == "bak" => Err(format!("{} is a backup", path.display())),
+                _ => Ok(()),
+            })),
+            ..Default::default()
+        };
+
+        let paths = vec![PathBuf::from("a.txt"), PathBuf::from("b.bak")];
+        assert_eq!(
+            rejections(&rules, &paths),
+            [(
+                Some(PathBuf::from("b.bak")),
+                RejectionReason::Predicate("b.bak is a backup".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn rejections_are_attributed_to_paths() {
+        let dir = temp_dir("attributed");
+        let root = dir.join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("large.txt"), "123456").unwrap();
+        fs::write(dir.join("outside.txt"), "1").unwrap();
+        let rules = SelectionRules {
+            max_count: Some(2),
+            max_file_size: Some(5),
+            must_be_readable: true,
+            allowed_roots: vec![&root],
+            predicate: Some(Box::new(|path| {
+                if path.ends_with("outside.txt") {
+                    Err("outside".to_string())
+                } else {
+                    Ok(())
+                }
+            })),
+            ..Default::default()
+        };
+
+        let paths = vec![
+            root.join("large.txt"),
+            dir.join("outside.txt"),
+            root.join("missing.txt"),
+        ];
+        // The rules for the whole selection come first, then each path's in order
+        assert_eq!(
+            rejections(&rules, &paths),
+            [
+                (None, RejectionReason::TooManyItems { max: 2, count: 3 }),
+                (
+                    Some(root.join("large.txt")),
+                    RejectionReason::TooLarge { max: 5, size: 6 }
+                ),
+                (
+                    Some(dir.join("outside.txt")),
+                    RejectionReason::OutsideAllowedRoots
+                ),
+                (
+                    Some(dir.join("outside.txt")),
+                    RejectionReason::Predicate("outside".to_string())
+                ),
+                (Some(root.join("missing.txt")), RejectionReason::NotReadable),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lexical_normalization() {
+        assert_eq!(
+            lexically_normalize(Path::new("/a/./b/../c")),
+            Path::new("/a/c")
+        );
+        assert_eq!(
+            lexically_normalize(Path::new("/a/../../b")),
+            Path::new("/b")
+        );
+        assert_eq!(lexically_normalize(Path::new("../a/..")), Path::new(".."));
+    }
+
+    #[test]
+    fn within_allowed_roots() {
+        let dir = temp_dir("within");
+        let root = dir.join("projects");
+        fs::create_dir_all(root.join("app")).unwrap();
+        fs::create_dir_all(dir.join("other")).unwrap();
+
+        assert!(is_within(&root, &root));
+        assert!(is_within(&root.join("app"), &root));
+        assert!(is_within(&root.join("app").join("missing.txt"), &root));
+        assert!(is_within(&root.join("new").join("..").join("app"), &root));
+        assert!(!is_within(&root.join("..").join("other"), &root));
+        assert!(!is_within(
+            &root.join("missing").join("..").join("..").join("x"),
+            &root
+        ));
+        assert!(!is_within(&dir.join("projects-old"), &root));
+        assert!(!is_within(&dir, &root));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_are_resolved() {
+        let dir = temp_dir("links");
+        let root = dir.join("projects");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(dir.join("other")).unwrap();
+        std::os::unix::fs::symlink(dir.join("other"), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(&root, dir.join("alias")).unwrap();
+
+        assert!(!is_within(&root.join("escape").join("x.txt"), &root));
+        assert!(is_within(&dir.join("alias").join("x.txt"), &root));
+        assert!(is_within(&root.join("x.txt"), &dir.join("alias")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn verbatim_paths_are_within_plain_roots() {
+        assert!(is_within(
+            Path::new(r"\\?\C:\Projects\x"),
+            Path::new(r"c:\projects")
+        ));
+        assert!(!is_within(
+            Path::new(r"C:\Projects\..\Windows\x"),
+            Path::new(r"C:\Projects")
+        ));
+    }
+
+    #[test]
+    fn writable_directories() {
+        let dir = temp_dir("writable");
+        fs::write(dir.join("file.txt"), "").unwrap();
+
+        assert!(is_writable_directory(&dir));
+        assert!(!is_writable_directory(&dir.join("file.txt")));
+        assert!(!is_writable_directory(&dir.join("missing")));
+        // The check leaves the directory unchanged
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,744 @@
+// Resolution of Windows shortcut (`.lnk`) files. Shortcuts are parsed from the MS-SHLLINK binary
+// format rather than through `IShellLink`, so that they can also be resolved on other platforms,
+// for example when they have been copied from a Windows share.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Determines whether the selected paths that are Windows shortcut (`.lnk`) files are resolved to
+/// the items that they refer to. Shortcuts are returned as they are when `FOS_NODEREFERENCELINKS`
+/// is set, or by the dialogs of other platforms.
+///
+/// The target is read from the shortcut itself: the path on a local drive, the path on a network
+/// share, the path containing environment variables and the path relative to the shortcut are
+/// tried in turn, and the first that exists is used. If none of them exist, for example because
+/// the shortcut was created on another machine, the target is unresolved: `Replace` keeps the
+/// shortcut, and `Annotate` reports the first path recorded in the shortcut as its target. Paths
+/// that are not shortcuts, or whose target cannot be read, are left unchanged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShortcutResolution {
+    /// Return shortcuts as they were selected
+    #[default]
+    Off,
+    /// Return the target of each shortcut in place of the shortcut, when the target exists
+    Replace,
+    /// Return shortcuts as they were selected, along with their targets in the
+    /// `shortcut_targets` of the result
+    Annotate,
+}
+
+impl ShortcutResolution {
+    // Resolves the shortcuts among the selected paths, returning the paths and, when annotating,
+    // the target of each path
+    pub(crate) fn apply(self, paths: Vec<PathBuf>) -> (Vec<PathBuf>, Vec<Option<PathBuf>>) {
+        match self {
+            ShortcutResolution::Off => (paths, vec![]),
+            ShortcutResolution::Replace => {
+                let paths = paths
+                    .into_iter()
+                    .map(|path| match read_target(&path) {
+                        Some(Target::Resolved(target)) => target,
+                        _ => path,
+                    })
+                    .collect();
+                (paths, vec![])
+            }
+            ShortcutResolution::Annotate => {
+                let targets = paths
+                    .iter()
+                    .map(|path| read_target(path).map(Target::into_path))
+                    .collect();
+                (paths, targets)
+            }
+        }
+    }
+}
+
+const HEADER_SIZE: usize = 0x4C;
+// 00021401-0000-0000-C000-000000000046
+const LINK_CLSID: [u8; 16] = [
+    0x01, 0x14, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+// Shortcuts are small, anything larger is not read
+const MAX_SHORTCUT_SIZE: u64 = 1024 * 1024;
+
+// LinkFlags
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x1;
+const HAS_LINK_INFO: u32 = 0x2;
+const HAS_NAME: u32 = 0x4;
+const HAS_RELATIVE_PATH: u32 = 0x8;
+const HAS_WORKING_DIR: u32 = 0x10;
+const HAS_ARGUMENTS: u32 = 0x20;
+const HAS_ICON_LOCATION: u32 = 0x40;
+const IS_UNICODE: u32 = 0x80;
+const FORCE_NO_LINK_INFO: u32 = 0x100;
+
+// LinkInfoFlags
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x1;
+const COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX: u32 = 0x2;
+
+// CommonNetworkRelativeLinkFlags
+const VALID_DEVICE: u32 = 0x1;
+
+const ENVIRONMENT_VARIABLE_DATA_BLOCK: u32 = 0xA000_0001;
+
+// The characters of Windows-1252 from 0x80 to 0x9F, where it differs from ISO-8859-1. Unassigned
+// bytes are decoded as the C1 control character of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+// The locations of the target recorded in a shortcut
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ShellLink {
+    // The path of the target, or of a folder containing it, on a local drive
+    pub(crate) local_base_path: Option<String>,
+    // The network share containing the target, i.e. `\\server\share`
+    pub(crate) net_name: Option<String>,
+    // The drive letter that the network share was mapped to, i.e. `Z:`
+    pub(crate) device_name: Option<String>,
+    // The remainder of the path after the local base path or the network share
+    pub(crate) common_path_suffix: String,
+    // The path of the target relative to the shortcut
+    pub(crate) relative_path: Option<String>,
+    // The path of the target containing environment variables, i.e. `%USERPROFILE%\todo.txt`
+    pub(crate) environment_target: Option<String>,
+}
+
+// The target of a shortcut
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Target {
+    // A path of the target that exists
+    Resolved(PathBuf),
+    // The first path recorded in the shortcut, when none of them exist
+    Unresolved(PathBuf),
+}
+
+impl Target {
+    fn into_path(self) -> PathBuf {
+        match self {
+            Target::Resolved(path) | Target::Unresolved(path) => path,
+        }
+    }
+}
+
+impl ShellLink {
+    // Returns the first path of the target that exists, or the first path recorded in the
+    // shortcut as an unresolved target if none of them exist. Environment variables are looked up
+    // with `lookup`.
+    pub(crate) fn target<F>(&self, link_dir: &Path, lookup: F) -> Option<Target>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut candidates = vec![];
+        if let Some(local_base_path) = &self.local_base_path {
+            candidates.push(join_suffix(local_base_path, &self.common_path_suffix));
+        }
+        if let Some(net_name) = &self.net_name {
+            candidates.push(join_suffix(net_name, &self.common_path_suffix));
+        }
+        if let Some(device_name) = &self.device_name {
+            candidates.push(join_suffix(device_name, &self.common_path_suffix));
+        }
+        if let Some(environment_target) = &self.environment_target {
+            candidates.extend(expand_environment(environment_target, lookup));
+        }
+        let mut candidates = candidates
+            .into_iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        if let Some(relative_path) = &self.relative_path {
+            let relative_path = if cfg!(windows) {
+                relative_path.clone()
+            } else {
+                relative_path.replace('\\', "/")
+            };
+            candidates.push(link_dir.join(relative_path));
+        }
+
+        match candidates.iter().position(|candidate| candidate.exists()) {
+            Some(index) => Some(Target::Resolved(candidates.swap_remove(index))),
+            None => candidates.into_iter().next().map(Target::Unresolved),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ParseError(pub(crate) String);
+
+// Parses the contents of a shortcut file
+pub(crate) fn parse(data: &[u8]) -> Result<ShellLink, ParseError> {
+    if read_u32(data, 0)? as usize != HEADER_SIZE || data.get(4..20) != Some(&LINK_CLSID[..]) {
+        return Err(ParseError("not a shell link".to_string()));
+    }
+    let flags = read_u32(data, 0x14)?;
+    let mut link = ShellLink::default();
+    let mut offset = HEADER_SIZE;
+
+    // The LinkTargetIDList is not used, as the shell namespace cannot be parsed portably
+    if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+        offset += 2 + read_u16(data, offset)? as usize;
+    }
+
+    if flags & HAS_LINK_INFO != 0 {
+        let size = read_u32(data, offset)? as usize;
+        let link_info = section(data, offset, size)?;
+        if flags & FORCE_NO_LINK_INFO == 0 {
+            parse_link_info(link_info, &mut link)?;
+        }
+        offset += size;
+    }
+
+    // The StringData structures that are present appear in the order of their flags
+    let unicode = flags & IS_UNICODE != 0;
+    for flag in [
+        HAS_NAME,
+        HAS_RELATIVE_PATH,
+        HAS_WORKING_DIR,
+        HAS_ARGUMENTS,
+        HAS_ICON_LOCATION,
+    ]
+    .iter()
+    {
+        if flags & flag != 0 {
+            let (value, size) = read_string_data(data, offset, unicode)?;
+            if *flag == HAS_RELATIVE_PATH {
+                link.relative_path = Some(value);
+            }
+            offset += size;
+        }
+    }
+
+    // The ExtraData blocks are terminated by a block smaller than 4 bytes, which some shortcuts
+    // omit at the end of the file
+    while offset < data.len() {
+        let size = read_u32(data, offset)? as usize;
+        if size < 4 {
+            break;
+        }
+        let block = section(data, offset, size)?;
+        if read_u32(block, 4)? == ENVIRONMENT_VARIABLE_DATA_BLOCK {
+            let target_ansi = decode_ansi(until_nul(section(block, 8, 260)?));
+            let target_unicode = decode_utf16(section(block, 268, 520)?);
+            link.environment_target = Some(if target_unicode.is_empty() {
+                target_ansi
+            } else {
+                target_unicode
+            });
+        }
+        offset += size;
+    }
+
+    Ok(link)
+}
+
+fn parse_link_info(link_info: &[u8], link: &mut ShellLink) -> Result<(), ParseError> {
+    let header_size = read_u32(link_info, 4)?;
+    let flags = read_u32(link_info, 8)?;
+    let local_base_path_offset = read_u32(link_info, 0x10)? as usize;
+    let network_offset = read_u32(link_info, 0x14)? as usize;
+    let suffix_offset = read_u32(link_info, 0x18)? as usize;
+    // The Unicode offsets are only present in headers of at least 0x24 bytes
+    let (local_base_path_offset_unicode, suffix_offset_unicode) = if header_size >= 0x24 {
+        (
+            read_u32(link_info, 0x1C)? as usize,
+            read_u32(link_info, 0x20)? as usize,
+        )
+    } else {
+        (0, 0)
+    };
+
+    if flags & VOLUME_ID_AND_LOCAL_BASE_PATH != 0 {
+        link.local_base_path = Some(read_string(
+            link_info,
+            local_base_path_offset,
+            local_base_path_offset_unicode,
+        )?);
+    }
+
+    if flags & COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX != 0 {
+        let size = read_u32(link_info, network_offset)? as usize;
+        let network = section(link_info, network_offset, size)?;
+        let network_flags = read_u32(network, 4)?;
+        let net_name_offset = read_u32(network, 8)? as usize;
+        let device_name_offset = read_u32(network, 0xC)? as usize;
+        // The Unicode offsets are only present when the names follow them
+        let (net_name_offset_unicode, device_name_offset_unicode) = if net_name_offset > 0x14 {
+            (
+                read_u32(network, 0x14)? as usize,
+                read_u32(network, 0x18)? as usize,
+            )
+        } else {
+            (0, 0)
+        };
+
+        link.net_name = Some(read_string(
+            network,
+            net_name_offset,
+            net_name_offset_unicode,
+        )?);
+        if network_flags & VALID_DEVICE != 0 {
+            link.device_name = Some(read_string(
+                network,
+                device_name_offset,
+                device_name_offset_unicode,
+            )?);
+        }
+    }
+
+    if suffix_offset != 0 || suffix_offset_unicode != 0 {
+        link.common_path_suffix = read_string(link_info, suffix_offset, suffix_offset_unicode)?;
+    }
+    Ok(())
+}
+
+// Returns the target of the shortcut at the path, or `None` if the path is not a shortcut or its
+// target cannot be read
+fn read_target(path: &Path) -> Option<Target> {
+    let is_shortcut = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("lnk"));
+    if !is_shortcut {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_SHORTCUT_SIZE {
+        return None;
+    }
+
+    let link = parse(&fs::read(path).ok()?).ok()?;
+    let link_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    link.target(link_dir, |name| env::var(name).ok())
+}
+
+// Expands the `%NAME%` environment variables in a path, returning `None` if any of them are not
+// defined. A `%` without a closing `%` is left as it is, as Windows does.
+fn expand_environment<F>(path: &str, lookup: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find('%') {
+        let end = match rest[start + 1..].find('%') {
+            Some(length) => start + 1 + length,
+            None => break,
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&lookup(&rest[start + 1..end])?);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Some(expanded)
+}
+
+// Appends the common path suffix of a shortcut to its base path or network share
+fn join_suffix(base: &str, suffix: &str) -> String {
+    if suffix.is_empty() || base.ends_with('\\') {
+        format!("{}{}", base, suffix)
+    } else {
+        format!("{}\\{}", base, suffix)
+    }
+}
+
+fn section(data: &[u8], offset: usize, size: usize) -> Result<&[u8], ParseError> {
+    offset
+        .checked_add(size)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| ParseError(format!("truncated structure at offset {}", offset)))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ParseError> {
+    let bytes = section(data, offset, 2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ParseError> {
+    let bytes = section(data, offset, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Reads a null-terminated string of a LinkInfo structure, preferring the Unicode form when its
+// offset is set
+fn read_string(data: &[u8], offset: usize, offset_unicode: usize) -> Result<String, ParseError> {
+    let (offset, unicode) = if offset_unicode != 0 {
+        (offset_unicode, true)
+    } else {
+        (offset, false)
+    };
+    let bytes = data
+        .get(offset..)
+        .filter(|bytes| !bytes.is_empty())
+        .ok_or_else(|| ParseError(format!("string offset {} is out of bounds", offset)))?;
+    if unicode {
+        Ok(decode_utf16(bytes))
+    } else {
+        Ok(decode_ansi(until_nul(bytes)))
+    }
+}
+
+// Reads a StringData structure, returning the string and the size of the structure
+fn read_string_data(
+    data: &[u8],
+    offset: usize,
+    unicode: bool,
+) -> Result<(String, usize), ParseError> {
+    let length = read_u16(data, offset)? as usize;
+    let size = if unicode { length * 2 } else { length };
+    let bytes = section(data, offset + 2, size)?;
+    let value = if unicode {
+        decode_utf16(bytes)
+    } else {
+        decode_ansi(bytes)
+    };
+    Ok((value, 2 + size))
+}
+
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+// Decodes UTF-16LE up to the first null character. Unpaired surrogates are replaced.
+fn decode_utf16(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+// Decodes a string in the ANSI code page of the machine that created the shortcut, which is
+// assumed to be Windows-1252. Shortcuts also contain the Unicode form of strings that the code
+// page cannot represent.
+fn decode_ansi(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| match b {
+            0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+            _ => *b as char,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    const LOCAL: &[u8] = include_bytes!("../tests/fixtures/lnk/local.lnk");
+    const UNICODE: &[u8] = include_bytes!("../tests/fixtures/lnk/unicode.lnk");
+    const NETWORK: &[u8] = include_bytes!("../tests/fixtures/lnk/network.lnk");
+    const RELATIVE: &[u8] = include_bytes!("../tests/fixtures/lnk/relative.lnk");
+    const ENVIRONMENT: &[u8] = include_bytes!("../tests/fixtures/lnk/environment.lnk");
+    // Shortcuts laid out as Explorer saves them, with the structures that are not read
+    const LOCAL_EXPLORER: &[u8] = include_bytes!("../tests/fixtures/lnk/local-explorer.lnk");
+    const UNC_EXPLORER: &[u8] = include_bytes!("../tests/fixtures/lnk/unc-explorer.lnk");
+    const ENVIRONMENT_EXPLORER: &[u8] =
+        include_bytes!("../tests/fixtures/lnk/environment-explorer.lnk");
+    const RELATIVE_EXPLORER: &[u8] = include_bytes!("../tests/fixtures/lnk/relative-explorer.lnk");
+    const EXPLORER: [&[u8]; 4] = [
+        LOCAL_EXPLORER,
+        UNC_EXPLORER,
+        ENVIRONMENT_EXPLORER,
+        RELATIVE_EXPLORER,
+    ];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wfd-shortcut-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn no_variables(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn local_path() {
+        let link = parse(LOCAL).unwrap();
+        assert_eq!(
+            link,
+            ShellLink {
+                local_base_path: Some(r"C:\Users\Public\Documents\Résumé – 2024.docx".to_string()),
+                relative_path: Some(r"..\..\Public\Documents\Résumé – 2024.docx".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            link.target(Path::new("missing"), no_variables),
+            Some(Target::Unresolved(PathBuf::from(
+                r"C:\Users\Public\Documents\Résumé – 2024.docx"
+            )))
+        );
+    }
+
+    #[test]
+    fn unicode_path() {
+        let link = parse(UNICODE).unwrap();
+        assert_eq!(
+            link.local_base_path.as_deref(),
+            Some(r"C:\Users\Zoë\Документы\")
+        );
+        assert_eq!(link.common_path_suffix, "отчёт.txt");
+        assert_eq!(
+            link.target(Path::new("missing"), no_variables),
+            Some(Target::Unresolved(PathBuf::from(
+                r"C:\Users\Zoë\Документы\отчёт.txt"
+            )))
+        );
+    }
+
+    #[test]
+    fn network_path() {
+        let link = parse(NETWORK).unwrap();
+        assert_eq!(link.local_base_path, None);
+        assert_eq!(link.net_name.as_deref(), Some(r"\\fileserver\projects"));
+        assert_eq!(link.device_name.as_deref(), Some("Z:"));
+        assert_eq!(link.common_path_suffix, r"plans\roadmap.xlsx");
+        assert_eq!(
+            link.relative_path.as_deref(),
+            Some(r"..\plans\roadmap.xlsx")
+        );
+        assert_eq!(
+            link.target(Path::new("missing"), no_variables),
+            Some(Target::Unresolved(PathBuf::from(
+                r"\\fileserver\projects\plans\roadmap.xlsx"
+            )))
+        );
+    }
+
+    #[test]
+    fn environment_path() {
+        let link = parse(ENVIRONMENT).unwrap();
+        assert_eq!(
+            link.environment_target.as_deref(),
+            Some(r"%USERPROFILE%\Desktop\todo.txt")
+        );
+        assert_eq!(
+            link.target(Path::new("missing"), |name| match name {
+                "USERPROFILE" => Some(r"C:\Users\ben".to_string()),
+                _ => None,
+            }),
+            Some(Target::Unresolved(PathBuf::from(
+                r"C:\Users\ben\Desktop\todo.txt"
+            )))
+        );
+        assert_eq!(link.target(Path::new("missing"), no_variables), None);
+    }
+
+    #[test]
+    fn relative_path_is_preferred_when_it_exists() {
+        let dir = temp_dir("relative");
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data").join("notes.txt"), "notes").unwrap();
+
+        let mut link = parse(RELATIVE).unwrap();
+        assert_eq!(link.relative_path.as_deref(), Some(r".\data\notes.txt"));
+        let target = dir.join(".").join("data").join("notes.txt");
+        assert_eq!(
+            link.target(&dir, no_variables),
+            Some(Target::Resolved(target.clone()))
+        );
+
+        // A recorded local path is only used when it exists
+        link.local_base_path = Some(r"C:\missing\notes.txt".to_string());
+        assert_eq!(
+            link.target(&dir, no_variables),
+            Some(Target::Resolved(target))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn explorer_shortcuts() {
+        // Each shortcut has an ID list, a VolumeID or network share and a TrackerDataBlock
+        let tracker_block = [0x60, 0, 0, 0, 0x03, 0, 0, 0xA0];
+        for data in EXPLORER.iter() {
+            assert_ne!(read_u32(data, 0x14).unwrap() & HAS_LINK_TARGET_ID_LIST, 0);
+            assert!(data.windows(8).any(|block| block == tracker_block));
+        }
+
+        let link = parse(LOCAL_EXPLORER).unwrap();
+        assert_eq!(
+            link,
+            ShellLink {
+                local_base_path: Some(r"C:\Users\Public\Documents\Budget 2024.xlsx".to_string()),
+                relative_path: Some(r"..\..\..\Public\Documents\Budget 2024.xlsx".to_string()),
+                ..Default::default()
+            }
+        );
+
+        let link = parse(UNC_EXPLORER).unwrap();
+        assert_eq!(
+            link,
+            ShellLink {
+                net_name: Some(r"\\fileserver\projects".to_string()),
+                common_path_suffix: r"plans\roadmap.xlsx".to_string(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            link.target(Path::new("missing"), no_variables),
+            Some(Target::Unresolved(PathBuf::from(
+                r"\\fileserver\projects\plans\roadmap.xlsx"
+            )))
+        );
+
+        let link = parse(ENVIRONMENT_EXPLORER).unwrap();
+        assert_eq!(
+            link,
+            ShellLink {
+                local_base_path: Some(r"C:\Users\ben\Desktop\todo.txt".to_string()),
+                environment_target: Some(r"%USERPROFILE%\Desktop\todo.txt".to_string()),
+                ..Default::default()
+            }
+        );
+
+        // The recorded drive doesn't exist here, so the relative path is used
+        let dir = temp_dir("explorer");
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data").join("notes.txt"), "notes").unwrap();
+        let link = parse(RELATIVE_EXPLORER).unwrap();
+        assert_eq!(
+            link.local_base_path.as_deref(),
+            Some(r"D:\Projects\data\notes.txt")
+        );
+        assert_eq!(
+            link.target(&dir, no_variables),
+            Some(Target::Resolved(
+                dir.join(".").join("data").join("notes.txt")
+            ))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolution_modes() {
+        let dir = temp_dir("modes");
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data").join("notes.txt"), "notes").unwrap();
+        let shortcut = dir.join("Notes.LNK");
+        fs::write(&shortcut, RELATIVE).unwrap();
+        let broken = dir.join("broken.lnk");
+        fs::write(&broken, &RELATIVE[..HEADER_SIZE + 3]).unwrap();
+        let unresolved = dir.join("network.lnk");
+        fs::write(&unresolved, NETWORK).unwrap();
+        let file = dir.join("data").join("notes.txt");
+        let target = dir.join(".").join("data").join("notes.txt");
+        let paths = vec![
+            shortcut.clone(),
+            broken.clone(),
+            unresolved.clone(),
+            file.clone(),
+        ];
+
+        assert_eq!(
+            ShortcutResolution::Off.apply(paths.clone()),
+            (paths.clone(), vec![])
+        );
+        // Shortcuts whose target does not exist are kept
+        assert_eq!(
+            ShortcutResolution::Replace.apply(paths.clone()),
+            (vec![target.clone(), broken, unresolved, file], vec![])
+        );
+        assert_eq!(
+            ShortcutResolution::Annotate.apply(paths.clone()),
+            (
+                paths,
+                vec![
+                    Some(target),
+                    None,
+                    Some(PathBuf::from(r"\\fileserver\projects\plans\roadmap.xlsx")),
+                    None
+                ]
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_shortcuts() {
+        assert!(parse(b"").is_err());
+        assert!(parse(&LOCAL[..HEADER_SIZE - 1]).is_err());
+
+        let mut wrong_size = LOCAL.to_vec();
+        wrong_size[0] = 0x4D;
+        assert!(parse(&wrong_size).is_err());
+
+        let mut wrong_clsid = LOCAL.to_vec();
+        wrong_clsid[4] = 0;
+        assert_eq!(
+            parse(&wrong_clsid),
+            Err(ParseError("not a shell link".to_string()))
+        );
+
+        // A LinkInfo structure whose local base path is out of bounds
+        let mut bad_offset = LOCAL.to_vec();
+        let link_info = HEADER_SIZE + 2 + read_u16(LOCAL, HEADER_SIZE).unwrap() as usize;
+        bad_offset[link_info + 0x10..link_info + 0x14].copy_from_slice(&0xFFFFu32.to_le_bytes());
+        assert!(parse(&bad_offset).is_err());
+    }
+
+    #[test]
+    fn truncated_shortcuts_do_not_panic() {
+        let fixtures = [LOCAL, UNICODE, NETWORK, RELATIVE, ENVIRONMENT];
+        for data in fixtures.iter().chain(EXPLORER.iter()) {
+            for length in 0..data.len() {
+                let _ = parse(&data[..length]);
+            }
+            // Without the terminal block
+            assert!(parse(&data[..data.len() - 4]).is_ok());
+        }
+        assert!(parse(&NETWORK[..NETWORK.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        let lookup = |name: &str| match name {
+            "SystemRoot" => Some(r"C:\Windows".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        assert_eq!(
+            expand_environment(r"%SystemRoot%\notepad.exe", lookup).as_deref(),
+            Some(r"C:\Windows\notepad.exe")
+        );
+        assert_eq!(
+            expand_environment(r"a%EMPTY%b", lookup).as_deref(),
+            Some("ab")
+        );
+        assert_eq!(
+            expand_environment(r"100% done", lookup).as_deref(),
+            Some("100% done")
+        );
+        assert_eq!(expand_environment(r"%MISSING%\file", lookup), None);
+    }
+
+    #[test]
+    fn decodes_windows_1252() {
+        assert_eq!(
+            decode_ansi(b"R\xe9sum\xe9 \x96 \x80\x81"),
+            "Résumé – €\u{81}"
+        );
+        assert_eq!(
+            decode_utf16(&[0x41, 0x00, 0x3D, 0xD8, 0x42, 0x00]),
+            "A\u{FFFD}B"
+        );
+        assert_eq!(decode_utf16(&[0x41, 0x00, 0x00, 0x00, 0x42, 0x00]), "A");
+    }
+
+    #[test]
+    fn ignores_files_that_are_not_shortcuts() {
+        let dir = temp_dir("not-shortcuts");
+        let text = dir.join("notes.txt");
+        fs::write(&text, RELATIVE).unwrap();
+        assert_eq!(read_target(&text), None);
+        assert_eq!(read_target(&dir.join("missing.lnk")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,504 @@
+// Instrumentation of the dialog lifecycle and of the COM calls made by it, emitted through the
+// `tracing` crate when the `tracing` feature is enabled. Without the feature every function in
+// this module is a no-op, so that call sites don't need to be feature gated.
+
+use std::io;
+use std::time::Duration;
+
+use crate::backend::{DialogKind, Selection};
+use crate::{DialogError, DialogParams};
+
+pub(crate) use self::imp::SpanGuard;
+#[cfg(feature = "tracing")]
+pub use self::imp::{set_path_redaction, PathRedaction};
+
+// The stages of the dialog lifecycle, each of which is run inside its own span
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Create,
+    Configure,
+    Show,
+    Collect,
+}
+
+// Returns the span that the whole lifecycle of a dialog is run inside of
+pub(crate) fn dialog_span(kind: DialogKind) -> SpanGuard {
+    imp::dialog_span(kind)
+}
+
+// Returns the span that a single stage of the dialog lifecycle is run inside of
+pub(crate) fn stage_span(stage: Stage) -> SpanGuard {
+    imp::stage_span(stage)
+}
+
+// Emits an event for a completed COM call
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn com_call(method: &str, hresult: i32, elapsed: Duration) {
+    imp::com_call(method, hresult, elapsed)
+}
+
+// Emits the effective FOS_* options bitmask of a dialog after calling `GetOptions` or `SetOptions`
+#[cfg_attr(not(all(windows, feature = "native-windows")), allow(dead_code))]
+pub(crate) fn options(method: &str, options: u32) {
+    imp::options(method, options)
+}
+
+// Emits the parameters that a dialog is shown with
+pub(crate) fn parameters(params: &DialogParams) {
+    imp::parameters(params)
+}
+
+// Emits the items that were selected in a dialog
+pub(crate) fn selection(selection: &Selection) {
+    imp::selection(selection)
+}
+
+// Emits the result of a dialog
+pub(crate) fn outcome(result: &Result<Selection, DialogError>) {
+    imp::outcome(result)
+}
+
+// Emits an error that the broker recovers from, such as failing to accept a connection
+#[cfg_attr(not(unix), allow(dead_code))]
+pub(crate) fn broker_error(context: &str, error: &io::Error) {
+    imp::broker_error(context, error)
+}
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use std::io;
+    use std::sync::atomic::{AtomicU8, Ordering};
+    use std::time::Duration;
+
+    use tracing::{debug, debug_span, info, info_span, span::EnteredSpan, warn};
+
+    use super::Stage;
+    use crate::backend::{DialogKind, Selection};
+    use crate::{DialogError, DialogParams};
+
+    /// Determines how file system paths are included in the events emitted with the `tracing`
+    /// feature, see [`set_path_redaction`]
+    ///
+    /// [`set_path_redaction`]: fn.set_path_redaction.html
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum PathRedaction {
+        /// Paths are replaced with `<redacted>`
+        #[default]
+        Redacted,
+        /// Only the final component of each path is included, i.e. `report.txt`
+        FileName,
+        /// Paths are included in full
+        Full,
+    }
+
+    const SEPARATORS: &[char] = &['/', '\\'];
+
+    static PATH_REDACTION: AtomicU8 = AtomicU8::new(PathRedaction::Redacted as u8);
+
+    /// Sets how file system paths are included in the events emitted with the `tracing` feature.
+    /// Paths often contain user names and other personal information, so they are redacted by
+    /// default.
+    pub fn set_path_redaction(redaction: PathRedaction) {
+        PATH_REDACTION.store(redaction as u8, Ordering::Relaxed);
+    }
+
+    fn path_redaction() -> PathRedaction {
+        match PATH_REDACTION.load(Ordering::Relaxed) {
+            x if x == PathRedaction::FileName as u8 => PathRedaction::FileName,
+            x if x == PathRedaction::Full as u8 => PathRedaction::Full,
+            _ => PathRedaction::Redacted,
+        }
+    }
+
+    // Returns the path in the form allowed by the current `PathRedaction`. Empty paths are
+    // returned as-is, so that unset fields can be told apart from redacted ones.
+    fn redact(path: &str) -> String {
+        if path.is_empty() {
+            return String::new();
+        }
+
+        match path_redaction() {
+            PathRedaction::Full => path.to_string(),
+            // Both separators are handled, so that Windows paths are redacted on every platform
+            PathRedaction::FileName => path
+                .trim_end_matches(SEPARATORS)
+                .rsplit(SEPARATORS)
+                .next()
+                .filter(|file_name| !file_name.is_empty())
+                .unwrap_or("<redacted>")
+                .to_string(),
+            PathRedaction::Redacted => "<redacted>".to_string(),
+        }
+    }
+
+    pub(crate) struct SpanGuard {
+        _span: EnteredSpan,
+    }
+
+    pub(crate) fn dialog_span(kind: DialogKind) -> SpanGuard {
+        let kind = match kind {
+            DialogKind::Open => "open",
+            DialogKind::Save => "save",
+        };
+        SpanGuard {
+            _span: info_span!("dialog", kind).entered(),
+        }
+    }
+
+    pub(crate) fn stage_span(stage: Stage) -> SpanGuard {
+        let span = match stage {
+            Stage::Create => debug_span!("create"),
+            Stage::Configure => debug_span!("configure"),
+            Stage::Show => debug_span!("show"),
+            Stage::Collect => debug_span!("collect"),
+        };
+        SpanGuard {
+            _span: span.entered(),
+        }
+    }
+
+    pub(crate) fn com_call(method: &str, hresult: i32, elapsed: Duration) {
+        let hresult_hex = format!("{:#010x}", hresult as u32);
+        let duration_us = elapsed.as_micros() as u64;
+        if hresult >= 0 {
+            debug!(method, hresult = %hresult_hex, duration_us, "COM call succeeded");
+        } else {
+            warn!(method, hresult = %hresult_hex, duration_us, "COM call failed");
+        }
+    }
+
+    pub(crate) fn options(method: &str, options: u32) {
+        debug!(method, options = %format!("{:#x}", options), "dialog options");
+    }
+
+    pub(crate) fn parameters(params: &DialogParams) {
+        debug!(
+            title = params.title,
+            file_types = params.file_types.len(),
+            file_type_index = params.file_type_index,
+            options = %format!("{:#x}", params.options),
+            un_options = %format!("{:#x}", params.un_options),
+            default_folder = %redact(&params.default_folder.to_string()),
+            folder = %redact(&params.folder.to_string()),
+            file_name = %redact(params.file_name),
+            save_as_item = %redact(&params.save_as_item.to_string_lossy()),
+            "dialog parameters"
+        );
+    }
+
+    pub(crate) fn selection(selection: &Selection) {
+        let paths = selection
+            .paths
+            .iter()
+            .map(|path| redact(&path.to_string_lossy()))
+            .collect::<Vec<_>>();
+        debug!(
+            count = selection.paths.len(),
+            paths = ?paths,
+            file_type_index = selection.file_type_index,
+            "dialog selection"
+        );
+    }
+
+    pub(crate) fn outcome(result: &Result<Selection, DialogError>) {
+        match result {
+            Ok(selection) => info!(count = selection.paths.len(), "dialog completed"),
+            Err(DialogError::UserCancelled) => info!("dialog cancelled"),
+            // Only the kind of the error is included, as errors can contain paths
+            Err(e) => warn!(error = error_kind(e), "dialog failed"),
+        }
+    }
+
+    pub(crate) fn broker_error(context: &str, error: &io::Error) {
+        warn!(context, error = %error, "broker error");
+    }
+
+    fn error_kind(error: &DialogError) -> &'static str {
+        match error {
+            DialogError::UserCancelled => "UserCancelled",
+            DialogError::UnsupportedFilepath => "UnsupportedFilepath",
+            DialogError::HResultFailed { .. } => "HResultFailed",
+            DialogError::InvalidPath { .. } => "InvalidPath",
+            DialogError::SelectionRejected { .. } => "SelectionRejected",
+            DialogError::Io { .. } => "Io",
+            DialogError::NonInteractive => "NonInteractive",
+            DialogError::InteriorNul { .. } => "InteriorNul",
+            DialogError::UriConversion { .. } => "UriConversion",
+            DialogError::Unsupported { .. } => "Unsupported",
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod imp {
+    use std::io;
+    use std::time::Duration;
+
+    use super::Stage;
+    use crate::backend::{DialogKind, Selection};
+    use crate::{DialogError, DialogParams};
+
+    pub(crate) struct SpanGuard;
+
+    pub(crate) fn dialog_span(_kind: DialogKind) -> SpanGuard {
+        SpanGuard
+    }
+
+    pub(crate) fn stage_span(_stage: Stage) -> SpanGuard {
+        SpanGuard
+    }
+
+    pub(crate) fn com_call(_method: &str, _hresult: i32, _elapsed: Duration) {}
+
+    pub(crate) fn options(_method: &str, _options: u32) {}
+
+    pub(crate) fn parameters(_params: &DialogParams) {}
+
+    pub(crate) fn selection(_selection: &Selection) {}
+
+    pub(crate) fn outcome(_result: &Result<Selection, DialogError>) {}
+
+    pub(crate) fn broker_error(_context: &str, _error: &io::Error) {}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use super::*;
+    use crate::backend::tests::FakeBackend;
+    use crate::backend::{self, DialogKind};
+
+    type Fields = BTreeMap<String, String>;
+
+    #[derive(Clone, Debug, Default)]
+    struct Recording {
+        // The name, parent name and fields of each span, in creation order
+        spans: Vec<(String, Option<String>, Fields)>,
+        // The name of the enclosing span and the fields of each event
+        events: Vec<(Option<String>, Fields)>,
+        stack: Vec<usize>,
+    }
+
+    impl Recording {
+        fn current(&self) -> Option<String> {
+            self.stack.last().map(|&index| self.spans[index].0.clone())
+        }
+
+        fn events_with_message(&self, message: &str) -> Vec<&(Option<String>, Fields)> {
+            self.events
+                .iter()
+                .filter(|(_, fields)| fields.get("message").map(String::as_str) == Some(message))
+                .collect()
+        }
+    }
+
+    // A subscriber that records the structure of the spans and events emitted
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Recording>>);
+
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    impl<'a> Visit for FieldVisitor<'a> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+            let mut recording = self.0.lock().unwrap();
+            let mut fields = Fields::new();
+            attributes.record(&mut FieldVisitor(&mut fields));
+            let parent = recording.current();
+            recording
+                .spans
+                .push((attributes.metadata().name().to_string(), parent, fields));
+            Id::from_u64(recording.spans.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut recording = self.0.lock().unwrap();
+            let mut fields = Fields::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            let parent = recording.current();
+            recording.events.push((parent, fields));
+        }
+
+        fn enter(&self, span: &Id) {
+            let mut recording = self.0.lock().unwrap();
+            recording.stack.push(span.into_u64() as usize - 1);
+        }
+
+        fn exit(&self, _span: &Id) {
+            let mut recording = self.0.lock().unwrap();
+            recording.stack.pop();
+        }
+    }
+
+    fn record<F: FnOnce()>(f: F) -> Recording {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), f);
+        let recording = recorder.0.lock().unwrap();
+        recording.clone()
+    }
+
+    fn run_fake_dialog(backend: &mut FakeBackend, kind: DialogKind, params: &DialogParams) {
+        let _ = backend::run(backend, kind, params);
+    }
+
+    #[test]
+    fn lifecycle_spans() {
+        let recording = record(|| {
+            let mut backend = FakeBackend {
+                selection: vec![PathBuf::from(r"C:\report.txt")],
+                file_type_index: 1,
+                ..Default::default()
+            };
+            run_fake_dialog(&mut backend, DialogKind::Open, &DialogParams::default());
+        });
+
+        let spans = recording
+            .spans
+            .iter()
+            .map(|(name, parent, _)| (name.as_str(), parent.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                ("dialog", None),
+                ("create", Some("dialog")),
+                ("configure", Some("dialog")),
+                ("show", Some("dialog")),
+                ("collect", Some("dialog")),
+            ]
+        );
+        assert_eq!(recording.spans[0].2["kind"], "open");
+        assert!(recording.stack.is_empty());
+
+        let completed = recording.events_with_message("dialog completed");
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].0.as_deref(), Some("dialog"));
+        assert_eq!(completed[0].1["count"], "1");
+
+        let selection = recording.events_with_message("dialog selection");
+        assert_eq!(selection.len(), 1);
+        assert_eq!(selection[0].0.as_deref(), Some("collect"));
+        assert_eq!(selection[0].1["file_type_index"], "1");
+    }
+
+    #[test]
+    fn com_call_events() {
+        let recording = record(|| {
+            let mut backend = FakeBackend {
+                failing_method: Some(("IModalWindow::Show", 0x8000_4005_u32 as i32)),
+                ..Default::default()
+            };
+            let params = DialogParams {
+                options: crate::FOS_PICKFOLDERS,
+                ..Default::default()
+            };
+            run_fake_dialog(&mut backend, DialogKind::Save, &params);
+        });
+
+        let succeeded = recording.events_with_message("COM call succeeded");
+        let methods = succeeded
+            .iter()
+            .map(|(parent, fields)| (parent.as_deref(), fields["method"].as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            vec![
+                (Some("create"), "CoCreateInstance"),
+                (Some("configure"), "IFileDialog::SetOptions"),
+            ]
+        );
+        assert_eq!(succeeded[0].1["hresult"], "0x00000000");
+        assert!(succeeded[0].1.contains_key("duration_us"));
+
+        let failed = recording.events_with_message("COM call failed");
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0.as_deref(), Some("show"));
+        assert_eq!(failed[0].1["method"], "IModalWindow::Show");
+        assert_eq!(failed[0].1["hresult"], "0x80004005");
+
+        let options = recording.events_with_message("dialog options");
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].1["options"], "0x20");
+
+        // The collect stage is never reached
+        assert!(!recording.spans.iter().any(|(name, _, _)| name == "collect"));
+        let outcome = recording.events_with_message("dialog failed");
+        assert_eq!(outcome.len(), 1);
+        assert_eq!(outcome[0].1["error"], "HResultFailed");
+    }
+
+    // All redaction modes are tested together as the setting is global
+    #[test]
+    fn path_redaction() {
+        let params = DialogParams {
+            file_name: "draft.txt",
+            save_as_item: Path::new(r"C:\Users\ben\Documents\report.txt"),
+            ..Default::default()
+        };
+        let fields = |redaction: PathRedaction| {
+            set_path_redaction(redaction);
+            let recording = record(|| {
+                let mut backend = FakeBackend {
+                    selection: vec![PathBuf::from(r"C:\Users\ben\Documents\report.txt")],
+                    ..Default::default()
+                };
+                run_fake_dialog(&mut backend, DialogKind::Save, &params);
+            });
+            let parameters = recording.events_with_message("dialog parameters")[0]
+                .1
+                .clone();
+            let selection = recording.events_with_message("dialog selection")[0]
+                .1
+                .clone();
+            (parameters, selection)
+        };
+
+        let (parameters, selection) = fields(PathRedaction::Full);
+        assert_eq!(
+            parameters["save_as_item"],
+            r"C:\Users\ben\Documents\report.txt"
+        );
+        assert_eq!(parameters["folder"], "");
+        assert_eq!(
+            selection["paths"],
+            r#"["C:\\Users\\ben\\Documents\\report.txt"]"#
+        );
+
+        let (parameters, selection) = fields(PathRedaction::FileName);
+        assert_eq!(parameters["file_name"], "draft.txt");
+        assert_eq!(parameters["save_as_item"], "report.txt");
+        assert_eq!(selection["paths"], r#"["report.txt"]"#);
+
+        let (parameters, selection) = fields(PathRedaction::Redacted);
+        assert_eq!(parameters["file_name"], "<redacted>");
+        assert_eq!(parameters["save_as_item"], "<redacted>");
+        assert_eq!(parameters["folder"], "");
+        assert_eq!(selection["paths"], r#"["<redacted>"]"#);
+    }
+}
@@ -0,0 +1,604 @@
+// Conversion between file paths and `file://` URIs
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::path_form;
+use crate::DialogError;
+
+// The characters, in addition to unreserved characters, that are not percent-encoded in the path
+// of a file URI
+const PATH_SAFE: &[u8] = b"!$&'()*+,;=:@/";
+
+// The characters, in addition to unreserved characters, that are not percent-encoded in the host
+// of a file URI
+const HOST_SAFE: &[u8] = b"!$&'()*+,;=";
+
+/// The conventions used to convert between paths and `file://` URIs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UriStyle {
+    /// The conventions of the platform that the application is running on
+    #[default]
+    Native,
+    /// Drive paths such as `C:\dir` map to `file:///C:/dir` and UNC paths such as
+    /// `\\server\share\dir` map to `file://server/share/dir`
+    Windows,
+    /// Absolute paths such as `/home/ben` map to `file:///home/ben`, and URIs naming a host other
+    /// than `localhost` are rejected
+    Unix,
+}
+
+impl UriStyle {
+    fn is_windows(self) -> bool {
+        match self {
+            UriStyle::Native => cfg!(windows),
+            UriStyle::Windows => true,
+            UriStyle::Unix => false,
+        }
+    }
+}
+
+/// The reason that a path or `file://` URI could not be converted, returned in a
+/// [`DialogError::UriConversion`] error
+///
+/// [`DialogError::UriConversion`]: enum.DialogError.html#variant.UriConversion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UriErrorReason {
+    /// The value does not start with a URI scheme such as `file:`
+    MissingScheme,
+    /// The URI uses a scheme other than `file`, which is included, i.e. `"https"`
+    UnsupportedScheme(String),
+    /// The URI names a remote host, which is included. Only supported by the Windows style, where
+    /// it is mapped to a UNC path.
+    RemoteHost(String),
+    /// The URI contains a `%` that is not followed by two hexadecimal digits, or an escaped path
+    /// separator such as `%2F`, which cannot be part of a file name
+    MalformedEscape,
+    /// The path or the decoded URI contains a null character
+    NullCharacter,
+    /// The path is not absolute, or the URI does not contain an absolute path in the style
+    NotAbsolute,
+    /// The path or the decoded URI cannot be represented in the style or on the platform, such as
+    /// a path that isn't valid Unicode when using the Windows style
+    InvalidUnicode,
+}
+
+/// Converts an absolute path into a `file://` URI, percent-encoding characters where necessary.
+/// Verbatim (`\\?\`) prefixes are removed from Windows paths.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use wfd::{path_to_file_uri, UriStyle};
+///
+/// let uri = path_to_file_uri(Path::new(r"\\server\share\Q1 #2.csv"), UriStyle::Windows);
+/// assert_eq!(uri.unwrap(), "file://server/share/Q1%20%232.csv");
+/// ```
+///
+/// # Errors
+/// If the path is not absolute, contains a null character or cannot be represented in the style,
+/// the [`UriConversion`] error is returned.
+///
+/// [`UriConversion`]: enum.DialogError.html#variant.UriConversion
+pub fn path_to_file_uri(path: &Path, style: UriStyle) -> Result<String, DialogError> {
+    let result = if style.is_windows() {
+        windows_path_to_uri(path)
+    } else {
+        unix_path_to_uri(path)
+    };
+    result.map_err(|reason| DialogError::UriConversion {
+        input: path.to_string_lossy().into_owned(),
+        reason,
+    })
+}
+
+/// Converts a `file://` URI into a path, decoding percent-encoded characters. Any query or
+/// fragment is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+/// use wfd::{file_uri_to_path, UriStyle};
+///
+/// let path = file_uri_to_path("file:///home/ben/Q1%20%232.csv", UriStyle::Unix);
+/// assert_eq!(path.unwrap(), PathBuf::from("/home/ben/Q1 #2.csv"));
+/// ```
+///
+/// # Errors
+/// If the value is not a `file://` URI, or it cannot be represented as an absolute path in the
+/// style, the [`UriConversion`] error is returned.
+///
+/// [`UriConversion`]: enum.DialogError.html#variant.UriConversion
+pub fn file_uri_to_path(uri: &str, style: UriStyle) -> Result<PathBuf, DialogError> {
+    parse_file_uri(uri, style.is_windows()).map_err(|reason| DialogError::UriConversion {
+        input: uri.to_string(),
+        reason,
+    })
+}
+
+// Returns true if the value starts with the `file:` scheme
+pub(crate) fn is_file_uri(value: &str) -> bool {
+    strip_prefix_ignore_case(value, "file:").is_some()
+}
+
+// Converts a path field that may contain a `file://` URI into a path. Only used by the native
+// Windows dialog.
+#[cfg_attr(not(all(windows, feature = "native-windows")), allow(dead_code))]
+pub(crate) fn input_path(path: &Path) -> Result<Cow<'_, Path>, DialogError> {
+    match path.to_str().filter(|path| is_file_uri(path)) {
+        Some(uri) => file_uri_to_path(uri, UriStyle::Native).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(path)),
+    }
+}
+
+fn windows_path_to_uri(path: &Path) -> Result<String, UriErrorReason> {
+    let path = path.to_str().ok_or(UriErrorReason::InvalidUnicode)?;
+    if path.contains('\0') {
+        return Err(UriErrorReason::NullCharacter);
+    }
+
+    let normalized = path_form::normalize_input(OsStr::new(path));
+    let normalized = normalized.to_str().ok_or(UriErrorReason::InvalidUnicode)?;
+    if normalized.starts_with(r"\\?\") || normalized.starts_with(r"\\.\") {
+        // Device and volume GUID paths have no URI form
+        Err(UriErrorReason::NotAbsolute)
+    } else if let Some(unc) = normalized.strip_prefix(r"\\") {
+        let (host, share_path) = unc.split_at(unc.find('\\').unwrap_or(unc.len()));
+        if host.is_empty() || share_path.len() < 2 {
+            return Err(UriErrorReason::NotAbsolute);
+        }
+        Ok(format!(
+            "file://{}{}",
+            percent_encode(host.as_bytes(), HOST_SAFE),
+            percent_encode(share_path.replace('\\', "/").as_bytes(), PATH_SAFE)
+        ))
+    } else if drive_prefix_len(normalized.as_bytes()).is_some() {
+        Ok(format!(
+            "file:///{}",
+            percent_encode(normalized.replace('\\', "/").as_bytes(), PATH_SAFE)
+        ))
+    } else {
+        Err(UriErrorReason::NotAbsolute)
+    }
+}
+
+fn unix_path_to_uri(path: &Path) -> Result<String, UriErrorReason> {
+    let bytes = path_bytes(path).ok_or(UriErrorReason::InvalidUnicode)?;
+    if bytes.contains(&0) {
+        Err(UriErrorReason::NullCharacter)
+    } else if !bytes.starts_with(b"/") {
+        Err(UriErrorReason::NotAbsolute)
+    } else {
+        Ok(format!("file://{}", percent_encode(bytes, PATH_SAFE)))
+    }
+}
+
+fn parse_file_uri(uri: &str, windows: bool) -> Result<PathBuf, UriErrorReason> {
+    let scheme_end = uri
+        .find(':')
+        .filter(|&end| is_scheme(&uri[..end]))
+        .ok_or(UriErrorReason::MissingScheme)?;
+    let scheme = &uri[..scheme_end];
+    if !scheme.eq_ignore_ascii_case("file") {
+        return Err(UriErrorReason::UnsupportedScheme(scheme.to_string()));
+    }
+
+    let rest = &uri[scheme_end + 1..];
+    let rest = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+    // The authority is optional, i.e. `file:/home/ben` is equivalent to `file:///home/ben`
+    let (host, path) = match rest.strip_prefix("//") {
+        Some(authority) => authority.split_at(authority.find('/').unwrap_or(authority.len())),
+        None => ("", rest),
+    };
+
+    let host = decode_component(host, windows)?;
+    let host = String::from_utf8(host).map_err(|_| UriErrorReason::InvalidUnicode)?;
+    let host = if host.eq_ignore_ascii_case("localhost") {
+        String::new()
+    } else {
+        host
+    };
+    let path = decode_component(path, windows)?;
+    if host.contains('\0') || path.contains(&0) {
+        return Err(UriErrorReason::NullCharacter);
+    }
+
+    if windows {
+        windows_uri_path(&host, path)
+    } else {
+        unix_uri_path(&host, path)
+    }
+}
+
+// Decodes the host or path of a URI. Escaped separators are refused, as with GLib's
+// `g_filename_from_uri`, since decoding them would split a name into several components.
+fn decode_component(encoded: &str, windows: bool) -> Result<Vec<u8>, UriErrorReason> {
+    let separators: &[u8] = if windows { b"/\\" } else { b"/" };
+    let count = |bytes: &[u8]| bytes.iter().filter(|b| separators.contains(b)).count();
+
+    let decoded = percent_decode(encoded).ok_or(UriErrorReason::MalformedEscape)?;
+    // Bytes other than escapes are copied unchanged, so any extra separators were escaped
+    if count(&decoded) > count(encoded.as_bytes()) {
+        return Err(UriErrorReason::MalformedEscape);
+    }
+    Ok(decoded)
+}
+
+fn windows_uri_path(host: &str, path: Vec<u8>) -> Result<PathBuf, UriErrorReason> {
+    let path = String::from_utf8(path).map_err(|_| UriErrorReason::InvalidUnicode)?;
+    let path = path.replace('/', r"\");
+
+    if !host.is_empty() {
+        return if path.len() > 1 {
+            Ok(PathBuf::from(format!(r"\\{}{}", host, path)))
+        } else {
+            Err(UriErrorReason::NotAbsolute)
+        };
+    }
+
+    // UNC paths are also written with the host in the path, i.e. `file:////server/share` or
+    // `file://///server/share`
+    if path.starts_with(r"\\") {
+        let unc = path.trim_start_matches('\\');
+        return if unc.contains('\\') {
+            Ok(PathBuf::from(format!(r"\\{}", unc)))
+        } else {
+            Err(UriErrorReason::NotAbsolute)
+        };
+    }
+
+    // Drives are also written with a `|` instead of the `:` in legacy URIs, i.e. `file:///C|/dir`
+    let drive_path = path.strip_prefix('\\').unwrap_or(&path);
+    match drive_prefix_len(drive_path.as_bytes()) {
+        Some(len) => {
+            let mut windows_path = format!("{}:", &drive_path[..1]);
+            windows_path.push_str(&drive_path[2..]);
+            if len == 2 {
+                windows_path.push('\\');
+            }
+            Ok(PathBuf::from(windows_path))
+        }
+        None => Err(UriErrorReason::NotAbsolute),
+    }
+}
+
+fn unix_uri_path(host: &str, path: Vec<u8>) -> Result<PathBuf, UriErrorReason> {
+    if !host.is_empty() {
+        Err(UriErrorReason::RemoteHost(host.to_string()))
+    } else if !path.starts_with(b"/") {
+        Err(UriErrorReason::NotAbsolute)
+    } else {
+        path_from_bytes(path).ok_or(UriErrorReason::InvalidUnicode)
+    }
+}
+
+// Returns the length of the drive at the start of a path, i.e. 3 for `C:\dir` or 2 for `C:`
+fn drive_prefix_len(path: &[u8]) -> Option<usize> {
+    match path {
+        [letter, b':' | b'|'] if letter.is_ascii_alphabetic() => Some(2),
+        [letter, b':' | b'|', b'\\', ..] if letter.is_ascii_alphabetic() => Some(3),
+        _ => None,
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Option<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(path.as_os_str().as_bytes())
+}
+
+// Paths on other platforms can only be mapped to bytes when they are valid Unicode
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Option<&[u8]> {
+    path.to_str().map(str::as_bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+pub(crate) fn percent_encode(bytes: &[u8], safe: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) || safe.contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+// Decodes percent-encoded bytes, returning `None` if an escape sequence is malformed
+pub(crate) fn percent_decode(encoded: &str) -> Option<Vec<u8>> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = encoded
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(decoded)
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let candidate = s.get(..prefix.len())?;
+    if candidate.eq_ignore_ascii_case(prefix) {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use UriErrorReason::*;
+
+    fn to_path(uri: &str, style: UriStyle) -> Result<PathBuf, UriErrorReason> {
+        file_uri_to_path(uri, style).map_err(|e| match e {
+            DialogError::UriConversion { input, reason } => {
+                assert_eq!(input, uri);
+                reason
+            }
+            other => panic!("unexpected error {:?}", other),
+        })
+    }
+
+    fn to_uri(path: &str, style: UriStyle) -> Result<String, UriErrorReason> {
+        path_to_file_uri(Path::new(path), style).map_err(|e| match e {
+            DialogError::UriConversion { reason, .. } => reason,
+            other => panic!("unexpected error {:?}", other),
+        })
+    }
+
+    // URIs and the Windows paths that they are converted to
+    const WINDOWS_URIS: &[(&str, Result<&str, UriErrorReason>)] = &[
+        (
+            "file:///C:/Users/ben/report.txt",
+            Ok(r"C:\Users\ben\report.txt"),
+        ),
+        ("FILE:///c:/Users", Ok(r"c:\Users")),
+        ("file:///C:", Ok(r"C:\")),
+        ("file:///C:/", Ok(r"C:\")),
+        ("file:///C|/legacy/pipe", Ok(r"C:\legacy\pipe")),
+        ("file:/C:/no/authority", Ok(r"C:\no\authority")),
+        ("file://localhost/C:/local", Ok(r"C:\local")),
+        ("file://LOCALHOST/C:/local", Ok(r"C:\local")),
+        ("file:///C:/a%20b/%23c%25.txt", Ok(r"C:\a b\#c%.txt")),
+        (
+            "file:///C:/donn%C3%A9es/%E6%97%A5%E6%9C%AC",
+            Ok(r"C:\données\日本"),
+        ),
+        (
+            "file:///C:/Users/ben/report.txt?query=1#fragment",
+            Ok(r"C:\Users\ben\report.txt"),
+        ),
+        (
+            "file://server/share/dir/file.txt",
+            Ok(r"\\server\share\dir\file.txt"),
+        ),
+        ("file://server/share", Ok(r"\\server\share")),
+        ("file:////server/share/dir", Ok(r"\\server\share\dir")),
+        ("file://///server/share/dir", Ok(r"\\server\share\dir")),
+        ("file://my%20server/share", Ok(r"\\my server\share")),
+        ("file://server", Err(NotAbsolute)),
+        ("file://server/", Err(NotAbsolute)),
+        ("file:////server", Err(NotAbsolute)),
+        ("file:///dir/without/drive", Err(NotAbsolute)),
+        ("file:relative/path", Err(NotAbsolute)),
+        ("file:///C:/bad%2", Err(MalformedEscape)),
+        ("file:///C:/bad%zz", Err(MalformedEscape)),
+        ("file:///C:/a%2Fb", Err(MalformedEscape)),
+        ("file:///C:/a%5cb", Err(MalformedEscape)),
+        ("file://server%5Cshare/dir", Err(MalformedEscape)),
+        ("file:///C:/nul%00", Err(NullCharacter)),
+        ("file:///C:/invalid%FF", Err(InvalidUnicode)),
+        (
+            "https://example.com/report.txt",
+            Err(UnsupportedScheme(String::new())),
+        ),
+        ("C:/Users/ben", Err(UnsupportedScheme(String::new()))),
+        (r"C:\Users\ben", Err(UnsupportedScheme(String::new()))),
+        ("/home/ben", Err(MissingScheme)),
+        ("", Err(MissingScheme)),
+    ];
+
+    // URIs and the Unix paths that they are converted to
+    const UNIX_URIS: &[(&str, Result<&str, UriErrorReason>)] = &[
+        ("file:///home/ben/report.txt", Ok("/home/ben/report.txt")),
+        ("file:///", Ok("/")),
+        ("file:/home/ben", Ok("/home/ben")),
+        ("file://localhost/home/ben", Ok("/home/ben")),
+        ("File://LocalHost/home/ben", Ok("/home/ben")),
+        (
+            "file:///home/ben/My%20Exports/donn%C3%A9es%20%231.csv",
+            Ok("/home/ben/My Exports/données #1.csv"),
+        ),
+        ("file:///home/ben/%3Fq%26a", Ok("/home/ben/?q&a")),
+        ("file:///home/ben/a%2fb", Err(MalformedEscape)),
+        ("file:///home/ben/a%5Cb", Ok(r"/home/ben/a\b")),
+        (
+            "file:///home/ben/report.txt#page=2",
+            Ok("/home/ben/report.txt"),
+        ),
+        ("file:///home//ben/", Ok("/home//ben/")),
+        ("file://server/share", Err(RemoteHost(String::new()))),
+        ("file:relative", Err(NotAbsolute)),
+        ("file:///home/%", Err(MalformedEscape)),
+        ("file:///home/%00", Err(NullCharacter)),
+        ("smb://server/share", Err(UnsupportedScheme(String::new()))),
+        (
+            "data:text/plain,hello",
+            Err(UnsupportedScheme(String::new())),
+        ),
+        ("/home/ben", Err(MissingScheme)),
+        ("1file:///home", Err(MissingScheme)),
+    ];
+
+    // Compares results, filling in the scheme or host of errors from the URI
+    fn check(uri: &str, style: UriStyle, expected: &Result<&str, UriErrorReason>) {
+        let expected = match expected {
+            Ok(path) => Ok(PathBuf::from(path)),
+            Err(UnsupportedScheme(_)) => {
+                Err(UnsupportedScheme(uri[..uri.find(':').unwrap()].to_string()))
+            }
+            Err(RemoteHost(_)) => Err(RemoteHost(
+                uri[7..uri[7..].find('/').unwrap() + 7].to_string(),
+            )),
+            Err(reason) => Err(reason.clone()),
+        };
+        assert_eq!(to_path(uri, style), expected, "{}", uri);
+    }
+
+    #[test]
+    fn windows_uri_corpus() {
+        for (uri, expected) in WINDOWS_URIS {
+            check(uri, UriStyle::Windows, expected);
+        }
+    }
+
+    #[test]
+    fn unix_uri_corpus() {
+        for (uri, expected) in UNIX_URIS {
+            check(uri, UriStyle::Unix, expected);
+        }
+    }
+
+    #[test]
+    fn windows_paths_to_uris() {
+        let cases = [
+            (
+                r"C:\Users\ben\report.txt",
+                Ok("file:///C:/Users/ben/report.txt"),
+            ),
+            (r"C:\", Ok("file:///C:/")),
+            ("C:/forward/slashes", Ok("file:///C:/forward/slashes")),
+            (r"C:\a b\#c%.txt", Ok("file:///C:/a%20b/%23c%25.txt")),
+            (
+                r"C:\données\日本",
+                Ok("file:///C:/donn%C3%A9es/%E6%97%A5%E6%9C%AC"),
+            ),
+            (r"C:\it's (1);[2]", Ok("file:///C:/it's%20(1);%5B2%5D")),
+            (r"\\?\C:\verbatim", Ok("file:///C:/verbatim")),
+            (r"\\server\share\dir", Ok("file://server/share/dir")),
+            (r"\\?\UNC\server\share", Ok("file://server/share")),
+            (r"\\my server\share", Ok("file://my%20server/share")),
+            (r"\\server", Err(NotAbsolute)),
+            (r"\\.\PhysicalDrive0", Err(NotAbsolute)),
+            (r"\\?\Volume{guid}\dir", Err(NotAbsolute)),
+            (r"relative\path", Err(NotAbsolute)),
+            (r"\rooted", Err(NotAbsolute)),
+            ("C:relative", Err(NotAbsolute)),
+            ("shell:Downloads", Err(NotAbsolute)),
+            ("C:\\nul\0", Err(NullCharacter)),
+        ];
+        for (path, expected) in &cases {
+            let expected = expected.clone().map(String::from);
+            assert_eq!(to_uri(path, UriStyle::Windows), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn unix_paths_to_uris() {
+        let cases = [
+            ("/home/ben/report.txt", Ok("file:///home/ben/report.txt")),
+            ("/", Ok("file:///")),
+            (
+                "/home/ben/My Exports/données #1.csv",
+                Ok("file:///home/ben/My%20Exports/donn%C3%A9es%20%231.csv"),
+            ),
+            ("/home/ben/?q&a=1%", Ok("file:///home/ben/%3Fq&a=1%25")),
+            ("/home/ben/back\\slash", Ok("file:///home/ben/back%5Cslash")),
+            ("relative/path", Err(NotAbsolute)),
+            ("", Err(NotAbsolute)),
+            ("/nul\0", Err(NullCharacter)),
+        ];
+        for (path, expected) in &cases {
+            let expected = expected.clone().map(String::from);
+            assert_eq!(to_uri(path, UriStyle::Unix), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for (_, expected) in WINDOWS_URIS {
+            if let Ok(path) = expected {
+                let uri = to_uri(path, UriStyle::Windows).unwrap();
+                assert_eq!(to_path(&uri, UriStyle::Windows), Ok(PathBuf::from(path)));
+            }
+        }
+        for (_, expected) in UNIX_URIS {
+            if let Ok(path) = expected {
+                let uri = to_uri(path, UriStyle::Unix).unwrap();
+                assert_eq!(to_path(&uri, UriStyle::Unix), Ok(PathBuf::from(path)));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_invalid_unicode() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/tmp/\xff\xfe.bin"));
+        let uri = path_to_file_uri(path, UriStyle::Unix).unwrap();
+        assert_eq!(uri, "file:///tmp/%FF%FE.bin");
+        assert_eq!(file_uri_to_path(&uri, UriStyle::Unix).unwrap(), path);
+
+        assert_eq!(to_uri_reason(path, UriStyle::Windows), InvalidUnicode);
+    }
+
+    #[cfg(unix)]
+    fn to_uri_reason(path: &Path, style: UriStyle) -> UriErrorReason {
+        match path_to_file_uri(path, style) {
+            Err(DialogError::UriConversion { reason, .. }) => reason,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn input_paths() {
+        let native = if cfg!(windows) {
+            ("file:///C:/Users/ben", r"C:\Users\ben")
+        } else {
+            ("file:///home/ben", "/home/ben")
+        };
+        assert_eq!(
+            input_path(Path::new(native.0)).unwrap(),
+            Path::new(native.1)
+        );
+        assert!(matches!(
+            input_path(Path::new(native.1)).unwrap(),
+            Cow::Borrowed(_)
+        ));
+        assert!(input_path(Path::new("file://")).is_err());
+    }
+}
@@ -0,0 +1,467 @@
+// A minimal XML reader and writer, sufficient for the small, machine-written XML files that wfd
+// reads and updates such as `recently-used.xbel`. Comments, processing instructions and DOCTYPE
+// declarations are skipped when reading, and namespaces are not resolved, so prefixed names such
+// as `bookmark:application` are kept as-is.
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Node>,
+}
+
+impl Element {
+    pub(crate) fn new(name: &str) -> Self {
+        Element {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_child(mut self, child: Element) -> Self {
+        self.children.push(Node::Element(child));
+        self
+    }
+
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Sets the value of an attribute, keeping its position if it already exists
+    pub(crate) fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attributes.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.attributes.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub(crate) fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub(crate) fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    // Returns the child element with the given name, creating it if it does not exist
+    pub(crate) fn child_or_insert(&mut self, name: &str) -> &mut Element {
+        let index = match self
+            .children
+            .iter()
+            .position(|child| matches!(child, Node::Element(e) if e.name == name))
+        {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::Element(Element::new(name)));
+                self.children.len() - 1
+            }
+        };
+        match &mut self.children[index] {
+            Node::Element(element) => element,
+            Node::Text(_) => unreachable!("index refers to an element"),
+        }
+    }
+
+    // The concatenated text content of the element and its descendants
+    pub(crate) fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Element(element) => text.push_str(&element.text()),
+                Node::Text(t) => text.push_str(t),
+            }
+        }
+        text
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ParseError(pub(crate) String);
+
+// Parses a document, returning its root element
+pub(crate) fn parse(input: &str) -> Result<Element, ParseError> {
+    let mut parser = Parser { input, pos: 0 };
+    parser.skip_misc()?;
+    let root = parser.parse_element()?;
+    parser.skip_misc()?;
+    if parser.pos < input.len() {
+        return Err(parser.error("unexpected content after the root element"));
+    }
+    Ok(root)
+}
+
+// Writes a document with the given root element, indenting elements that do not contain text.
+// Whitespace-only text between elements is replaced by the indentation.
+pub(crate) fn write(root: &Element) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    write_element(&mut output, root, 0);
+    output.push('\n');
+    output
+}
+
+fn write_element(output: &mut String, element: &Element, depth: usize) {
+    output.push('<');
+    output.push_str(&element.name);
+    for (name, value) in &element.attributes {
+        let _ = write!(output, " {}=\"{}\"", name, escape(value));
+    }
+
+    let has_text = element
+        .children
+        .iter()
+        .any(|child| matches!(child, Node::Text(t) if !t.trim().is_empty()));
+    let children = element
+        .children
+        .iter()
+        .filter(|child| has_text || matches!(child, Node::Element(_)))
+        .collect::<Vec<_>>();
+    if children.is_empty() {
+        output.push_str("/>");
+        return;
+    }
+
+    output.push('>');
+    for child in &children {
+        match child {
+            Node::Element(child) => {
+                if !has_text {
+                    output.push('\n');
+                    output.push_str(&"  ".repeat(depth + 1));
+                }
+                write_element(output, child, depth + 1);
+            }
+            Node::Text(text) => output.push_str(&escape(text)),
+        }
+    }
+    if !has_text {
+        output.push('\n');
+        output.push_str(&"  ".repeat(depth));
+    }
+    let _ = write!(output, "</{}>", element.name);
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError(format!("{} at byte {}", message, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // Skips past the next occurrence of `terminator`
+    fn skip_past(&mut self, terminator: &str) -> Result<(), ParseError> {
+        match self.rest().find(terminator) {
+            Some(index) => {
+                self.pos += index + terminator.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing '{}'", terminator))),
+        }
+    }
+
+    // Skips whitespace, comments, processing instructions and DOCTYPE declarations
+    fn skip_misc(&mut self) -> Result<(), ParseError> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") && !rest.starts_with("<![CDATA[") {
+                // A DOCTYPE declaration can contain an internal subset in square brackets, whose
+                // markup declarations contain '>' characters themselves
+                let has_subset = match (rest.find('['), rest.find('>')) {
+                    (Some(bracket), Some(end)) => bracket < end,
+                    _ => false,
+                };
+                if has_subset {
+                    self.skip_past("]")?;
+                }
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, ParseError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '/' | '>' | '<'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ParseError> {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", s)))
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<Element, ParseError> {
+        self.expect("<")?;
+        let mut element = Element::new(&self.parse_name()?);
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            } else if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let name = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => quote,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let end = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.error("unterminated attribute value"))?;
+            let value = unescape(&self.rest()[..end]);
+            self.pos += end + 1;
+            element.attributes.push((name, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.parse_name()?;
+                if name != element.name {
+                    return Err(self.error(&format!(
+                        "expected '</{}>' but found '</{}>'",
+                        element.name, name
+                    )));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let end = self
+                    .rest()
+                    .find("]]>")
+                    .ok_or_else(|| self.error("unterminated CDATA section"))?;
+                push_text(&mut element, &self.rest()[..end]);
+                self.pos += end + 3;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                let child = self.parse_element()?;
+                element.children.push(Node::Element(child));
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("missing '</{}>'", element.name)));
+            } else {
+                // Whitespace between elements is not significant in the documents read by wfd
+                let end = rest.find('<').unwrap_or(rest.len());
+                if !rest[..end].trim().is_empty() {
+                    push_text(&mut element, &unescape(&rest[..end]));
+                }
+                self.pos += end;
+            }
+        }
+    }
+}
+
+// Appends text to an element, merging it with any directly preceding text
+fn push_text(element: &mut Element, text: &str) {
+    match element.children.last_mut() {
+        Some(Node::Text(existing)) => existing.push_str(text),
+        _ => element.children.push(Node::Text(text.to_string())),
+    }
+}
+
+// Replaces the predefined entities and character references in text. Unknown or malformed
+// references are left as-is.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('&') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        let replacement = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end + 1))
+        });
+
+        match replacement {
+            Some((c, len)) => {
+                result.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_document() {
+        let root = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE root>
+<!-- comment -->
+<root a="1" b='two &amp; &#x33;'>
+  <child name="x"/>
+  <text>Hello &lt;world&gt; <![CDATA[<raw>]]></text>
+  <!-- inner comment -->
+  <ns:child/>
+</root>
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(root.name, "root");
+        assert_eq!(root.attribute("a"), Some("1"));
+        assert_eq!(root.attribute("b"), Some("two & 3"));
+        assert_eq!(
+            root.elements().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+            vec!["child", "text", "ns:child"]
+        );
+        assert_eq!(root.child("child").unwrap().attribute("name"), Some("x"));
+        assert_eq!(root.child("text").unwrap().text(), "Hello <world> <raw>");
+    }
+
+    #[test]
+    fn parse_doctype_with_internal_subset() {
+        let root = parse(
+            r#"<!DOCTYPE root [
+<!ELEMENT root (child)*>
+<!-- a comment describing the element --><!ATTLIST child name CDATA #REQUIRED>
+]>
+<root><child name="x"/></root>"#,
+        )
+        .unwrap();
+        assert_eq!(root.child("child").unwrap().attribute("name"), Some("x"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("<root>").is_err());
+        assert!(parse("<root></other>").is_err());
+        assert!(parse("<root a=1/>").is_err());
+        assert!(parse("<root/><second/>").is_err());
+    }
+
+    #[test]
+    fn unescape_references() {
+        assert_eq!(unescape("&lt;&gt;&amp;&quot;&apos;"), "<>&\"'");
+        assert_eq!(unescape("&#65;&#x42;"), "AB");
+        assert_eq!(unescape("&unknown; & &#xZZ;"), "&unknown; & &#xZZ;");
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let root = Element::new("root")
+            .with_attribute("quote", "\"'<&>")
+            .with_child(Element::new("empty"))
+            .with_child(Element::new("parent").with_child(Element::new("child")));
+        let written = write(&root);
+
+        assert_eq!(
+            written,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<root quote="&quot;&apos;&lt;&amp;&gt;">
+  <empty/>
+  <parent>
+    <child/>
+  </parent>
+</root>
+"#
+        );
+        assert_eq!(parse(&written).unwrap(), root);
+    }
+}
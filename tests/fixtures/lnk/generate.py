@@ -0,0 +1,416 @@
+#!/usr/bin/env python3
+"""Generates the .lnk fixtures used by the tests of src/shortcut.rs.
+
+The files follow the MS-SHLLINK format. The first set contains only the structures that wfd reads
+to find the target of a shortcut. The `*-explorer.lnk` files are laid out like the shortcuts that
+Explorer saves, with a full LinkTargetIDList of shell items, a VolumeID, the ExtraData blocks for
+link tracking, the property store and the known folder, so that the parser is exercised with the
+structures that it skips as well. They are built here from the format documentation rather than
+captured from Windows. Run from this directory to regenerate them.
+"""
+
+import struct
+import uuid
+from datetime import datetime
+
+LINK_CLSID = bytes.fromhex("0114020000000000c000000000000046")
+
+HAS_LINK_TARGET_ID_LIST = 0x1
+HAS_LINK_INFO = 0x2
+HAS_NAME = 0x4
+HAS_RELATIVE_PATH = 0x8
+HAS_WORKING_DIR = 0x10
+IS_UNICODE = 0x80
+HAS_EXP_STRING = 0x200
+
+VOLUME_ID_AND_LOCAL_BASE_PATH = 0x1
+COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX = 0x2
+
+ENABLE_TARGET_METADATA = 0x80000
+
+ENVIRONMENT_VARIABLE_DATA_BLOCK = 0xA0000001
+TRACKER_DATA_BLOCK = 0xA0000003
+SPECIAL_FOLDER_DATA_BLOCK = 0xA0000005
+PROPERTY_STORE_DATA_BLOCK = 0xA0000009
+KNOWN_FOLDER_DATA_BLOCK = 0xA000000B
+
+FILE_ATTRIBUTE_DIRECTORY = 0x10
+FILE_ATTRIBUTE_ARCHIVE = 0x20
+
+DRIVE_FIXED = 3
+WNNC_NET_LANMAN = 0x20000
+VALID_DEVICE = 0x1
+VALID_NET_TYPE = 0x2
+
+
+def header(flags):
+    return (
+        struct.pack("<I16sII", 0x4C, LINK_CLSID, flags, 0x20)
+        + bytes(24)  # creation, access and write times
+        + struct.pack("<IiIHHII", 0, 0, 1, 0, 0, 0, 0)
+    )
+
+
+def id_list():
+    # A single opaque item, which wfd skips
+    item = struct.pack("<H", 6) + b"\x1f\x50\x00\x00"
+    return struct.pack("<H", len(item) + 2) + item + struct.pack("<H", 0)
+
+
+def ansi(text):
+    # Characters outside of the code page are replaced, as Windows does
+    return text.encode("cp1252", "replace") + b"\0"
+
+
+def utf16(text):
+    return text.encode("utf-16-le") + b"\0\0"
+
+
+def volume_id(label, serial=0x1234ABCD):
+    label = ansi(label)
+    return struct.pack("<IIII", 0x10 + len(label), DRIVE_FIXED, serial, 0x10) + label
+
+
+def link_info(local_base_path=None, network=None, suffix="", unicode=False, label="Windows"):
+    """Builds a LinkInfo structure. `network` is a (net_name, device_name) pair, where the device
+    name is None for a share that is not mapped to a drive letter."""
+    header_size = 0x24 if unicode else 0x1C
+    flags = 0
+    body = b""
+    offsets = {}
+
+    def append(name, data):
+        nonlocal body
+        offsets[name] = header_size + len(body)
+        body += data
+
+    if local_base_path is not None:
+        flags |= VOLUME_ID_AND_LOCAL_BASE_PATH
+        append("volume_id", volume_id(label))
+        append("local_base_path", ansi(local_base_path))
+    if network is not None:
+        flags |= COMMON_NETWORK_RELATIVE_LINK_AND_PATH_SUFFIX
+        net_name, device_name = network
+        strings = ansi(net_name)
+        if device_name is None:
+            network_flags, device_offset = VALID_NET_TYPE, 0
+        else:
+            network_flags, device_offset = VALID_DEVICE | VALID_NET_TYPE, 0x14 + len(strings)
+            strings += ansi(device_name)
+        common = struct.pack(
+            "<IIIII", 0x14 + len(strings), network_flags, 0x14, device_offset, WNNC_NET_LANMAN
+        )
+        append("network", common + strings)
+    append("suffix", ansi(suffix))
+    if unicode:
+        append("local_base_path_unicode", utf16(local_base_path or ""))
+        append("suffix_unicode", utf16(suffix))
+
+    fields = [
+        header_size,
+        flags,
+        offsets.get("volume_id", 0),
+        offsets.get("local_base_path", 0),
+        offsets.get("network", 0),
+        offsets["suffix"],
+    ]
+    if unicode:
+        fields += [
+            offsets["local_base_path_unicode"] if local_base_path is not None else 0,
+            offsets["suffix_unicode"],
+        ]
+    fixed = struct.pack("<I" + "I" * len(fields), header_size + len(body), *fields)
+    assert len(fixed) == header_size
+    return fixed + body
+
+
+def string_data(text, unicode):
+    if unicode:
+        return struct.pack("<H", len(text.encode("utf-16-le")) // 2) + text.encode("utf-16-le")
+    return struct.pack("<H", len(text)) + text.encode("cp1252")
+
+
+def environment_block(target):
+    target_ansi = target.encode("cp1252").ljust(260, b"\0")
+    target_unicode = target.encode("utf-16-le").ljust(520, b"\0")
+    return struct.pack("<II", 0x314, ENVIRONMENT_VARIABLE_DATA_BLOCK) + target_ansi + target_unicode
+
+
+def terminal_block():
+    return struct.pack("<I", 0)
+
+
+def shortcut(path, flags, *parts, header=header):
+    with open(path, "wb") as f:
+        f.write(header(flags) + b"".join(parts))
+
+
+# The structures of the shortcuts saved by Explorer that wfd does not read
+
+
+def filetime(time):
+    return int((time - datetime(1601, 1, 1)).total_seconds()) * 10_000_000
+
+
+def dos_datetime(time):
+    date = ((time.year - 1980) << 9) | (time.month << 5) | time.day
+    clock = (time.hour << 11) | (time.minute << 5) | (time.second // 2)
+    return date | (clock << 16)
+
+
+def guid(text):
+    return uuid.UUID(text).bytes_le
+
+
+def explorer_header(attributes, size, time):
+    def build(flags):
+        return (
+            struct.pack("<I16sII", 0x4C, LINK_CLSID, flags, attributes)
+            + struct.pack("<QQQ", filetime(time), filetime(time), filetime(time))
+            # The file size, icon index, SW_SHOWNORMAL, no hot key and the reserved fields
+            + struct.pack("<IiIHHII", size, 0, 1, 0, 0, 0, 0)
+        )
+
+    return build
+
+
+def root_item(folder_clsid):
+    # A root folder such as My Computer or Network, sorted in the position Explorer uses
+    return struct.pack("<BB", 0x1F, 0x50) + guid(folder_clsid)
+
+
+def drive_item(drive):
+    return (b"\x2f" + drive.encode("ascii") + b":\\").ljust(0x17, b"\0")
+
+
+def network_item(kind, location):
+    # A server (0x42) or share (0xC3) of the network, with only its location
+    return struct.pack("<BBB", kind, 0, 0) + ansi(location)
+
+
+def file_entry_item(name, short_name, directory, size, time, file_reference):
+    kind = 0x31 if directory else 0x32
+    attributes = FILE_ATTRIBUTE_DIRECTORY if directory else FILE_ATTRIBUTE_ARCHIVE
+    data = struct.pack("<BBIIH", kind, 0, size, dos_datetime(time), attributes)
+    data += short_name.encode("ascii") + b"\0"
+    if len(data) % 2:
+        data += b"\0"
+
+    # The version 9 extension block with the long name and the NTFS file reference. Its offset is
+    # from the start of the item, including the size of the item.
+    offset = 2 + len(data)
+    block = struct.pack("<II", dos_datetime(time), dos_datetime(time))
+    block += struct.pack("<HHQQ", 0x2E, 0, file_reference, 0)
+    block += struct.pack("<HII", 0, 0, 0)
+    block += utf16(name)
+    block += struct.pack("<H", offset)
+    return data + struct.pack("<HHI", 8 + len(block), 9, 0xBEEF0004) + block
+
+
+def explorer_id_list(*items):
+    """Builds a LinkTargetIDList, returning it and the offset of each item within the list."""
+    body = b""
+    offsets = []
+    for item in items:
+        offsets.append(len(body))
+        body += struct.pack("<H", len(item) + 2) + item
+    body += struct.pack("<H", 0)
+    return struct.pack("<H", len(body)) + body, offsets
+
+
+def tracker_block(machine, volume, file):
+    machine_id = machine.encode("ascii").ljust(16, b"\0")
+    droid = guid(volume) + guid(file)
+    return (
+        struct.pack("<IIII", 0x60, TRACKER_DATA_BLOCK, 0x58, 0)
+        + machine_id
+        + droid  # the current identifiers of the volume and file
+        + droid  # the identifiers when the shortcut was created
+    )
+
+
+def special_folder_block(csidl, offset):
+    return struct.pack("<IIII", 0x10, SPECIAL_FOLDER_DATA_BLOCK, csidl, offset)
+
+
+def known_folder_block(folder_id, offset):
+    return struct.pack("<II", 0x1C, KNOWN_FOLDER_DATA_BLOCK) + guid(folder_id) + struct.pack(
+        "<I", offset
+    )
+
+
+def property_store_block(sid):
+    """A property store holding the SID of the user who owns the target, as Explorer records."""
+    text = utf16(sid)
+    # VT_LPWSTR, with the number of characters including the terminator, padded to 4 bytes
+    value = struct.pack("<HHI", 0x1F, 0, len(text) // 2) + text
+    value += bytes(-len(value) % 4)
+    value = struct.pack("<IIB", 9 + len(value), 4, 0) + value
+    storage = b"1SPS" + guid("46588AE2-4CBC-4338-BBFC-139326986DCE") + value
+    storage += struct.pack("<I", 0)
+    storage = struct.pack("<I", 4 + len(storage)) + storage + struct.pack("<I", 0)
+    return struct.pack("<II", 8 + len(storage), PROPERTY_STORE_DATA_BLOCK) + storage
+
+
+# A file on a local drive, with ANSI strings
+shortcut(
+    "local.lnk",
+    HAS_LINK_TARGET_ID_LIST | HAS_LINK_INFO | HAS_RELATIVE_PATH | HAS_WORKING_DIR,
+    id_list(),
+    link_info(local_base_path="C:\\Users\\Public\\Documents\\Résumé – 2024.docx"),
+    string_data("..\\..\\Public\\Documents\\Résumé – 2024.docx", False),
+    string_data("C:\\Users\\Public\\Documents", False),
+    terminal_block(),
+)
+
+# A file on a local drive whose path is only representable in the Unicode fields
+shortcut(
+    "unicode.lnk",
+    HAS_LINK_INFO | HAS_NAME | IS_UNICODE,
+    link_info(local_base_path="C:\\Users\\Zoë\\Документы\\", suffix="отчёт.txt", unicode=True),
+    string_data("Отчёт", True),
+    terminal_block(),
+)
+
+# A file on a network share, mapped to a drive letter
+shortcut(
+    "network.lnk",
+    HAS_LINK_TARGET_ID_LIST | HAS_LINK_INFO | HAS_RELATIVE_PATH | IS_UNICODE,
+    id_list(),
+    link_info(network=("\\\\fileserver\\projects", "Z:"), suffix="plans\\roadmap.xlsx"),
+    string_data("..\\plans\\roadmap.xlsx", True),
+    terminal_block(),
+)
+
+# A file next to the shortcut, which only has a relative path
+shortcut(
+    "relative.lnk",
+    HAS_RELATIVE_PATH | IS_UNICODE,
+    string_data(".\\data\\notes.txt", True),
+    terminal_block(),
+)
+
+# A file whose path contains environment variables
+shortcut(
+    "environment.lnk",
+    HAS_LINK_TARGET_ID_LIST | HAS_EXP_STRING | IS_UNICODE,
+    id_list(),
+    environment_block("%USERPROFILE%\\Desktop\\todo.txt"),
+    terminal_block(),
+)
+
+# Shortcuts laid out as Explorer saves them
+
+MY_COMPUTER = "20D04FE0-3AEA-1069-A2D8-08002B30309D"
+NETWORK = "F02C1A0D-BE21-4350-88B0-7367FC96EF3C"
+FOLDERID_PUBLIC_DOCUMENTS = "ED4824AF-DCE4-45A8-81E2-FC7965083634"
+FOLDERID_DESKTOP = "B4BFCC3A-DB2C-424C-B029-7FE99A87C641"
+CSIDL_COMMON_DOCUMENTS = 0x2E
+CSIDL_DESKTOPDIRECTORY = 0x10
+USER_SID = "S-1-5-21-3623811015-3361044348-30300820-1013"
+VOLUME = "8A3C5E21-7B0D-4F1E-9C62-3D5F7A1B2C40"
+MODIFIED = datetime(2024, 3, 14, 9, 26, 52)
+
+# A file on a local drive, with a shortcut on the desktop of another user
+id_list, offsets = explorer_id_list(
+    root_item(MY_COMPUTER),
+    drive_item("C"),
+    file_entry_item("Users", "Users", True, 0, MODIFIED, 0x0001_0000_0000_01D4),
+    file_entry_item("Public", "Public", True, 0, MODIFIED, 0x0001_0000_0000_5C21),
+    file_entry_item("Documents", "DOCUME~1", True, 0, MODIFIED, 0x0001_0000_0000_5C2A),
+    file_entry_item("Budget 2024.xlsx", "BUDGET~1.XLS", False, 48_213, MODIFIED, 0x0003_0000_0004_1E07),
+)
+shortcut(
+    "local-explorer.lnk",
+    HAS_LINK_TARGET_ID_LIST
+    | HAS_LINK_INFO
+    | HAS_RELATIVE_PATH
+    | HAS_WORKING_DIR
+    | IS_UNICODE
+    | ENABLE_TARGET_METADATA,
+    id_list,
+    link_info(local_base_path="C:\\Users\\Public\\Documents\\Budget 2024.xlsx", label=""),
+    string_data("..\\..\\..\\Public\\Documents\\Budget 2024.xlsx", True),
+    string_data("C:\\Users\\Public\\Documents", True),
+    special_folder_block(CSIDL_COMMON_DOCUMENTS, offsets[5]),
+    known_folder_block(FOLDERID_PUBLIC_DOCUMENTS, offsets[5]),
+    property_store_block(USER_SID),
+    tracker_block("desktop-7k2m", VOLUME, "4F2E8B90-E1A3-11EE-B0C1-00155D0A6B01"),
+    terminal_block(),
+    header=explorer_header(FILE_ATTRIBUTE_ARCHIVE, 48_213, MODIFIED),
+)
+
+# A file on a network share that is not mapped to a drive letter
+id_list, offsets = explorer_id_list(
+    root_item(NETWORK),
+    network_item(0x42, "\\\\fileserver"),
+    network_item(0xC3, "\\\\fileserver\\projects"),
+    file_entry_item("plans", "plans", True, 0, MODIFIED, 0x0002_0000_0000_8A11),
+    file_entry_item("roadmap.xlsx", "ROADMA~1.XLS", False, 20_480, MODIFIED, 0x0002_0000_0000_8A4F),
+)
+shortcut(
+    "unc-explorer.lnk",
+    HAS_LINK_TARGET_ID_LIST | HAS_LINK_INFO | HAS_WORKING_DIR | IS_UNICODE | ENABLE_TARGET_METADATA,
+    id_list,
+    link_info(network=("\\\\fileserver\\projects", None), suffix="plans\\roadmap.xlsx"),
+    string_data("\\\\fileserver\\projects\\plans", True),
+    property_store_block(USER_SID),
+    tracker_block("fileserver", "2B7D9E04-5A61-4C38-8F07-A1C2D3E4F506", "7C01D2E3-E1A3-11EE-9A4B-0050568C2D11"),
+    terminal_block(),
+    header=explorer_header(FILE_ATTRIBUTE_ARCHIVE, 20_480, MODIFIED),
+)
+
+# A file whose path was given with environment variables, as installers create them
+id_list, offsets = explorer_id_list(
+    root_item(MY_COMPUTER),
+    drive_item("C"),
+    file_entry_item("Users", "Users", True, 0, MODIFIED, 0x0001_0000_0000_01D4),
+    file_entry_item("ben", "ben", True, 0, MODIFIED, 0x0001_0000_0000_6D02),
+    file_entry_item("Desktop", "Desktop", True, 0, MODIFIED, 0x0001_0000_0000_6D19),
+    file_entry_item("todo.txt", "todo.txt", False, 312, MODIFIED, 0x0004_0000_0004_2210),
+)
+shortcut(
+    "environment-explorer.lnk",
+    HAS_LINK_TARGET_ID_LIST
+    | HAS_LINK_INFO
+    | HAS_WORKING_DIR
+    | IS_UNICODE
+    | HAS_EXP_STRING
+    | ENABLE_TARGET_METADATA,
+    id_list,
+    link_info(local_base_path="C:\\Users\\ben\\Desktop\\todo.txt", label=""),
+    string_data("%USERPROFILE%\\Desktop", True),
+    environment_block("%USERPROFILE%\\Desktop\\todo.txt"),
+    special_folder_block(CSIDL_DESKTOPDIRECTORY, offsets[5]),
+    known_folder_block(FOLDERID_DESKTOP, offsets[5]),
+    property_store_block(USER_SID),
+    tracker_block("desktop-7k2m", VOLUME, "4F2E8BA1-E1A3-11EE-B0C1-00155D0A6B01"),
+    terminal_block(),
+    header=explorer_header(FILE_ATTRIBUTE_ARCHIVE, 312, MODIFIED),
+)
+
+# A file in a folder next to the shortcut
+id_list, offsets = explorer_id_list(
+    root_item(MY_COMPUTER),
+    drive_item("D"),
+    file_entry_item("Projects", "PROJEC~1", True, 0, MODIFIED, 0x0001_0000_0000_0A31),
+    file_entry_item("data", "data", True, 0, MODIFIED, 0x0001_0000_0000_0A36),
+    file_entry_item("notes.txt", "notes.txt", False, 1_024, MODIFIED, 0x0002_0000_0000_0A3C),
+)
+shortcut(
+    "relative-explorer.lnk",
+    HAS_LINK_TARGET_ID_LIST
+    | HAS_LINK_INFO
+    | HAS_RELATIVE_PATH
+    | HAS_WORKING_DIR
+    | IS_UNICODE
+    | ENABLE_TARGET_METADATA,
+    id_list,
+    link_info(local_base_path="D:\\Projects\\data\\notes.txt", label="Data"),
+    string_data(".\\data\\notes.txt", True),
+    string_data("D:\\Projects\\data", True),
+    property_store_block(USER_SID),
+    tracker_block("desktop-7k2m", "C61E0A7B-93D4-4E25-B8F1-5A0C2D9E7B33", "4F2E8BB2-E1A3-11EE-B0C1-00155D0A6B01"),
+    terminal_block(),
+    header=explorer_header(FILE_ATTRIBUTE_ARCHIVE, 1_024, MODIFIED),
+)